@@ -4,6 +4,12 @@
     editor-cursor-up-keypress
     editor-cursor-down
     editor-cursor-down-keypress
+    editor-cursor-visual-up
+    editor-cursor-visual-up-keypress
+    editor-cursor-visual-down
+    editor-cursor-visual-down-keypress
+    editor-wrap-toggle
+    editor-line-numbers
     editor-cursor-left
     editor-cursor-left-keypress
     editor-cursor-left-wrap
@@ -32,12 +38,24 @@
     editor-insert-space-keypress
     editor-insert-newline
     editor-insert-newline-keypress
+    editor-indent
+    editor-indent-keypress
+    editor-dedent
+    editor-dedent-keypress
     editor-delete-back
     editor-delete-back-keypress
     editor-delete-forward
     editor-delete-forward-keypress
     editor-delete-region
     editor-delete-region-keypress
+    editor-copy-region
+    editor-copy-region-keypress
+    editor-paste
+    editor-paste-keypress
+    editor-copy-clipboard
+    editor-copy-clipboard-keypress
+    editor-paste-clipboard
+    editor-paste-clipboard-keypress
     editor-place-point-mark
     editor-place-point-mark-keypress
     editor-remove-mark
@@ -46,6 +64,42 @@
     editor-undo-keypress
     editor-redo
     editor-redo-keypress
+    editor-pane-split-horizontal
+    editor-pane-split-horizontal-keypress
+    editor-pane-split-vertical
+    editor-pane-split-vertical-keypress
+    editor-pane-close
+    editor-pane-close-keypress
+    editor-pane-grow
+    editor-pane-grow-keypress
+    editor-pane-shrink
+    editor-pane-shrink-keypress
+    editor-pane-focus-left
+    editor-pane-focus-left-keypress
+    editor-pane-focus-right
+    editor-pane-focus-right-keypress
+    editor-pane-focus-up
+    editor-pane-focus-up-keypress
+    editor-pane-focus-down
+    editor-pane-focus-down-keypress
+    editor-pane-focus-next
+    editor-pane-focus-next-keypress
+    editor-tab-new
+    editor-tab-new-keypress
+    editor-tab-close
+    editor-tab-close-keypress
+    editor-tab-next
+    editor-tab-next-keypress
+    editor-tab-previous
+    editor-tab-previous-keypress
+    editor-tab-move-left
+    editor-tab-move-left-keypress
+    editor-tab-move-right
+    editor-tab-move-right-keypress
+    editor-tab-switch
+    editor-tab-move
+    editor-tab-rename
+    editor-buffer-switch
     mode-state-create
     mode-state-state
     mode-state-state-change
@@ -64,6 +118,10 @@
     (koru-key)
     (minor-mode)
     (koru-buffer)
+    (koru-register)
+    (koru-pane)
+    (koru-tab)
+    (koru-gutter)
     (scheme koru)
     (scheme text-edit-mode))
 
@@ -100,6 +158,53 @@
       #t
       'key-sequence))
 
+  (define editor-cursor-visual-up
+    (command-create
+      'editor-cursor-visual-up
+      "Moves the cursors up a row of wrapped text"
+      (lambda () (let ((cursor-count (text-edit-mode-cursor-count)))
+                       (for i from 0 to (- cursor-count 1)
+                         (command-apply text-edit-mode-cursor-visual-up i))))))
+
+  (define editor-cursor-visual-up-keypress
+    (command-create
+      'editor-cursor-visual-up-keypress
+      "Moves the cursors up a row of wrapped text in response to a keypress"
+      (lambda (keys) (command-apply editor-cursor-visual-up))
+      #t
+      'key-sequence))
+
+  (define editor-cursor-visual-down
+    (command-create
+      'editor-cursor-visual-down
+      "Moves the cursors down a row of wrapped text"
+      (lambda () (let ((cursor-count (text-edit-mode-cursor-count)))
+                       (for i from 0 to (- cursor-count 1)
+                         (command-apply text-edit-mode-cursor-visual-down i))))))
+
+  (define editor-cursor-visual-down-keypress
+    (command-create
+      'editor-cursor-visual-down-keypress
+      "Moves the cursors down a row of wrapped text in response to a keypress"
+      (lambda (keys) (command-apply editor-cursor-visual-down))
+      #t
+      'key-sequence))
+
+  (define editor-wrap-toggle
+    (command-create
+      'editor-wrap-toggle
+      "Toggles wrapping long lines at the width of the view"
+      (lambda ()
+        (let ((buffer-name (current-buffer-name)))
+          (buffer-wrap-set! buffer-name (if (buffer-wrap buffer-name) #f 'viewport))))))
+
+  (define editor-line-numbers
+    (command-create
+      'editor-line-numbers
+      "Sets how lines are numbered in the gutter: off, absolute, relative, or hybrid"
+      (lambda (numbering) (gutter-line-numbers-set! numbering))
+      'text))
+
   (define editor-cursor-left
     (command-create
       'editor-cursor-left
@@ -292,11 +397,47 @@
       #t
       'key-sequence))
 
+  (define editor-insert-newline
+    (command-create
+      'editor-insert-newline
+      "Inserts an indented newline at each cursor"
+      (lambda () (let ((cursor-count (text-edit-mode-cursor-count)))
+                       (for i from 0 to (- cursor-count 1)
+                         (command-apply text-edit-mode-insert-newline i))))))
+
   (define editor-insert-newline-keypress
     (command-create
       'editor-insert-newline-keypress
-      "Inserts a space at each cursor in response to a keypress"
-      (lambda (keys) (command-apply editor-insert-text "\n"))
+      "Inserts an indented newline at each cursor in response to a keypress"
+      (lambda (keys) (command-apply editor-insert-newline))
+      #t
+      'key-sequence))
+
+  (define editor-indent
+    (command-create
+      'editor-indent
+      "Indents the lines under each cursor and its marked area"
+      (lambda () (command-apply text-edit-mode-indent))))
+
+  (define editor-indent-keypress
+    (command-create
+      'editor-indent-keypress
+      "Indents the lines under each cursor and its marked area in response to a keypress"
+      (lambda (keys) (command-apply editor-indent))
+      #t
+      'key-sequence))
+
+  (define editor-dedent
+    (command-create
+      'editor-dedent
+      "Dedents the lines under each cursor and its marked area"
+      (lambda () (command-apply text-edit-mode-dedent))))
+
+  (define editor-dedent-keypress
+    (command-create
+      'editor-dedent-keypress
+      "Dedents the lines under each cursor and its marked area in response to a keypress"
+      (lambda (keys) (command-apply editor-dedent))
       #t
       'key-sequence))
 
@@ -337,6 +478,7 @@
       'editor-delete-region
       "Deletes text in text region of each cursor"
       (lambda () (let ((cursor-count (text-edit-mode-cursor-count)))
+                       (kill-ring-begin-entry cursor-count)
                        (for i from 0 to (- cursor-count 1)
                          (command-apply text-edit-mode-delete-cursor-region i))))))
 
@@ -344,9 +486,74 @@
     (command-create
       'editor-delete-region-keypress
       "Deletes text in text region of each cursor in response to a keypress"
-      (lambda (keys) (let ((cursor-count (text-edit-mode-cursor-count)))
+      (lambda (keys) (command-apply editor-delete-region))
+      #t
+      'key-sequence))
+
+  (define editor-copy-region
+    (command-create
+      'editor-copy-region
+      "Copies text in text region of each cursor"
+      (lambda () (let ((cursor-count (text-edit-mode-cursor-count)))
+                       (kill-ring-begin-entry cursor-count)
                        (for i from 0 to (- cursor-count 1)
-                         (command-apply text-edit-mode-delete-cursor-region i))))
+                         (command-apply text-edit-mode-copy-cursor-region i))))))
+
+  (define editor-copy-region-keypress
+    (command-create
+      'editor-copy-region-keypress
+      "Copies text in text region of each cursor in response to a keypress"
+      (lambda (keys) (command-apply editor-copy-region))
+      #t
+      'key-sequence))
+
+  (define editor-paste
+    (command-create
+      'editor-paste
+      "Pastes text from the kill ring at each cursor"
+      (lambda () (let ((cursor-count (text-edit-mode-cursor-count)))
+                       (for i from 0 to (- cursor-count 1)
+                         (command-apply text-edit-mode-paste-at-cursor i))))))
+
+  (define editor-paste-keypress
+    (command-create
+      'editor-paste-keypress
+      "Pastes text from the kill ring at each cursor in response to a keypress"
+      (lambda (keys) (command-apply editor-paste))
+      #t
+      'key-sequence))
+
+  (define editor-copy-clipboard
+    (command-create
+      'editor-copy-clipboard
+      "Copies text in text region of each cursor to the system clipboard"
+      (lambda () (let ((cursor-count (text-edit-mode-cursor-count)))
+                       (kill-ring-begin-entry cursor-count)
+                       (for i from 0 to (- cursor-count 1)
+                         (text-edit-mode-copy-cursor-region-to i "+"))))))
+
+  (define editor-copy-clipboard-keypress
+    (command-create
+      'editor-copy-clipboard-keypress
+      "Copies text in text region of each cursor to the system clipboard in response to a keypress"
+      (lambda (keys) (command-apply editor-copy-clipboard))
+      #t
+      'key-sequence))
+
+  (define editor-paste-clipboard
+    (command-create
+      'editor-paste-clipboard
+      "Pastes text from the system clipboard at each cursor"
+      (lambda () (clipboard-request
+                   (lambda (text) (let ((cursor-count (text-edit-mode-cursor-count)))
+                                    (for i from 0 to (- cursor-count 1)
+                                      (text-edit-mode-paste-at-cursor-from i "+"))))))))
+
+  (define editor-paste-clipboard-keypress
+    (command-create
+      'editor-paste-clipboard-keypress
+      "Pastes text from the system clipboard at each cursor in response to a keypress"
+      (lambda (keys) (command-apply editor-paste-clipboard))
       #t
       'key-sequence))
 
@@ -399,6 +606,258 @@
       #t
       'key-sequence))
 
+  (define editor-pane-split-horizontal
+    (command-create
+      'editor-pane-split-horizontal
+      "Splits the focused pane into two, with the new pane below it"
+      (lambda () (pane-split 'horizontal))))
+
+  (define editor-pane-split-horizontal-keypress
+    (command-create
+      'editor-pane-split-horizontal-keypress
+      "Splits the focused pane into two, with the new pane below it in response to a keypress"
+      (lambda (keys) (command-apply editor-pane-split-horizontal))
+      #t
+      'key-sequence))
+
+  (define editor-pane-split-vertical
+    (command-create
+      'editor-pane-split-vertical
+      "Splits the focused pane into two, with the new pane to the right of it"
+      (lambda () (pane-split 'vertical))))
+
+  (define editor-pane-split-vertical-keypress
+    (command-create
+      'editor-pane-split-vertical-keypress
+      "Splits the focused pane into two, with the new pane to the right of it in response to a keypress"
+      (lambda (keys) (command-apply editor-pane-split-vertical))
+      #t
+      'key-sequence))
+
+  (define editor-pane-close
+    (command-create
+      'editor-pane-close
+      "Closes the focused pane, unless it is the last one"
+      (lambda () (pane-close))))
+
+  (define editor-pane-close-keypress
+    (command-create
+      'editor-pane-close-keypress
+      "Closes the focused pane, unless it is the last one in response to a keypress"
+      (lambda (keys) (command-apply editor-pane-close))
+      #t
+      'key-sequence))
+
+  (define editor-pane-grow
+    (command-create
+      'editor-pane-grow
+      "Makes the focused pane bigger"
+      (lambda () (pane-resize 5))))
+
+  (define editor-pane-grow-keypress
+    (command-create
+      'editor-pane-grow-keypress
+      "Makes the focused pane bigger in response to a keypress"
+      (lambda (keys) (command-apply editor-pane-grow))
+      #t
+      'key-sequence))
+
+  (define editor-pane-shrink
+    (command-create
+      'editor-pane-shrink
+      "Makes the focused pane smaller"
+      (lambda () (pane-resize -5))))
+
+  (define editor-pane-shrink-keypress
+    (command-create
+      'editor-pane-shrink-keypress
+      "Makes the focused pane smaller in response to a keypress"
+      (lambda (keys) (command-apply editor-pane-shrink))
+      #t
+      'key-sequence))
+
+  (define editor-pane-focus-left
+    (command-create
+      'editor-pane-focus-left
+      "Moves the focus to the pane to the left"
+      (lambda () (pane-focus 'left))))
+
+  (define editor-pane-focus-left-keypress
+    (command-create
+      'editor-pane-focus-left-keypress
+      "Moves the focus to the pane to the left in response to a keypress"
+      (lambda (keys) (command-apply editor-pane-focus-left))
+      #t
+      'key-sequence))
+
+  (define editor-pane-focus-right
+    (command-create
+      'editor-pane-focus-right
+      "Moves the focus to the pane to the right"
+      (lambda () (pane-focus 'right))))
+
+  (define editor-pane-focus-right-keypress
+    (command-create
+      'editor-pane-focus-right-keypress
+      "Moves the focus to the pane to the right in response to a keypress"
+      (lambda (keys) (command-apply editor-pane-focus-right))
+      #t
+      'key-sequence))
+
+  (define editor-pane-focus-up
+    (command-create
+      'editor-pane-focus-up
+      "Moves the focus to the pane above"
+      (lambda () (pane-focus 'up))))
+
+  (define editor-pane-focus-up-keypress
+    (command-create
+      'editor-pane-focus-up-keypress
+      "Moves the focus to the pane above in response to a keypress"
+      (lambda (keys) (command-apply editor-pane-focus-up))
+      #t
+      'key-sequence))
+
+  (define editor-pane-focus-down
+    (command-create
+      'editor-pane-focus-down
+      "Moves the focus to the pane below"
+      (lambda () (pane-focus 'down))))
+
+  (define editor-pane-focus-down-keypress
+    (command-create
+      'editor-pane-focus-down-keypress
+      "Moves the focus to the pane below in response to a keypress"
+      (lambda (keys) (command-apply editor-pane-focus-down))
+      #t
+      'key-sequence))
+
+  (define editor-pane-focus-next
+    (command-create
+      'editor-pane-focus-next
+      "Moves the focus to the next pane"
+      (lambda () (pane-focus-next))))
+
+  (define editor-pane-focus-next-keypress
+    (command-create
+      'editor-pane-focus-next-keypress
+      "Moves the focus to the next pane in response to a keypress"
+      (lambda (keys) (command-apply editor-pane-focus-next))
+      #t
+      'key-sequence))
+
+  (define editor-tab-new
+    (command-create
+      'editor-tab-new
+      "Opens a new tab showing the current buffer"
+      (lambda () (tab-new))))
+
+  (define editor-tab-new-keypress
+    (command-create
+      'editor-tab-new-keypress
+      "Opens a new tab showing the current buffer in response to a keypress"
+      (lambda (keys) (command-apply editor-tab-new))
+      #t
+      'key-sequence))
+
+  (define editor-tab-close
+    (command-create
+      'editor-tab-close
+      "Closes the current tab, unless it is the last one"
+      (lambda () (tab-close))))
+
+  (define editor-tab-close-keypress
+    (command-create
+      'editor-tab-close-keypress
+      "Closes the current tab, unless it is the last one in response to a keypress"
+      (lambda (keys) (command-apply editor-tab-close))
+      #t
+      'key-sequence))
+
+  (define editor-tab-next
+    (command-create
+      'editor-tab-next
+      "Switches to the next tab"
+      (lambda () (tab-next))))
+
+  (define editor-tab-next-keypress
+    (command-create
+      'editor-tab-next-keypress
+      "Switches to the next tab in response to a keypress"
+      (lambda (keys) (command-apply editor-tab-next))
+      #t
+      'key-sequence))
+
+  (define editor-tab-previous
+    (command-create
+      'editor-tab-previous
+      "Switches to the previous tab"
+      (lambda () (tab-previous))))
+
+  (define editor-tab-previous-keypress
+    (command-create
+      'editor-tab-previous-keypress
+      "Switches to the previous tab in response to a keypress"
+      (lambda (keys) (command-apply editor-tab-previous))
+      #t
+      'key-sequence))
+
+  (define editor-tab-move-left
+    (command-create
+      'editor-tab-move-left
+      "Moves the current tab one place to the left in the tab bar"
+      (lambda () (tab-move (max 0 (- (tab-current) 1))))))
+
+  (define editor-tab-move-left-keypress
+    (command-create
+      'editor-tab-move-left-keypress
+      "Moves the current tab one place to the left in the tab bar in response to a keypress"
+      (lambda (keys) (command-apply editor-tab-move-left))
+      #t
+      'key-sequence))
+
+  (define editor-tab-move-right
+    (command-create
+      'editor-tab-move-right
+      "Moves the current tab one place to the right in the tab bar"
+      (lambda () (tab-move (+ (tab-current) 1)))))
+
+  (define editor-tab-move-right-keypress
+    (command-create
+      'editor-tab-move-right-keypress
+      "Moves the current tab one place to the right in the tab bar in response to a keypress"
+      (lambda (keys) (command-apply editor-tab-move-right))
+      #t
+      'key-sequence))
+
+  (define editor-tab-switch
+    (command-create
+      'editor-tab-switch
+      "Switches to the tab at a position in the tab bar, counting from 1"
+      (lambda (position) (tab-switch (- position 1)))
+      'number))
+
+  (define editor-tab-move
+    (command-create
+      'editor-tab-move
+      "Moves the current tab to a position in the tab bar, counting from 1"
+      (lambda (position) (tab-move (max 0 (- position 1))))
+      'number))
+
+  (define editor-tab-rename
+    (command-create
+      'editor-tab-rename
+      "Renames the current tab"
+      (lambda (name) (tab-rename name))
+      'text))
+
+  (define editor-buffer-switch
+    (command-create
+      'editor-buffer-switch
+      "Shows an open buffer in the current pane"
+      (lambda (name) (buffer-change-focus name))
+      'buffer))
+
   (define editor-quit
     (command-create
       'editor-quit
@@ -8,8 +8,16 @@
     (koru-buffer)
     (koru-modal)
     (koru-task)
+    (koru-completion)
     (configs common)
-    (scheme text-edit-mode))
+    (scheme text-edit-mode)
+    (scheme syntax-mode)
+    (scheme lsp-mode)
+    (scheme diagnostics)
+    (scheme completion)
+    (scheme picker)
+    (scheme key-help)
+    (scheme status-line))
 
   (define (emacs-state emacs-mode)
     (modal-state (minor-mode-data emacs-mode)))
@@ -46,7 +54,8 @@
       "Removes the mark at the primary cursor and flushes the keybuffer. This also cancels any running tasks"
       (lambda () (begin
                        (flush-key-buffer)
-                       (command-bar-take)
+                       (completion-cancel)
+                       (command-bar-clear)
                        (command-bar-update)
                        (command-bar-hide)
                        (command-apply editor-remove-mark)
@@ -68,7 +77,7 @@
       (lambda (keys)
         (let ((emacs-mode (minor-mode-get 'emacs-mode)))
           (emacs-prefix-set! emacs-mode "Enter a command: ")
-          (command-bar-show)
+          (command-bar-show 'command)
           (command-bar-update "Enter a command: ")
           (emacs-callback-set!
             emacs-mode
@@ -150,6 +159,56 @@
       #t
       'key-sequence))
 
+  (define command-complete
+    (command-create
+      'command-complete
+      "Completes the command name or argument before the cursor in the command bar"
+      (lambda (keys)
+        (command-bar-complete)
+        (command-bar-update (emacs-prefix (minor-mode-get 'emacs-mode))))
+      #t
+      'key-sequence))
+
+  (define command-complete-previous
+    (command-create
+      'command-complete-previous
+      "Completes to the previous candidate in the command bar"
+      (lambda (keys)
+        (command-bar-complete-previous)
+        (command-bar-update (emacs-prefix (minor-mode-get 'emacs-mode))))
+      #t
+      'key-sequence))
+
+  (define command-history-previous
+    (command-create
+      'command-history-previous
+      "Shows the previous entry of the command bar's history that starts with what was typed"
+      (lambda (keys)
+        (command-bar-history-previous)
+        (command-bar-update (emacs-prefix (minor-mode-get 'emacs-mode))))
+      #t
+      'key-sequence))
+
+  (define command-history-next
+    (command-create
+      'command-history-next
+      "Shows the next entry of the command bar's history that starts with what was typed"
+      (lambda (keys)
+        (command-bar-history-next)
+        (command-bar-update (emacs-prefix (minor-mode-get 'emacs-mode))))
+      #t
+      'key-sequence))
+
+  (define command-history-search
+    (command-create
+      'command-history-search
+      "Searches backwards through the command bar's history for what was typed"
+      (lambda (keys)
+        (command-bar-history-search)
+        (command-bar-update (emacs-prefix (minor-mode-get 'emacs-mode))))
+      #t
+      'key-sequence))
+
   (define editor-save
     (command-create
       'editor-save
@@ -180,7 +239,7 @@
     (let ((emacs-mode (minor-mode-get 'emacs-mode)))
       (emacs-state-set! emacs-mode 'command)
       (emacs-prefix-set! emacs-mode "Enter a file path: ")
-      (command-bar-show)
+      (command-bar-show 'path)
       (command-bar-update "Enter a file path: ")
       (emacs-callback-set!
         emacs-mode
@@ -188,12 +247,12 @@
 
   (define (emacs-editor)
     (let ((emacs-editor-key-map (key-map-create editor-insert-text-keypress)))
-      (key-map-insert emacs-editor-key-map "UP" editor-cursor-up-keypress)
-      (key-map-insert emacs-editor-key-map "DOWN" editor-cursor-down-keypress)
+      (key-map-insert emacs-editor-key-map "UP" editor-cursor-visual-up-keypress)
+      (key-map-insert emacs-editor-key-map "DOWN" editor-cursor-visual-down-keypress)
       (key-map-insert emacs-editor-key-map "LEFT" editor-cursor-left-wrap-keypress)
       (key-map-insert emacs-editor-key-map "RIGHT" editor-cursor-right-wrap-keypress)
-      (key-map-insert emacs-editor-key-map "C-p" editor-cursor-up-keypress)
-      (key-map-insert emacs-editor-key-map "C-n" editor-cursor-down-keypress)
+      (key-map-insert emacs-editor-key-map "C-p" editor-cursor-visual-up-keypress)
+      (key-map-insert emacs-editor-key-map "C-n" editor-cursor-visual-down-keypress)
       (key-map-insert emacs-editor-key-map "C-b" editor-cursor-left-keypress)
       (key-map-insert emacs-editor-key-map "C-f" editor-cursor-right-keypress)
       (key-map-insert emacs-editor-key-map "BS" editor-delete-back-keypress)
@@ -202,12 +261,31 @@
       (key-map-insert emacs-editor-key-map "SPC" editor-insert-space-keypress)
       (key-map-insert emacs-editor-key-map "C-SPC" editor-place-point-mark-keypress)
       (key-map-insert emacs-editor-key-map "C-w" editor-delete-region-keypress)
+      (key-map-insert emacs-editor-key-map "A-w" editor-copy-region-keypress)
+      (key-map-insert emacs-editor-key-map "C-y" editor-paste-keypress)
       (key-map-insert emacs-editor-key-map "C-_" editor-undo-keypress)
       (key-map-insert emacs-editor-key-map "C-x u" editor-redo-keypress)
+      (key-map-insert emacs-editor-key-map "C-x 2" editor-pane-split-horizontal-keypress)
+      (key-map-insert emacs-editor-key-map "C-x 3" editor-pane-split-vertical-keypress)
+      (key-map-insert emacs-editor-key-map "C-x 0" editor-pane-close-keypress)
+      (key-map-insert emacs-editor-key-map "C-x o" editor-pane-focus-next-keypress)
+      (key-map-insert emacs-editor-key-map "C-x t 2" editor-tab-new-keypress)
+      (key-map-insert emacs-editor-key-map "C-x t 0" editor-tab-close-keypress)
+      (key-map-insert emacs-editor-key-map "C-x t o" editor-tab-next-keypress)
+      (key-map-insert emacs-editor-key-map "C-x t O" editor-tab-previous-keypress)
       (key-map-insert emacs-editor-key-map "A-x" emacs-enter-command)
+      (key-map-insert emacs-editor-key-map "A-/" completion-complete-keypress)
+      (key-map-insert emacs-editor-key-map "C-c >" editor-indent-keypress)
+      (key-map-insert emacs-editor-key-map "C-c <" editor-dedent-keypress)
       (key-map-insert emacs-editor-key-map "C-x C-s" editor-save)
       (key-map-insert emacs-editor-key-map "C-x C-w" editor-save-as)
       (key-map-insert emacs-editor-key-map "C-x C-c" editor-crash)
+      (key-map-insert emacs-editor-key-map "C-x C-f" picker-files-keypress)
+      (key-map-insert emacs-editor-key-map "C-x b" picker-buffers-keypress)
+      (key-map-insert emacs-editor-key-map "C-c p" picker-commands-keypress)
+      (key-map-insert emacs-editor-key-map "C-h k" describe-key-keypress)
+      (key-map-insert emacs-editor-key-map "C-h w" where-is-keypress)
+      (key-map-insert emacs-editor-key-map "C-h b" describe-bindings-keypress)
       emacs-editor-key-map))
 
   (define (emacs-command)
@@ -220,6 +298,11 @@
       (key-map-insert emacs-editor-key-map "DEL" command-delete-forward)
       (key-map-insert emacs-editor-key-map "ENTER" command-activate)
       (key-map-insert emacs-editor-key-map "SPC" command-insert-space)
+      (key-map-insert emacs-editor-key-map "TAB" command-complete)
+      (key-map-insert emacs-editor-key-map "S-TAB" command-complete-previous)
+      (key-map-insert emacs-editor-key-map "UP" command-history-previous)
+      (key-map-insert emacs-editor-key-map "DOWN" command-history-next)
+      (key-map-insert emacs-editor-key-map "C-r" command-history-search)
       emacs-editor-key-map))
 
   (define (emacs-editor-state-keymap)
@@ -256,5 +339,10 @@
 
   (define (init-emacs-config)
     (create-hook 'emacs-mode-change)
+    (status-line-setup 'emacs-mode 'emacs-mode-change)
+    (completion-setup)
+    (picker-setup)
     (add-hook 'buffer-open 'text-edit-mode text-edit-mode-file-open-hook)
+    (add-hook 'buffer-open 'syntax-mode syntax-mode-file-open-hook)
+    (add-hook 'buffer-open 'lsp-mode lsp-mode-file-open-hook)
     (add-hook 'buffer-open 'emacs-mode emacs-config-hook)))
\ No newline at end of file
@@ -7,9 +7,17 @@
     (minor-mode)
     (koru-buffer)
     (koru-modal)
+    (koru-completion)
     (major-mode)
     (configs common)
-    (scheme text-edit-mode))
+    (scheme text-edit-mode)
+    (scheme syntax-mode)
+    (scheme lsp-mode)
+    (scheme diagnostics)
+    (scheme completion)
+    (scheme picker)
+    (scheme key-help)
+    (scheme status-line))
 
   (define (kakoune-state kakoune-mode)
     (modal-state (minor-mode-data kakoune-mode)))
@@ -63,7 +71,8 @@
       "Clears the keybuffer and leaves the current mode if it isn't Normal mode"
       (lambda (keys)
         (flush-key-buffer)
-        (command-bar-take)
+        (completion-cancel)
+        (command-bar-clear)
         (command-bar-update)
         (command-bar-hide)
         (command-apply editor-remove-mark)
@@ -141,6 +150,56 @@
         #t
         'key-sequence)))
 
+  (define command-complete
+    (command-create
+      'command-complete
+      "Completes the command name or argument before the cursor in the command bar"
+      (lambda (keys)
+        (command-bar-complete)
+        (command-bar-update (kakoune-prefix (minor-mode-get 'kakoune-mode))))
+      #t
+      'key-sequence))
+
+  (define command-complete-previous
+    (command-create
+      'command-complete-previous
+      "Completes to the previous candidate in the command bar"
+      (lambda (keys)
+        (command-bar-complete-previous)
+        (command-bar-update (kakoune-prefix (minor-mode-get 'kakoune-mode))))
+      #t
+      'key-sequence))
+
+  (define command-history-previous
+    (command-create
+      'command-history-previous
+      "Shows the previous entry of the command bar's history that starts with what was typed"
+      (lambda (keys)
+        (command-bar-history-previous)
+        (command-bar-update (kakoune-prefix (minor-mode-get 'kakoune-mode))))
+      #t
+      'key-sequence))
+
+  (define command-history-next
+    (command-create
+      'command-history-next
+      "Shows the next entry of the command bar's history that starts with what was typed"
+      (lambda (keys)
+        (command-bar-history-next)
+        (command-bar-update (kakoune-prefix (minor-mode-get 'kakoune-mode))))
+      #t
+      'key-sequence))
+
+  (define command-history-search
+    (command-create
+      'command-history-search
+      "Searches backwards through the command bar's history for what was typed"
+      (lambda (keys)
+        (command-bar-history-search)
+        (command-bar-update (kakoune-prefix (minor-mode-get 'kakoune-mode))))
+      #t
+      'key-sequence))
+
   (define kakoune-enter-insert-keypress
     (command-create
       'kakoune-enter-insert
@@ -157,7 +216,7 @@
         (kakoune-callback-set! (minor-mode-get 'kakoune-mode)
           (lambda () (command-execute (command-bar-take))))
         (kakoune-prefix-set! (minor-mode-get 'kakoune-mode) ":")
-        (command-bar-show)
+        (command-bar-show 'command)
         (command-bar-update (kakoune-prefix (minor-mode-get 'kakoune-mode)))
         (kakoune-state-set! (minor-mode-get 'kakoune-mode) 'Command))
       'key-sequence))
@@ -343,14 +402,10 @@
 
   (define (kakoune-normal-mode-keymap)
     (let ((kakoune-key-map (key-map-create)))
-      (key-map-insert kakoune-key-map "UP" kak-move-cursor-up-keypress)
-      (key-map-insert kakoune-key-map "k"  kak-move-cursor-up-keypress)
-      (key-map-insert kakoune-key-map "DOWN"  kak-move-cursor-down-keypress)
-      (key-map-insert kakoune-key-map "j"  kak-move-cursor-down-keypress)
-      (key-map-insert kakoune-key-map "LEFT"  kak-move-cursor-left-keypress)
-      (key-map-insert kakoune-key-map "h"  kak-move-cursor-left-keypress)
-      (key-map-insert kakoune-key-map "RIGHT"  kak-move-cursor-right-keypress)
-      (key-map-insert kakoune-key-map "l"  kak-move-cursor-right-keypress)
+      (key-map-insert kakoune-key-map 'cursor-up kak-move-cursor-up-keypress)
+      (key-map-insert kakoune-key-map 'cursor-down kak-move-cursor-down-keypress)
+      (key-map-insert kakoune-key-map 'cursor-left kak-move-cursor-left-keypress)
+      (key-map-insert kakoune-key-map 'cursor-right kak-move-cursor-right-keypress)
       (key-map-insert kakoune-key-map "S-UP" kak-extend-cursor-up-keypress)
       (key-map-insert kakoune-key-map "K"  kak-extend-cursor-up-keypress)
       (key-map-insert kakoune-key-map "S-DOWN"  kak-extend-cursor-down-keypress)
@@ -367,6 +422,14 @@
       (key-map-insert kakoune-key-map "C-c" editor-cursor-add-below-keypress)
       (key-map-insert kakoune-key-map "x" kak-select-line-keypress)
       (key-map-insert kakoune-key-map "%" kak-select-buffer-keypress)
+      (key-map-insert kakoune-key-map ">" editor-indent-keypress)
+      (key-map-insert kakoune-key-map "<" editor-dedent-keypress)
+      (key-map-insert kakoune-key-map "SPC f" picker-files-keypress)
+      (key-map-insert kakoune-key-map "SPC b" picker-buffers-keypress)
+      (key-map-insert kakoune-key-map "SPC p" picker-commands-keypress)
+      (key-map-insert kakoune-key-map "SPC h k" describe-key-keypress)
+      (key-map-insert kakoune-key-map "SPC h w" where-is-keypress)
+      (key-map-insert kakoune-key-map "SPC h b" describe-bindings-keypress)
       kakoune-key-map))
 
 
@@ -380,6 +443,7 @@
       (key-map-insert kakoune-key-map "DEL" editor-delete-forward-keypress)
       (key-map-insert kakoune-key-map "ENTER" editor-insert-newline-keypress)
       (key-map-insert kakoune-key-map "SPC" editor-insert-space-keypress)
+      (key-map-insert kakoune-key-map "C-n" completion-complete-keypress)
       kakoune-key-map))
 
   (define (kakoune-command-mode-keymap)
@@ -390,6 +454,11 @@
       (key-map-insert kakoune-key-map "DEL" command-delete-forward)
       (key-map-insert kakoune-key-map "ENTER" command-activate)
       (key-map-insert kakoune-key-map "SPC" command-insert-space)
+      (key-map-insert kakoune-key-map "TAB" command-complete)
+      (key-map-insert kakoune-key-map "S-TAB" command-complete-previous)
+      (key-map-insert kakoune-key-map "UP" command-history-previous)
+      (key-map-insert kakoune-key-map "DOWN" command-history-next)
+      (key-map-insert kakoune-key-map "C-r" command-history-search)
       kakoune-key-map))
 
   (define (enter-normal-mode)
@@ -438,7 +507,16 @@
     (enter-normal-mode-first-time))
 
   (define (init-kakoune-config)
+    (key-group-set! 'cursor-up "UP" "k")
+    (key-group-set! 'cursor-down "DOWN" "j")
+    (key-group-set! 'cursor-left "LEFT" "h")
+    (key-group-set! 'cursor-right "RIGHT" "l")
     (add-special-key-binding "C-q" editor-crash)
     (create-hook 'kakoune-mode-change)
+    (status-line-setup 'kakoune-mode 'kakoune-mode-change)
+    (completion-setup)
+    (picker-setup)
     (add-hook 'buffer-open 'text-edit-mode text-edit-mode-file-open-hook)
+    (add-hook 'buffer-open 'syntax-mode syntax-mode-file-open-hook)
+    (add-hook 'buffer-open 'lsp-mode lsp-mode-file-open-hook)
     (add-hook 'buffer-open 'kakoune-mode kakoune-config-hook)))
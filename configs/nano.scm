@@ -8,7 +8,14 @@
     (koru-buffer)
     (koru-modal)
     (configs common)
-    (scheme text-edit-mode))
+    (scheme text-edit-mode)
+    (scheme syntax-mode)
+    (scheme lsp-mode)
+    (scheme diagnostics)
+    (scheme completion)
+    (scheme picker)
+    (scheme key-help)
+    (scheme status-line))
 
 
   (define (nano-state nano-mode)
@@ -45,7 +52,12 @@
 
   (define (init-nano-config)
     (create-hook 'nano-mode-change)
+    (status-line-setup 'nano-mode 'nano-mode-change)
+    (completion-setup)
+    (picker-setup)
     (add-hook 'buffer-open 'text-edit-mode text-edit-mode-file-open-hook)
+    (add-hook 'buffer-open 'syntax-mode syntax-mode-file-open-hook)
+    (add-hook 'buffer-open 'lsp-mode lsp-mode-file-open-hook)
     (add-hook 'buffer-open 'nano-mode nano-config-hook))
 
 
@@ -101,7 +113,7 @@
       (lambda (keys)
         (let ((nano-mode (minor-mode-get 'nano-mode)))
           (nano-prefix-set! nano-mode "")
-          (command-bar-take)
+          (command-bar-clear)
           (command-bar-update)
           (command-bar-hide)
           (nano-state-set! nano-mode 'edit)))
@@ -115,7 +127,7 @@
         (let ((nano-mode (minor-mode-get 'nano-mode)))
           (nano-prefix-set! nano-mode "File Name to write : ")
           (command-bar-insert (current-buffer-name))
-          (command-bar-show)
+          (command-bar-show 'path)
           (command-bar-update (nano-prefix nano-mode))
           (nano-state-set! nano-mode 'write)))
       'key-sequence))
@@ -127,7 +139,7 @@
       (lambda (keys)
         (let ((nano-mode (minor-mode-get 'nano-mode)))
           (nano-prefix-set! nano-mode "File to insert from home directory: ")
-          (command-bar-show)
+          (command-bar-show 'path)
           (command-bar-update (nano-prefix nano-mode))
           (nano-state-set! nano-mode 'read)))
       'key-sequence))
@@ -155,7 +167,7 @@
         (let ((nano-mode (minor-mode-get 'nano-mode)))
           (nano-prefix-set! nano-mode "File Name to write : ")
           (command-bar-insert (current-buffer-name))
-          (command-bar-show)
+          (command-bar-show 'path)
           (command-bar-update (nano-prefix nano-mode))
           (nano-state-set! nano-mode 'exit-write)))
       'key-sequence))
@@ -229,6 +241,36 @@
       #t
       'key-sequence))
 
+  (define command-history-previous
+    (command-create
+      'command-history-previous
+      "Shows the previous entry of the command bar's history that starts with what was typed"
+      (lambda (keys)
+        (command-bar-history-previous)
+        (command-bar-update (nano-prefix (minor-mode-get 'nano-mode))))
+      #t
+      'key-sequence))
+
+  (define command-history-next
+    (command-create
+      'command-history-next
+      "Shows the next entry of the command bar's history that starts with what was typed"
+      (lambda (keys)
+        (command-bar-history-next)
+        (command-bar-update (nano-prefix (minor-mode-get 'nano-mode))))
+      #t
+      'key-sequence))
+
+  (define command-history-search
+    (command-create
+      'command-history-search
+      "Searches backwards through the command bar's history for what was typed"
+      (lambda (keys)
+        (command-bar-history-search)
+        (command-bar-update (nano-prefix (minor-mode-get 'nano-mode))))
+      #t
+      'key-sequence))
+
   (define nano-crash
     (command-create
       'command-crash
@@ -240,8 +282,8 @@
 
   (define (nano-edit-key-map)
     (let ((nano-key-map (key-map-create editor-insert-text-keypress)))
-      (key-map-insert nano-key-map "UP" editor-cursor-up-keypress)
-      (key-map-insert nano-key-map "DOWN" editor-cursor-down-keypress)
+      (key-map-insert nano-key-map "UP" editor-cursor-visual-up-keypress)
+      (key-map-insert nano-key-map "DOWN" editor-cursor-visual-down-keypress)
       (key-map-insert nano-key-map "LEFT" editor-cursor-left-keypress)
       (key-map-insert nano-key-map "RIGHT" editor-cursor-right-keypress)
       (key-map-insert nano-key-map "BS" editor-delete-back-keypress)
@@ -252,6 +294,15 @@
       (key-map-insert nano-key-map "C-o" nano-write-mode-keypress)
       (key-map-insert nano-key-map "C-r" nano-read-mode-keypress)
       (key-map-insert nano-key-map "C-c" nano-crash)
+      (key-map-insert nano-key-map "C-]" completion-complete-keypress)
+      (key-map-insert nano-key-map "A-}" editor-indent-keypress)
+      (key-map-insert nano-key-map "A-{" editor-dedent-keypress)
+      (key-map-insert nano-key-map "A-f" picker-files-keypress)
+      (key-map-insert nano-key-map "A-b" picker-buffers-keypress)
+      (key-map-insert nano-key-map "A-p" picker-commands-keypress)
+      (key-map-insert nano-key-map "A-h k" describe-key-keypress)
+      (key-map-insert nano-key-map "A-h w" where-is-keypress)
+      (key-map-insert nano-key-map "A-h b" describe-bindings-keypress)
       nano-key-map))
 
   (define (nano-write-key-map)
@@ -262,6 +313,9 @@
       (key-map-insert nano-key-map "DEL" command-delete-forward)
       (key-map-insert nano-key-map "ENTER" command-submit-return)
       (key-map-insert nano-key-map "SPC" command-insert-space)
+      (key-map-insert nano-key-map "UP" command-history-previous)
+      (key-map-insert nano-key-map "DOWN" command-history-next)
+      (key-map-insert nano-key-map "C-r" command-history-search)
       (key-map-insert nano-key-map "C-c" nano-edit-mode-keypress)
       nano-key-map))
 
@@ -280,6 +334,9 @@
       (key-map-insert nano-key-map "DEL" command-delete-forward)
       (key-map-insert nano-key-map "ENTER" command-submit-return)
       (key-map-insert nano-key-map "SPC" command-insert-space)
+      (key-map-insert nano-key-map "UP" command-history-previous)
+      (key-map-insert nano-key-map "DOWN" command-history-next)
+      (key-map-insert nano-key-map "C-r" command-history-search)
       (key-map-insert nano-key-map "C-c" nano-edit-mode-keypress)
       nano-key-map))
 
@@ -7,9 +7,17 @@
     (minor-mode)
     (koru-buffer)
     (koru-modal)
+    (koru-completion)
     (major-mode)
     (configs common)
-    (scheme text-edit-mode))
+    (scheme text-edit-mode)
+    (scheme syntax-mode)
+    (scheme lsp-mode)
+    (scheme diagnostics)
+    (scheme completion)
+    (scheme picker)
+    (scheme key-help)
+    (scheme status-line))
 
   (define (vi-state vi-mode)
     (modal-state (minor-mode-data vi-mode)))
@@ -61,7 +69,8 @@
       "Clears the keybuffer and leaves the current mode if it isn't Normal mode"
       (lambda (keys)
         (flush-key-buffer)
-        (command-bar-take)
+        (completion-cancel)
+        (command-bar-clear)
         (command-bar-update)
         (command-bar-hide)
         (command-apply editor-remove-mark)
@@ -139,6 +148,56 @@
         #t
       'key-sequence)))
 
+  (define command-complete
+    (command-create
+      'command-complete
+      "Completes the command name or argument before the cursor in the command bar"
+      (lambda (keys)
+        (command-bar-complete)
+        (command-bar-update (vi-prefix (minor-mode-get 'vi-mode))))
+      #t
+      'key-sequence))
+
+  (define command-complete-previous
+    (command-create
+      'command-complete-previous
+      "Completes to the previous candidate in the command bar"
+      (lambda (keys)
+        (command-bar-complete-previous)
+        (command-bar-update (vi-prefix (minor-mode-get 'vi-mode))))
+      #t
+      'key-sequence))
+
+  (define command-history-previous
+    (command-create
+      'command-history-previous
+      "Shows the previous entry of the command bar's history that starts with what was typed"
+      (lambda (keys)
+        (command-bar-history-previous)
+        (command-bar-update (vi-prefix (minor-mode-get 'vi-mode))))
+      #t
+      'key-sequence))
+
+  (define command-history-next
+    (command-create
+      'command-history-next
+      "Shows the next entry of the command bar's history that starts with what was typed"
+      (lambda (keys)
+        (command-bar-history-next)
+        (command-bar-update (vi-prefix (minor-mode-get 'vi-mode))))
+      #t
+      'key-sequence))
+
+  (define command-history-search
+    (command-create
+      'command-history-search
+      "Searches backwards through the command bar's history for what was typed"
+      (lambda (keys)
+        (command-bar-history-search)
+        (command-bar-update (vi-prefix (minor-mode-get 'vi-mode))))
+      #t
+      'key-sequence))
+
   (define vi-enter-insert-keypress
     (command-create
       'vi-enter-insert
@@ -182,7 +241,40 @@
       'vi-visual-visual-delete-keypress
       "Deletes the highlighted region and enters into Normal mode."
       (lambda (keys)
-        (command-apply text-edit-mode-delete-cursor-region 0)
+        (command-apply editor-delete-region)
+        (command-apply editor-remove-mark)
+        (vi-state-set! (minor-mode-get 'vi-mode) 'Normal))
+      #t
+      'key-sequence))
+
+  (define vi-visual-visual-yank-keypress
+    (command-create
+      'vi-visual-visual-yank-keypress
+      "Copies the highlighted region and enters into Normal mode."
+      (lambda (keys)
+        (command-apply editor-copy-region)
+        (command-apply editor-remove-mark)
+        (vi-state-set! (minor-mode-get 'vi-mode) 'Normal))
+      #t
+      'key-sequence))
+
+  (define vi-visual-indent-keypress
+    (command-create
+      'vi-visual-indent-keypress
+      "Indents the highlighted lines and enters into Normal mode."
+      (lambda (keys)
+        (command-apply editor-indent)
+        (command-apply editor-remove-mark)
+        (vi-state-set! (minor-mode-get 'vi-mode) 'Normal))
+      #t
+      'key-sequence))
+
+  (define vi-visual-dedent-keypress
+    (command-create
+      'vi-visual-dedent-keypress
+      "Dedents the highlighted lines and enters into Normal mode."
+      (lambda (keys)
+        (command-apply editor-dedent)
         (command-apply editor-remove-mark)
         (vi-state-set! (minor-mode-get 'vi-mode) 'Normal))
       #t
@@ -196,7 +288,7 @@
         (vi-callback-set! (minor-mode-get 'vi-mode)
           (lambda () (command-execute (command-bar-take))))
         (vi-prefix-set! (minor-mode-get 'vi-mode) ":")
-        (command-bar-show)
+        (command-bar-show 'command)
         (command-bar-update (vi-prefix (minor-mode-get 'vi-mode)))
         (vi-state-set! (minor-mode-get 'vi-mode) 'Command))
       #t
@@ -204,14 +296,11 @@
 
   (define (vi-normal-mode-keymap)
     (let ((vi-key-map (key-map-create)))
-      (key-map-insert vi-key-map "UP" editor-cursor-up-keypress)
-      (key-map-insert vi-key-map "k" editor-cursor-up-keypress)
-      (key-map-insert vi-key-map "DOWN" editor-cursor-down-keypress)
-      (key-map-insert vi-key-map "j" editor-cursor-down-keypress)
-      (key-map-insert vi-key-map "LEFT" editor-cursor-left-keypress)
-      (key-map-insert vi-key-map "h" editor-cursor-left-keypress)
-      (key-map-insert vi-key-map "RIGHT" editor-cursor-right-keypress)
-      (key-map-insert vi-key-map "l" editor-cursor-right-keypress)
+      (key-map-timeout-set! vi-key-map 1000)
+      (key-map-insert vi-key-map 'cursor-up editor-cursor-up-keypress)
+      (key-map-insert vi-key-map 'cursor-down editor-cursor-down-keypress)
+      (key-map-insert vi-key-map 'cursor-left editor-cursor-left-keypress)
+      (key-map-insert vi-key-map 'cursor-right editor-cursor-right-keypress)
       (key-map-insert vi-key-map "i" vi-enter-insert-keypress)
       (key-map-insert vi-key-map "v" vi-enter-visual-keypress)
       (key-map-insert vi-key-map "V" vi-enter-visual-line-keypress)
@@ -221,28 +310,51 @@
       (key-map-insert vi-key-map "C-r" editor-redo-keypress)
       (key-map-insert vi-key-map "C-c" editor-cursor-add-below-keypress)
       (key-map-insert vi-key-map "x" editor-delete-forward-keypress)
+      (key-map-insert vi-key-map "p" editor-paste-keypress)
       (key-map-insert vi-key-map "$" editor-cursor-line-end-keypress)
       (key-map-insert vi-key-map "^" editor-cursor-line-start-keypress)
       (key-map-insert vi-key-map "g g" editor-cursor-buffer-start-keypress)
+      (key-map-insert vi-key-map "g k" editor-cursor-visual-up-keypress)
+      (key-map-insert vi-key-map "g j" editor-cursor-visual-down-keypress)
       (key-map-insert vi-key-map "G" editor-cursor-buffer-end-keypress)
+      (key-map-insert vi-key-map "> >" editor-indent-keypress)
+      (key-map-insert vi-key-map "< <" editor-dedent-keypress)
+      (key-map-insert vi-key-map "C-w s" editor-pane-split-horizontal-keypress)
+      (key-map-insert vi-key-map "C-w v" editor-pane-split-vertical-keypress)
+      (key-map-insert vi-key-map "C-w q" editor-pane-close-keypress)
+      (key-map-insert vi-key-map "C-w h" editor-pane-focus-left-keypress)
+      (key-map-insert vi-key-map "C-w l" editor-pane-focus-right-keypress)
+      (key-map-insert vi-key-map "C-w k" editor-pane-focus-up-keypress)
+      (key-map-insert vi-key-map "C-w j" editor-pane-focus-down-keypress)
+      (key-map-insert vi-key-map "C-w w" editor-pane-focus-next-keypress)
+      (key-map-insert vi-key-map "C-w >" editor-pane-grow-keypress)
+      (key-map-insert vi-key-map "C-w <" editor-pane-shrink-keypress)
+      (key-map-insert vi-key-map "g t" editor-tab-next-keypress)
+      (key-map-insert vi-key-map "g T" editor-tab-previous-keypress)
+      (key-map-insert vi-key-map "SPC f" picker-files-keypress)
+      (key-map-insert vi-key-map "SPC b" picker-buffers-keypress)
+      (key-map-insert vi-key-map "SPC p" picker-commands-keypress)
+      (key-map-insert vi-key-map "SPC h k" describe-key-keypress)
+      (key-map-insert vi-key-map "SPC h w" where-is-keypress)
+      (key-map-insert vi-key-map "SPC h b" describe-bindings-keypress)
       vi-key-map))
 
   (define (vi-visual-mode-keymap)
     (let ((vi-key-map (key-map-create)))
-      (key-map-insert vi-key-map "UP" editor-cursor-up-keypress)
-      (key-map-insert vi-key-map "k" editor-cursor-up-keypress)
-      (key-map-insert vi-key-map "DOWN" editor-cursor-down-keypress)
-      (key-map-insert vi-key-map "j" editor-cursor-down-keypress)
-      (key-map-insert vi-key-map "LEFT" editor-cursor-left-keypress)
-      (key-map-insert vi-key-map "h" editor-cursor-left-keypress)
-      (key-map-insert vi-key-map "RIGHT" editor-cursor-right-keypress)
-      (key-map-insert vi-key-map "l" editor-cursor-right-keypress)
+      (key-map-insert vi-key-map 'cursor-up editor-cursor-up-keypress)
+      (key-map-insert vi-key-map 'cursor-down editor-cursor-down-keypress)
+      (key-map-insert vi-key-map 'cursor-left editor-cursor-left-keypress)
+      (key-map-insert vi-key-map 'cursor-right editor-cursor-right-keypress)
       (key-map-insert vi-key-map "x" vi-visual-visual-delete-keypress)
       (key-map-insert vi-key-map "d" vi-visual-visual-delete-keypress)
+      (key-map-insert vi-key-map "y" vi-visual-visual-yank-keypress)
+      (key-map-insert vi-key-map ">" vi-visual-indent-keypress)
+      (key-map-insert vi-key-map "<" vi-visual-dedent-keypress)
       vi-key-map))
 
   (define (vi-insert-mode-keymap)
     (let ((vi-key-map (key-map-create editor-insert-text-keypress)))
+      (key-map-timeout-set! vi-key-map 1000)
       (key-map-insert vi-key-map "UP" editor-cursor-up-keypress)
       (key-map-insert vi-key-map "DOWN" editor-cursor-down-keypress)
       (key-map-insert vi-key-map "LEFT" editor-cursor-left-keypress)
@@ -251,6 +363,7 @@
       (key-map-insert vi-key-map "DEL" editor-delete-forward-keypress)
       (key-map-insert vi-key-map "ENTER" editor-insert-newline-keypress)
       (key-map-insert vi-key-map "SPC" editor-insert-space-keypress)
+      (key-map-insert vi-key-map "C-n" completion-complete-keypress)
       vi-key-map))
 
   (define (vi-command-mode-keymap)
@@ -261,6 +374,11 @@
       (key-map-insert vi-key-map "DEL" command-delete-forward)
       (key-map-insert vi-key-map "ENTER" command-activate)
       (key-map-insert vi-key-map "SPC" command-insert-space)
+      (key-map-insert vi-key-map "TAB" command-complete)
+      (key-map-insert vi-key-map "S-TAB" command-complete-previous)
+      (key-map-insert vi-key-map "UP" command-history-previous)
+      (key-map-insert vi-key-map "DOWN" command-history-next)
+      (key-map-insert vi-key-map "C-r" command-history-search)
       vi-key-map))
 
   (define (enter-normal-mode)
@@ -313,7 +431,16 @@
     (enter-normal-mode-first-time))
 
   (define (init-vi-config)
+    (key-group-set! 'cursor-up "UP" "k")
+    (key-group-set! 'cursor-down "DOWN" "j")
+    (key-group-set! 'cursor-left "LEFT" "h")
+    (key-group-set! 'cursor-right "RIGHT" "l")
     (add-special-key-binding "C-q" editor-crash)
     (create-hook 'vi-mode-change)
+    (status-line-setup 'vi-mode 'vi-mode-change)
+    (completion-setup)
+    (picker-setup)
     (add-hook 'buffer-open 'text-edit-mode text-edit-mode-file-open-hook)
+    (add-hook 'buffer-open 'syntax-mode syntax-mode-file-open-hook)
+    (add-hook 'buffer-open 'lsp-mode lsp-mode-file-open-hook)
     (add-hook 'buffer-open 'vi-mode vi-config-hook)))
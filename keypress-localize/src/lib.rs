@@ -1,30 +1,248 @@
-mod english_us;
-mod english_uk;
+use std::collections::HashMap;
+use std::fmt;
+
+/// The layouts that come with the editor, by name.
+const BUILTIN_LAYOUTS: &[(&str, &str)] = &[
+    ("english-us", include_str!("../layouts/english-us.layout")),
+    ("english-uk", include_str!("../layouts/english-uk.layout")),
+    ("dvorak", include_str!("../layouts/dvorak.layout")),
+    ("colemak", include_str!("../layouts/colemak.layout")),
+    ("german-qwertz", include_str!("../layouts/german-qwertz.layout")),
+    ("french-azerty", include_str!("../layouts/french-azerty.layout")),
+];
+
+/// The layout used until another one is picked.
+pub const DEFAULT_LAYOUT: &str = "english-us";
+
+/// A keyboard layout, which tells what a key turns into when it is pressed with Shift or AltGr,
+/// and what the dead keys of the layout turn the next key into.
+///
+/// Layouts are written as text files like this:
+///
+/// ```text
+/// // Lines starting with // are comments
+/// name german-qwertz
+///
+/// [shift]
+/// 7 /
+///
+/// [altgr]
+/// q @
+///
+/// [shift-altgr]
+/// , ×
+///
+/// [dead]
+/// ^ e ê
+/// ```
+///
+/// Every line of a section is a key followed by what it turns into.
+/// Lines of the `dead` section are a dead key, the key pressed after it, and what the two make together.
+#[derive(Debug, Clone, Default)]
+pub struct Layout {
+    name: String,
+    shift: HashMap<String, String>,
+    altgr: HashMap<String, String>,
+    shift_altgr: HashMap<String, String>,
+    dead: HashMap<String, HashMap<String, String>>,
+}
+
+/// An error in the text of a layout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
 
 #[derive(Clone, Copy)]
-pub enum KeyboardRegion {
-    EnglishUS,
-    EnglishUK,
+enum Section {
+    Shift,
+    AltGr,
+    ShiftAltGr,
+    Dead,
+}
+
+impl Layout {
+    /// Creates a layout where Shift only uppercases letters.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Reads a layout from its text, naming it `default_name` unless the text has a `name` line.
+    pub fn parse(default_name: &str, text: &str) -> Result<Self, ParseError> {
+        let mut layout = Layout::new(default_name);
+        let mut section = None;
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let error = |message: &str| ParseError { line: line_number, message: message.to_string() };
+            let tokens = line.split_whitespace().collect::<Vec<_>>();
+            let Some(first) = tokens.first() else {
+                continue;
+            };
+            if first.starts_with("//") {
+                continue;
+            }
+            if first.starts_with('[') && first.ends_with(']') && tokens.len() == 1 {
+                section = Some(match *first {
+                    "[shift]" => Section::Shift,
+                    "[altgr]" => Section::AltGr,
+                    "[shift-altgr]" => Section::ShiftAltGr,
+                    "[dead]" => Section::Dead,
+                    _ => return Err(error(&format!("unknown section {first}"))),
+                });
+                continue;
+            }
+            match (section, tokens.as_slice()) {
+                (None, ["name", name]) => layout.name = name.to_string(),
+                (None, _) => return Err(error("expected a name or a section")),
+                (Some(Section::Dead), [dead, key, composed]) => layout.set_dead_key(dead, key, composed),
+                (Some(Section::Dead), _) => return Err(error("expected a dead key, a key and what they make")),
+                (Some(section), [key, value]) => match section {
+                    Section::Shift => layout.set_shift(key, value),
+                    Section::AltGr => layout.set_altgr(key, value, false),
+                    Section::ShiftAltGr => layout.set_altgr(key, value, true),
+                    Section::Dead => unreachable!("dead keys are matched above"),
+                },
+                (Some(_), _) => return Err(error("expected a key and what it turns into")),
+            }
+        }
+        Ok(layout)
+    }
+
+    /// Gets one of the layouts that come with the editor.
+    pub fn builtin(name: &str) -> Option<Self> {
+        let (name, text) = BUILTIN_LAYOUTS.iter().find(|(builtin, _)| *builtin == name)?;
+        Some(Self::parse(name, text).expect("builtin layouts should parse"))
+    }
+
+    /// Lists the names of the layouts that come with the editor.
+    pub fn builtin_names() -> impl Iterator<Item = &'static str> {
+        BUILTIN_LAYOUTS.iter().map(|(name, _)| *name)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
+
+    pub fn set_shift(&mut self, key: &str, shifted: &str) {
+        self.shift.insert(key.to_string(), shifted.to_string());
+    }
+
+    pub fn set_altgr(&mut self, key: &str, value: &str, shift: bool) {
+        let layer = if shift { &mut self.shift_altgr } else { &mut self.altgr };
+        layer.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn set_dead_key(&mut self, dead: &str, key: &str, composed: &str) {
+        self.dead.entry(dead.to_string())
+            .or_default()
+            .insert(key.to_string(), composed.to_string());
+    }
+
+    /// Turns a key into what it would be if the Shift key was pressed with it.
+    ///
+    /// Keys in the layout's shift table are turned into what the table says.
+    /// Otherwise the key is uppercased, as long as it stays a single character.
+    /// If neither changes the key, then it is returned as it is.
+    pub fn shift(&self, key: &str) -> String {
+        if let Some(shifted) = self.shift.get(key) {
+            return shifted.clone();
+        }
+        let upper = key.to_uppercase();
+        if upper.chars().count() == 1 {
+            upper
+        } else {
+            key.to_string()
+        }
+    }
+
+    /// Gets what a key turns into when pressed with AltGr, if the layout has anything for it.
+    pub fn altgr(&self, key: &str, shift: bool) -> Option<&str> {
+        let layer = if shift { &self.shift_altgr } else { &self.altgr };
+        layer.get(key).map(String::as_str)
+    }
+
+    pub fn is_dead_key(&self, key: &str) -> bool {
+        self.dead.contains_key(key)
+    }
+
+    /// Gets what a dead key and the key pressed after it make together.
+    pub fn compose(&self, dead: &str, key: &str) -> Option<&str> {
+        self.dead.get(dead)?.get(key).map(String::as_str)
+    }
 }
 
 
-/// This function takes a keyboard region and performs a translation on the key as if the shift key was pressed with that code.
-/// 
-/// This function first tries to uppercase the string. 
-/// If this works, then we return the new string.
-/// If it doesn't do anything, we pass it through localization functions to see if it is a symbol to translate.
-pub fn canonicalize_keypress(
-    language: KeyboardRegion,
-    keypress: &str,
-) -> String {
-    let char = keypress.to_uppercase();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(text: &str) -> ParseError {
+        Layout::parse("test", text).expect_err("layout should not parse")
+    }
+
+    #[test]
+    fn builtin_layouts_parse() {
+        for (name, text) in BUILTIN_LAYOUTS {
+            let layout = Layout::parse(name, text).unwrap_or_else(|error| panic!("{name}: {error}"));
+            assert_eq!(layout.name(), *name);
+        }
+        assert!(Layout::builtin("no-such-layout").is_none());
+    }
+
+    #[test]
+    fn layouts_read_every_section() {
+        let layout = Layout::parse("default", "// A comment\nname custom\n\n[shift]\n7 /\n[altgr]\nq @\n[shift-altgr]\n, ×\n[dead]\n^ e ê\n").unwrap();
+        assert_eq!(layout.name(), "custom");
+        assert_eq!(layout.shift("7"), "/");
+        assert_eq!(layout.shift("a"), "A");
+        assert_eq!(layout.altgr("q", false), Some("@"));
+        assert_eq!(layout.altgr(",", true), Some("×"));
+        assert!(layout.is_dead_key("^"));
+        assert_eq!(layout.compose("^", "e"), Some("ê"));
+    }
+
+    #[test]
+    fn unknown_sections_are_errors() {
+        assert_eq!(parse_error("name x\n[meta]\n"), ParseError { line: 2, message: String::from("unknown section [meta]") });
+    }
+
+    #[test]
+    fn lines_before_a_section_must_be_a_name() {
+        assert_eq!(parse_error("7 /\n").line, 1);
+        assert_eq!(parse_error("name two words\n").message, "expected a name or a section");
+    }
 
-    if char != keypress {
-        return char;
+    #[test]
+    fn dead_keys_need_three_tokens() {
+        assert_eq!(parse_error("[dead]\n^ e\n"), ParseError {
+            line: 2,
+            message: String::from("expected a dead key, a key and what they make"),
+        });
     }
 
-    match language {
-        KeyboardRegion::EnglishUS => english_us::localize_english(keypress),
-        KeyboardRegion::EnglishUK => english_uk::localize_english(keypress),
+    #[test]
+    fn keys_need_two_tokens() {
+        for section in ["[shift]", "[altgr]", "[shift-altgr]"] {
+            assert_eq!(parse_error(&format!("{section}\n\n1 ! extra\n")), ParseError {
+                line: 3,
+                message: String::from("expected a key and what it turns into"),
+            });
+        }
     }
-}
\ No newline at end of file
+}
@@ -6,6 +6,15 @@ mod session;
 pub mod client;
 pub mod broker;
 pub(crate) mod buffer;
+pub(crate) mod lsp;
+pub(crate) mod completion;
+pub(crate) mod mouse;
+pub(crate) mod picker;
+pub(crate) mod key_hints;
+pub(crate) mod bindings;
+pub mod layout;
+pub(crate) mod tabs;
+pub(crate) mod status_line;
 pub mod scheme_api;
 
 use std::error::Error;
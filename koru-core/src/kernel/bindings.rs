@@ -0,0 +1,266 @@
+//! Tells what keys do, for finding out why a key does what it does.
+
+use std::sync::LazyLock;
+use log::error;
+use scheme_rs::exceptions::Exception;
+use scheme_rs::gc::Gc;
+use scheme_rs::lists::{self, List};
+use scheme_rs::proc::Procedure;
+use scheme_rs::records::Record;
+use scheme_rs::registry::bridge;
+use scheme_rs::symbols::Symbol;
+use scheme_rs::value::{UnpackedValue, Value};
+use tokio::sync::Mutex;
+use crate::kernel::input::KeyPress;
+use crate::kernel::scheme_api::command::Command;
+use crate::kernel::scheme_api::session::SessionState;
+use crate::keymap::{KeyMap, Resolution};
+
+/// Where a key map that keys are looked up in comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyMapSource {
+    /// The special key map, which is given every key on its own before the other key maps.
+    Special,
+    /// The key map that `add-key-binding` adds to.
+    Main,
+    /// A key map added with `add-key-map`.
+    Named(Symbol),
+}
+
+impl KeyMapSource {
+    pub fn symbol(&self) -> Symbol {
+        match self {
+            KeyMapSource::Special => Symbol::intern("special"),
+            KeyMapSource::Main => Symbol::intern("main"),
+            KeyMapSource::Named(name) => *name,
+        }
+    }
+}
+
+/// Keys that are being read for a callback instead of being run.
+struct Reading {
+    keys: Vec<KeyPress>,
+    callback: Procedure,
+}
+
+static READING: LazyLock<Mutex<Option<Reading>>> = LazyLock::new(|| {
+    Mutex::new(None)
+});
+
+/// How a binding fares against the key maps that come before it.
+enum Standing {
+    Active,
+    /// The binding runs, and keeps a binding in a later key map from running.
+    Conflict(usize),
+    /// The binding never runs since a binding or default in an earlier key map runs first.
+    Shadowed(usize),
+}
+
+/// A binding or a default command of one of the active key maps.
+struct Binding {
+    map: usize,
+    /// None for the key map's default command.
+    keys: Option<Vec<KeyPress>>,
+    command: Gc<Command>,
+    standing: Standing,
+}
+
+/// Looks the keys up in every key map, in the order that keys are looked up in.
+///
+/// The special key map is only ever given the newest key.
+fn lookup(keys: &[KeyPress], maps: &[(KeyMapSource, KeyMap)]) -> Vec<Resolution> {
+    maps.iter()
+        .map(|(source, map)| match (source, keys.last()) {
+            (KeyMapSource::Special, Some(key)) => map.resolve(std::slice::from_ref(key)),
+            (KeyMapSource::Special, None) => Resolution::Unbound,
+            (_, _) => map.resolve(keys),
+        })
+        .collect()
+}
+
+/// Checks if the keys would run a command, or if they can't run one no matter what comes after them.
+fn finished(resolutions: &[Resolution]) -> bool {
+    let runs = resolutions.iter()
+        .any(|resolution| matches!(resolution, Resolution::Bound(_) | Resolution::Default(_)));
+    runs || !resolutions.iter().any(|resolution| matches!(resolution, Resolution::Prefix(_)))
+}
+
+/// Gives a key to the callback of `key-sequence-read` instead of running it.
+///
+/// Returns false if no keys are being read.
+pub async fn read_key(keypress: &KeyPress) -> bool {
+    let reading = {
+        let mut guard = READING.lock().await;
+        let Some(reading) = guard.as_mut() else {
+            return false;
+        };
+        reading.keys.push(keypress.clone());
+        let maps = SessionState::active_key_maps().await;
+        if !finished(&lookup(&reading.keys, &maps)) {
+            return true;
+        }
+        guard.take()
+    };
+    if let Some(reading) = reading {
+        let keys = reading.keys.into_iter()
+            .map(|press| Value::from(Record::from_rust_type(press)))
+            .collect::<Vec<_>>();
+        if let Err(err) = reading.callback.call(&[lists::slice_to_list(&keys)]).await {
+            error!("{}", err);
+        }
+    }
+    true
+}
+
+/// Finds what keeps a binding in the key map at the index from running, if anything does.
+///
+/// A special key anywhere in the keys runs as soon as it is pressed.
+/// Otherwise the keys are given to the earlier key maps one more key at a time,
+/// and the first of them to run a command stops the keys from reaching this key map.
+/// Returns the index of the key map, along with the keys it has a binding for if it isn't its default.
+fn shadowing(map: usize, keys: &[KeyPress], maps: &[(KeyMapSource, KeyMap)]) -> Option<(usize, Option<Vec<KeyPress>>)> {
+    if map == 0 {
+        return None;
+    }
+    let special = &maps[0].1;
+    for key in keys {
+        if let Resolution::Bound(_) | Resolution::Default(_) = special.resolve(std::slice::from_ref(key)) {
+            return Some((0, Some(vec![key.clone()])));
+        }
+    }
+    for length in 1..=keys.len() {
+        for (index, (_, earlier)) in maps.iter().enumerate().take(map).skip(1) {
+            match earlier.resolve(&keys[..length]) {
+                Resolution::Bound(_) => return Some((index, Some(keys[..length].to_vec()))),
+                Resolution::Default(_) => return Some((index, None)),
+                Resolution::Prefix(_) | Resolution::Unbound => {}
+            }
+        }
+    }
+    None
+}
+
+/// Lists every binding and default command of the key maps, flagging the ones that never run and the ones that keep others from running.
+fn bindings(maps: &[(KeyMapSource, KeyMap)]) -> Vec<Binding> {
+    let mut bindings = Vec::new();
+    for (index, (_, map)) in maps.iter().enumerate() {
+        if let Some(default) = map.get_default() {
+            // A default catches every key that gets to its key map, so only an earlier default stops it
+            let earlier = maps.iter().take(index).position(|(_, map)| map.get_default().is_some());
+            let standing = earlier.map_or(Standing::Active, Standing::Shadowed);
+            bindings.push(Binding { map: index, keys: None, command: default, standing });
+        }
+        let mut map_bindings = map.bindings();
+        map_bindings.sort_by_cached_key(|(keys, _)| keys_to_string(keys));
+        for (keys, command) in map_bindings {
+            let standing = match shadowing(index, &keys, maps) {
+                Some((earlier, _)) => Standing::Shadowed(earlier),
+                None => Standing::Active,
+            };
+            bindings.push(Binding { map: index, keys: Some(keys), command, standing });
+        }
+    }
+
+    // A binding that shadows another one is in conflict with it
+    let mut conflicts = Vec::new();
+    for binding in &bindings {
+        let (Standing::Shadowed(_), Some(keys)) = (&binding.standing, &binding.keys) else {
+            continue;
+        };
+        if let Some((earlier, Some(prefix))) = shadowing(binding.map, keys, maps) {
+            conflicts.push((earlier, prefix, binding.map));
+        }
+    }
+    for (earlier, prefix, later) in conflicts {
+        let shadower = bindings.iter_mut().find(|binding| {
+            binding.map == earlier && binding.keys.as_deref() == Some(prefix.as_slice())
+        });
+        if let Some(shadower) = shadower
+            && matches!(shadower.standing, Standing::Active) {
+            shadower.standing = Standing::Conflict(later);
+        }
+    }
+    bindings
+}
+
+fn keys_to_string(keys: &[KeyPress]) -> String {
+    keys.iter().map(ToString::to_string).collect::<Vec<_>>().join(" ")
+}
+
+/// Reads keys from either a string like `"C-x C-s"` or a list of keypresses.
+pub(crate) fn keys_from(value: &Value) -> Result<Vec<KeyPress>, Exception> {
+    if let UnpackedValue::String(keys) = value.clone().unpack() {
+        return keys.to_string()
+            .split_whitespace()
+            .map(|key| KeyPress::from_string(key).ok_or_else(|| Exception::error(format!("Invalid key: {key}"))))
+            .collect();
+    }
+    let keys: List = value.try_into()?;
+    keys.into_iter()
+        .map(|key| {
+            let key: Gc<KeyPress> = key.try_to_rust_type()?;
+            Ok((*key).clone())
+        })
+        .collect()
+}
+
+fn command_value(command: &Gc<Command>) -> Value {
+    Value::from(Record::from_rust_type((**command).clone()))
+}
+
+#[bridge(name = "key-sequence-read", lib = "(koru-key)")]
+pub async fn key_sequence_read(callback: &Value) -> Result<Vec<Value>, Exception> {
+    let callback: Procedure = callback.clone().try_into()?;
+    *READING.lock().await = Some(Reading { keys: Vec::new(), callback });
+    Ok(Vec::new())
+}
+
+#[bridge(name = "key-lookup", lib = "(koru-key)")]
+pub async fn key_lookup(keys: &Value) -> Result<Vec<Value>, Exception> {
+    let keys = keys_from(keys)?;
+    let maps = SessionState::active_key_maps().await;
+    let resolutions = lookup(&keys, &maps);
+    let entries = maps.iter().zip(resolutions)
+        .map(|((source, _), resolution)| {
+            let (how, command) = match resolution {
+                Resolution::Bound(command) => ("bound", command_value(&command)),
+                Resolution::Default(command) => ("default", command_value(&command)),
+                Resolution::Prefix(Some(command)) => ("prefix", command_value(&command)),
+                Resolution::Prefix(None) => ("prefix", Value::from(false)),
+                Resolution::Unbound => ("unbound", Value::from(false)),
+            };
+            lists::slice_to_list(&[
+                Value::from(source.symbol()),
+                Value::from(Symbol::intern(how)),
+                command,
+            ])
+        })
+        .collect::<Vec<_>>();
+    Ok(vec![lists::slice_to_list(&entries)])
+}
+
+#[bridge(name = "key-bindings", lib = "(koru-key)")]
+pub async fn key_bindings() -> Result<Vec<Value>, Exception> {
+    let maps = SessionState::active_key_maps().await;
+    let entries = bindings(&maps).into_iter()
+        .map(|binding| {
+            let keys = match &binding.keys {
+                Some(keys) => Value::from(keys_to_string(keys)),
+                None => Value::from(false),
+            };
+            let (standing, other) = match binding.standing {
+                Standing::Active => ("active", Value::from(false)),
+                Standing::Conflict(map) => ("conflict", Value::from(maps[map].0.symbol())),
+                Standing::Shadowed(map) => ("shadowed", Value::from(maps[map].0.symbol())),
+            };
+            lists::slice_to_list(&[
+                Value::from(maps[binding.map].0.symbol()),
+                keys,
+                command_value(&binding.command),
+                Value::from(Symbol::intern(standing)),
+                other,
+            ])
+        })
+        .collect::<Vec<_>>();
+    Ok(vec![lists::slice_to_list(&entries)])
+}
@@ -7,7 +7,8 @@ use log::error;
 use tokio::sync::mpsc::{Receiver, Sender};
 use crate::attr_set::AttrSet;
 use crate::kernel;
-use crate::kernel::input::KeyPress;
+use crate::kernel::input::{KeyPress, MouseEvent};
+use crate::kernel::layout::PaneLayout;
 use crate::kernel::scheme_api::session::SessionState;
 use crate::kernel::session::Session;
 use crate::styled_text::{ColorDefinition, StyledFile};
@@ -26,6 +27,9 @@ impl Message {
     pub fn make_response(self, kind: MessageKind) -> Self {
         Self { destination: self.source, source: self.destination, kind }
     }
+    pub fn source(&self) -> usize {
+        self.source
+    }
 }
 
 impl PartialEq for Message {
@@ -45,9 +49,11 @@ pub enum MessageKind {
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum GeneralMessage {
     KeyEvent(KeyPress),
-    MouseEvent,
+    /// Something the mouse did over a pane.
+    MouseEvent(MouseEvent),
     Command,
-    Draw(StyledFile),
+    /// Tells the client what to draw in each pane.
+    Draw(PaneLayout),
     SetColorDef(ColorDefinition),
     UpdateMessageBar(String),
     FlushKeyBuffer,
@@ -57,6 +63,26 @@ pub enum GeneralMessage {
     ShowCommandBar,
     HideCommandBar,
     UpdateCommandBar(StyledFile),
+    /// Tells the client to put the text into the system clipboard.
+    SetClipboard(String),
+    /// Asks the client for the contents of the system clipboard.
+    RequestClipboard,
+    /// The client's response to `RequestClipboard`.
+    /// This is None if the client couldn't read the system clipboard.
+    ClipboardContents(Option<String>),
+    /// Tells the client to draw a popup anchored at the main cursor, replacing any popup that was already shown.
+    ShowPopup(StyledFile),
+    HidePopup,
+    /// Tells the client to draw a picker in the middle of the buffer area, replacing any picker that was already shown.
+    ShowPicker(StyledFile),
+    HidePicker,
+    /// Tells the client to draw the keys that can come next along the bottom of the buffer area, replacing any that were already shown.
+    ShowKeyHints(StyledFile),
+    HideKeyHints,
+    /// Tells the client what to draw in the tab bar, the tab bar is hidden when this is empty.
+    UpdateTabBar(StyledFile),
+    /// Tells the session how many columns and lines the client has to draw buffers in.
+    ViewportSize(usize, usize),
     Quit,
 }
 
@@ -82,6 +108,20 @@ pub enum BackendMessage {
     ShowCommandBar,
     HideCommandBar,
     UpdateCommandBar(StyledFile),
+    UpdateMessageBar(String),
+    SetClipboard(String),
+    RequestClipboard,
+    ShowPopup(StyledFile),
+    HidePopup,
+    ShowPicker(StyledFile),
+    HidePicker,
+    ShowKeyHints(StyledFile),
+    HideKeyHints,
+    /// Draws the panes again, for changes that don't come from a keypress
+    Redraw,
+    /// The keys in the key buffer waited too long for the rest of a binding,
+    /// unless the key buffer has changed since it had this generation
+    KeySequenceTimeout(usize),
     Quit
 }
 
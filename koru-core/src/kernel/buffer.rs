@@ -2,8 +2,20 @@ mod text_buffer;
 mod text_buffer_table;
 mod cursor;
 mod undo;
+mod syntax;
+mod highlight_store;
+mod diagnostics;
+mod indent;
+pub(crate) mod gutter;
+pub(crate) mod wrap;
+pub(crate) mod width;
 
-pub use text_buffer::TextBufferImpl;
+pub use text_buffer::{TextBufferImpl, DEFAULT_HIGHLIGHT_LAYER, SYNTAX_HIGHLIGHT_LAYER};
 pub use text_buffer_table::{BufferHandle, TextBufferTable};
 pub use cursor::*;
-pub use undo::{EditValue, EditOperation, UndoTree};
\ No newline at end of file
+pub use undo::{EditValue, EditOperation, UndoTree};
+pub use syntax::SyntaxTree;
+pub use diagnostics::{Diagnostic, Severity};
+pub use indent::Indent;
+pub use gutter::{Fold, LineNumbers, Sign};
+pub use wrap::Wrap;
\ No newline at end of file
@@ -69,6 +69,9 @@ pub struct Cursor {
     /// The state that the mark is currently in
     pub mark_state: CursorMark,
     main_cursor: bool,
+    /// The display column that moving up and down tries to stay in.
+    /// This is counted from the start of the wrapped row when moving by visual lines.
+    goal_column: Option<usize>,
 }
 
 impl Cursor {
@@ -81,6 +84,7 @@ impl Cursor {
             mark: None,
             main_cursor: false,
             mark_state: CursorMark::None,
+            goal_column: None,
         }
     }
 
@@ -93,6 +97,7 @@ impl Cursor {
             mark: None,
             main_cursor: true,
             mark_state: CursorMark::None,
+            goal_column: None,
         }
     }
 
@@ -131,6 +136,18 @@ impl Cursor {
     pub fn set_column(&mut self, column: usize) {
         self.real_cursor.column = column;
         self.logical_cursor.column = column;
+        self.goal_column = None;
+    }
+
+    pub fn goal_column(&self) -> Option<usize> {
+        self.goal_column
+    }
+
+    /// Moves the cursor to a position while remembering the column within a wrapped row to move back towards.
+    pub fn set_visual_position(&mut self, line: usize, column: usize, goal_column: usize) {
+        self.set_line(line);
+        self.set_column(column);
+        self.goal_column = Some(goal_column);
     }
 
     pub fn set_line(&mut self, line: usize) {
@@ -147,32 +164,45 @@ impl Cursor {
         self.logical_cursor.column == line_len
     }
 
-    pub fn move_up(&mut self, buffer: &dyn TextBufferImpl) {
+    pub fn move_up(&mut self, buffer: &dyn TextBufferImpl, tab_width: usize) {
+        let goal = self.vertical_goal(buffer, tab_width);
         self.logical_cursor.line = self.logical_cursor.line.saturating_sub(1);
         self.real_cursor.line = self.logical_cursor.line;
-        self.real_cursor.column = self.logical_cursor.column;
+        self.real_cursor.column = buffer.column_at_display(self.line(), goal, tab_width);
 
         let line_len = buffer.line_length(self.line());
         if line_len < self.real_cursor.column {
             self.real_cursor.column = line_len;
         }
-
+        // The goal column remembers where to go back to, so the logical cursor doesn't have to
+        self.logical_cursor.column = self.real_cursor.column;
+        self.goal_column = Some(goal);
     }
 
-    pub fn move_down(&mut self, buffer: &dyn TextBufferImpl) {
+    pub fn move_down(&mut self, buffer: &dyn TextBufferImpl, tab_width: usize) {
         if buffer.is_there_next_line(self.line()) {
+            let goal = self.vertical_goal(buffer, tab_width);
             self.logical_cursor.line = self.logical_cursor.line + 1;
             self.real_cursor.line = self.logical_cursor.line;
-            self.real_cursor.column = self.logical_cursor.column;
+            self.real_cursor.column = buffer.column_at_display(self.line(), goal, tab_width);
 
             let line_len = buffer.line_length(self.line());
             if line_len < self.real_cursor.column {
                 self.real_cursor.column = line_len;
             }
+            self.logical_cursor.column = self.real_cursor.column;
+            self.goal_column = Some(goal);
         }
     }
 
+    /// The display column that moving to another line should land on, so that tabs and wide characters don't shift the cursor sideways.
+    fn vertical_goal(&self, buffer: &dyn TextBufferImpl, tab_width: usize) -> usize {
+        self.goal_column
+            .unwrap_or_else(|| buffer.display_column(self.line(), self.column(), tab_width))
+    }
+
     pub fn move_left(&mut self, line_len: usize) {
+        self.goal_column = None;
         if self.real_cursor.column > line_len {
             self.real_cursor.column = line_len;
             self.logical_cursor.column = line_len;
@@ -183,6 +213,7 @@ impl Cursor {
     }
 
     pub fn move_right(&mut self, line_len: usize) {
+        self.goal_column = None;
         self.logical_cursor.column = self.logical_cursor.column.saturating_add(1);
         self.real_cursor.column = self.logical_cursor.column;
 
@@ -222,6 +253,7 @@ impl Cursor {
         if let Some(mark) = self.mark.as_mut() {
             std::mem::swap(&mut self.real_cursor, mark);
             self.logical_cursor = self.real_cursor;
+            self.goal_column = None;
         }
     }
 
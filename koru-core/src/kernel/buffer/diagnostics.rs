@@ -0,0 +1,401 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+use crop::Rope;
+use scheme_rs::exceptions::Exception;
+use scheme_rs::lists::{self, List};
+use scheme_rs::num::{Number, SimpleNumber};
+use scheme_rs::registry::bridge;
+use scheme_rs::value::{UnpackedValue, Value};
+use crate::kernel::scheme_api::session::SessionState;
+use crate::styled_text::{ColorType, Highlight, TextAttribute};
+
+/// How bad a diagnostic is, from worst to least bad.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl Severity {
+    /// Converts the severity numbers that language servers use.
+    ///
+    /// Servers are allowed to leave the severity out, in which case it is treated as an error.
+    pub fn from_lsp(severity: Option<i64>) -> Self {
+        match severity {
+            Some(2) => Severity::Warning,
+            Some(3) => Severity::Information,
+            Some(4) => Severity::Hint,
+            _ => Severity::Error,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Information => "info",
+            Severity::Hint => "hint",
+        }
+    }
+
+    /// The glyph that goes next to the line number.
+    pub fn sign(&self) -> &'static str {
+        match self {
+            Severity::Error => "E",
+            Severity::Warning => "W",
+            Severity::Information => "I",
+            Severity::Hint => "H",
+        }
+    }
+
+    pub fn color(&self) -> ColorType {
+        match self {
+            Severity::Error => ColorType::Error,
+            Severity::Warning => ColorType::Warning,
+            Severity::Information => ColorType::Accent,
+            Severity::Hint => ColorType::Subtext0,
+        }
+    }
+
+    /// The highlight used to underline the text a diagnostic covers.
+    pub fn highlight(&self) -> Highlight {
+        Highlight {
+            fg_color: Some(self.color()),
+            bg_color: None,
+            attribute: TextAttribute::Underline,
+        }
+    }
+}
+
+impl TryFrom<&str> for Severity {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, <Severity as TryFrom<&str>>::Error> {
+        match value {
+            "error" => Ok(Severity::Error),
+            "warning" => Ok(Severity::Warning),
+            "info" | "information" => Ok(Severity::Information),
+            "hint" => Ok(Severity::Hint),
+            _ => Err(format!("Unknown severity: {value}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// The bytes of the buffer that the diagnostic is about.
+    pub range: Range<usize>,
+    pub severity: Severity,
+    pub message: String,
+    /// The tool that reported the diagnostic, like `rustc` or `shellcheck`.
+    pub source: String,
+}
+
+/// The diagnostics of a buffer, grouped by whoever set them.
+///
+/// Each owner replaces all of its diagnostics at once,
+/// so a language server and a linter can report on the same buffer without clearing each other's diagnostics.
+pub struct Diagnostics {
+    groups: Vec<(String, Vec<Diagnostic>)>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self {
+            groups: Vec::new(),
+        }
+    }
+
+    /// Replaces the diagnostics that an owner set before.
+    pub fn set(&mut self, owner: &str, diagnostics: Vec<Diagnostic>) {
+        self.clear(Some(owner));
+        if !diagnostics.is_empty() {
+            self.groups.push((owner.to_string(), diagnostics));
+        }
+    }
+
+    /// Removes the diagnostics of an owner, or all of them if there is no owner.
+    pub fn clear(&mut self, owner: Option<&str>) {
+        match owner {
+            Some(owner) => self.groups.retain(|(group, _)| group != owner),
+            None => self.groups.clear(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Iterates over the diagnostics ordered by where they start.
+    pub fn sorted(&self) -> Vec<&Diagnostic> {
+        let mut diagnostics = self.groups.iter()
+            .flat_map(|(_, diagnostics)| diagnostics.iter())
+            .collect::<Vec<_>>();
+        diagnostics.sort_by_key(|diagnostic| (diagnostic.range.start, diagnostic.severity));
+        diagnostics
+    }
+
+    /// Gets the diagnostics that touch a range of bytes, worst first.
+    pub fn in_range(&self, range: Range<usize>) -> Vec<&Diagnostic> {
+        let mut diagnostics = self.groups.iter()
+            .flat_map(|(_, diagnostics)| diagnostics.iter())
+            .filter(|diagnostic| {
+                // Empty diagnostics still count for the range that they sit in
+                diagnostic.range.start < range.end
+                    && (range.start < diagnostic.range.end || range.start <= diagnostic.range.start)
+            })
+            .collect::<Vec<_>>();
+        diagnostics.sort_by_key(|diagnostic| (diagnostic.severity, diagnostic.range.start));
+        diagnostics
+    }
+
+    /// Finds the worst severity on each of the lines in a range that has diagnostics.
+    ///
+    /// A diagnostic counts for every line it touches, the same as with `in_range`.
+    pub fn line_severities(&self, rope: &Rope, lines: Range<usize>) -> BTreeMap<usize, Severity> {
+        let mut severities = BTreeMap::new();
+        for (_, diagnostics) in self.groups.iter() {
+            for diagnostic in diagnostics {
+                let start = diagnostic.range.start.min(rope.byte_len());
+                let end = diagnostic.range.end.min(rope.byte_len()).max(start);
+                let first_line = rope.line_of_byte(start);
+                // A diagnostic that ends right at the start of a line doesn't touch that line
+                let last_line = if end > start { rope.line_of_byte(end - 1) } else { first_line };
+                for line in first_line.max(lines.start)..=last_line.min(lines.end.saturating_sub(1)) {
+                    severities.entry(line)
+                        .and_modify(|severity: &mut Severity| *severity = (*severity).min(diagnostic.severity))
+                        .or_insert(diagnostic.severity);
+                }
+            }
+        }
+        severities
+    }
+
+    /// Moves the diagnostics for `removed` bytes at `start` being replaced with `inserted` bytes.
+    ///
+    /// Diagnostics that were entirely removed shrink down to where the text was removed instead of disappearing,
+    /// since the problem is usually still there until the owner reports again.
+    pub fn edit(&mut self, start: usize, removed: usize, inserted: usize) {
+        let removed_end = start + removed;
+        let shift = |offset: usize, is_end: bool| {
+            if offset < start {
+                offset
+            } else if offset > removed_end || (offset == removed_end && removed > 0) {
+                offset - removed + inserted
+            } else if offset == start && removed == 0 {
+                // Text typed at the very start of a diagnostic pushes it along, text typed at its end doesn't grow it
+                if is_end { offset } else { offset + inserted }
+            } else {
+                start
+            }
+        };
+        for (_, diagnostics) in self.groups.iter_mut() {
+            for diagnostic in diagnostics.iter_mut() {
+                let new_start = shift(diagnostic.range.start, false);
+                let new_end = shift(diagnostic.range.end, true).max(new_start);
+                diagnostic.range = new_start..new_end;
+            }
+        }
+    }
+}
+
+impl Default for Diagnostics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Converts a line and grapheme column into a byte offset, clamping it to the text.
+pub fn byte_of_grid(rope: &Rope, line: usize, column: usize) -> usize {
+    if line >= rope.line_len() {
+        return rope.byte_len();
+    }
+    let line_start = rope.byte_of_line(line);
+    line_start + rope.line(line).graphemes()
+        .take(column)
+        .map(|grapheme| grapheme.len())
+        .sum::<usize>()
+}
+
+/// Converts a byte offset into a line and grapheme column.
+pub fn grid_of_byte(rope: &Rope, byte_offset: usize) -> (usize, usize) {
+    let byte_offset = byte_offset.min(rope.byte_len());
+    let line = rope.line_of_byte(byte_offset);
+    let line_start = rope.byte_of_line(line);
+    (line, rope.byte_slice(line_start..byte_offset).graphemes().count())
+}
+
+/// Reads a `(line column)` list.
+fn grid_arg(value: &Value) -> Result<(usize, usize), Exception> {
+    let list: List = value.try_into()?;
+    match list.as_slice() {
+        [line, column] => {
+            let line: Number = line.try_into()?;
+            let column: Number = column.try_into()?;
+            Ok((line.try_into()?, column.try_into()?))
+        }
+        _ => Err(Exception::error("A position must contain two integers")),
+    }
+}
+
+/// Reads a severity from either a string or a symbol.
+fn severity_arg(value: &Value) -> Result<Severity, Exception> {
+    let name = match value.clone().unpack() {
+        UnpackedValue::String(string) => string.to_string(),
+        UnpackedValue::Symbol(symbol) => symbol.to_string(),
+        other => return Err(Exception::type_error("String or Symbol", other.type_name())),
+    };
+    Severity::try_from(name.as_str()).map_err(Exception::error)
+}
+
+/// A diagnostic as it comes from Scheme: its start, end, severity, message, and source.
+type DiagnosticArg = ((usize, usize), (usize, usize), Severity, String, String);
+
+/// Reads a `((line column) (line column) severity message [source])` list.
+fn diagnostic_arg(value: &Value, owner: &str) -> Result<DiagnosticArg, Exception> {
+    let list: List = value.try_into()?;
+    match list.as_slice() {
+        [start, end, severity, message, rest @ ..] => {
+            let source = match rest.first() {
+                Some(source) => source.clone().try_into()?,
+                None => owner.to_string(),
+            };
+            Ok((grid_arg(start)?, grid_arg(end)?, severity_arg(severity)?, message.clone().try_into()?, source))
+        }
+        _ => Err(Exception::error("A diagnostic must contain a start, end, severity, and message")),
+    }
+}
+
+fn grid_value(rope: &Rope, byte_offset: usize) -> Value {
+    let (line, column) = grid_of_byte(rope, byte_offset);
+    lists::slice_to_list(&[
+        Value::from(SimpleNumber::from(line)),
+        Value::from(SimpleNumber::from(column)),
+    ])
+}
+
+fn diagnostic_value(rope: &Rope, diagnostic: &Diagnostic) -> Value {
+    lists::slice_to_list(&[
+        grid_value(rope, diagnostic.range.start),
+        grid_value(rope, diagnostic.range.end),
+        Value::from(diagnostic.severity.name().to_string()),
+        Value::from(diagnostic.message.clone()),
+        Value::from(diagnostic.source.clone()),
+    ])
+}
+
+#[bridge(name = "diagnostics-set!", lib = "(koru-diagnostics)")]
+pub async fn diagnostics_set(buffer_name: &Value, owner: &Value, diagnostics: &Value) -> Result<Vec<Value>, Exception> {
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let owner: String = owner.clone().try_into()?;
+    let diagnostics: List = diagnostics.try_into()?;
+    let diagnostics = diagnostics.into_iter()
+        .map(|diagnostic| diagnostic_arg(&diagnostic, &owner))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    handle.set_diagnostics(&owner, |rope| {
+        diagnostics.into_iter()
+            .map(|((start_line, start_column), (end_line, end_column), severity, message, source)| {
+                let start = byte_of_grid(rope, start_line, start_column);
+                let end = byte_of_grid(rope, end_line, end_column).max(start);
+                Diagnostic { range: start..end, severity, message, source }
+            })
+            .collect()
+    }).await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "diagnostics-clear!", lib = "(koru-diagnostics)")]
+pub async fn diagnostics_clear(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let Some((buffer_name, rest)) = args.split_first() else {
+        return Err(Exception::wrong_num_of_args(1, args.len()));
+    };
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let owner: Option<String> = match rest.first() {
+        Some(owner) => Some(owner.clone().try_into()?),
+        None => None,
+    };
+
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    handle.clear_diagnostics(owner.as_deref()).await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "diagnostics-get", lib = "(koru-diagnostics)")]
+pub async fn diagnostics_get(buffer_name: &Value) -> Result<Vec<Value>, Exception> {
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    let (rope, diagnostics) = handle.diagnostics().await;
+    let values = diagnostics.iter()
+        .map(|diagnostic| diagnostic_value(&rope, diagnostic))
+        .collect::<Vec<_>>();
+    Ok(vec![lists::slice_to_list(&values)])
+}
+
+#[bridge(name = "diagnostics-at-line", lib = "(koru-diagnostics)")]
+pub async fn diagnostics_at_line(buffer_name: &Value, line: &Value) -> Result<Vec<Value>, Exception> {
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let line: SimpleNumber = line.clone().try_into()?;
+    let line: usize = line.try_into()?;
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    let (rope, diagnostics) = handle.line_diagnostics(line).await;
+    let values = diagnostics.iter()
+        .map(|diagnostic| diagnostic_value(&rope, diagnostic))
+        .collect::<Vec<_>>();
+    Ok(vec![lists::slice_to_list(&values)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(range: Range<usize>, severity: Severity) -> Diagnostic {
+        Diagnostic {
+            range,
+            severity,
+            message: String::new(),
+            source: String::new(),
+        }
+    }
+
+    #[test]
+    fn line_severities_keep_the_worst_severity_per_line() {
+        let rope = Rope::from("one\ntwo\nthree\nfour\n");
+        let mut diagnostics = Diagnostics::new();
+        diagnostics.set("lint", vec![
+            diagnostic(0..2, Severity::Hint),
+            // Ends right at the start of line 2, so it only touches lines 0 and 1
+            diagnostic(2..8, Severity::Warning),
+        ]);
+        diagnostics.set("lsp", vec![
+            diagnostic(1..1, Severity::Error),
+            diagnostic(14..14, Severity::Information),
+        ]);
+
+        let severities = diagnostics.line_severities(&rope, 0..4);
+        assert_eq!(severities, BTreeMap::from([
+            (0, Severity::Error),
+            (1, Severity::Warning),
+            (3, Severity::Information),
+        ]));
+        for line in 0..4 {
+            let start = rope.byte_of_line(line);
+            let end = start + rope.line(line).byte_len() + 1;
+            let worst = diagnostics.in_range(start..end).first().map(|diagnostic| diagnostic.severity);
+            assert_eq!(severities.get(&line).copied(), worst);
+        }
+    }
+
+    #[test]
+    fn line_severities_only_cover_the_lines_asked_for() {
+        let rope = Rope::from("one\ntwo\nthree\n");
+        let mut diagnostics = Diagnostics::new();
+        diagnostics.set("lint", vec![diagnostic(0..12, Severity::Error)]);
+        assert_eq!(diagnostics.line_severities(&rope, 1..2), BTreeMap::from([(1, Severity::Error)]));
+        assert!(Diagnostics::new().is_empty());
+        assert!(!diagnostics.is_empty());
+    }
+}
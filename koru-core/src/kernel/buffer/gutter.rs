@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+use std::sync::{LazyLock, RwLock};
+use scheme_rs::exceptions::Exception;
+use scheme_rs::num::SimpleNumber;
+use scheme_rs::registry::bridge;
+use scheme_rs::symbols::Symbol;
+use scheme_rs::value::{UnpackedValue, Value};
+use unicode_segmentation::UnicodeSegmentation;
+use crate::kernel::scheme_api::session::SessionState;
+use crate::styled_text::{display_width, ColorType};
+
+/// How many columns the sign column takes up
+pub const SIGN_COLUMNS: usize = 2;
+/// The fewest columns that line numbers take up, so the gutter doesn't grow for the first few lines
+pub const MIN_NUMBER_COLUMNS: usize = 3;
+
+/// How lines are numbered in the gutter of every buffer.
+static LINE_NUMBERS: LazyLock<RwLock<LineNumbers>> = LazyLock::new(|| {
+    RwLock::new(LineNumbers::default())
+});
+
+/// Gets how lines are numbered in the gutter.
+pub fn line_numbers() -> LineNumbers {
+    *LINE_NUMBERS.read().expect("line numbers lock poisoned")
+}
+
+/// How lines are numbered in the gutter.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum LineNumbers {
+    Off,
+    /// Every line shows its own number
+    #[default]
+    Absolute,
+    /// Every line shows how far it is from the main cursor's line
+    Relative,
+    /// Like relative, but the main cursor's line shows its own number
+    Hybrid,
+}
+
+impl LineNumbers {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineNumbers::Off => "off",
+            LineNumbers::Absolute => "absolute",
+            LineNumbers::Relative => "relative",
+            LineNumbers::Hybrid => "hybrid",
+        }
+    }
+
+    /// Gets the number drawn for a line, counting lines from 0.
+    pub fn number(&self, line: usize, main_line: usize) -> Option<usize> {
+        match self {
+            LineNumbers::Off => None,
+            LineNumbers::Absolute => Some(line + 1),
+            LineNumbers::Relative => Some(line.abs_diff(main_line)),
+            LineNumbers::Hybrid if line == main_line => Some(line + 1),
+            LineNumbers::Hybrid => Some(line.abs_diff(main_line)),
+        }
+    }
+}
+
+/// A short piece of text drawn in the sign column of the gutter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sign {
+    pub text: String,
+    pub color: ColorType,
+}
+
+/// Whether the lines below a line are shown or hidden.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Fold {
+    Open,
+    Closed,
+}
+
+impl TryFrom<&str> for LineNumbers {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "off" => Ok(LineNumbers::Off),
+            "absolute" => Ok(LineNumbers::Absolute),
+            "relative" => Ok(LineNumbers::Relative),
+            "hybrid" => Ok(LineNumbers::Hybrid),
+            _ => Err(format!("Unknown line numbering: {value}")),
+        }
+    }
+}
+
+impl Fold {
+    /// The marker drawn in the fold column.
+    pub fn marker(&self) -> &'static str {
+        match self {
+            Fold::Open => "▾",
+            Fold::Closed => "▸",
+        }
+    }
+}
+
+/// The signs and fold markers of a buffer, by line.
+///
+/// They move with the lines they are on as lines are added and removed above them.
+#[derive(Debug, Default)]
+pub struct LineMarks {
+    signs: BTreeMap<usize, Sign>,
+    folds: BTreeMap<usize, Fold>,
+}
+
+impl LineMarks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn sign(&self, line: usize) -> Option<&Sign> {
+        self.signs.get(&line)
+    }
+
+    pub fn set_sign(&mut self, line: usize, sign: Sign) {
+        self.signs.insert(line, sign);
+    }
+
+    /// Removes the sign on a line, or all of the signs if there is no line.
+    pub fn clear_signs(&mut self, line: Option<usize>) {
+        match line {
+            Some(line) => {
+                self.signs.remove(&line);
+            }
+            None => self.signs.clear(),
+        }
+    }
+
+    pub fn has_signs(&self) -> bool {
+        !self.signs.is_empty()
+    }
+
+    pub fn fold(&self, line: usize) -> Option<Fold> {
+        self.folds.get(&line).copied()
+    }
+
+    pub fn set_fold(&mut self, line: usize, fold: Fold) {
+        self.folds.insert(line, fold);
+    }
+
+    /// Removes the fold marker on a line, or all of them if there is no line.
+    pub fn clear_folds(&mut self, line: Option<usize>) {
+        match line {
+            Some(line) => {
+                self.folds.remove(&line);
+            }
+            None => self.folds.clear(),
+        }
+    }
+
+    pub fn has_folds(&self) -> bool {
+        !self.folds.is_empty()
+    }
+
+    /// Moves the marks for an edit that removes `removed` lines starting at `first_moved` and adds `added` lines in their place.
+    ///
+    /// Marks on the removed lines are dropped, since those lines were joined into the line before them.
+    pub fn edit(&mut self, first_moved: usize, removed: usize, added: usize) {
+        if removed == 0 && added == 0 {
+            return;
+        }
+        Self::shift(&mut self.signs, first_moved, removed, added);
+        Self::shift(&mut self.folds, first_moved, removed, added);
+    }
+
+    fn shift<T>(marks: &mut BTreeMap<usize, T>, first_moved: usize, removed: usize, added: usize) {
+        let after = marks.split_off(&first_moved);
+        for (line, mark) in after {
+            if line >= first_moved + removed {
+                marks.insert(line - removed + added, mark);
+            }
+        }
+    }
+}
+
+/// Cuts text down to a number of columns, padding it with spaces if it is shorter.
+pub fn fit_columns(text: &str, columns: usize) -> String {
+    let mut fitted = String::new();
+    let mut width = 0;
+    for grapheme in text.graphemes(true) {
+        let grapheme_width = display_width(grapheme);
+        if width + grapheme_width > columns {
+            break;
+        }
+        width += grapheme_width;
+        fitted.push_str(grapheme);
+    }
+    fitted.push_str(&" ".repeat(columns - width));
+    fitted
+}
+
+/// Reads a name from either a string or a symbol.
+fn name_arg(value: &Value) -> Result<String, Exception> {
+    match value.clone().unpack() {
+        UnpackedValue::String(string) => Ok(string.to_string()),
+        UnpackedValue::Symbol(symbol) => Ok(symbol.to_string()),
+        other => Err(Exception::type_error("String or Symbol", other.type_name())),
+    }
+}
+
+fn line_arg(value: &Value) -> Result<usize, Exception> {
+    let line: SimpleNumber = value.clone().try_into()?;
+    line.try_into()
+}
+
+#[bridge(name = "gutter-line-numbers-set!", lib = "(koru-gutter)")]
+pub async fn gutter_line_numbers_set(line_numbers: &Value) -> Result<Vec<Value>, Exception> {
+    let line_numbers = LineNumbers::try_from(name_arg(line_numbers)?.as_str()).map_err(Exception::error)?;
+    *LINE_NUMBERS.write().expect("line numbers lock poisoned") = line_numbers;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "gutter-line-numbers", lib = "(koru-gutter)")]
+pub async fn gutter_line_numbers() -> Result<Vec<Value>, Exception> {
+    Ok(vec![Value::from(Symbol::intern(line_numbers().name()))])
+}
+
+#[bridge(name = "gutter-sign-set!", lib = "(koru-gutter)")]
+pub async fn gutter_sign_set(buffer_name: &Value, line: &Value, text: &Value, color: &Value) -> Result<Vec<Value>, Exception> {
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let line = line_arg(line)?;
+    let text: String = text.clone().try_into()?;
+    let color = ColorType::try_from(name_arg(color)?.as_str()).map_err(Exception::error)?;
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    handle.set_sign(line, Sign { text, color }).await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "gutter-sign-clear!", lib = "(koru-gutter)")]
+pub async fn gutter_sign_clear(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let Some((buffer_name, rest)) = args.split_first() else {
+        return Err(Exception::wrong_num_of_args(1, args.len()));
+    };
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let line = match rest.first() {
+        Some(line) => Some(line_arg(line)?),
+        None => None,
+    };
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    handle.clear_signs(line).await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "gutter-fold-set!", lib = "(koru-gutter)")]
+pub async fn gutter_fold_set(buffer_name: &Value, line: &Value, fold: &Value) -> Result<Vec<Value>, Exception> {
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let line = line_arg(line)?;
+    let fold = match name_arg(fold)?.as_str() {
+        "open" => Fold::Open,
+        "closed" => Fold::Closed,
+        other => return Err(Exception::error(format!("Unknown fold state: {other}"))),
+    };
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    handle.set_fold(line, fold).await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "gutter-fold-clear!", lib = "(koru-gutter)")]
+pub async fn gutter_fold_clear(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let Some((buffer_name, rest)) = args.split_first() else {
+        return Err(Exception::wrong_num_of_args(1, args.len()));
+    };
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let line = match rest.first() {
+        Some(line) => Some(line_arg(line)?),
+        None => None,
+    };
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    handle.clear_folds(line).await;
+    Ok(Vec::new())
+}
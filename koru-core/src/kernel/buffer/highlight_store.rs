@@ -0,0 +1,370 @@
+use std::ops::{Bound, Range, RangeBounds};
+
+struct Span<V> {
+    start: usize,
+    end: usize,
+    value: V,
+}
+
+/// Maps non-overlapping byte ranges to values while keeping up with edits to the text.
+///
+/// The spans are split in two at a gap.
+/// Spans before the gap are stored in order with their real offsets.
+/// Spans after the gap are stored in reverse order, so that the span nearest the gap is at the end,
+/// and they have a shift that has not been applied to them yet.
+/// An edit moves the gap to where it happens, fixes up the spans that touch the edited range and then changes the shift,
+/// so typing in one spot only visits the spans near the cursor.
+pub struct HighlightStore<V> {
+    before: Vec<Span<V>>,
+    after: Vec<Span<V>>,
+    /// Added to the offsets of the spans after the gap to get their real offsets.
+    /// This uses wrapping arithmetic so that it can stand in for a negative number.
+    shift: usize,
+}
+
+impl<V: Clone> HighlightStore<V> {
+    pub fn new() -> Self {
+        Self {
+            before: Vec::new(),
+            after: Vec::new(),
+            shift: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.before.clear();
+        self.after.clear();
+        self.shift = 0;
+    }
+
+    fn range_bounds(range: impl RangeBounds<usize>) -> Range<usize> {
+        let start = match range.start_bound() {
+            Bound::Included(start) => *start,
+            Bound::Excluded(start) => *start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => *end + 1,
+            Bound::Excluded(end) => *end,
+            Bound::Unbounded => usize::MAX,
+        };
+        start..end
+    }
+
+    /// Moves a span from after the gap to before it.
+    fn pop_after(&mut self) -> Option<Span<V>> {
+        self.after.pop().map(|mut span| {
+            span.start = span.start.wrapping_add(self.shift);
+            span.end = span.end.wrapping_add(self.shift);
+            span
+        })
+    }
+
+    fn push_after(&mut self, mut span: Span<V>) {
+        span.start = span.start.wrapping_sub(self.shift);
+        span.end = span.end.wrapping_sub(self.shift);
+        self.after.push(span);
+    }
+
+    fn after_start(&self, span: &Span<V>) -> usize {
+        span.start.wrapping_add(self.shift)
+    }
+
+    /// Moves the gap so that every span before it starts before the offset and every span after it starts at or after the offset.
+    fn move_gap(&mut self, offset: usize) {
+        while self.before.last().is_some_and(|span| span.start >= offset) {
+            let span = self.before.pop().expect("there is a last span");
+            self.push_after(span);
+        }
+        while self.after.last().is_some_and(|span| self.after_start(span) < offset) {
+            let span = self.pop_after().expect("there is a last span");
+            self.before.push(span);
+        }
+    }
+
+    /// Removes everything within the range, splitting spans that cross either end of it.
+    pub fn remove(&mut self, range: impl RangeBounds<usize>) {
+        let range = Self::range_bounds(range);
+        if range.is_empty() {
+            return;
+        }
+        self.move_gap(range.start);
+
+        // Only the last span before the gap can reach into the range since spans don't overlap
+        let mut right_piece = None;
+        if let Some(span) = self.before.last_mut()
+            && span.end > range.start {
+            if span.end > range.end {
+                right_piece = Some(Span {
+                    start: range.end,
+                    end: span.end,
+                    value: span.value.clone(),
+                });
+            }
+            span.end = range.start;
+        }
+
+        while self.after.last().is_some_and(|span| self.after_start(span) < range.end) {
+            let mut span = self.pop_after().expect("there is a last span");
+            if span.end > range.end {
+                span.start = range.end;
+                right_piece = Some(span);
+            }
+        }
+
+        if let Some(span) = right_piece {
+            self.push_after(span);
+        }
+    }
+
+    /// Sets the value of a range, overwriting anything that was there.
+    pub fn insert(&mut self, range: impl RangeBounds<usize>, value: V) {
+        let range = Self::range_bounds(range);
+        if range.is_empty() {
+            return;
+        }
+        self.remove(range.clone());
+        self.before.push(Span {
+            start: range.start,
+            end: range.end,
+            value,
+        });
+    }
+
+    /// Updates the spans for text being replaced.
+    ///
+    /// `removed` bytes starting at `start` get replaced with `inserted` bytes.
+    /// Spans after the edit are shifted.
+    /// Spans that overlap the removed text are clipped, and removed if nothing is left of them.
+    /// Text inserted strictly inside of a span extends the span, while text inserted at either end of a span does not.
+    pub fn edit(&mut self, start: usize, removed: usize, inserted: usize) {
+        self.move_gap(start);
+        let removed_end = start + removed;
+
+        if removed > 0 {
+            if let Some(span) = self.before.last_mut()
+                && span.end > start {
+                span.end = if span.end > removed_end {
+                    span.end - removed
+                } else {
+                    start
+                };
+            }
+
+            let mut survivor = None;
+            while self.after.last().is_some_and(|span| self.after_start(span) < removed_end) {
+                let span = self.pop_after().expect("there is a last span");
+                if span.end > removed_end {
+                    survivor = Some(Span {
+                        start,
+                        end: span.end - removed,
+                        value: span.value,
+                    });
+                }
+            }
+
+            self.shift = self.shift.wrapping_sub(removed);
+            if let Some(span) = survivor {
+                self.push_after(span);
+            }
+        }
+
+        if inserted > 0 {
+            if let Some(span) = self.before.last_mut()
+                && span.end > start {
+                span.end += inserted;
+            }
+            self.shift = self.shift.wrapping_add(inserted);
+        }
+    }
+
+    /// Gets the value of the span containing an offset.
+    pub fn get(&self, offset: usize) -> Option<&V> {
+        let index = self.before.partition_point(|span| span.start <= offset);
+        if let Some(span) = index.checked_sub(1).map(|index| &self.before[index])
+            && offset < span.end {
+            return Some(&span.value);
+        }
+        // The spans after the gap are in reverse, so this finds the last span that starts at or before the offset
+        let index = self.after.partition_point(|span| self.after_start(span) > offset);
+        let span = self.after.get(index)?;
+        if offset < span.end.wrapping_add(self.shift) {
+            Some(&span.value)
+        } else {
+            None
+        }
+    }
+
+    /// Iterates over the spans in order.
+    pub fn iter(&self) -> impl Iterator<Item = (Range<usize>, &V)> {
+        let before = self.before.iter()
+            .map(|span| (span.start..span.end, &span.value));
+        let after = self.after.iter()
+            .rev()
+            .map(|span| (span.start.wrapping_add(self.shift)..span.end.wrapping_add(self.shift), &span.value));
+        before.chain(after)
+    }
+}
+
+impl<V: Clone> Default for HighlightStore<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+    use super::HighlightStore;
+
+    fn store(spans: &[(Range<usize>, char)]) -> HighlightStore<char> {
+        let mut store = HighlightStore::new();
+        for (range, value) in spans {
+            store.insert(range.clone(), *value);
+        }
+        store
+    }
+
+    fn spans(store: &HighlightStore<char>) -> Vec<(Range<usize>, char)> {
+        store.iter().map(|(range, value)| (range, *value)).collect()
+    }
+
+    /// Applies an edit to plain spans the slow way, following the rules that [`HighlightStore::edit`] documents.
+    fn edit_spans(spans: &[(Range<usize>, char)], start: usize, removed: usize, inserted: usize) -> Vec<(Range<usize>, char)> {
+        let removed_end = start + removed;
+        let clip = |offset: usize| {
+            if offset <= start {
+                offset
+            } else if offset >= removed_end {
+                offset - removed
+            } else {
+                start
+            }
+        };
+        spans.iter()
+            .map(|(range, value)| (clip(range.start)..clip(range.end), *value))
+            .filter(|(range, _)| !range.is_empty())
+            .map(|(range, value)| {
+                if range.start >= start {
+                    (range.start + inserted..range.end + inserted, value)
+                } else if range.end > start {
+                    (range.start..range.end + inserted, value)
+                } else {
+                    (range, value)
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn insert_inside_a_span_extends_it() {
+        let mut store = store(&[(10..20, 'a'), (30..40, 'b')]);
+        store.edit(15, 0, 5);
+        assert_eq!(spans(&store), vec![(10..25, 'a'), (35..45, 'b')]);
+    }
+
+    #[test]
+    fn insert_before_a_span_shifts_it() {
+        let mut store = store(&[(10..20, 'a'), (30..40, 'b')]);
+        store.edit(5, 0, 3);
+        assert_eq!(spans(&store), vec![(13..23, 'a'), (33..43, 'b')]);
+    }
+
+    #[test]
+    fn insert_at_the_start_of_a_span_shifts_it() {
+        let mut store = store(&[(10..20, 'a')]);
+        store.edit(10, 0, 2);
+        assert_eq!(spans(&store), vec![(12..22, 'a')]);
+    }
+
+    #[test]
+    fn insert_at_the_end_of_a_span_leaves_it() {
+        let mut store = store(&[(10..20, 'a'), (20..30, 'b')]);
+        store.edit(20, 0, 4);
+        assert_eq!(spans(&store), vec![(10..20, 'a'), (24..34, 'b')]);
+    }
+
+    #[test]
+    fn delete_inside_a_span_shrinks_it() {
+        let mut store = store(&[(10..20, 'a'), (30..40, 'b')]);
+        store.edit(12, 3, 0);
+        assert_eq!(spans(&store), vec![(10..17, 'a'), (27..37, 'b')]);
+    }
+
+    #[test]
+    fn delete_over_the_start_of_a_span_clips_it() {
+        let mut store = store(&[(10..20, 'a')]);
+        store.edit(5, 10, 0);
+        assert_eq!(spans(&store), vec![(5..10, 'a')]);
+    }
+
+    #[test]
+    fn delete_over_the_end_of_a_span_clips_it() {
+        let mut store = store(&[(10..20, 'a'), (30..40, 'b')]);
+        store.edit(15, 10, 0);
+        assert_eq!(spans(&store), vec![(10..15, 'a'), (20..30, 'b')]);
+    }
+
+    #[test]
+    fn delete_covering_spans_removes_them() {
+        let mut store = store(&[(0..5, 'a'), (10..20, 'b'), (20..25, 'c'), (30..40, 'd')]);
+        store.edit(8, 20, 0);
+        assert_eq!(spans(&store), vec![(0..5, 'a'), (10..20, 'd')]);
+        assert_eq!(store.get(8), None);
+        assert_eq!(store.get(10), Some(&'d'));
+    }
+
+    #[test]
+    fn delete_across_spans_clips_both_ends() {
+        let mut store = store(&[(0..5, 'a'), (10..20, 'b'), (30..40, 'c')]);
+        store.edit(3, 10, 0);
+        assert_eq!(spans(&store), vec![(0..3, 'a'), (3..10, 'b'), (20..30, 'c')]);
+    }
+
+    #[test]
+    fn replacing_text_inside_a_span_keeps_it_whole() {
+        let mut store = store(&[(10..20, 'a'), (30..40, 'b')]);
+        store.edit(12, 4, 1);
+        assert_eq!(spans(&store), vec![(10..17, 'a'), (27..37, 'b')]);
+    }
+
+    #[test]
+    fn insert_overwrites_and_splits_spans() {
+        let mut store = store(&[(0..10, 'a'), (10..20, 'b')]);
+        store.insert(5..12, 'c');
+        assert_eq!(spans(&store), vec![(0..5, 'a'), (5..12, 'c'), (12..20, 'b')]);
+        store.remove(2..3);
+        assert_eq!(spans(&store), vec![(0..2, 'a'), (3..5, 'a'), (5..12, 'c'), (12..20, 'b')]);
+    }
+
+    #[test]
+    fn gap_moves_across_the_whole_buffer() {
+        let mut expected = (0..50)
+            .map(|index| (index * 10..index * 10 + 6, char::from(b'a' + (index % 26) as u8)))
+            .collect::<Vec<_>>();
+        let mut store = store(&expected);
+        // Jumps between the ends and the middle so the gap keeps crossing every span
+        let mut seed = 7usize;
+        for step in 0..400 {
+            let length = expected.last().map_or(0, |(range, _)| range.end) + 10;
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let start = match step % 4 {
+                0 => 0,
+                1 => length - 1,
+                _ => (seed >> 33) % length,
+            };
+            let removed = ((seed >> 20) % 8).min(length - start);
+            let inserted = (seed >> 10) % 6;
+            store.edit(start, removed, inserted);
+            expected = edit_spans(&expected, start, removed, inserted);
+            assert_eq!(spans(&store), expected, "after editing {start} removing {removed} inserting {inserted}");
+        }
+        let length = expected.last().map_or(0, |(range, _)| range.end) + 10;
+        for offset in 0..length {
+            let value = expected.iter()
+                .find(|(range, _)| range.contains(&offset))
+                .map(|(_, value)| value);
+            assert_eq!(store.get(offset), value, "at {offset}");
+        }
+    }
+}
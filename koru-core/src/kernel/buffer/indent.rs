@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use crop::Rope;
+
+/// How many lines are looked at when detecting the indentation of a buffer.
+const DETECTION_LINES: usize = 1000;
+
+/// The indentation style of a buffer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Indent {
+    /// Whether a level of indentation is a tab instead of spaces
+    pub use_tabs: bool,
+    /// How many columns a level of indentation takes up
+    pub width: usize,
+}
+
+impl Indent {
+    pub fn new(use_tabs: bool, width: usize) -> Self {
+        Self {
+            use_tabs,
+            width: width.max(1),
+        }
+    }
+
+    /// The text for a single level of indentation.
+    pub fn unit(&self) -> String {
+        if self.use_tabs {
+            String::from("\t")
+        } else {
+            " ".repeat(self.width)
+        }
+    }
+
+    /// The text that indents a line to a column.
+    ///
+    /// With tabs, whatever doesn't fit in a whole tab is made up with spaces.
+    pub fn text_for_column(&self, column: usize) -> String {
+        if self.use_tabs {
+            let mut text = "\t".repeat(column / self.width);
+            text.push_str(&" ".repeat(column % self.width));
+            text
+        } else {
+            " ".repeat(column)
+        }
+    }
+
+    /// Guesses the indentation of some text from its leading whitespace.
+    ///
+    /// Returns None if no line is indented.
+    pub fn detect(text: &Rope) -> Option<Self> {
+        let mut tab_lines = 0;
+        let mut space_lines = 0;
+        let mut deltas: HashMap<usize, usize> = HashMap::new();
+        let mut previous_spaces = 0;
+
+        for line in text.lines().take(DETECTION_LINES) {
+            let mut chars = line.chars();
+            let Some(first) = chars.next() else {
+                continue;
+            };
+            match first {
+                '\t' => {
+                    tab_lines += 1;
+                    previous_spaces = 0;
+                }
+                ' ' => {
+                    let spaces = 1 + chars.by_ref().take_while(|c| *c == ' ').count();
+                    // Lines that are only whitespace or continue an aligned comment say little about the style
+                    let rest = line.chars().nth(spaces);
+                    if rest.is_none_or(|c| c.is_whitespace() || c == '*') {
+                        continue;
+                    }
+                    space_lines += 1;
+                    let delta = spaces.abs_diff(previous_spaces);
+                    if (2..=8).contains(&delta) {
+                        *deltas.entry(delta).or_default() += 1;
+                    }
+                    previous_spaces = spaces;
+                }
+                c if c.is_whitespace() => {}
+                _ => previous_spaces = 0,
+            }
+        }
+
+        if tab_lines == 0 && space_lines == 0 {
+            return None;
+        }
+        if tab_lines > space_lines {
+            return Some(Self::new(true, Self::default().width));
+        }
+        // Ties go to the smaller width since deeper nesting also shows up as multiples of it
+        let width = deltas.into_iter()
+            .max_by_key(|(delta, count)| (*count, std::cmp::Reverse(*delta)))
+            .map(|(delta, _)| delta)
+            .unwrap_or(Self::default().width);
+        Some(Self::new(false, width))
+    }
+}
+
+impl Default for Indent {
+    fn default() -> Self {
+        Self {
+            use_tabs: false,
+            width: 4,
+        }
+    }
+}
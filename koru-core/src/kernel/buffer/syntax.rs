@@ -0,0 +1,452 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::{Arc, LazyLock, RwLock};
+use crop::Rope;
+use libloading::Library;
+use scheme_rs::exceptions::Exception;
+use scheme_rs::gc::Gc;
+use scheme_rs::registry::bridge;
+use scheme_rs::value::Value;
+use tree_sitter::{InputEdit, Language, Node, Parser, Point, Query, QueryCursor, StreamingIterator, Tree};
+use tree_sitter_language::LanguageFn;
+use crate::kernel::buffer::{EditOperation, EditValue};
+use crate::kernel::buffer::highlight_store::HighlightStore;
+use crate::kernel::scheme_api::session::SessionState;
+use crate::styled_text::{ColorType, Highlight, TextAttribute};
+
+/// These use a std lock rather than a tokio one since they get read while a buffer is being drawn.
+static GRAMMARS: LazyLock<RwLock<HashMap<String, Arc<Grammar>>>> = LazyLock::new(|| {
+    RwLock::new(HashMap::new())
+});
+static FILE_TYPES: LazyLock<RwLock<HashMap<String, String>>> = LazyLock::new(|| {
+    RwLock::new(HashMap::new())
+});
+static CAPTURE_HIGHLIGHTS: LazyLock<RwLock<HashMap<String, Highlight>>> = LazyLock::new(|| {
+    RwLock::new(default_capture_highlights())
+});
+
+fn capture_highlight(fg_color: ColorType, attribute: TextAttribute) -> Highlight {
+    Highlight {
+        fg_color: Some(fg_color),
+        bg_color: None,
+        attribute,
+    }
+}
+
+fn default_capture_highlights() -> HashMap<String, Highlight> {
+    let defaults = [
+        ("keyword", ColorType::Keyword, TextAttribute::empty()),
+        ("function", ColorType::Function, TextAttribute::empty()),
+        ("function.method", ColorType::Method, TextAttribute::empty()),
+        ("function.macro", ColorType::Macro, TextAttribute::empty()),
+        ("method", ColorType::Method, TextAttribute::empty()),
+        ("macro", ColorType::Macro, TextAttribute::empty()),
+        ("attribute", ColorType::Macro, TextAttribute::empty()),
+        ("type", ColorType::Type, TextAttribute::empty()),
+        ("type.interface", ColorType::Interface, TextAttribute::empty()),
+        ("interface", ColorType::Interface, TextAttribute::empty()),
+        ("constructor", ColorType::Type, TextAttribute::empty()),
+        ("comment", ColorType::Comment, TextAttribute::Italic),
+        ("string", ColorType::String, TextAttribute::empty()),
+        ("string.escape", ColorType::Accent, TextAttribute::empty()),
+        ("escape", ColorType::Accent, TextAttribute::empty()),
+        ("constant", ColorType::Literal, TextAttribute::empty()),
+        ("number", ColorType::Literal, TextAttribute::empty()),
+        ("boolean", ColorType::Literal, TextAttribute::empty()),
+        ("character", ColorType::Literal, TextAttribute::empty()),
+        ("float", ColorType::Literal, TextAttribute::empty()),
+        ("literal", ColorType::Literal, TextAttribute::empty()),
+        ("operator", ColorType::Operator, TextAttribute::empty()),
+        ("label", ColorType::Accent, TextAttribute::empty()),
+        ("tag", ColorType::Tags, TextAttribute::empty()),
+    ];
+    defaults.into_iter()
+        .map(|(name, color, attribute)| (name.to_string(), capture_highlight(color, attribute)))
+        .collect()
+}
+
+/// Finds the highlight for a capture name.
+///
+/// Names are tried from most to least specific,
+/// so `function.method.builtin` falls back to `function.method` and then `function`.
+fn lookup_capture(name: &str) -> Option<Highlight> {
+    let highlights = CAPTURE_HIGHLIGHTS.read().expect("capture highlights lock poisoned");
+    let mut name = name;
+    loop {
+        if let Some(highlight) = highlights.get(name) {
+            return Some(highlight.clone());
+        }
+        let (parent, _) = name.rsplit_once('.')?;
+        name = parent;
+    }
+}
+
+/// A tree-sitter grammar loaded from a shared library along with its highlight query.
+pub struct Grammar {
+    name: String,
+    language: Language,
+    highlights: Query,
+    /// This must come last so that the library gets unloaded after the language and query are dropped.
+    _library: Library,
+}
+
+impl Grammar {
+    /// Loads a grammar from a shared library.
+    ///
+    /// The library must export a function named `tree_sitter_<name>` with dashes replaced by underscores,
+    /// which is what the tree-sitter cli generates.
+    pub fn load<P: AsRef<Path>>(name: &str, library_path: P, highlights: &str) -> Result<Self, Box<dyn Error>> {
+        let symbol = format!("tree_sitter_{}", name.replace('-', "_"));
+        // Safety: tree-sitter grammars have no initialization routines,
+        // and the symbol is the language function that the tree-sitter cli generates.
+        let (library, language) = unsafe {
+            let library = Library::new(library_path.as_ref())?;
+            let language_fn = *library.get::<unsafe extern "C" fn() -> *const ()>(symbol.as_bytes())?;
+            let language = Language::new(LanguageFn::from_raw(language_fn));
+            (library, language)
+        };
+        let highlights = Query::new(&language, highlights)?;
+        Ok(Self {
+            name: name.to_string(),
+            language,
+            highlights,
+            _library: library,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+pub fn register_grammar(grammar: Grammar) {
+    let mut grammars = GRAMMARS.write().expect("grammar lock poisoned");
+    grammars.insert(grammar.name.clone(), Arc::new(grammar));
+}
+
+pub fn get_grammar(name: &str) -> Option<Arc<Grammar>> {
+    let grammars = GRAMMARS.read().expect("grammar lock poisoned");
+    grammars.get(name).cloned()
+}
+
+fn point_of_byte(rope: &Rope, byte_offset: usize) -> Point {
+    let byte_offset = byte_offset.min(rope.byte_len());
+    let row = rope.line_of_byte(byte_offset);
+    Point {
+        row,
+        column: byte_offset - rope.byte_of_line(row),
+    }
+}
+
+/// Moves a point past some text.
+fn advance_point(mut point: Point, text: &str) -> Point {
+    match text.rfind('\n') {
+        Some(last_newline) => {
+            point.row += text.matches('\n').count();
+            point.column = text.len() - last_newline - 1;
+        }
+        None => {
+            point.column += text.len();
+        }
+    }
+    point
+}
+
+/// Maps an offset from before an edit to where it is after the edit.
+fn shift_offset(offset: usize, start: usize, old_end: usize, new_end: usize) -> usize {
+    if offset >= old_end {
+        offset - old_end + new_end
+    } else {
+        offset.min(start)
+    }
+}
+
+/// Grows a range so that it covers whole lines.
+fn expand_to_lines(rope: &Rope, range: Range<usize>) -> Range<usize> {
+    let len = rope.byte_len();
+    let start = range.start.min(len);
+    let end = range.end.min(len);
+    let start = rope.byte_of_line(rope.line_of_byte(start));
+    let end_line = rope.line_of_byte(end) + 1;
+    let end = if end_line < rope.line_len() {
+        rope.byte_of_line(end_line)
+    } else {
+        len
+    };
+    start..end
+}
+
+/// How many bytes at the start of a line get highlighted.
+///
+/// Highlighting a huge line costs as much as the whole line no matter how little of it is drawn.
+const LINE_HIGHLIGHT_LIMIT: usize = 4096;
+
+/// Splits a range of whole lines into the parts of each line that get highlighted.
+fn cap_line_lengths(rope: &Rope, range: Range<usize>) -> Vec<Range<usize>> {
+    let mut parts: Vec<Range<usize>> = Vec::new();
+    let mut line_start = range.start;
+    while line_start < range.end {
+        let line = rope.line_of_byte(line_start);
+        let line_end = if line + 1 < rope.line_len() {
+            rope.byte_of_line(line + 1)
+        } else {
+            rope.byte_len()
+        };
+        let line_end = line_end.min(range.end).max(line_start + 1);
+        let end = line_end.min(line_start + LINE_HIGHLIGHT_LIMIT);
+        match parts.last_mut() {
+            Some(last) if last.end == line_start => last.end = end,
+            _ => parts.push(line_start..end),
+        }
+        line_start = line_end;
+    }
+    parts
+}
+
+/// The parse tree of a buffer.
+///
+/// Edits are fed in as they happen and the reparse is deferred until the buffer gets drawn.
+pub struct SyntaxTree {
+    parser: Parser,
+    tree: Option<Tree>,
+    /// Ranges of the current text that were edited since the last reparse.
+    edited: Vec<Range<usize>>,
+    /// This must come last so that the grammar outlives the parser and tree.
+    grammar: Arc<Grammar>,
+}
+
+impl SyntaxTree {
+    pub fn new(grammar: Arc<Grammar>) -> Result<Self, Box<dyn Error>> {
+        let mut parser = Parser::new();
+        parser.set_language(&grammar.language)?;
+        Ok(Self {
+            parser,
+            tree: None,
+            edited: Vec::new(),
+            grammar,
+        })
+    }
+
+    pub fn grammar_name(&self) -> &str {
+        self.grammar.name()
+    }
+
+    /// Records an edit in the tree.
+    ///
+    /// This must be called before the edit gets applied to the rope
+    /// since the positions of the removed text come from the old text.
+    pub fn edit(&mut self, rope: &Rope, operation: &EditOperation) {
+        match &operation.value {
+            EditValue::Insert { text } => self.edit_range(rope, operation.byte_offset, 0, text),
+            EditValue::Delete { count } => self.edit_range(rope, operation.byte_offset, *count, ""),
+            EditValue::Replace { count, text } => self.edit_range(rope, operation.byte_offset, *count, text),
+            EditValue::Bulk(_) => {
+                // The rope changes between each operation so there is no way to describe these from here.
+                // Reparsing from scratch is the safe option.
+                self.tree = None;
+            }
+        }
+    }
+
+    fn edit_range(&mut self, rope: &Rope, start_byte: usize, removed: usize, inserted: &str) {
+        let old_end_byte = start_byte + removed;
+        let new_end_byte = start_byte + inserted.len();
+        let start_position = point_of_byte(rope, start_byte);
+        let edit = InputEdit {
+            start_byte,
+            old_end_byte,
+            new_end_byte,
+            start_position,
+            old_end_position: point_of_byte(rope, old_end_byte),
+            new_end_position: advance_point(start_position, inserted),
+        };
+        if let Some(tree) = &mut self.tree {
+            tree.edit(&edit);
+        }
+        for range in self.edited.iter_mut() {
+            range.start = shift_offset(range.start, start_byte, old_end_byte, new_end_byte);
+            range.end = shift_offset(range.end, start_byte, old_end_byte, new_end_byte);
+        }
+        self.edited.push(start_byte..new_end_byte);
+    }
+
+    /// Reparses the rope and recomputes the highlights of the parts that changed.
+    pub fn update(&mut self, rope: &Rope, highlights: &mut HighlightStore<Highlight>) {
+        if self.tree.is_some() && self.edited.is_empty() {
+            return;
+        }
+
+        let mut chunks = Vec::new();
+        let mut offset = 0;
+        for chunk in rope.chunks() {
+            chunks.push((offset, chunk));
+            offset += chunk.len();
+        }
+        let mut read = |byte: usize, _: Point| -> &[u8] {
+            let index = chunks.partition_point(|(start, chunk)| start + chunk.len() <= byte);
+            match chunks.get(index) {
+                Some((start, chunk)) => &chunk.as_bytes()[byte - start..],
+                None => &[],
+            }
+        };
+
+        let old_tree = self.tree.take();
+        let Some(new_tree) = self.parser.parse_with_options(&mut read, old_tree.as_ref(), None) else {
+            return;
+        };
+
+        let mut ranges = std::mem::take(&mut self.edited);
+        match &old_tree {
+            Some(old_tree) => ranges.extend(old_tree.changed_ranges(&new_tree)
+                .map(|range| range.start_byte..range.end_byte)),
+            None => ranges.push(0..rope.byte_len()),
+        }
+        let mut ranges = ranges.into_iter()
+            .map(|range| expand_to_lines(rope, range))
+            .collect::<Vec<_>>();
+        ranges.sort_by_key(|range| range.start);
+
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+
+        let capture_highlights = self.grammar.highlights.capture_names()
+            .iter()
+            .map(|name| lookup_capture(name))
+            .collect::<Vec<_>>();
+        for range in merged {
+            highlights.remove(range.clone());
+            for part in cap_line_lengths(rope, range) {
+                self.highlight_range(rope, &new_tree, part, &capture_highlights, highlights);
+            }
+        }
+        self.tree = Some(new_tree);
+    }
+
+    fn highlight_range(
+        &self,
+        rope: &Rope,
+        tree: &Tree,
+        range: Range<usize>,
+        capture_highlights: &[Option<Highlight>],
+        highlights: &mut HighlightStore<Highlight>,
+    ) {
+        let mut cursor = QueryCursor::new();
+        cursor.set_byte_range(range.clone());
+        let text = |node: Node| std::iter::once(rope.byte_slice(node.byte_range()).to_string());
+        let mut captures = cursor.captures(&self.grammar.highlights, tree.root_node(), text);
+
+        let mut last_node = None;
+        while let Some((query_match, index)) = captures.next() {
+            let capture = query_match.captures[*index];
+            let node_range = capture.node.byte_range();
+            // When several patterns capture the same node, the first one in the query wins.
+            if last_node.as_ref() == Some(&node_range) {
+                continue;
+            }
+            let Some(highlight) = &capture_highlights[capture.index as usize] else {
+                continue;
+            };
+            let start = node_range.start.max(range.start);
+            let end = node_range.end.min(range.end);
+            highlights.insert(start..end, highlight.clone());
+            last_node = Some(node_range);
+        }
+    }
+}
+
+#[bridge(name = "syntax-grammar-load", lib = "(koru-syntax)")]
+pub async fn syntax_grammar_load(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let Some((name, rest)) = args.split_first() else {
+        return Err(Exception::wrong_num_of_args(3, args.len()));
+    };
+    let Some((library_path, rest)) = rest.split_first() else {
+        return Err(Exception::wrong_num_of_args(3, args.len()));
+    };
+    let Some((query_path, _)) = rest.split_first() else {
+        return Err(Exception::wrong_num_of_args(3, args.len()));
+    };
+    let name: String = name.clone().try_into()?;
+    let library_path: String = library_path.clone().try_into()?;
+    let query_path: String = query_path.clone().try_into()?;
+
+    let highlights = tokio::fs::read_to_string(&query_path).await
+        .map_err(|err| Exception::error(format!("Unable to read {query_path}: {err}")))?;
+    let grammar = Grammar::load(&name, &library_path, &highlights)
+        .map_err(|err| Exception::error(format!("Unable to load grammar {name}: {err}")))?;
+    register_grammar(grammar);
+    Ok(Vec::new())
+}
+
+#[bridge(name = "syntax-grammar-loaded?", lib = "(koru-syntax)")]
+pub async fn syntax_grammar_loaded(name: &Value) -> Result<Vec<Value>, Exception> {
+    let name: String = name.clone().try_into()?;
+    Ok(vec![Value::from(get_grammar(&name).is_some())])
+}
+
+#[bridge(name = "syntax-file-type-set!", lib = "(koru-syntax)")]
+pub async fn syntax_file_type_set(extension: &Value, grammar: &Value) -> Result<Vec<Value>, Exception> {
+    let extension: String = extension.clone().try_into()?;
+    let grammar: String = grammar.clone().try_into()?;
+    FILE_TYPES.write().expect("file type lock poisoned").insert(extension, grammar);
+    Ok(Vec::new())
+}
+
+#[bridge(name = "syntax-file-type", lib = "(koru-syntax)")]
+pub async fn syntax_file_type(extension: &Value) -> Result<Vec<Value>, Exception> {
+    let extension: String = extension.clone().try_into()?;
+    let grammar = FILE_TYPES.read().expect("file type lock poisoned").get(&extension).cloned();
+    match grammar {
+        Some(grammar) => Ok(vec![Value::from(grammar)]),
+        None => Ok(vec![Value::from(false)]),
+    }
+}
+
+#[bridge(name = "syntax-enable", lib = "(koru-syntax)")]
+pub async fn syntax_enable(buffer_name: &Value, grammar: &Value) -> Result<Vec<Value>, Exception> {
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let grammar_name: String = grammar.clone().try_into()?;
+    let Some(grammar) = get_grammar(&grammar_name) else {
+        return Err(Exception::error(format!("Grammar not loaded: {grammar_name}")));
+    };
+    let syntax = SyntaxTree::new(grammar)
+        .map_err(|err| Exception::error(format!("Unable to use grammar {grammar_name}: {err}")))?;
+
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    handle.set_syntax(Some(syntax)).await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "syntax-disable", lib = "(koru-syntax)")]
+pub async fn syntax_disable(buffer_name: &Value) -> Result<Vec<Value>, Exception> {
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    handle.set_syntax(None).await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "syntax-buffer-grammar", lib = "(koru-syntax)")]
+pub async fn syntax_buffer_grammar(buffer_name: &Value) -> Result<Vec<Value>, Exception> {
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    match handle.syntax_grammar().await {
+        Some(grammar) => Ok(vec![Value::from(grammar)]),
+        None => Ok(vec![Value::from(false)]),
+    }
+}
+
+#[bridge(name = "syntax-capture-set!", lib = "(koru-syntax)")]
+pub async fn syntax_capture_set(capture: &Value, highlight: &Value) -> Result<Vec<Value>, Exception> {
+    let capture: String = capture.clone().try_into()?;
+    let highlight: Gc<Highlight> = highlight.clone().try_to_rust_type()?;
+    CAPTURE_HIGHLIGHTS.write()
+        .expect("capture highlights lock poisoned")
+        .insert(capture, (*highlight).clone());
+    Ok(Vec::new())
+}
@@ -1,51 +1,121 @@
 use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::io::{ErrorKind, SeekFrom};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use crop::{Rope, RopeBuilder};
-use intervalmap::IntervalMap;
 use scheme_rs::exceptions::Exception;
 use tokio::io::{AsyncSeekExt, AsyncWriteExt};
 use unicode_segmentation::UnicodeSegmentation;
 use crate::kernel::buffer::cursor::{Cursor, CursorDirection};
-use crate::kernel::buffer::{EditOperation, EditValue, UndoTree};
-use crate::styled_text::{Highlight, StyledFile, StyledText, TextChunk};
+use crate::kernel::buffer::{EditOperation, EditValue, Indent, SyntaxTree, UndoTree, Wrap};
+use crate::kernel::buffer::width::{column_at_display, display_column};
+use crate::kernel::buffer::wrap::{column_window, row_of_column, row_starts, DEFAULT_VIEWPORT_COLUMNS};
+use crate::kernel::buffer::diagnostics::{byte_of_grid, grid_of_byte, Diagnostic, Diagnostics, Severity};
+use crate::kernel::buffer::highlight_store::HighlightStore;
+use crate::kernel::buffer::gutter::{fit_columns, LineMarks, LineNumbers, MIN_NUMBER_COLUMNS, SIGN_COLUMNS};
+use crate::kernel::lsp::{DocumentSync, LanguageClient};
+use crate::styled_text::{grapheme_width, ColorType, Highlight, StyledFile, StyledText, TextAttribute, TextChunk, DEFAULT_TAB_WIDTH};
+
+/// The layer that `buffer-place-highlight` uses when no layer is given.
+pub const DEFAULT_HIGHLIGHT_LAYER: &str = "default";
+/// The layer that holds the highlights from the syntax tree.
+pub const SYNTAX_HIGHLIGHT_LAYER: &str = "syntax";
+/// The layer that underlines the text that diagnostics are about.
+pub const DIAGNOSTICS_HIGHLIGHT_LAYER: &str = "diagnostics";
+const DEFAULT_LAYER_PRIORITY: isize = 100;
+const DIAGNOSTICS_LAYER_PRIORITY: isize = 50;
+const SYNTAX_LAYER_PRIORITY: isize = 0;
+
+struct HighlightLayer {
+    name: String,
+    priority: isize,
+    /// Maps bytes to a particular Highlight
+    highlights: HighlightStore<Highlight>,
+}
 
+/// Highlights split up into named layers.
+///
+/// When layers overlap, the colors come from the layer with the highest priority that sets them
+/// and the text attributes of all the layers get combined.
 struct HighlightManager {
-    /// Maps bytes to a particular Highlight
-    highlights: IntervalMap<usize, Highlight>,
+    /// Sorted from highest to lowest priority.
+    layers: Vec<HighlightLayer>,
 }
 
 impl HighlightManager {
     fn new() -> Self {
-        let highlights = IntervalMap::new();
-        Self {
+        let mut manager = Self {
+            layers: Vec::new(),
+        };
+        manager.set_layer_priority(SYNTAX_HIGHLIGHT_LAYER, SYNTAX_LAYER_PRIORITY);
+        manager.set_layer_priority(DIAGNOSTICS_HIGHLIGHT_LAYER, DIAGNOSTICS_LAYER_PRIORITY);
+        manager.set_layer_priority(DEFAULT_HIGHLIGHT_LAYER, DEFAULT_LAYER_PRIORITY);
+        manager
+    }
+
+    /// Creates a layer or changes its priority.
+    ///
+    /// Layers with the same priority are ordered by when they were created, with older layers on top.
+    fn set_layer_priority(&mut self, name: &str, priority: isize) {
+        let highlights = match self.layers.iter().position(|layer| layer.name == name) {
+            Some(index) => self.layers.remove(index).highlights,
+            None => HighlightStore::new(),
+        };
+        let index = self.layers.partition_point(|layer| layer.priority >= priority);
+        self.layers.insert(index, HighlightLayer {
+            name: name.to_string(),
+            priority,
             highlights,
+        });
+    }
+
+    /// Gets a layer, creating it with the default priority if it doesn't exist yet.
+    fn layer_mut(&mut self, name: &str) -> &mut HighlightStore<Highlight> {
+        let index = match self.layers.iter().position(|layer| layer.name == name) {
+            Some(index) => index,
+            None => {
+                self.set_layer_priority(name, DEFAULT_LAYER_PRIORITY);
+                self.layers.iter().position(|layer| layer.name == name)
+                    .expect("layer was just created")
+            }
+        };
+        &mut self.layers[index].highlights
+    }
+
+    fn remove_layer(&mut self, name: &str) {
+        self.layers.retain(|layer| layer.name != name);
+    }
+
+    fn layer_names(&self) -> Vec<(String, isize)> {
+        self.layers.iter()
+            .map(|layer| (layer.name.clone(), layer.priority))
+            .collect()
+    }
+
+    fn insert_highlight(&mut self, layer: &str, range: impl std::ops::RangeBounds<usize>, highlight: Highlight) {
+        self.layer_mut(layer).insert(range, highlight);
+    }
+
+    fn clear_layer(&mut self, name: &str) {
+        if let Some(layer) = self.layers.iter_mut().find(|layer| layer.name == name) {
+            layer.highlights.clear();
         }
     }
 
-    fn insert_highlight(&mut self, range: impl std::ops::RangeBounds<usize>, highlight: Highlight) {
-        self.highlights.insert(range, highlight);
+    /// Gets the highlight at a byte with all of the layers merged together.
+    fn get(&self, byte: usize) -> Option<Highlight> {
+        let mut highlights = self.layers.iter()
+            .filter_map(|layer| layer.highlights.get(byte));
+        let first = highlights.next()?.clone();
+        Some(highlights.fold(first, |above, below| above.over(below)))
     }
 
-    /// Adds and subtracts and offset from the highlights
+    /// Shifts and clips the highlights for `subtract` bytes at `byte_start` being replaced by `add` bytes.
     fn add_remove_offset(&mut self, byte_start: usize, add: usize, subtract: usize) {
-        let mut old_highlights = IntervalMap::new();
-        std::mem::swap(&mut old_highlights, &mut self.highlights);
-
-        for (mut interval, highlight) in old_highlights {
-            if interval.contains(&byte_start) {
-                interval.end += add;
-                interval.end -= subtract;
-                self.highlights.insert(interval, highlight);
-            } else if interval.start < byte_start {
-                self.highlights.insert(interval, highlight);
-            } else if interval.start > byte_start {
-                interval.start += add;
-                interval.start -= subtract;
-                interval.end += add;
-                interval.end -= subtract;
-                self.highlights.insert(interval, highlight);
-            }
+        for layer in self.layers.iter_mut() {
+            layer.highlights.edit(byte_start, subtract, add);
         }
     }
 }
@@ -56,6 +126,19 @@ pub struct TextBuffer {
     path: Option<PathBuf>,
     undo_tree: UndoTree,
     highlights: HighlightManager,
+    syntax: Option<SyntaxTree>,
+    lsp: Option<DocumentSync>,
+    diagnostics: Diagnostics,
+    indent: Indent,
+    wrap: Wrap,
+    /// The width of the text area of the pane that the buffer was last drawn in, which is what it wraps to at the viewport.
+    viewport_columns: usize,
+    /// How many columns apart tab stops are
+    tab_width: usize,
+    /// Whether the text changed since the buffer was opened or last saved
+    modified: bool,
+    /// The signs and fold markers drawn in the gutter
+    marks: LineMarks,
 }
 
 impl TextBuffer {
@@ -63,13 +146,24 @@ impl TextBuffer {
         let text = buffer.into();
         let mut builder = RopeBuilder::new();
         builder.append(text);
+        let buffer = builder.build();
+        let indent = Indent::detect(&buffer).unwrap_or_default();
 
         TextBuffer {
-            buffer: builder.build(),
+            buffer,
             name: name.into(),
             path: None,
             undo_tree: UndoTree::new(),
             highlights: HighlightManager::new(),
+            syntax: None,
+            lsp: None,
+            diagnostics: Diagnostics::new(),
+            indent,
+            wrap: Wrap::default(),
+            viewport_columns: DEFAULT_VIEWPORT_COLUMNS,
+            tab_width: DEFAULT_TAB_WIDTH,
+            modified: false,
+            marks: LineMarks::new(),
         }
     }
 
@@ -80,6 +174,15 @@ impl TextBuffer {
             path: None,
             undo_tree: UndoTree::new(),
             highlights: HighlightManager::new(),
+            syntax: None,
+            lsp: None,
+            diagnostics: Diagnostics::new(),
+            indent: Indent::default(),
+            wrap: Wrap::default(),
+            viewport_columns: DEFAULT_VIEWPORT_COLUMNS,
+            tab_width: DEFAULT_TAB_WIDTH,
+            modified: false,
+            marks: LineMarks::new(),
         }
     }
 
@@ -99,8 +202,255 @@ impl TextBuffer {
         self.buffer.clone()
     }
 
+    pub fn indent(&self) -> Indent {
+        self.indent
+    }
+
+    pub fn set_indent(&mut self, indent: Indent) {
+        self.indent = indent;
+    }
+
+    pub fn wrap(&self) -> Wrap {
+        self.wrap
+    }
+
+    pub fn set_wrap(&mut self, wrap: Wrap) {
+        self.wrap = wrap;
+    }
+
+    /// Records the width of the text area of the pane that the buffer is being drawn in.
+    ///
+    /// A buffer shown in more than one pane is laid out for each of them in turn, so this is set before each one.
+    pub fn set_viewport_columns(&mut self, columns: usize) {
+        self.viewport_columns = columns;
+    }
+
+    pub fn modified(&self) -> bool {
+        self.modified
+    }
+
+    pub fn tab_width(&self) -> usize {
+        self.tab_width
+    }
+
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.tab_width = tab_width.max(1);
+    }
+
+    /// Finds how many columns the sign, number, and fold columns of the gutter take up.
+    ///
+    /// The sign column shows the sign set on a line, or the worst diagnostic on it,
+    /// and is left out along with the fold column when the buffer has nothing to show in them.
+    fn gutter_columns(&self, line_numbers: LineNumbers) -> (usize, usize, usize) {
+        let sign_columns = if self.marks.has_signs() || !self.diagnostics.is_empty() { SIGN_COLUMNS } else { 0 };
+        let number_columns = match line_numbers {
+            LineNumbers::Off => 0,
+            _ => self.buffer.line_len().max(1).to_string().len().max(MIN_NUMBER_COLUMNS),
+        };
+        let fold_columns = if self.marks.has_folds() { 1 } else { 0 };
+        (sign_columns, number_columns, fold_columns)
+    }
+
+    /// Gets how many columns wide the gutter is, which is 0 if there is nothing to show in it.
+    pub fn gutter_width(&self, line_numbers: LineNumbers) -> usize {
+        match self.gutter_columns(line_numbers) {
+            (0, 0, 0) => 0,
+            // A space keeps the text from running into the gutter
+            (sign_columns, number_columns, fold_columns) => sign_columns + number_columns + fold_columns + 1,
+        }
+    }
+
+    /// Draws the gutter next to the text, with a row for every row that the text is drawn on.
+    ///
+    /// Lines are numbered against `main_line`, which matters for relative numbers.
+    pub fn draw_gutter(&self, line_numbers: LineNumbers, main_line: usize) -> StyledFile {
+        let mut gutter = StyledFile::new();
+        let width = self.gutter_width(line_numbers);
+        if width == 0 {
+            return gutter;
+        }
+        let (sign_columns, number_columns, fold_columns) = self.gutter_columns(line_numbers);
+        let line_count = self.buffer.line_len().max(1);
+        let severities = if sign_columns > 0 {
+            self.diagnostics.line_severities(&self.buffer, 0..line_count)
+        } else {
+            BTreeMap::new()
+        };
+
+        let row_starts = self.row_starts();
+        let style = |text: String, fg_color: ColorType| StyledText::Style {
+            text: TextChunk::from(text),
+            fg_color,
+            bg_color: ColorType::Base,
+            attribute: TextAttribute::empty(),
+        };
+        for line in 0..line_count {
+            let mut segments = Vec::new();
+            if sign_columns > 0 {
+                let sign = match self.marks.sign(line) {
+                    Some(sign) => Some((sign.text.clone(), sign.color)),
+                    None => severities.get(&line)
+                        .map(|severity| (severity.sign().to_string(), severity.color())),
+                };
+                let (text, color) = sign.unwrap_or_else(|| (String::new(), ColorType::Text));
+                segments.push(style(fit_columns(&text, sign_columns), color));
+            }
+            if let Some(number) = line_numbers.number(line, main_line) {
+                let color = if line == main_line { ColorType::Text } else { ColorType::Overlay1 };
+                segments.push(style(format!("{number: >number_columns$}"), color));
+            }
+            if fold_columns > 0 {
+                let marker = self.marks.fold(line).map(|fold| fold.marker()).unwrap_or(" ");
+                segments.push(style(marker.to_string(), ColorType::Overlay1));
+            }
+            segments.push(style(String::from(" "), ColorType::Text));
+            gutter.push_line(segments);
+
+            // The rows that a wrapped line continues on are left blank
+            let rows = row_starts.as_ref()
+                .and_then(|row_starts| row_starts.get(line))
+                .map(Vec::len)
+                .unwrap_or(1);
+            for _ in 1..rows {
+                gutter.push_line(vec![style(" ".repeat(width), ColorType::Text)]);
+            }
+        }
+        gutter
+    }
+
+    /// Finds the display columns where the rows of every line start, or None if lines aren't wrapped.
+    pub fn row_starts(&self) -> Option<Vec<Vec<usize>>> {
+        let width = self.wrap.width(self.viewport_columns)?;
+        Some(self.buffer.lines()
+            .map(|line| {
+                row_starts(line, width, self.tab_width).into_iter()
+                    .map(|start| display_column(line, start, self.tab_width))
+                    .collect()
+            })
+            .collect())
+    }
+
+    /// Converts a line and column into the row and display column they are drawn at.
+    pub fn visual_position(&self, line: usize, column: usize) -> (usize, usize) {
+        let Some(width) = self.wrap.width(self.viewport_columns) else {
+            if line >= self.buffer.line_len() {
+                return (line, column);
+            }
+            return (line, display_column(self.buffer.line(line), column, self.tab_width));
+        };
+        let mut row = 0;
+        for line in self.buffer.lines().take(line) {
+            row += row_starts(line, width, self.tab_width).len();
+        }
+        if line >= self.buffer.line_len() {
+            return (row, column);
+        }
+        let line_slice = self.buffer.line(line);
+        let starts = row_starts(line_slice, width, self.tab_width);
+        let line_row = row_of_column(&starts, column);
+        let display = display_column(line_slice, column, self.tab_width);
+        (row + line_row, display - display_column(line_slice, starts[line_row], self.tab_width))
+    }
+
+    /// Converts a row and display column that text is drawn at back into a line and column.
+    ///
+    /// This undoes `visual_position`, rows past the end of the buffer give the last line
+    /// and display columns past the end of a row give the end of that row.
+    pub fn logical_position(&self, row: usize, display: usize) -> (usize, usize) {
+        if self.buffer.line_len() == 0 {
+            return (0, 0);
+        }
+        let last_line = self.buffer.line_len() - 1;
+        let Some(width) = self.wrap.width(self.viewport_columns) else {
+            let line = row.min(last_line);
+            let column = column_at_display(self.buffer.line(line), display, self.tab_width);
+            return (line, column.min(self.buffer.line_length(line)));
+        };
+        let mut rows_before = 0;
+        for (line, line_slice) in self.buffer.lines().enumerate() {
+            let starts = row_starts(line_slice, width, self.tab_width);
+            if row >= rows_before + starts.len() && line < last_line {
+                rows_before += starts.len();
+                continue;
+            }
+            let line_row = (row - rows_before).min(starts.len() - 1);
+            // A column at the start of the next row would be drawn there, so rows other than the last end before it
+            let row_end = match starts.get(line_row + 1) {
+                Some(next_start) => next_start - 1,
+                None => self.buffer.line_length(line),
+            };
+            let row_start = display_column(line_slice, starts[line_row], self.tab_width);
+            let column = column_at_display(line_slice, row_start + display, self.tab_width);
+            return (line, column.min(row_end));
+        }
+        (last_line, 0)
+    }
+
+    /// Moves a cursor up or down by a row of wrapped text.
+    ///
+    /// The cursor tries to stay in the same display column of the row it moves to, even after passing through shorter rows.
+    /// This is the same as moving by lines when wrapping is off.
+    pub fn move_cursor_visual(&self, cursor: Cursor, up: bool) -> Result<Cursor, Exception> {
+        let Some(width) = self.wrap.width(self.viewport_columns) else {
+            let direction = if up { CursorDirection::Up } else { CursorDirection::Down };
+            return self.move_cursor(cursor, direction, |_| Ok(false));
+        };
+        if self.buffer.line_len() == 0 {
+            return Ok(cursor);
+        }
+        let line_slice = self.buffer.line(cursor.line());
+        let starts = row_starts(line_slice, width, self.tab_width);
+        let row = row_of_column(&starts, cursor.column());
+        let goal = cursor.goal_column().unwrap_or_else(|| {
+            display_column(line_slice, cursor.column(), self.tab_width)
+                - display_column(line_slice, starts[row], self.tab_width)
+        });
+
+        let (line, starts, row) = if up {
+            if row > 0 {
+                (cursor.line(), starts, row - 1)
+            } else if cursor.line() > 0 {
+                let starts = row_starts(self.buffer.line(cursor.line() - 1), width, self.tab_width);
+                let row = starts.len() - 1;
+                (cursor.line() - 1, starts, row)
+            } else {
+                return Ok(cursor);
+            }
+        } else if row + 1 < starts.len() {
+            (cursor.line(), starts, row + 1)
+        } else if self.buffer.is_there_next_line(cursor.line()) {
+            (cursor.line() + 1, row_starts(self.buffer.line(cursor.line() + 1), width, self.tab_width), 0)
+        } else {
+            return Ok(cursor);
+        };
+
+        // A column at the start of the next row would be drawn there, so rows other than the last end before it
+        let row_end = match starts.get(row + 1) {
+            Some(next_start) => next_start - 1,
+            None => self.buffer.line_length(line),
+        };
+        let line_slice = self.buffer.line(line);
+        let row_start = display_column(line_slice, starts[row], self.tab_width);
+        let column = column_at_display(line_slice, row_start + goal, self.tab_width);
+        let mut cursor = cursor;
+        cursor.set_visual_position(line, column.min(row_end), goal);
+        Ok(cursor)
+    }
+
+    /// Gets the whitespace at the start of a line.
+    pub fn line_indentation(&self, line: usize) -> String {
+        if line >= self.buffer.line_len() {
+            return String::new();
+        }
+        self.buffer.line(line)
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .collect()
+    }
+
     pub fn insert_highlight(
         &mut self,
+        layer: &str,
         highlight: Highlight,
         (start_row, start_col): (usize, usize),
         (end_row, end_col) : (usize, usize)
@@ -119,11 +469,254 @@ impl TextBuffer {
             end_byte += grapheme.len();
         }
 
-        self.highlights.insert_highlight(start_byte..=end_byte, highlight);
+        self.highlights.insert_highlight(layer, start_byte..=end_byte, highlight);
+    }
+
+    pub fn clear_highlights(&mut self, layer: &str) {
+        self.highlights.clear_layer(layer);
+    }
+
+    pub fn set_highlight_layer_priority(&mut self, layer: &str, priority: isize) {
+        self.highlights.set_layer_priority(layer, priority);
+    }
+
+    pub fn remove_highlight_layer(&mut self, layer: &str) {
+        self.highlights.remove_layer(layer);
+    }
+
+    /// Gets the names and priorities of the highlight layers from highest to lowest priority.
+    pub fn highlight_layers(&self) -> Vec<(String, isize)> {
+        self.highlights.layer_names()
+    }
+
+    /// Replaces the syntax tree of the buffer, throwing out any syntax highlights.
+    pub fn set_syntax(&mut self, syntax: Option<SyntaxTree>) {
+        self.highlights.clear_layer(SYNTAX_HIGHLIGHT_LAYER);
+        self.syntax = syntax;
+    }
+
+    pub fn syntax_grammar(&self) -> Option<String> {
+        self.syntax.as_ref().map(|syntax| syntax.grammar_name().to_string())
+    }
+
+    /// Reparses the buffer if it has been edited since the last time the syntax highlights were updated.
+    pub fn update_syntax(&mut self) {
+        if let Some(syntax) = &mut self.syntax {
+            syntax.update(&self.buffer, self.highlights.layer_mut(SYNTAX_HIGHLIGHT_LAYER));
+        }
+    }
+
+    /// Replaces the diagnostics that an owner set on the buffer before.
+    ///
+    /// The diagnostics are built from the text of the buffer so that their positions can be turned into bytes.
+    pub fn set_diagnostics(&mut self, owner: &str, diagnostics: impl FnOnce(&Rope) -> Vec<Diagnostic>) {
+        let diagnostics = diagnostics(&self.buffer);
+        self.diagnostics.set(owner, diagnostics);
+        self.update_diagnostic_highlights();
+    }
+
+    /// Removes the diagnostics of an owner, or all of the diagnostics if there is no owner.
+    pub fn clear_diagnostics(&mut self, owner: Option<&str>) {
+        self.diagnostics.clear(owner);
+        self.update_diagnostic_highlights();
+    }
+
+    /// Gets all of the diagnostics ordered by where they start.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.diagnostics.sorted().into_iter().cloned().collect()
+    }
+
+    /// Gets the diagnostics on a line, worst first.
+    pub fn line_diagnostics(&self, line: usize) -> Vec<Diagnostic> {
+        if line >= self.buffer.line_len() {
+            return Vec::new();
+        }
+        let start = self.buffer.byte_of_line(line);
+        let end = start + self.buffer.line(line).byte_len() + 1;
+        self.diagnostics.in_range(start..end).into_iter().cloned().collect()
+    }
+
+    /// Redraws the underlines of the diagnostics.
+    ///
+    /// The highlight layer shifts with edits on its own, so this is only needed when the diagnostics are replaced.
+    fn update_diagnostic_highlights(&mut self) {
+        self.highlights.clear_layer(DIAGNOSTICS_HIGHLIGHT_LAYER);
+        let mut diagnostics = self.diagnostics.sorted();
+        // Worse diagnostics go in last so that they win where diagnostics overlap
+        diagnostics.sort_by_key(|diagnostic| std::cmp::Reverse(diagnostic.severity));
+        for diagnostic in diagnostics {
+            let mut range = diagnostic.range.clone();
+            if range.is_empty() && range.start < self.buffer.byte_len() {
+                // Empty diagnostics underline the grapheme they sit on so they can still be seen
+                let grapheme = self.buffer.byte_slice(range.start..).graphemes().next();
+                range.end += grapheme.map(|grapheme| grapheme.len()).unwrap_or(0);
+            }
+            self.highlights.insert_highlight(DIAGNOSTICS_HIGHLIGHT_LAYER, range, diagnostic.severity.highlight());
+        }
+    }
+
+    pub fn marks_mut(&mut self) -> &mut LineMarks {
+        &mut self.marks
+    }
+
+    /// Gets the worst severity of the diagnostics on a line.
+    pub fn line_severity(&self, line: usize) -> Option<Severity> {
+        self.line_diagnostics(line).first().map(|diagnostic| diagnostic.severity)
+    }
+
+    /// Starts keeping a language server in sync with the buffer.
+    ///
+    /// This replaces any language server that the buffer was attached to before.
+    pub fn attach_lsp(&mut self, client: Arc<LanguageClient>, path: &Path) {
+        // Close the old document first in case the new client is for the same server
+        self.detach_lsp();
+        self.lsp = Some(DocumentSync::open(client, path, &self.buffer));
+    }
+
+    pub fn detach_lsp(&mut self) {
+        self.lsp = None;
+        self.clear_diagnostics(Some(crate::kernel::lsp::DIAGNOSTICS_OWNER));
+    }
+
+    pub fn lsp_language(&self) -> Option<String> {
+        self.lsp.as_ref().map(|lsp| lsp.client().language().to_string())
+    }
+
+    /// Sends any edits that the language server hasn't seen yet.
+    pub fn flush_lsp(&mut self) {
+        if let Some(lsp) = &mut self.lsp {
+            lsp.flush(&self.buffer);
+        }
+    }
+
+    /// Gets the language server and document uri of the buffer after sending it any pending edits.
+    pub fn lsp_document(&mut self) -> Option<(Arc<LanguageClient>, String)> {
+        self.flush_lsp();
+        self.lsp.as_ref().map(|lsp| (lsp.client().clone(), lsp.uri().to_string()))
     }
 
-    pub fn clear_highlights(&mut self) {
-        self.highlights.highlights = IntervalMap::new();
+    /// Converts a line and grapheme column into a language server position.
+    pub fn lsp_position(&self, line: usize, column: usize) -> serde_json::Value {
+        let byte_offset = self.calculate_byte_offset(line, column);
+        crate::kernel::lsp::position_of_byte(&self.buffer, byte_offset)
+    }
+
+    /// Replaces ranges of bytes as a single undoable edit, moving the cursors and their marks along with the text.
+    ///
+    /// The edits must be sorted from the end of the buffer to the start.
+    /// A cursor inside of a replaced range ends up at the start of it.
+    pub async fn apply_text_edits(&mut self, edits: Vec<(Range<usize>, String)>, mut cursors: Vec<Cursor>) -> Vec<Cursor> {
+        let shift = |offset: usize| edits.iter().fold(offset, |offset, (range, text)| {
+            if offset < range.start {
+                offset
+            } else if offset >= range.end && (offset > range.start || range.is_empty()) {
+                offset - range.len() + text.len()
+            } else {
+                range.start
+            }
+        });
+        let offsets = cursors.iter()
+            .map(|cursor| {
+                let point = shift(byte_of_grid(&self.buffer, cursor.line(), cursor.column()));
+                let mark = cursor.mark.map(|mark| shift(byte_of_grid(&self.buffer, mark.line, mark.column)));
+                (point, mark)
+            })
+            .collect::<Vec<_>>();
+
+        self.replace_ranges(edits).await;
+
+        for (cursor, (point, mark)) in cursors.iter_mut().zip(offsets) {
+            let (line, column) = grid_of_byte(&self.buffer, point);
+            cursor.set_line(line);
+            cursor.set_column(column);
+            if let (Some(cursor_mark), Some(mark)) = (&mut cursor.mark, mark) {
+                let (line, column) = grid_of_byte(&self.buffer, mark);
+                cursor_mark.line = line;
+                cursor_mark.column = column;
+            }
+        }
+        cursors
+    }
+
+    /// Replaces ranges of bytes as a single undoable edit.
+    ///
+    /// The edits must be sorted from the end of the buffer to the start.
+    async fn replace_ranges(&mut self, edits: Vec<(Range<usize>, String)>) {
+        if edits.is_empty() {
+            return;
+        }
+        self.undo_tree.start_transaction().await;
+        for (range, text) in edits {
+            let start = range.start;
+            let old_text = self.buffer.byte_slice(range.clone()).to_string();
+            if !range.is_empty() {
+                self.rope_delete(range);
+            }
+            if !text.is_empty() {
+                self.rope_insert(start, &text);
+            }
+            self.undo_tree.replace(start, old_text, text).await;
+        }
+        self.undo_tree.end_transaction().await;
+    }
+
+    /// Builds the edit that gets handed to the syntax tree and language server before the rope changes.
+    fn pending_edit(&self, byte_offset: usize, value: impl FnOnce() -> EditValue) -> Option<EditOperation> {
+        if self.syntax.is_some() || self.lsp.is_some() {
+            Some(EditOperation::new(byte_offset, value()))
+        } else {
+            None
+        }
+    }
+
+    /// Inserts text into the rope, keeping the highlights, syntax tree and language server in sync.
+    fn rope_insert(&mut self, byte_offset: usize, text: &str) {
+        if let Some(operation) = self.pending_edit(byte_offset, || EditValue::Insert { text: text.to_string() }) {
+            if let Some(syntax) = &mut self.syntax {
+                syntax.edit(&self.buffer, &operation);
+            }
+            if let Some(lsp) = &mut self.lsp {
+                lsp.edit(&self.buffer, &operation);
+            }
+        }
+        self.highlights.add_remove_offset(byte_offset, text.len(), 0);
+        self.diagnostics.edit(byte_offset, 0, text.len());
+        let added = text.bytes().filter(|byte| *byte == b'\n').count();
+        let line = self.buffer.line_of_byte(byte_offset);
+        // Text added at the start of a line pushes the whole line down along with its marks
+        let first_moved = if self.buffer.byte_of_line(line) == byte_offset { line } else { line + 1 };
+        self.marks.edit(first_moved, 0, added);
+        self.buffer.insert(byte_offset, text);
+        self.modified = true;
+    }
+
+    /// Deletes a range from the rope, keeping the highlights, syntax tree and language server in sync.
+    fn rope_delete(&mut self, range: impl std::ops::RangeBounds<usize>) {
+        use std::ops::Bound;
+        let start = match range.start_bound() {
+            Bound::Included(start) => *start,
+            Bound::Excluded(start) => *start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => *end + 1,
+            Bound::Excluded(end) => *end,
+            Bound::Unbounded => self.buffer.byte_len(),
+        };
+        if let Some(operation) = self.pending_edit(start, || EditValue::Delete { count: end - start }) {
+            if let Some(syntax) = &mut self.syntax {
+                syntax.edit(&self.buffer, &operation);
+            }
+            if let Some(lsp) = &mut self.lsp {
+                lsp.edit(&self.buffer, &operation);
+            }
+        }
+        self.highlights.add_remove_offset(start, 0, end - start);
+        self.diagnostics.edit(start, end - start, 0);
+        let removed = self.buffer.byte_slice(start..end).bytes().filter(|byte| *byte == b'\n').count();
+        self.marks.edit(self.buffer.line_of_byte(start) + 1, removed, 0);
+        self.buffer.delete(start..end);
+        self.modified = true;
     }
 
     /// Pred returns false if we should terminate and true if we should loop on a given grapheme.
@@ -168,7 +761,7 @@ impl TextBuffer {
                             }
                             return Ok(cursor);
                         };
-                        cursor.move_up(&self.buffer);
+                        cursor.move_up(&self.buffer, self.tab_width);
                         cursor.set_column(length);
                     } else if !at_line_start {
                         cursor.move_left(self.buffer.line_length(cursor.line()));
@@ -186,7 +779,7 @@ impl TextBuffer {
                 loop {
                     let at_line_end = cursor.at_line_end(&self.buffer);
                     if at_line_end && wrap {
-                        cursor.move_down(&self.buffer);
+                        cursor.move_down(&self.buffer, self.tab_width);
                         let Some((start, _, _)) = self.buffer.previous_line_information(cursor.line()) else {
                             if cursor.is_main() {
                                 return Ok(cursor);
@@ -206,7 +799,7 @@ impl TextBuffer {
             }
             CursorDirection::Up => {
                 loop {
-                    cursor.move_up(&self.buffer);
+                    cursor.move_up(&self.buffer, self.tab_width);
                     char = self.buffer.line(cursor.line()).graphemes().skip(cursor.column().saturating_sub(1)).next().unwrap_or(Cow::Borrowed("\n"));
                     if !pred(&char)? {
                         break;
@@ -216,7 +809,7 @@ impl TextBuffer {
             }
             CursorDirection::Down => {
                 loop {
-                    cursor.move_down(&self.buffer);
+                    cursor.move_down(&self.buffer, self.tab_width);
                     char = self.buffer.line(cursor.line()).graphemes().skip(cursor.column().saturating_sub(1)).next().unwrap_or(Cow::Borrowed("\n"));
                     if !pred(&char)? {
                         break;
@@ -225,32 +818,37 @@ impl TextBuffer {
                 Ok(cursor)
             }
             CursorDirection::LineStart => {
+                let line_length = self.buffer.line_length(cursor.line());
                 while cursor.column() != 0 {
-                    cursor.move_left(self.buffer.line_length(cursor.line()));
+                    cursor.move_left(line_length);
                 }
                 Ok(cursor)
             }
             CursorDirection::LineEnd => {
-                while cursor.column() != self.buffer.line_length(cursor.line()) {
-                    cursor.move_right(self.buffer.line_length(cursor.line()));
+                // Counting the graphemes of a long line is slow, so it is only done once
+                let line_length = self.buffer.line_length(cursor.line());
+                while cursor.column() != line_length {
+                    cursor.move_right(line_length);
                 }
                 Ok(cursor)
             }
             CursorDirection::BufferStart => {
+                let line_length = self.buffer.line_length(cursor.line());
                 while cursor.column() != 0 {
-                    cursor.move_left(self.buffer.line_length(cursor.line()));
+                    cursor.move_left(line_length);
                 }
                 while cursor.line() != 0 {
-                    cursor.move_up(&self.buffer);
+                    cursor.move_up(&self.buffer, self.tab_width);
                 }
                 Ok(cursor)
             }
             CursorDirection::BufferEnd => {
                 while cursor.line() < self.buffer.line_len() - 1 {
-                    cursor.move_down(&self.buffer);
+                    cursor.move_down(&self.buffer, self.tab_width);
                 }
-                while cursor.column() != self.buffer.line_length(cursor.line()) {
-                    cursor.move_right(self.buffer.line_length(cursor.line()));
+                let line_length = self.buffer.line_length(cursor.line());
+                while cursor.column() != line_length {
+                    cursor.move_right(line_length);
                 }
                 Ok(cursor)
             }
@@ -336,7 +934,7 @@ impl TextBuffer {
     pub fn calculate_byte_offset(&self, line: usize, column: usize) -> usize {
         let mut byte_offset = 0;
         for line in 0..line {
-            byte_offset += self.buffer.line_length(line);
+            byte_offset += self.buffer.line(line).byte_len();
         }
         byte_offset += '\n'.len_utf8() * line;
         if self.buffer.line_len() == 0 {
@@ -364,8 +962,7 @@ impl TextBuffer {
                 text_after_newline = 0;
             }
         }
-        self.buffer.insert(byte_offset, &text);
-        self.highlights.add_remove_offset(byte_offset, text.len(), 0);
+        self.rope_insert(byte_offset, text);
 
         let editor_cursor = cursors[cursor_index];
 
@@ -423,10 +1020,9 @@ impl TextBuffer {
         let text = self.buffer.byte_slice(character_offset..byte_offset);
         let text = text.to_string();
         let replacement_cursor = self.move_cursor(cursors[cursor_index], CursorDirection::Left { wrap: true }, |_| Ok(false))?;
-        self.buffer.delete(character_offset..byte_offset);
+        self.rope_delete(character_offset..byte_offset);
 
         let mut new_cursors = self.delete_text(&text, cursor_index, cursors)?;
-        self.highlights.add_remove_offset(character_offset, 0, text.len());
 
         new_cursors[cursor_index] = replacement_cursor;
 
@@ -451,15 +1047,99 @@ impl TextBuffer {
 
         let text = self.buffer.byte_slice(range.clone());
         let text = text.to_string();
-        self.buffer.delete(range);
+        self.rope_delete(range);
 
         let new_cursors = self.delete_text(&text, cursor_index, cursors)?;
-        self.highlights.add_remove_offset(byte_offset, 0, text.len());
 
         self.undo_tree.delete(byte_offset, text).await;
         Ok(new_cursors)
     }
 
+    /// Gets the text within the marked region of the cursor.
+    ///
+    /// This uses the same bounds as `delete_region` so that what gets copied is what would get deleted.
+    /// Box regions are joined with newlines from top to bottom.
+    /// Returns None if the cursor has no mark.
+    pub fn region_text(&self, cursor: Cursor) -> Option<String> {
+        use crate::kernel::buffer::cursor::CursorMark;
+        if !cursor.is_mark_set() {
+            return None;
+        }
+        if self.buffer.byte_len() == 0 {
+            return Some(String::new());
+        }
+
+        let cursor_line = cursor.line();
+        let cursor_col = cursor.column();
+        let mark_line = cursor.mark_line().unwrap();
+        let mark_col = cursor.mark_column().unwrap();
+
+        match cursor.mark_state {
+            CursorMark::Point => {
+                let mark_offset = self.calculate_byte_offset(mark_line, mark_col);
+                let cursor_offset = self.calculate_byte_offset(cursor_line, cursor_col);
+
+                let range = if mark_offset <= cursor_offset {
+                    mark_offset..=cursor_offset
+                } else {
+                    cursor_offset..=mark_offset
+                };
+                Some(self.buffer.byte_slice(range).to_string())
+            }
+            CursorMark::Line => {
+                let (min_line, max_line) = if cursor_line <= mark_line {
+                    (cursor_line, mark_line)
+                } else {
+                    (mark_line, cursor_line)
+                };
+
+                let start_offset = self.calculate_byte_offset(min_line, 0);
+                let end_line_len = self.buffer.line_length(max_line);
+                let end_offset = self.calculate_byte_offset(max_line, end_line_len);
+
+                let range_end = if self.buffer.is_there_next_line(max_line) {
+                    end_offset + '\n'.len_utf8()
+                } else {
+                    end_offset
+                };
+
+                Some(self.buffer.byte_slice(start_offset..range_end).to_string())
+            }
+            CursorMark::Box => {
+                let (min_line, max_line) = if cursor_line <= mark_line {
+                    (cursor_line, mark_line)
+                } else {
+                    (mark_line, cursor_line)
+                };
+
+                let (min_col, max_col) = if cursor_col <= mark_col {
+                    (cursor_col, mark_col)
+                } else {
+                    (mark_col, cursor_col)
+                };
+
+                let mut text = String::new();
+                for line_no in min_line..=max_line {
+                    if line_no != min_line {
+                        text.push('\n');
+                    }
+                    let line_len = self.buffer.line_length(line_no);
+                    let actual_min_col = min_col.min(line_len);
+                    let actual_max_col = (max_col + 1).min(line_len);
+
+                    if actual_min_col < actual_max_col {
+                        let start_offset = self.calculate_byte_offset(line_no, actual_min_col);
+                        let end_offset = self.calculate_byte_offset(line_no, actual_max_col);
+                        text.push_str(&self.buffer.byte_slice(start_offset..end_offset).to_string());
+                    }
+                }
+                Some(text)
+            }
+            CursorMark::File => Some(self.buffer.to_string()),
+            CursorMark::None => None,
+        }
+    }
+
     // Refactored delete_region function for TextBuffer
     // This handles Point, Line, Box, and File mark types
 
@@ -493,10 +1173,9 @@ impl TextBuffer {
 
                 let text = self.buffer.byte_slice(range.clone());
                 let text = text.to_string();
-                self.buffer.delete(range);
+                self.rope_delete(range);
 
                 let new_cursors = self.delete_text(&text, cursor_index, cursors);
-                self.highlights.add_remove_offset(start, 0, text.len());
                 self.undo_tree.delete(start, text).await;
                 new_cursors
             }
@@ -522,10 +1201,9 @@ impl TextBuffer {
 
                 let text = self.buffer.byte_slice(start_offset..range_end);
                 let text = text.to_string();
-                self.buffer.delete(start_offset..range_end);
+                self.rope_delete(start_offset..range_end);
 
                 let new_cursors = self.delete_text(&text, cursor_index, cursors)?;
-                self.highlights.add_remove_offset(start_offset, 0, text.len());
                 self.undo_tree.delete(start_offset, text).await;
                 Ok(new_cursors)
             }
@@ -574,8 +1252,7 @@ impl TextBuffer {
                         }
 
                         // Actually delete from buffer
-                        self.buffer.delete(start_offset..end_offset);
-                        self.highlights.add_remove_offset(start_offset, 0, text_str.len());
+                        self.rope_delete(start_offset..end_offset);
                     }
                 }
 
@@ -589,10 +1266,9 @@ impl TextBuffer {
                 // File selection: delete entire buffer
                 let text = self.buffer.to_string();
                 let len = self.buffer.byte_len();
-                self.buffer.delete(0..len);
+                self.rope_delete(0..len);
 
                 let new_cursors = self.delete_text(&text, cursor_index, cursors)?;
-                self.highlights.add_remove_offset(0, 0, text.len());
                 self.undo_tree.delete(0, text).await;
                 Ok(new_cursors)
             }
@@ -659,11 +1335,10 @@ impl TextBuffer {
             };
             let old_text = self.buffer.byte_slice(range);
             let old_text = old_text.to_string();
-            self.buffer.delete(mark_offset..cursor_offset);
+            self.rope_delete(mark_offset..cursor_offset);
 
             let cursors = self.delete_text(&old_text, cursor_index, cursors)?;
             let cursors = self.insert_text(start, &text, cursor_index, cursors);
-            self.highlights.add_remove_offset(start - 1, text.len(), old_text.len());
 
             self.undo_tree.replace(start - 1, old_text, text).await;
             cursors
@@ -683,13 +1358,83 @@ impl TextBuffer {
 
             let cursors = self.delete_text(&old_text, cursor_index, cursors)?;
             let cursors = self.insert_text(byte_offset, &old_text, cursor_index, cursors);
-            self.highlights.add_remove_offset(byte_offset - 1, text.len(), old_text.len());
 
             self.undo_tree.replace(byte_offset - 1, old_text, text).await;
             cursors
         }
     }
 
+    /// Indents or dedents the lines covered by the cursors by one level as a single undoable edit.
+    ///
+    /// A cursor without a mark covers its own line, and a box mark shifts the text from the left edge of the box.
+    /// Each line is only shifted once no matter how many cursors cover it.
+    /// Blank lines are not indented, and dedenting removes a tab or up to a level's width of spaces.
+    pub async fn shift_lines(&mut self, mut cursors: Vec<Cursor>, dedent: bool) -> Vec<Cursor> {
+        use crate::kernel::buffer::cursor::CursorMark;
+        let line_count = self.buffer.line_len();
+        if line_count == 0 {
+            return cursors;
+        }
+
+        // The column each covered line gets shifted from
+        let mut lines: BTreeMap<usize, usize> = BTreeMap::new();
+        for cursor in &cursors {
+            let (first_line, last_line, column) = match (cursor.mark_state, cursor.mark) {
+                (CursorMark::File, _) => (0, line_count - 1, 0),
+                (CursorMark::Box, Some(mark)) => {
+                    (mark.line.min(cursor.line()), mark.line.max(cursor.line()), mark.column.min(cursor.column()))
+                }
+                (_, Some(mark)) => (mark.line.min(cursor.line()), mark.line.max(cursor.line()), 0),
+                _ => (cursor.line(), cursor.line(), 0),
+            };
+            for line in first_line..=last_line.min(line_count - 1) {
+                let entry = lines.entry(line).or_insert(column);
+                *entry = (*entry).min(column);
+            }
+        }
+
+        let unit = self.indent.unit();
+        let mut edits = Vec::new();
+        // The column each line was shifted from, and how many graphemes were added and removed there
+        let mut shifts: HashMap<usize, (usize, usize, usize)> = HashMap::new();
+        for (&line_no, &column) in lines.iter().rev() {
+            let line = self.buffer.line(line_no);
+            let rest = line.graphemes().skip(column).collect::<Vec<_>>();
+            let start = self.calculate_byte_offset(line_no, column);
+            if dedent {
+                let removed = if rest.first().is_some_and(|grapheme| grapheme == "\t") {
+                    1
+                } else {
+                    rest.iter().take(self.indent.width).take_while(|grapheme| *grapheme == " ").count()
+                };
+                if removed == 0 {
+                    continue;
+                }
+                edits.push((start..(start + removed), String::new()));
+                shifts.insert(line_no, (column, 0, removed));
+            } else {
+                if rest.iter().all(|grapheme| grapheme.trim().is_empty()) {
+                    continue;
+                }
+                edits.push((start..start, unit.clone()));
+                shifts.insert(line_no, (column, unit.len(), 0));
+            }
+        }
+        self.replace_ranges(edits).await;
+
+        let shift_column = |line: usize, column: usize| match shifts.get(&line) {
+            Some(&(from, added, removed)) if column >= from => (column + added).saturating_sub(removed).max(from),
+            _ => column,
+        };
+        for cursor in cursors.iter_mut() {
+            cursor.set_column(shift_column(cursor.line(), cursor.column()));
+            if let Some(mark) = &mut cursor.mark {
+                mark.column = shift_column(mark.line, mark.column);
+            }
+        }
+        cursors
+    }
+
     pub async fn start_transaction(&mut self) {
         self.undo_tree.start_transaction().await;
     }
@@ -703,22 +1448,19 @@ impl TextBuffer {
             EditValue::Insert {
                 text
             } => {
-                self.highlights.add_remove_offset(edit_info.byte_offset, text.len(), 0);
-                self.buffer.insert(edit_info.byte_offset, text);
+                self.rope_insert(edit_info.byte_offset, &text);
             }
             EditValue::Delete {
                 count
             } => {
-                self.highlights.add_remove_offset(edit_info.byte_offset, 0, count);
-                self.buffer.delete(edit_info.byte_offset..(edit_info.byte_offset + count));
+                self.rope_delete(edit_info.byte_offset..(edit_info.byte_offset + count));
             }
             EditValue::Replace {
                 text,
                 count
             } => {
-                self.highlights.add_remove_offset(edit_info.byte_offset, text.len(), count);
-                self.buffer.delete(edit_info.byte_offset..(edit_info.byte_offset + count));
-                self.buffer.insert(edit_info.byte_offset, text);
+                self.rope_delete(edit_info.byte_offset..(edit_info.byte_offset + count));
+                self.rope_insert(edit_info.byte_offset, &text);
             }
             EditValue::Bulk(ops) => {
                 for op in ops {
@@ -773,12 +1515,22 @@ impl TextBuffer {
         file.seek(SeekFrom::Start(0)).await.map_err(|err| Exception::error(err))?;
         file.write_all(string.as_bytes()).await.map_err(|err| Exception::error(err))?;
         file.flush().await.map_err(|err| Exception::error(err))?;
+        self.modified = false;
+        if let Some(lsp) = &mut self.lsp {
+            lsp.save(&self.buffer);
+        }
         Ok(())
     }
 
     pub async fn save_as(&mut self, new_name: &str) -> Result<(), Exception> {
         let path = PathBuf::from(new_name);
-        self.path = Some(path);
+        self.path = Some(path.clone());
+        // The language server knows the document by its path, so it has to be reopened under the new one
+        if let Some(lsp) = self.lsp.take() {
+            let client = lsp.client().clone();
+            drop(lsp);
+            self.lsp = Some(DocumentSync::open(client, &path, &self.buffer));
+        }
         self.save().await?;
         Ok(())
     }
@@ -787,86 +1539,93 @@ impl TextBuffer {
         self.path.as_ref().map(|p| p.to_string_lossy().to_string())
     }
 
-    pub fn draw(&self) -> StyledFile {
-        let mut current_line = Vec::new();
+    fn push_span(&self, line: &mut Vec<StyledText>, style: Option<&Highlight>, start: usize, end: usize) {
+        if start >= end {
+            return;
+        }
+        let text = TextChunk::new(self.buffer.clone(), start, end);
+        match style {
+            Some(style) => line.push(StyledText::Style {
+                text,
+                fg_color: style.fg_color.unwrap_or(ColorType::Text),
+                bg_color: style.bg_color.unwrap_or(ColorType::Base),
+                attribute: style.attribute,
+            }),
+            None => line.push(StyledText::None { text }),
+        }
+    }
+
+    /// Finds the display columns to draw around a position, or None if every column has to be drawn.
+    ///
+    /// Wrapped lines are drawn in full since all of their rows can be seen.
+    pub fn column_window(&self, line: usize, column: usize) -> Option<Range<usize>> {
+        if self.wrap != Wrap::Off {
+            return None;
+        }
+        Some(column_window(self.visual_position(line, column).1, self.viewport_columns))
+    }
+
+    /// Draws the text with its highlights.
+    ///
+    /// With a window, only the graphemes of each line that are drawn in that range of display columns become styled text,
+    /// so huge lines cost about as much to draw as the view is wide.
+    pub fn draw(&self, window: Option<Range<usize>>) -> StyledFile {
         let mut styled_file = StyledFile::new();
-        let mut span_start = 0;
-        let mut i = 0;
-        let mut current_style: Option<Highlight> = None;
-        for ch in self.buffer.graphemes() {
-            i += ch.len();
-            if ch.contains('\n')  {
-                if i > span_start {
-                    if let Some(style) = &current_style {
-                        current_line.push(StyledText::Style {
-                            text: TextChunk::new(self.buffer.clone(), span_start, i),
-                            fg_color: style.fg_color,
-                            bg_color: style.bg_color,
-                            attribute: style.attribute,
-                        });
-                    } else {
-                        current_line.push(StyledText::None { text: TextChunk::new(self.buffer.clone(), span_start, i)});
+        if let Some(window) = &window {
+            styled_file.set_column_start(window.start);
+        }
+        let mut line_start = 0;
+        for (line_index, line) in self.buffer.raw_lines().enumerate() {
+            let line_end = line_start + line.byte_len();
+            let text_end = line_start + self.buffer.line(line_index).byte_len();
+            let mut i = line_start;
+            let mut display = 0;
+            let mut graphemes = line.graphemes().peekable();
+
+            if let Some(window) = &window {
+                let mut column = 0;
+                while let Some(grapheme) = graphemes.peek() {
+                    let width = grapheme_width(grapheme, display, self.tab_width);
+                    if display + width > window.start || i >= text_end {
+                        break;
                     }
+                    display += width;
+                    column += 1;
+                    i += grapheme.len();
+                    graphemes.next();
+                }
+                if column > 0 {
+                    styled_file.set_line_start(line_index, column, display);
                 }
-                styled_file.push_line(current_line);
-                current_line = Vec::new();
-                span_start = i;
-                continue;
             }
 
-            match self.highlights.highlights.get(i) {
-                None => {
-                    if let Some(style) = &current_style {
-                        current_line.push(StyledText::Style {
-                            text: TextChunk::new(self.buffer.clone(), span_start, i),
-                            fg_color: style.fg_color,
-                            bg_color: style.bg_color,
-                            attribute: style.attribute,
-                        });
-
-                        span_start = i;
-                        current_style = None;
-                    }
+            let mut current_line = Vec::new();
+            let mut span_start = i;
+            let mut current_style = self.highlights.get(i);
+            for grapheme in graphemes {
+                if i >= text_end || window.as_ref().is_some_and(|window| display >= window.end) {
+                    break;
                 }
-                Some(new_style) => {
-                    if let Some(style) = &mut current_style {
-                        if new_style != style {
-                            if i > span_start {
-                                current_line.push(StyledText::Style {
-                                    text: TextChunk::new(self.buffer.clone(), span_start, i),
-                                    fg_color: style.fg_color,
-                                    bg_color: style.bg_color,
-                                    attribute: style.attribute,
-                                });
-                            }
-                            span_start = i;
-                            *style = new_style.clone();
-                        }
-                    } else {
-                        current_line.push(StyledText::None { text: TextChunk::new(self.buffer.clone(), span_start, i)});
-                        span_start = i;
-                        current_style = Some(new_style.clone());
-                    }
-
+                // The highlight is looked up at the start of the grapheme so that the first grapheme of a line gets styled too
+                let new_style = self.highlights.get(i);
+                if new_style != current_style {
+                    self.push_span(&mut current_line, current_style.as_ref(), span_start, i);
+                    span_start = i;
+                    current_style = new_style;
                 }
+                display += grapheme_width(&grapheme, display, self.tab_width);
+                i += grapheme.len();
             }
-        }
 
-        // Flush final span and line
-        if span_start < self.buffer.byte_len() {
-            if let Some(style) = &current_style {
-                current_line.push(StyledText::Style {
-                    text: TextChunk::new(self.buffer.clone(), span_start, i),
-                    fg_color: style.fg_color,
-                    bg_color: style.bg_color,
-                    attribute: style.attribute,
-                });
+            // The line break goes with the last span, even when the text past the end of the window was left out
+            if i == text_end {
+                self.push_span(&mut current_line, current_style.as_ref(), span_start, line_end);
             } else {
-                current_line.push(StyledText::None { text: TextChunk::new(self.buffer.clone(), span_start, i)});
+                self.push_span(&mut current_line, current_style.as_ref(), span_start, i);
+                self.push_span(&mut current_line, None, text_end, line_end);
             }
-        }
-        if !current_line.is_empty() {
             styled_file.push_line(current_line);
+            line_start = line_end;
         }
         styled_file
     }
@@ -897,11 +1656,15 @@ pub trait TextBufferImpl {
     }
     /// Returns a byte position and byte size of that char, this skips things like diacritics
     fn next_n_chars(&self, line_no: usize, n: usize) -> (usize, usize);
+    /// Returns the display column that a column of a line is drawn at
+    fn display_column(&self, line_no: usize, column: usize, tab_width: usize) -> usize;
+    /// Returns the column of the grapheme that is drawn over a display column of a line
+    fn column_at_display(&self, line_no: usize, display_column: usize, tab_width: usize) -> usize;
 }
 
 impl TextBufferImpl for Rope {
     fn line_length(&self, line_no: usize) -> usize {
-        self.line(line_no).byte_len()
+        self.line(line_no).graphemes().count()
     }
 
     fn is_there_next_line(&self, line_no: usize) -> bool {
@@ -951,4 +1714,55 @@ impl TextBufferImpl for Rope {
         }
         (pos, size)
     }
-}
\ No newline at end of file
+
+    fn display_column(&self, line_no: usize, column: usize, tab_width: usize) -> usize {
+        display_column(self.line(line_no), column, tab_width)
+    }
+
+    fn column_at_display(&self, line_no: usize, display_column: usize, tab_width: usize) -> usize {
+        column_at_display(self.line(line_no), display_column, tab_width)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::buffer::GridCursor;
+
+    fn cursor(line: usize, column: usize) -> Cursor {
+        Cursor::new_main(GridCursor::new(line, column))
+    }
+
+    fn position(cursor: &Cursor) -> (usize, usize) {
+        (cursor.line(), cursor.column())
+    }
+
+    #[tokio::test]
+    async fn text_edits_move_cursors_along_with_the_text() {
+        let mut buffer = TextBuffer::new("let add = 1;\nadd + add\n", "test");
+        // Renaming `add` to `sum_of` everywhere, from the end of the buffer to the start
+        let edits = vec![
+            (19..22, String::from("sum_of")),
+            (13..16, String::from("sum_of")),
+            (4..7, String::from("sum_of")),
+        ];
+        let mut marked = cursor(1, 8);
+        marked.mark = Some(GridCursor::new(0, 11));
+        let cursors = vec![cursor(0, 0), cursor(0, 5), marked, cursor(1, 9)];
+
+        let cursors = buffer.apply_text_edits(edits, cursors).await;
+        assert_eq!(buffer.get_buffer().to_string(), "let sum_of = 1;\nsum_of + sum_of\n");
+        let positions = cursors.iter().map(position).collect::<Vec<_>>();
+        // A cursor inside of a replaced word ends up at its start
+        assert_eq!(positions, vec![(0, 0), (0, 4), (1, 9), (1, 15)]);
+        let mark = cursors[2].mark.unwrap();
+        assert_eq!((mark.line, mark.column), (0, 14));
+    }
+
+    #[tokio::test]
+    async fn text_edits_that_add_lines_push_cursors_down() {
+        let mut buffer = TextBuffer::new("a\nb\n", "test");
+        let cursors = buffer.apply_text_edits(vec![(0..0, String::from("x\ny\n"))], vec![cursor(0, 0), cursor(1, 1)]).await;
+        assert_eq!(buffer.get_buffer().to_string(), "x\ny\na\nb\n");
+        assert_eq!(cursors.iter().map(position).collect::<Vec<_>>(), vec![(2, 0), (3, 1)]);
+    }
+}
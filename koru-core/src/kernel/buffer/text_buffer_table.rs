@@ -1,20 +1,22 @@
 use std::collections::{HashMap, VecDeque};
 use std::error::Error;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, LazyLock};
 use crop::Rope;
 use scheme_rs::exceptions::Exception;
 use scheme_rs::gc::Gc;
-use scheme_rs::lists::List;
-use scheme_rs::num::Number;
+use scheme_rs::lists::{self, List, Pair};
+use scheme_rs::num::{Number, SimpleNumber};
 use scheme_rs::records::Record;
 use scheme_rs::registry::bridge;
-use scheme_rs::value::Value;
+use scheme_rs::symbols::Symbol;
+use scheme_rs::value::{UnpackedValue, Value};
 use tokio::io::AsyncReadExt;
 use tokio::sync::{RwLock, Mutex};
 use crate::kernel::buffer::text_buffer::TextBuffer;
 use crate::kernel::buffer::cursor::{Cursor, CursorDirection};
-use crate::kernel::buffer::Cursors;
+use crate::kernel::buffer::{Cursors, Diagnostic, Fold, Indent, LineNumbers, Sign, Wrap, Severity, SyntaxTree, DEFAULT_HIGHLIGHT_LAYER, SYNTAX_HIGHLIGHT_LAYER};
+use crate::kernel::lsp::{resolve_edits, LanguageClient};
 use crate::kernel::scheme_api::session::SessionState;
 use crate::styled_text::{Highlight, StyledFile};
 
@@ -73,8 +75,9 @@ impl TextBufferTable {
             contents.push('\n');
         }
 
-        let name = path.into_os_string().into_string().expect("String is not convertable");
-        let buffer = TextBuffer::new(contents, name.clone());
+        let name = path.clone().into_os_string().into_string().expect("String is not convertable");
+        let mut buffer = TextBuffer::new(contents, name.clone());
+        buffer.attach_path(&path);
         
         Ok(self.insert_internal(name, buffer))
     }
@@ -114,6 +117,82 @@ impl BufferHandle {
     pub async fn get_name(&self) -> String {
         self.handle.lock().await.get_name()
     }
+
+    pub async fn indent(&self) -> Indent {
+        self.handle.lock().await.indent()
+    }
+
+    pub async fn set_indent(&self, indent: Indent) {
+        self.handle.lock().await.set_indent(indent)
+    }
+
+    pub async fn line_indentation(&self, line: usize) -> String {
+        self.handle.lock().await.line_indentation(line)
+    }
+
+    pub async fn wrap(&self) -> Wrap {
+        self.handle.lock().await.wrap()
+    }
+
+    pub async fn set_wrap(&self, wrap: Wrap) {
+        self.handle.lock().await.set_wrap(wrap)
+    }
+
+    pub async fn set_viewport_columns(&self, columns: usize) {
+        self.handle.lock().await.set_viewport_columns(columns)
+    }
+
+    pub async fn modified(&self) -> bool {
+        self.handle.lock().await.modified()
+    }
+
+    pub async fn tab_width(&self) -> usize {
+        self.handle.lock().await.tab_width()
+    }
+
+    pub async fn set_tab_width(&self, tab_width: usize) {
+        self.handle.lock().await.set_tab_width(tab_width)
+    }
+
+    pub async fn gutter_width(&self, line_numbers: LineNumbers) -> usize {
+        self.handle.lock().await.gutter_width(line_numbers)
+    }
+
+    pub async fn draw_gutter(&self, line_numbers: LineNumbers, main_line: usize) -> StyledFile {
+        self.handle.lock().await.draw_gutter(line_numbers, main_line)
+    }
+
+    pub async fn set_sign(&self, line: usize, sign: Sign) {
+        self.handle.lock().await.marks_mut().set_sign(line, sign)
+    }
+
+    pub async fn clear_signs(&self, line: Option<usize>) {
+        self.handle.lock().await.marks_mut().clear_signs(line)
+    }
+
+    pub async fn set_fold(&self, line: usize, fold: Fold) {
+        self.handle.lock().await.marks_mut().set_fold(line, fold)
+    }
+
+    pub async fn clear_folds(&self, line: Option<usize>) {
+        self.handle.lock().await.marks_mut().clear_folds(line)
+    }
+
+    pub async fn row_starts(&self) -> Option<Vec<Vec<usize>>> {
+        self.handle.lock().await.row_starts()
+    }
+
+    pub async fn visual_position(&self, line: usize, column: usize) -> (usize, usize) {
+        self.handle.lock().await.visual_position(line, column)
+    }
+
+    pub async fn logical_position(&self, row: usize, display_column: usize) -> (usize, usize) {
+        self.handle.lock().await.logical_position(row, display_column)
+    }
+
+    pub async fn move_cursor_visual(&self, cursor: Cursor, up: bool) -> Result<Cursor, Exception> {
+        self.handle.lock().await.move_cursor_visual(cursor, up)
+    }
     
     pub async fn move_cursors(&self, cursors: Vec<Cursor>, direction: CursorDirection, pred: impl Fn(&str) -> Result<bool, Exception> + Clone) -> Result<Vec<Cursor>, Exception> {
         self.handle.lock().await.move_cursors(cursors, direction, pred)
@@ -174,6 +253,10 @@ impl BufferHandle {
         self.handle.lock().await.delete_forward(cursor_index, cursors).await
     }
 
+    pub async fn region_text(&self, cursor: Cursor) -> Option<String> {
+        self.handle.lock().await.region_text(cursor)
+    }
+
     pub async fn delete_region(&self, cursor_index: usize, cursors: Vec<Cursor>) -> Result<Vec<Cursor>, Exception> {
         self.handle.lock().await.delete_region(cursor_index, cursors).await
     }
@@ -182,6 +265,10 @@ impl BufferHandle {
         self.handle.lock().await.replace(text, cursor_index, cursors).await
     }
     
+    pub async fn shift_lines(&self, cursors: Vec<Cursor>, dedent: bool) -> Vec<Cursor> {
+        self.handle.lock().await.shift_lines(cursors, dedent).await
+    }
+
     pub async fn start_transaction(&self) {
         self.handle.lock().await.start_transaction().await;
     }
@@ -200,19 +287,45 @@ impl BufferHandle {
 
     pub async fn insert_highlight(
         &self,
+        layer: &str,
         highlight: Highlight,
         start: (usize, usize),
         end : (usize, usize)
     ) {
-        self.handle.lock().await.insert_highlight(highlight, start, end);
+        self.handle.lock().await.insert_highlight(layer, highlight, start, end);
+    }
+
+    pub async fn clear_highlights(&self, layer: &str) {
+        self.handle.lock().await.clear_highlights(layer);
+    }
+
+    pub async fn set_highlight_layer_priority(&self, layer: &str, priority: isize) {
+        self.handle.lock().await.set_highlight_layer_priority(layer, priority);
+    }
+
+    pub async fn remove_highlight_layer(&self, layer: &str) {
+        self.handle.lock().await.remove_highlight_layer(layer);
     }
 
-    pub async fn clear_highlights(&self) {
-        self.handle.lock().await.clear_highlights();
+    pub async fn highlight_layers(&self) -> Vec<(String, isize)> {
+        self.handle.lock().await.highlight_layers()
     }
 
-    pub async fn draw(&self) -> StyledFile {
-        self.handle.lock().await.draw()
+    pub async fn set_syntax(&self, syntax: Option<SyntaxTree>) {
+        self.handle.lock().await.set_syntax(syntax);
+    }
+
+    pub async fn syntax_grammar(&self) -> Option<String> {
+        self.handle.lock().await.syntax_grammar()
+    }
+
+    /// Draws the buffer, leaving out the parts of long lines that are far from the focus.
+    pub async fn draw(&self, focus: Option<(usize, usize)>) -> StyledFile {
+        let mut guard = self.handle.lock().await;
+        guard.update_syntax();
+        guard.flush_lsp();
+        let window = focus.and_then(|(line, column)| guard.column_window(line, column));
+        guard.draw(window)
     }
 
     pub async fn save(&self) -> Result<(), Exception> {
@@ -226,6 +339,59 @@ impl BufferHandle {
     pub async fn get_path(&self) -> Option<String> {
         self.handle.lock().await.get_path()
     }
+
+    pub async fn set_diagnostics(&self, owner: &str, diagnostics: impl FnOnce(&Rope) -> Vec<Diagnostic>) {
+        self.handle.lock().await.set_diagnostics(owner, diagnostics);
+    }
+
+    pub async fn clear_diagnostics(&self, owner: Option<&str>) {
+        self.handle.lock().await.clear_diagnostics(owner);
+    }
+
+    /// Gets the diagnostics of the buffer along with the text that their ranges are in.
+    pub async fn diagnostics(&self) -> (Rope, Vec<Diagnostic>) {
+        let guard = self.handle.lock().await;
+        (guard.get_buffer(), guard.diagnostics())
+    }
+
+    pub async fn line_diagnostics(&self, line: usize) -> (Rope, Vec<Diagnostic>) {
+        let guard = self.handle.lock().await;
+        (guard.get_buffer(), guard.line_diagnostics(line))
+    }
+
+    pub async fn line_severity(&self, line: usize) -> Option<Severity> {
+        self.handle.lock().await.line_severity(line)
+    }
+
+    pub async fn attach_lsp(&self, client: Arc<LanguageClient>, path: &Path) {
+        self.handle.lock().await.attach_lsp(client, path);
+    }
+
+    pub async fn detach_lsp(&self) {
+        self.handle.lock().await.detach_lsp();
+    }
+
+    pub async fn lsp_language(&self) -> Option<String> {
+        self.handle.lock().await.lsp_language()
+    }
+
+    /// Gets the language server, document uri, and lsp position of a line and column in the buffer.
+    pub async fn lsp_position(&self, line: usize, column: usize) -> Option<(Arc<LanguageClient>, String, serde_json::Value)> {
+        let mut guard = self.handle.lock().await;
+        let (client, uri) = guard.lsp_document()?;
+        Some((client, uri, guard.lsp_position(line, column)))
+    }
+
+    pub async fn lsp_document(&self) -> Option<(Arc<LanguageClient>, String)> {
+        self.handle.lock().await.lsp_document()
+    }
+
+    /// Applies a list of lsp `TextEdit`s to the buffer as a single undoable edit, returning the moved cursors.
+    pub async fn apply_lsp_edits(&self, edits: &[serde_json::Value], cursors: Vec<Cursor>) -> Vec<Cursor> {
+        let mut guard = self.handle.lock().await;
+        let edits = resolve_edits(&guard.get_buffer(), edits);
+        guard.apply_text_edits(edits, cursors).await
+    }
 }
 
 
@@ -233,9 +399,7 @@ impl BufferHandle {
 pub async fn buffer_from_file(path: &Value) -> Result<Vec<Value>, Exception> {
     let path: String = path.clone().try_into()?;
 
-    let handle = TextBufferTable::open(path).await
-        .map_err(|e| Exception::error(e))?;
-    let name = handle.get_name().await;
+    let name = SessionState::open_file(&path).await?;
 
     Ok(vec![Value::from(name)])
 }
@@ -254,10 +418,7 @@ pub async fn buffer_create(args: &[Value]) -> Result<Vec<Value>, Exception> {
 
     let buffer_name: String = path.clone().try_into()?;
 
-    let handle = TextBufferTable::create(buffer_name, contents).await
-        .map_err(|e| Exception::error(e))?;
-
-    let name = handle.get_name().await;
+    let name = SessionState::create_buffer(&buffer_name, &contents).await?;
     Ok(vec![Value::from(name)])
 }
 
@@ -330,16 +491,16 @@ pub async fn text_edit_draw(args: &[Value]) -> Result<Vec<Value>, Exception> {
     let Some((cursors, _)) = rest.split_first() else {
         return Err(Exception::wrong_num_of_args(2, args.len()));
     };
+    let cursors: Gc<Cursors> = cursors.try_to_rust_type()?;
     let buffer = {
         let buffer_name: String = buffer_name.clone().try_into()?;
         let state = SessionState::get_state();
         let mut guard = state.write().await;
         let mut buffers = guard.get_buffers_mut().await;
         let buffer = buffers.get_mut(&buffer_name).unwrap();
-        buffer.render_styled_text().await;
+        buffer.render_styled_text(&cursors.cursors).await;
         buffer.clone()
     };
-    let cursors: Gc<Cursors> = cursors.try_to_rust_type()?;
 
     let styled_text = buffer.get_styled_text(&cursors.cursors);
 
@@ -347,6 +508,15 @@ pub async fn text_edit_draw(args: &[Value]) -> Result<Vec<Value>, Exception> {
     Ok(vec![value])
 }
 
+/// Reads the name of a highlight layer from either a string or a symbol.
+fn layer_name(value: &Value) -> Result<String, Exception> {
+    match value.clone().unpack() {
+        UnpackedValue::String(string) => Ok(string.to_string()),
+        UnpackedValue::Symbol(symbol) => Ok(symbol.to_string()),
+        other => Err(Exception::type_error("String or Symbol", other.type_name())),
+    }
+}
+
 #[bridge(name = "buffer-place-highlight", lib = "(koru-buffer)")]
 pub async fn place_highlight(args: &[Value]) -> Result<Vec<Value>, Exception> {
     let Some((buffer_name, rest)) = args.split_first() else {
@@ -358,23 +528,19 @@ pub async fn place_highlight(args: &[Value]) -> Result<Vec<Value>, Exception> {
     let Some((start, rest)) = rest.split_first() else {
         return Err(Exception::wrong_num_of_args(4, args.len()));
     };
-    let Some((end, _)) = rest.split_first() else {
+    let Some((end, rest)) = rest.split_first() else {
         return Err(Exception::wrong_num_of_args(4, args.len()));
     };
-    let buffer_name: String = buffer_name.clone().try_into()?;
+    let layer = match rest.first() {
+        Some(layer) => layer_name(layer)?,
+        None => DEFAULT_HIGHLIGHT_LAYER.to_string(),
+    };
     let highlight: Gc<Highlight> = highlight.clone().try_to_rust_type()?;
     let start: List = start.try_into()?;
     let end: List = end.try_into()?;
 
-    let buffer = {
-        let state = SessionState::get_state();
-        let guard = state.read().await;
-        let buffers = guard.get_buffers().await;
-        buffers.get(buffer_name.as_str()).cloned()
-    };
-    let Some(buffer) = buffer else {
-        return Err(Exception::error(String::from("Buffer not found")))
-    };
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
     let start = start.as_slice();
     let start = match start {
         [row, col] => {
@@ -398,25 +564,155 @@ pub async fn place_highlight(args: &[Value]) -> Result<Vec<Value>, Exception> {
         _ => return Err(Exception::error("`start` must contain two integers"))
     };
 
-    let handle = buffer.get_handle();
-    handle.insert_highlight((*highlight).clone(), start, end).await;
+    handle.insert_highlight(&layer, (*highlight).clone(), start, end).await;
     Ok(vec![])
 }
 
 #[bridge(name = "buffer-clear-highlight", lib = "(koru-buffer)")]
-pub async fn clear_highlights(buffer_name: &Value) -> Result<Vec<Value>, Exception> {
+pub async fn clear_highlights(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let Some((buffer_name, rest)) = args.split_first() else {
+        return Err(Exception::wrong_num_of_args(1, args.len()));
+    };
+    let layer = match rest.first() {
+        Some(layer) => layer_name(layer)?,
+        None => DEFAULT_HIGHLIGHT_LAYER.to_string(),
+    };
+
     let buffer_name: String = buffer_name.clone().try_into()?;
-    let buffer = {
-        let state = SessionState::get_state();
-        let guard = state.read().await;
-        let buffers = guard.get_buffers().await;
-        buffers.get(buffer_name.as_str()).cloned()
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    handle.clear_highlights(&layer).await;
+    Ok(vec![])
+}
+
+#[bridge(name = "buffer-highlight-layer-set!", lib = "(koru-buffer)")]
+pub async fn set_highlight_layer(buffer_name: &Value, layer: &Value, priority: &Value) -> Result<Vec<Value>, Exception> {
+    let layer = layer_name(layer)?;
+    let priority: isize = priority.clone().try_into()?;
+
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    handle.set_highlight_layer_priority(&layer, priority).await;
+    Ok(vec![])
+}
+
+#[bridge(name = "buffer-highlight-layer-remove!", lib = "(koru-buffer)")]
+pub async fn remove_highlight_layer(buffer_name: &Value, layer: &Value) -> Result<Vec<Value>, Exception> {
+    let layer = layer_name(layer)?;
+    if layer == SYNTAX_HIGHLIGHT_LAYER || layer == DEFAULT_HIGHLIGHT_LAYER {
+        return Err(Exception::error(format!("The {layer} highlight layer can't be removed")));
+    }
+
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    handle.remove_highlight_layer(&layer).await;
+    Ok(vec![])
+}
+
+#[bridge(name = "buffer-highlight-layers", lib = "(koru-buffer)")]
+pub async fn highlight_layers(buffer_name: &Value) -> Result<Vec<Value>, Exception> {
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    let layers = handle.highlight_layers().await
+        .into_iter()
+        .map(|(name, priority)| {
+            let pair = Pair::new(Value::from(name), Value::from(SimpleNumber::from(priority as i64)), false);
+            Value::new(UnpackedValue::Pair(pair))
+        })
+        .collect::<Vec<_>>();
+    Ok(vec![lists::slice_to_list(&layers)])
+}
+
+#[bridge(name = "buffer-indent", lib = "(koru-buffer)")]
+pub async fn buffer_indent(buffer_name: &Value) -> Result<Vec<Value>, Exception> {
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    let indent = handle.indent().await;
+    let style = if indent.use_tabs { "tabs" } else { "spaces" };
+    let indent = [
+        Value::from(Symbol::intern(style)),
+        Value::from(SimpleNumber::from(indent.width as i64)),
+    ];
+    Ok(vec![lists::slice_to_list(&indent)])
+}
+
+#[bridge(name = "buffer-indent-set!", lib = "(koru-buffer)")]
+pub async fn buffer_set_indent(buffer_name: &Value, style: &Value, width: &Value) -> Result<Vec<Value>, Exception> {
+    let style: Symbol = style.clone().try_into()?;
+    let use_tabs = match style.to_string().as_str() {
+        "tabs" => true,
+        "spaces" => false,
+        other => return Err(Exception::error(format!("Unknown indent style: {other}"))),
     };
-    let Some(buffer) = buffer else {
-        return Err(Exception::error(String::from("Buffer not found")))
+    let width: SimpleNumber = width.clone().try_into()?;
+    let width: usize = width.try_into()?;
+    if width == 0 {
+        return Err(Exception::error(String::from("The indent width must be at least 1")));
+    }
+
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    handle.set_indent(Indent::new(use_tabs, width)).await;
+    Ok(vec![])
+}
+
+#[bridge(name = "buffer-tab-width", lib = "(koru-buffer)")]
+pub async fn buffer_tab_width(buffer_name: &Value) -> Result<Vec<Value>, Exception> {
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    Ok(vec![Value::from(SimpleNumber::from(handle.tab_width().await as i64))])
+}
+
+#[bridge(name = "buffer-tab-width-set!", lib = "(koru-buffer)")]
+pub async fn buffer_set_tab_width(buffer_name: &Value, width: &Value) -> Result<Vec<Value>, Exception> {
+    let width: SimpleNumber = width.clone().try_into()?;
+    let width: usize = width.try_into()?;
+    if width == 0 {
+        return Err(Exception::error(String::from("The tab width must be at least 1")));
+    }
+
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    handle.set_tab_width(width).await;
+    Ok(vec![])
+}
+
+#[bridge(name = "buffer-modified?", lib = "(koru-buffer)")]
+pub async fn buffer_modified(buffer_name: &Value) -> Result<Vec<Value>, Exception> {
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    Ok(vec![Value::from(handle.modified().await)])
+}
+
+#[bridge(name = "buffer-wrap", lib = "(koru-buffer)")]
+pub async fn buffer_wrap(buffer_name: &Value) -> Result<Vec<Value>, Exception> {
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    let wrap = match handle.wrap().await {
+        Wrap::Off => Value::from(false),
+        Wrap::Viewport => Value::from(Symbol::intern("viewport")),
+        Wrap::Column(column) => Value::from(SimpleNumber::from(column as i64)),
     };
+    Ok(vec![wrap])
+}
 
-    let handle = buffer.get_handle();
-    handle.clear_highlights().await;
+#[bridge(name = "buffer-wrap-set!", lib = "(koru-buffer)")]
+pub async fn buffer_set_wrap(buffer_name: &Value, wrap: &Value) -> Result<Vec<Value>, Exception> {
+    let wrap = match wrap.clone().unpack() {
+        UnpackedValue::Boolean(false) => Wrap::Off,
+        UnpackedValue::Symbol(symbol) if symbol == Symbol::intern("viewport") => Wrap::Viewport,
+        UnpackedValue::Number(_) => {
+            let column: SimpleNumber = wrap.clone().try_into()?;
+            let column: usize = column.try_into()?;
+            if column == 0 {
+                return Err(Exception::error(String::from("The fill column must be at least 1")));
+            }
+            Wrap::Column(column)
+        }
+        other => return Err(Exception::type_error("#f, viewport or Integer", other.type_name())),
+    };
+
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    handle.set_wrap(wrap).await;
     Ok(vec![])
-}
\ No newline at end of file
+}
@@ -0,0 +1,33 @@
+use crop::RopeSlice;
+use crate::styled_text::grapheme_width;
+
+/// Gets the display column that a column of a line is drawn at.
+///
+/// Columns past the end of the line take up one display column each.
+pub fn display_column(line: RopeSlice, column: usize, tab_width: usize) -> usize {
+    let mut display_column = 0;
+    let mut count = 0;
+    for grapheme in line.graphemes().take(column) {
+        display_column += grapheme_width(&grapheme, display_column, tab_width);
+        count += 1;
+    }
+    display_column + column - count
+}
+
+/// Gets the column of the grapheme that is drawn over a display column of a line.
+///
+/// A display column in the middle of a tab or a wide character gives the column of that grapheme.
+/// Display columns past the end of the line give columns past the end of the line.
+pub fn column_at_display(line: RopeSlice, display_column: usize, tab_width: usize) -> usize {
+    let mut current = 0;
+    let mut count = 0;
+    for grapheme in line.graphemes() {
+        let width = grapheme_width(&grapheme, current, tab_width);
+        if current + width > display_column {
+            return count;
+        }
+        current += width;
+        count += 1;
+    }
+    count + display_column - current
+}
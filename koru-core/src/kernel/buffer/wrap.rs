@@ -0,0 +1,143 @@
+use std::ops::Range;
+use crop::RopeSlice;
+use crate::styled_text::grapheme_width;
+
+/// The columns that a buffer is wrapped to before it has been drawn in a pane.
+pub const DEFAULT_VIEWPORT_COLUMNS: usize = 80;
+
+/// Gets the display columns of unwrapped lines to draw so that a display column can be seen.
+///
+/// Frontends scroll just far enough to keep the main cursor in view, so the window reaches a few viewports to each side of it.
+/// It starts on a multiple of the viewport width so that it only moves once the cursor gets near one of its edges.
+pub fn column_window(display_column: usize, viewport_columns: usize) -> Range<usize> {
+    let columns = viewport_columns.max(1);
+    let start = display_column.saturating_sub(columns * 2) / columns * columns;
+    start..start + columns * 5
+}
+
+/// How the lines of a buffer are soft wrapped.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Wrap {
+    /// Lines are drawn on a single row and scroll horizontally
+    #[default]
+    Off,
+    /// Lines wrap at the width of the viewport
+    Viewport,
+    /// Lines wrap at a fill column
+    Column(usize),
+}
+
+impl Wrap {
+    /// The number of columns that rows wrap at in a viewport of the given width, or None if wrapping is off.
+    pub fn width(&self, viewport_columns: usize) -> Option<usize> {
+        match self {
+            Wrap::Off => None,
+            Wrap::Viewport => Some(viewport_columns.max(1)),
+            Wrap::Column(column) => Some((*column).max(1)),
+        }
+    }
+}
+
+/// Finds the columns where the rows of a wrapped line start.
+///
+/// The first row always starts at column 0.
+/// Rows are `width` display columns wide, so tabs and wide characters take up more of a row than other graphemes.
+/// Rows are broken after the last whitespace that fits, or in the middle of a word that is wider than a row.
+/// Whitespace never starts a row, it hangs past the end of the row before it instead.
+pub fn row_starts(line: RopeSlice, width: usize, tab_width: usize) -> Vec<usize> {
+    let mut starts = vec![0];
+    let mut row_start = 0;
+    let mut row_start_display = 0;
+    let mut break_at = None;
+    let mut display_column = 0;
+    for (column, grapheme) in line.graphemes().enumerate() {
+        let grapheme_width = grapheme_width(&grapheme, display_column, tab_width);
+        if grapheme.chars().all(char::is_whitespace) {
+            display_column += grapheme_width;
+            break_at = Some((column + 1, display_column));
+            continue;
+        }
+        if display_column + grapheme_width - row_start_display > width && column > row_start {
+            if let Some((break_column, break_display)) = break_at.filter(|(break_column, _)| *break_column > row_start) {
+                row_start = break_column;
+                row_start_display = break_display;
+                starts.push(row_start);
+            }
+            // A wide grapheme can still be too wide for what is left after the whitespace
+            if display_column + grapheme_width - row_start_display > width && column > row_start {
+                row_start = column;
+                row_start_display = display_column;
+                starts.push(row_start);
+            }
+            break_at = None;
+        }
+        display_column += grapheme_width;
+    }
+    starts
+}
+
+/// Finds which row of a wrapped line a column is drawn on.
+pub fn row_of_column(starts: &[usize], column: usize) -> usize {
+    starts.iter().rposition(|start| *start <= column).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crop::Rope;
+    use super::{row_of_column, row_starts};
+
+    fn starts(text: &str, width: usize) -> Vec<usize> {
+        let rope = Rope::from(text);
+        row_starts(rope.byte_slice(..), width, 4)
+    }
+
+    #[test]
+    fn short_lines_take_one_row() {
+        assert_eq!(starts("", 5), vec![0]);
+        assert_eq!(starts("hello", 5), vec![0]);
+    }
+
+    #[test]
+    fn rows_break_after_the_last_whitespace_that_fits() {
+        assert_eq!(starts("one two three", 9), vec![0, 8]);
+        assert_eq!(starts("a b c d e f", 4), vec![0, 4, 8]);
+    }
+
+    #[test]
+    fn whitespace_after_a_word_that_fits_exactly_hangs_past_the_row() {
+        assert_eq!(starts("hello world", 5), vec![0, 6]);
+        assert_eq!(starts("hello   world", 5), vec![0, 8]);
+        assert_eq!(starts("hello ", 5), vec![0]);
+    }
+
+    #[test]
+    fn tabs_take_up_the_columns_to_the_next_tab_stop() {
+        // The tab goes from column 1 to the tab stop at 4, so "b" doesn't fit on the first row
+        assert_eq!(starts("a\tb", 4), vec![0, 2]);
+        assert_eq!(starts("a\tb", 5), vec![0]);
+    }
+
+    #[test]
+    fn wide_graphemes_move_to_the_next_row_whole() {
+        assert_eq!(starts("日本語", 4), vec![0, 2]);
+        assert_eq!(starts("ab日", 3), vec![0, 2]);
+    }
+
+    #[test]
+    fn words_longer_than_a_row_break_in_the_middle() {
+        assert_eq!(starts("abcdefghij", 4), vec![0, 4, 8]);
+        assert_eq!(starts("ab cdefghij", 4), vec![0, 3, 7]);
+    }
+
+    #[test]
+    fn columns_are_on_the_row_that_starts_at_or_before_them() {
+        let starts = [0, 3, 7];
+        assert_eq!(row_of_column(&starts, 0), 0);
+        assert_eq!(row_of_column(&starts, 2), 0);
+        assert_eq!(row_of_column(&starts, 3), 1);
+        assert_eq!(row_of_column(&starts, 6), 1);
+        assert_eq!(row_of_column(&starts, 7), 2);
+        assert_eq!(row_of_column(&starts, 100), 2);
+        assert_eq!(row_of_column(&[0], 5), 0);
+    }
+}
@@ -0,0 +1,562 @@
+mod fuzzy;
+mod sources;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{LazyLock, RwLock};
+use crop::Rope;
+use log::error;
+use scheme_rs::exceptions::Exception;
+use scheme_rs::lists::{self, List};
+use scheme_rs::num::SimpleNumber;
+use scheme_rs::proc::Procedure;
+use scheme_rs::registry::bridge;
+use scheme_rs::symbols::Symbol;
+use scheme_rs::value::{UnpackedValue, Value};
+use tokio::sync::Mutex;
+use crate::kernel::broker::{BackendMessage, MessageKind};
+use crate::kernel::scheme_api::session::SessionState;
+use crate::styled_text::{ColorType, StyledFile, StyledText, TextAttribute, TextChunk};
+
+pub use fuzzy::{fuzzy_match, FuzzyMatch};
+use sources::Snippet;
+
+/// The hook that is emitted whenever a completion ends, whether a candidate was accepted or not.
+pub const COMPLETION_END_HOOK: &str = "completion-end";
+
+/// The most candidates shown in the popup at once.
+const POPUP_ROWS: usize = 10;
+const MAX_LABEL_WIDTH: usize = 40;
+const MAX_DETAIL_WIDTH: usize = 30;
+
+/// Something that can be inserted at the cursors.
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    /// What is shown in the popup and matched against what was typed.
+    pub label: String,
+    /// What gets inserted.
+    pub text: String,
+    /// Extra information shown next to the label, like a type or where it came from.
+    pub detail: String,
+    /// The column on the main cursor's line that the candidate replaces text from.
+    pub start: usize,
+}
+
+struct Source {
+    name: String,
+    priority: i64,
+    procedure: Procedure,
+}
+
+/// The registered sources, highest priority first.
+static SOURCES: LazyLock<RwLock<Vec<Source>>> = LazyLock::new(|| {
+    RwLock::new(Vec::new())
+});
+
+struct Completion {
+    buffer_name: String,
+    line: usize,
+    /// The column of the main cursor when the candidates were last ranked.
+    column: usize,
+    /// The start of the word that was before the main cursor when the completion started.
+    word_start: usize,
+    candidates: Vec<Candidate>,
+    /// The candidates that match what was typed, best first.
+    matches: Vec<(usize, FuzzyMatch)>,
+    selected: usize,
+    /// The first match that is visible in the popup.
+    scroll: usize,
+}
+
+static COMPLETION: LazyLock<Mutex<Option<Completion>>> = LazyLock::new(|| {
+    Mutex::new(None)
+});
+
+/// Checks if a char can be part of the word that is being completed.
+pub fn is_word(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+fn line_graphemes(rope: &Rope, line: usize) -> Vec<String> {
+    if line >= rope.line_len() {
+        return Vec::new();
+    }
+    rope.line(line).graphemes()
+        .map(|grapheme| grapheme.to_string())
+        .collect()
+}
+
+/// Finds the column that the word before a column starts at.
+fn word_start(graphemes: &[String], column: usize) -> usize {
+    let mut start = column.min(graphemes.len());
+    while start > 0 && graphemes[start - 1].chars().all(is_word) {
+        start -= 1;
+    }
+    start
+}
+
+/// Matches the candidates against the text between their start and the cursor.
+///
+/// Candidates that would insert exactly what was typed are left out since accepting them does nothing.
+fn rank(candidates: &[Candidate], graphemes: &[String], column: usize) -> Vec<(usize, FuzzyMatch)> {
+    let mut typed: HashMap<usize, String> = HashMap::new();
+    let mut matches = candidates.iter()
+        .enumerate()
+        .filter(|(_, candidate)| candidate.start <= column && column <= graphemes.len())
+        .filter_map(|(index, candidate)| {
+            let typed = typed.entry(candidate.start)
+                .or_insert_with(|| graphemes[candidate.start..column].concat());
+            if candidate.text == *typed {
+                return None;
+            }
+            fuzzy_match(typed, &candidate.label).map(|fuzzy| (index, fuzzy))
+        })
+        .collect::<Vec<_>>();
+    // The sort is stable so equal scores keep the order of the sources
+    matches.sort_by_key(|(_, fuzzy)| std::cmp::Reverse(fuzzy.score));
+    matches
+}
+
+/// Cuts text down to its first line and to a width, ending it with `…` if it was cut.
+pub(crate) fn truncate(text: &str, width: usize) -> String {
+    let text = text.lines().next().unwrap_or_default();
+    if text.chars().count() <= width {
+        text.to_string()
+    } else {
+        let mut truncated = text.chars().take(width - 1).collect::<String>();
+        truncated.push('…');
+        truncated
+    }
+}
+
+pub(crate) fn segment(text: String, fg_color: ColorType, bg_color: ColorType) -> StyledText {
+    StyledText::Style {
+        text: TextChunk::from(text),
+        fg_color,
+        bg_color,
+        attribute: TextAttribute::empty(),
+    }
+}
+
+/// Splits text into segments where the chars at the matched positions are in the accent color.
+pub(crate) fn highlight_matches(text: &str, positions: &[usize], bg_color: ColorType) -> Vec<StyledText> {
+    let mut segments = Vec::new();
+    let mut run = String::new();
+    let mut run_matched = false;
+    for (index, ch) in text.chars().enumerate() {
+        let matched = positions.contains(&index);
+        if matched != run_matched && !run.is_empty() {
+            let fg_color = if run_matched { ColorType::Accent } else { ColorType::Text };
+            segments.push(segment(std::mem::take(&mut run), fg_color, bg_color));
+        }
+        run_matched = matched;
+        run.push(ch);
+    }
+    if !run.is_empty() {
+        let fg_color = if run_matched { ColorType::Accent } else { ColorType::Text };
+        segments.push(segment(run, fg_color, bg_color));
+    }
+    segments
+}
+
+impl Completion {
+    fn selected_candidate(&self) -> &Candidate {
+        &self.candidates[self.matches[self.selected].0]
+    }
+
+    fn select(&mut self, selected: usize) {
+        self.selected = selected;
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + POPUP_ROWS {
+            self.scroll = self.selected + 1 - POPUP_ROWS;
+        }
+    }
+
+    /// Draws the visible matches, one per line, with the matched chars of each label in the accent color.
+    fn popup(&self) -> StyledFile {
+        let visible = &self.matches[self.scroll..self.matches.len().min(self.scroll + POPUP_ROWS)];
+        let labels = visible.iter()
+            .map(|(index, _)| truncate(&self.candidates[*index].label, MAX_LABEL_WIDTH))
+            .collect::<Vec<_>>();
+        let details = visible.iter()
+            .map(|(index, _)| truncate(&self.candidates[*index].detail, MAX_DETAIL_WIDTH))
+            .collect::<Vec<_>>();
+        let label_width = labels.iter().map(|label| label.chars().count()).max().unwrap_or(0);
+        let detail_width = details.iter().map(|detail| detail.chars().count()).max().unwrap_or(0);
+
+        let mut file = StyledFile::new();
+        for (row, ((_, fuzzy), (label, detail))) in visible.iter().zip(labels.iter().zip(details.iter())).enumerate() {
+            let bg_color = if self.scroll + row == self.selected {
+                ColorType::Selection
+            } else {
+                ColorType::Surface0
+            };
+            let mut line = vec![segment(String::from(" "), ColorType::Text, bg_color)];
+            line.extend(highlight_matches(label, &fuzzy.positions, bg_color));
+            line.push(segment(" ".repeat(label_width - label.chars().count()), ColorType::Text, bg_color));
+            if detail_width > 0 {
+                let padding = " ".repeat(detail_width - detail.chars().count());
+                line.push(segment(format!("  {detail}{padding}"), ColorType::Subtext0, bg_color));
+            }
+            line.push(segment(String::from(" "), ColorType::Text, bg_color));
+            file.push_line(line);
+        }
+        file
+    }
+}
+
+async fn show_popup(popup: StyledFile) {
+    if let Err(err) = SessionState::send_message(MessageKind::BackEnd(BackendMessage::ShowPopup(popup))).await {
+        error!("{}", err);
+    }
+}
+
+/// Hides the popup and runs the `completion-end` hook.
+///
+/// This must not be called while the completion is locked since the hook can look at the completion.
+async fn end() {
+    if let Err(err) = SessionState::send_message(MessageKind::BackEnd(BackendMessage::HidePopup)).await {
+        error!("{}", err);
+    }
+    let _ = SessionState::emit_hook_blocking(Symbol::intern(COMPLETION_END_HOOK), &[]).await;
+}
+
+/// Reads what a source returned into candidates.
+///
+/// A candidate is either a string or a list of the label, the text to insert, the detail, and the start column.
+/// Everything past the label is optional.
+fn candidates_from(value: &Value, word_start: usize) -> Result<Vec<Candidate>, Exception> {
+    let list: List = value.try_into()?;
+    list.into_iter()
+        .map(|value| {
+            if let UnpackedValue::String(label) = value.clone().unpack() {
+                let label = label.to_string();
+                return Ok(Candidate { text: label.clone(), label, detail: String::new(), start: word_start });
+            }
+            let parts: List = (&value).try_into()?;
+            let parts = parts.as_slice();
+            let Some(label) = parts.first() else {
+                return Err(Exception::error("A candidate must have a label"));
+            };
+            let label: String = label.clone().try_into()?;
+            let text = match parts.get(1) {
+                Some(text) => text.clone().try_into()?,
+                None => label.clone(),
+            };
+            let detail = match parts.get(2) {
+                Some(detail) => detail.clone().try_into()?,
+                None => String::new(),
+            };
+            let start = match parts.get(3) {
+                Some(start) if start.is_true() => {
+                    let start: SimpleNumber = start.clone().try_into()?;
+                    start.try_into()?
+                }
+                _ => word_start,
+            };
+            Ok(Candidate { label, text, detail, start })
+        })
+        .collect()
+}
+
+fn candidates_to_list(candidates: Vec<Candidate>) -> Value {
+    let candidates = candidates.into_iter()
+        .map(|candidate| lists::slice_to_list(&[
+            Value::from(candidate.label),
+            Value::from(candidate.text),
+            Value::from(candidate.detail),
+            Value::from(SimpleNumber::from(candidate.start)),
+        ]))
+        .collect::<Vec<_>>();
+    lists::slice_to_list(&candidates)
+}
+
+/// Asks every source for candidates at once and ranks them.
+///
+/// Sources that fail are logged and skipped.
+/// Returns the number of candidates that matched.
+pub async fn start(buffer_name: String, line: usize, column: usize) -> Result<usize, Exception> {
+    let was_active = COMPLETION.lock().await.take().is_some();
+    if was_active {
+        end().await;
+    }
+
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    let rope = handle.get_text().await;
+    let graphemes = line_graphemes(&rope, line);
+    let column = column.min(graphemes.len());
+    let word_start = word_start(&graphemes, column);
+    let prefix = graphemes[word_start..column].concat();
+
+    let sources = SOURCES.read().expect("completion source lock poisoned")
+        .iter()
+        .map(|source| (source.name.clone(), source.procedure.clone()))
+        .collect::<Vec<_>>();
+    let args = [
+        Value::from(buffer_name.clone()),
+        Value::from(prefix),
+        Value::from(SimpleNumber::from(line)),
+        Value::from(SimpleNumber::from(column)),
+    ];
+    let results = futures::future::join_all(sources.iter().map(|(_, procedure)| procedure.call(&args))).await;
+
+    let mut seen = HashSet::new();
+    let mut candidates = Vec::new();
+    for ((name, _), result) in sources.iter().zip(results) {
+        let found = result.and_then(|values| match values.first() {
+            Some(value) => candidates_from(value, word_start),
+            None => Ok(Vec::new()),
+        });
+        match found {
+            Ok(found) => {
+                candidates.extend(found.into_iter()
+                    .filter(|candidate| seen.insert((candidate.label.clone(), candidate.text.clone()))));
+            }
+            Err(err) => error!("Completion source '{}' failed: {}", name, err),
+        }
+    }
+
+    let matches = rank(&candidates, &graphemes, column);
+    let count = matches.len();
+    if count == 0 {
+        return Ok(0);
+    }
+    let completion = Completion {
+        buffer_name,
+        line,
+        column,
+        word_start,
+        candidates,
+        matches,
+        selected: 0,
+        scroll: 0,
+    };
+    let popup = completion.popup();
+    *COMPLETION.lock().await = Some(completion);
+    show_popup(popup).await;
+    Ok(count)
+}
+
+/// Re-ranks the candidates after the main cursor moved.
+///
+/// The completion ends if the cursor left the line or the word it started in, or if nothing matches anymore.
+pub async fn refresh(buffer_name: &str, line: usize, column: usize) {
+    let mut guard = COMPLETION.lock().await;
+    let Some(completion) = guard.as_mut() else {
+        return;
+    };
+    if completion.column == column && completion.buffer_name == buffer_name && completion.line == line {
+        return;
+    }
+    let still_valid = completion.buffer_name == buffer_name
+        && completion.line == line
+        && completion.word_start <= column;
+    let matches = if still_valid {
+        match SessionState::get_buffer_handle(buffer_name).await {
+            Ok(handle) => {
+                let graphemes = line_graphemes(&handle.get_text().await, line);
+                rank(&completion.candidates, &graphemes, column)
+            }
+            Err(_) => Vec::new(),
+        }
+    } else {
+        Vec::new()
+    };
+    if matches.is_empty() {
+        *guard = None;
+        drop(guard);
+        end().await;
+        return;
+    }
+    completion.column = column;
+    completion.matches = matches;
+    completion.selected = 0;
+    completion.scroll = 0;
+    let popup = completion.popup();
+    drop(guard);
+    show_popup(popup).await;
+}
+
+/// Moves the selection to the next or previous match, wrapping around at the ends.
+async fn move_selection(forward: bool) {
+    let mut guard = COMPLETION.lock().await;
+    let Some(completion) = guard.as_mut() else {
+        return;
+    };
+    let count = completion.matches.len();
+    let selected = if forward {
+        (completion.selected + 1) % count
+    } else {
+        (completion.selected + count - 1) % count
+    };
+    completion.select(selected);
+    let popup = completion.popup();
+    drop(guard);
+    show_popup(popup).await;
+}
+
+#[bridge(name = "completion-source-add!", lib = "(koru-completion)")]
+pub async fn completion_source_add(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let Some((name, rest)) = args.split_first() else {
+        return Err(Exception::wrong_num_of_args(2, args.len()));
+    };
+    let Some((procedure, rest)) = rest.split_first() else {
+        return Err(Exception::wrong_num_of_args(2, args.len()));
+    };
+    let name: String = name.clone().try_into()?;
+    let procedure: Procedure = procedure.clone().try_into()?;
+    let priority = match rest.first() {
+        Some(priority) => i64::try_from(priority)?,
+        None => 0,
+    };
+
+    let mut sources = SOURCES.write().expect("completion source lock poisoned");
+    sources.retain(|source| source.name != name);
+    sources.push(Source { name, priority, procedure });
+    sources.sort_by_key(|source| std::cmp::Reverse(source.priority));
+    Ok(Vec::new())
+}
+
+#[bridge(name = "completion-source-remove!", lib = "(koru-completion)")]
+pub async fn completion_source_remove(name: &Value) -> Result<Vec<Value>, Exception> {
+    let name: String = name.clone().try_into()?;
+    SOURCES.write().expect("completion source lock poisoned").retain(|source| source.name != name);
+    Ok(Vec::new())
+}
+
+#[bridge(name = "completion-sources", lib = "(koru-completion)")]
+pub async fn completion_sources() -> Result<Vec<Value>, Exception> {
+    let names = SOURCES.read().expect("completion source lock poisoned")
+        .iter()
+        .map(|source| Value::from(source.name.clone()))
+        .collect::<Vec<_>>();
+    Ok(vec![lists::slice_to_list(&names)])
+}
+
+#[bridge(name = "completion-start", lib = "(koru-completion)")]
+pub async fn completion_start(buffer_name: &Value, line: &Value, column: &Value) -> Result<Vec<Value>, Exception> {
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let line: SimpleNumber = line.clone().try_into()?;
+    let column: SimpleNumber = column.clone().try_into()?;
+    let count = start(buffer_name, line.try_into()?, column.try_into()?).await?;
+    Ok(vec![Value::from(SimpleNumber::from(count))])
+}
+
+#[bridge(name = "completion-active?", lib = "(koru-completion)")]
+pub async fn completion_active() -> Result<Vec<Value>, Exception> {
+    let active = COMPLETION.lock().await.is_some();
+    Ok(vec![Value::from(active)])
+}
+
+#[bridge(name = "completion-next", lib = "(koru-completion)")]
+pub async fn completion_next() -> Result<Vec<Value>, Exception> {
+    move_selection(true).await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "completion-previous", lib = "(koru-completion)")]
+pub async fn completion_previous() -> Result<Vec<Value>, Exception> {
+    move_selection(false).await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "completion-accept", lib = "(koru-completion)")]
+pub async fn completion_accept() -> Result<Vec<Value>, Exception> {
+    let Some(completion) = COMPLETION.lock().await.take() else {
+        return Ok(vec![Value::from(false)]);
+    };
+    end().await;
+    let candidate = completion.selected_candidate();
+    let count = completion.column.saturating_sub(candidate.start);
+    Ok(vec![lists::slice_to_list(&[
+        Value::from(candidate.text.clone()),
+        Value::from(SimpleNumber::from(count)),
+    ])])
+}
+
+#[bridge(name = "completion-cancel", lib = "(koru-completion)")]
+pub async fn completion_cancel() -> Result<Vec<Value>, Exception> {
+    let was_active = COMPLETION.lock().await.take().is_some();
+    if was_active {
+        end().await;
+    }
+    Ok(Vec::new())
+}
+
+#[bridge(name = "completion-buffer-words", lib = "(koru-completion)")]
+pub async fn completion_buffer_words(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let [buffer_name, prefix, line, column, ..] = args else {
+        return Err(Exception::wrong_num_of_args(4, args.len()));
+    };
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let prefix: String = prefix.clone().try_into()?;
+    let line: SimpleNumber = line.clone().try_into()?;
+    let line: usize = line.try_into()?;
+    let column: SimpleNumber = column.clone().try_into()?;
+    let column: usize = column.try_into()?;
+
+    let handles = {
+        let state = SessionState::get_state();
+        let guard = state.read().await;
+        let buffers = guard.get_buffers().await;
+        buffers.iter()
+            .filter(|(name, _)| **name != buffer_name)
+            .map(|(_, buffer)| buffer.get_handle())
+            .collect::<Vec<_>>()
+    };
+    let mut others = Vec::with_capacity(handles.len());
+    for handle in handles {
+        others.push(handle.get_text().await);
+    }
+    let rope = SessionState::get_buffer_handle(&buffer_name).await?.get_text().await;
+    let start = column - prefix.chars().count().min(column);
+    let candidates = sources::buffer_words(&prefix, start, line, &rope, &others);
+    Ok(vec![candidates_to_list(candidates)])
+}
+
+#[bridge(name = "completion-file-paths", lib = "(koru-completion)")]
+pub async fn completion_file_paths(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let [buffer_name, _, line, column, ..] = args else {
+        return Err(Exception::wrong_num_of_args(4, args.len()));
+    };
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let line: SimpleNumber = line.clone().try_into()?;
+    let column: SimpleNumber = column.clone().try_into()?;
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    let candidates = sources::file_paths(&handle, line.try_into()?, column.try_into()?).await;
+    Ok(vec![candidates_to_list(candidates)])
+}
+
+#[bridge(name = "completion-snippets", lib = "(koru-completion)")]
+pub async fn completion_snippets(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let [_, prefix, _, column, ..] = args else {
+        return Err(Exception::wrong_num_of_args(4, args.len()));
+    };
+    let prefix: String = prefix.clone().try_into()?;
+    let column: SimpleNumber = column.clone().try_into()?;
+    let column: usize = column.try_into()?;
+    let start = column - prefix.chars().count().min(column);
+    Ok(vec![candidates_to_list(sources::snippets(&prefix, start))])
+}
+
+#[bridge(name = "completion-snippet-add!", lib = "(koru-completion)")]
+pub async fn completion_snippet_add(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let [trigger, body, rest @ ..] = args else {
+        return Err(Exception::wrong_num_of_args(2, args.len()));
+    };
+    let trigger: String = trigger.clone().try_into()?;
+    let body: String = body.clone().try_into()?;
+    let description = match rest.first() {
+        Some(description) => description.clone().try_into()?,
+        None => String::from("snippet"),
+    };
+    sources::add_snippet(Snippet { trigger, body, description });
+    Ok(Vec::new())
+}
+
+#[bridge(name = "completion-snippet-remove!", lib = "(koru-completion)")]
+pub async fn completion_snippet_remove(trigger: &Value) -> Result<Vec<Value>, Exception> {
+    let trigger: String = trigger.clone().try_into()?;
+    sources::remove_snippet(&trigger);
+    Ok(Vec::new())
+}
@@ -0,0 +1,92 @@
+/// How well a pattern matched a piece of text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    pub score: i64,
+    /// The char indices of the text that matched the pattern.
+    pub positions: Vec<usize>,
+}
+
+const MATCH_SCORE: i64 = 16;
+const CONSECUTIVE_BONUS: i64 = 8;
+const BOUNDARY_BONUS: i64 = 12;
+const START_BONUS: i64 = 10;
+const EXACT_CASE_BONUS: i64 = 1;
+const GAP_PENALTY: i64 = 1;
+const LEADING_GAP_PENALTY: i64 = 2;
+const MAX_LEADING_GAP_PENALTY: i64 = 12;
+
+/// Checks if a char starts a new word, like the `b` in `foo_bar`, `foo-bar` or `fooBar`.
+fn is_boundary(previous: Option<char>, current: char) -> bool {
+    match previous {
+        None => true,
+        Some(previous) => {
+            !previous.is_alphanumeric() && current.is_alphanumeric()
+                || previous.is_lowercase() && current.is_uppercase()
+                || !previous.is_numeric() && current.is_numeric()
+        }
+    }
+}
+
+/// Matches a pattern against text where the chars of the pattern have to appear in order but not next to each other.
+///
+/// The match is case insensitive unless the pattern has an uppercase letter in it.
+/// Matches score higher when the chars are next to each other or at the start of words.
+/// Returns None if the text doesn't contain the pattern.
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<FuzzyMatch> {
+    let case_sensitive = pattern.chars().any(char::is_uppercase);
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let eq = |a: char, b: char| {
+        if case_sensitive {
+            a == b
+        } else {
+            a.to_lowercase().eq(b.to_lowercase())
+        }
+    };
+
+    // The first pass finds the earliest place that a match can end.
+    // The second pass walks back from there so the match is as tight as possible.
+    let mut index = 0;
+    let mut end = 0;
+    for &ch in &pattern {
+        while index < text.len() && !eq(ch, text[index]) {
+            index += 1;
+        }
+        if index == text.len() {
+            return None;
+        }
+        end = index;
+        index += 1;
+    }
+    let mut positions = vec![0; pattern.len()];
+    let mut index = end;
+    for (pattern_index, &ch) in pattern.iter().enumerate().rev() {
+        while !eq(ch, text[index]) {
+            index -= 1;
+        }
+        positions[pattern_index] = index;
+        index = index.saturating_sub(1);
+    }
+
+    let mut score = 0;
+    for (pattern_index, &position) in positions.iter().enumerate() {
+        score += MATCH_SCORE;
+        if pattern[pattern_index] == text[position] {
+            score += EXACT_CASE_BONUS;
+        }
+        let previous = position.checked_sub(1).map(|previous| text[previous]);
+        if is_boundary(previous, text[position]) {
+            score += BOUNDARY_BONUS;
+        }
+        match pattern_index.checked_sub(1).map(|previous| positions[previous]) {
+            Some(previous) if previous + 1 == position => score += CONSECUTIVE_BONUS,
+            Some(previous) => score -= GAP_PENALTY * (position - previous - 1) as i64,
+            None if position == 0 => score += START_BONUS,
+            None => score -= (LEADING_GAP_PENALTY * position as i64).min(MAX_LEADING_GAP_PENALTY),
+        }
+    }
+    // Shorter texts are better matches for the same pattern
+    score -= text.len().saturating_sub(positions.len()) as i64 / 4;
+
+    Some(FuzzyMatch { score, positions })
+}
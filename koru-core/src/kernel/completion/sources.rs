@@ -0,0 +1,165 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, RwLock};
+use crop::Rope;
+use crate::kernel::buffer::BufferHandle;
+use crate::kernel::completion::{fuzzy_match, is_word, Candidate};
+
+/// The most words the buffer word source will give back.
+const MAX_WORDS: usize = 500;
+
+/// Text that gets inserted when its trigger is completed.
+#[derive(Clone)]
+pub struct Snippet {
+    pub trigger: String,
+    pub body: String,
+    pub description: String,
+}
+
+static SNIPPETS: LazyLock<RwLock<Vec<Snippet>>> = LazyLock::new(|| {
+    RwLock::new(Vec::new())
+});
+
+/// Adds a snippet, replacing any snippet with the same trigger.
+pub fn add_snippet(snippet: Snippet) {
+    let mut snippets = SNIPPETS.write().expect("snippet lock poisoned");
+    snippets.retain(|existing| existing.trigger != snippet.trigger);
+    snippets.push(snippet);
+}
+
+pub fn remove_snippet(trigger: &str) {
+    SNIPPETS.write().expect("snippet lock poisoned").retain(|snippet| snippet.trigger != trigger);
+}
+
+pub fn snippets(prefix: &str, start: usize) -> Vec<Candidate> {
+    SNIPPETS.read().expect("snippet lock poisoned")
+        .iter()
+        .filter(|snippet| fuzzy_match(prefix, &snippet.trigger).is_some())
+        .map(|snippet| Candidate {
+            label: snippet.trigger.clone(),
+            text: snippet.body.clone(),
+            detail: snippet.description.clone(),
+            start,
+        })
+        .collect()
+}
+
+/// Splits a line into its words.
+fn line_words(line: &str) -> impl Iterator<Item = &str> {
+    line.split(|ch: char| !is_word(ch))
+        .filter(|word| !word.is_empty())
+}
+
+/// Collects the words of the buffers that match the prefix.
+///
+/// Words from the first buffer come first, ordered by how close they are to the line.
+/// Words from the other buffers come after.
+pub fn buffer_words(prefix: &str, start: usize, line: usize, rope: &Rope, others: &[Rope]) -> Vec<Candidate> {
+    let mut seen = HashSet::new();
+    let mut words = Vec::new();
+    let mut collect = |text: &str| {
+        for word in line_words(text) {
+            if words.len() == MAX_WORDS {
+                return;
+            }
+            if word != prefix && fuzzy_match(prefix, word).is_some() && seen.insert(word.to_string()) {
+                words.push(word.to_string());
+            }
+        }
+    };
+
+    let line_count = rope.line_len();
+    for distance in 0..line_count {
+        if let Some(above) = line.checked_sub(distance)
+            && above < line_count {
+            collect(&rope.line(above).to_string());
+        }
+        if distance != 0 && line + distance < line_count {
+            collect(&rope.line(line + distance).to_string());
+        }
+    }
+    for other in others {
+        for text in other.lines() {
+            collect(&text.to_string());
+        }
+    }
+
+    words.into_iter()
+        .map(|word| Candidate {
+            label: word.clone(),
+            text: word,
+            detail: String::from("word"),
+            start,
+        })
+        .collect()
+}
+
+/// Checks if a char can't be part of a path that is being typed, like quotes and brackets.
+fn ends_path(ch: char) -> bool {
+    ch.is_whitespace() || matches!(ch, '"' | '\'' | '`' | '(' | ')' | '[' | ']' | '{' | '}' | '<' | '>' | ',' | ';' | '=')
+}
+
+/// Lists the entries of the directory in the path before the cursor.
+///
+/// Only text with a `/` in it is treated as a path so that plain words don't list the working directory.
+/// Relative paths are relative to the directory of the buffer's file, or the working directory if it has none.
+/// The candidates replace the last part of the path.
+pub async fn file_paths(handle: &BufferHandle, line: usize, column: usize) -> Vec<Candidate> {
+    let rope = handle.get_text().await;
+    if line >= rope.line_len() {
+        return Vec::new();
+    }
+    let before_cursor = rope.line(line).graphemes()
+        .take(column)
+        .collect::<String>();
+    let token_start = before_cursor.rfind(ends_path)
+        .map(|index| index + before_cursor[index..].chars().next().map_or(0, char::len_utf8))
+        .unwrap_or(0);
+    let token = &before_cursor[token_start..];
+    let Some(slash) = token.rfind('/') else {
+        return Vec::new();
+    };
+    let (directory, name) = (&token[..=slash], &token[slash + 1..]);
+
+    let directory = if let Some(rest) = directory.strip_prefix("~/") {
+        let Some(home) = std::env::var_os("HOME") else {
+            return Vec::new();
+        };
+        PathBuf::from(home).join(rest)
+    } else if Path::new(directory).is_absolute() {
+        PathBuf::from(directory)
+    } else {
+        let base = handle.get_path().await
+            .and_then(|path| Path::new(&path).parent().map(Path::to_path_buf))
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default();
+        base.join(directory)
+    };
+
+    let Ok(mut entries) = tokio::fs::read_dir(&directory).await else {
+        return Vec::new();
+    };
+    let start = column - name.chars().count().min(column);
+    let show_hidden = name.starts_with('.');
+    let mut candidates = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if file_name.starts_with('.') && !show_hidden || fuzzy_match(name, &file_name).is_none() {
+            continue;
+        }
+        let is_directory = entry.file_type().await.is_ok_and(|file_type| file_type.is_dir());
+        let (text, detail) = if is_directory {
+            (format!("{file_name}/"), "directory")
+        } else {
+            (file_name, "file")
+        };
+        candidates.push(Candidate {
+            label: text.clone(),
+            text,
+            detail: String::from(detail),
+            start,
+        });
+    }
+    candidates.sort_by(|a, b| a.label.cmp(&b.label));
+    candidates
+}
@@ -1,5 +1,8 @@
 mod key;
 mod key_buffer;
+pub mod keyboard;
+mod mouse;
 
 pub use key_buffer::KeyBuffer;
-pub use key::*;
\ No newline at end of file
+pub use key::*;
+pub use mouse::*;
\ No newline at end of file
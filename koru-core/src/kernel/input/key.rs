@@ -2,12 +2,12 @@ use scheme_rs::records::Record;
 use std::sync::{Arc};
 use bitflags::{bitflags, Flags};
 use scheme_rs::exceptions::Exception;
-use scheme_rs::gc::{OpaqueGcPtr, Trace};
+use scheme_rs::gc::{Gc, OpaqueGcPtr, Trace};
 use scheme_rs::lists;
 use scheme_rs::records::{rtd, RecordTypeDescriptor, SchemeCompatible};
 use scheme_rs::registry::bridge;
 use scheme_rs::value::Value;
-use keypress_localize::KeyboardRegion;
+use keypress_localize::Layout;
 use crate::kernel::scheme_api::session::SessionState;
 
 bitflags! {
@@ -273,6 +273,17 @@ impl KeyPress {
         format!("{}", self.key)
     }
 
+    /// The names of the keys that aren't typed as a character, as they are written in key strings.
+    pub fn key_names() -> impl Iterator<Item = String> {
+        const NAMED_KEYS: &[&str] = &[
+            "SPC", "TAB", "ENTER", "DASH", "ESC", "BS", "DEL", "UP", "DOWN", "LEFT", "RIGHT",
+            "HOME", "END", "PAGEUP", "PAGEDOWN",
+        ];
+        NAMED_KEYS.iter()
+            .map(|name| name.to_string())
+            .chain((1..=35).map(|number| format!("F{number}")))
+    }
+
     fn match_key_string(key_string: &str) -> Option<KeyValue> {
         let key = match key_string {
             "SPC" => KeyValue::ControlKey(ControlKey::Space),
@@ -350,14 +361,23 @@ impl KeyPress {
         None
     }
     
-    pub fn canonicalize(mut self, region: KeyboardRegion) -> Self {
+    pub fn canonicalize(mut self, layout: &Layout) -> Self {
         match &mut self.key {
             KeyValue::CharacterKey(c) => {
-                if !self.modifiers.contains(ModifierKey::Shift) {
+                let shift = self.modifiers.contains(ModifierKey::Shift);
+                // AltGr comes through as Control and Alt pressed together
+                let altgr = ModifierKey::Control | ModifierKey::Alt;
+                if self.modifiers.contains(altgr)
+                    && let Some(new_value) = layout.altgr(c, shift) {
+                    *c = new_value.into();
+                    self.modifiers.remove(altgr | ModifierKey::Shift);
                     return self;
                 }
-                
-                let new_value = keypress_localize::canonicalize_keypress(region, c);
+                if !shift {
+                    return self;
+                }
+
+                let new_value = layout.shift(c);
                 if new_value.as_str() != c.as_ref() {
                     *c = new_value.into_boxed_str();
                     self.modifiers.remove(ModifierKey::Shift);
@@ -371,9 +391,9 @@ impl KeyPress {
 
 impl PartialEq for KeyPress {
     fn eq(&self, other: &Self) -> bool {
-        let region = SessionState::get_keyboard_region();
-        let self_can = self.clone().canonicalize(region);
-        let other_can = other.clone().canonicalize(region);
+        let layout = SessionState::get_keyboard_layout();
+        let self_can = self.clone().canonicalize(&layout);
+        let other_can = other.clone().canonicalize(&layout);
         self_can.key == other_can.key && self_can.modifiers == other_can.modifiers
     }
 }
@@ -424,4 +444,17 @@ pub fn string_to_key_list(string: &Value) -> Result<Vec<Value>, Exception> {
     let value = lists::slice_to_list(&vec);
 
     Ok(vec![value])
-}
\ No newline at end of file
+}
+
+#[bridge(name = "key-sequence->string", lib = "(koru-key)")]
+pub fn key_list_to_string(keys: &Value) -> Result<Vec<Value>, Exception> {
+    let keys: lists::List = keys.try_into()?;
+    let keys = keys.into_iter()
+        .map(|key| {
+            let key: Gc<KeyPress> = key.try_to_rust_type()?;
+            Ok(key.to_string())
+        })
+        .collect::<Result<Vec<String>, Exception>>()?;
+
+    Ok(vec![Value::from(keys.join(" "))])
+}
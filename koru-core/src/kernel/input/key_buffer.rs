@@ -3,13 +3,16 @@ use crate::kernel::input::KeyPress;
 const KEY_BUFFER_CAPACITY: usize = 4;
 
 pub struct KeyBuffer {
-    buffer: Vec<KeyPress>
+    buffer: Vec<KeyPress>,
+    /// Counts the changes to the buffer, so a timer knows if keys were added or flushed while it waited.
+    generation: usize,
 }
 
 impl KeyBuffer {
     pub fn new() -> Self {
         KeyBuffer {
-            buffer: Vec::with_capacity(KEY_BUFFER_CAPACITY)
+            buffer: Vec::with_capacity(KEY_BUFFER_CAPACITY),
+            generation: 0,
         }
     }
     
@@ -18,6 +21,7 @@ impl KeyBuffer {
             self.buffer.clear();
         }
         self.buffer.push(key);
+        self.generation += 1;
         /*let string = self.buffer.iter().map(ToString::to_string).reduce(|a, b| a + " " + &b).unwrap();
         println!("{}", string);
         println!("{:?}", self.buffer);*/
@@ -25,6 +29,7 @@ impl KeyBuffer {
     
     pub fn clear(&mut self) {
         self.buffer.clear();
+        self.generation += 1;
     }
     
     pub fn is_empty(&self) -> bool {
@@ -38,4 +43,8 @@ impl KeyBuffer {
     pub fn get(&self) -> &[KeyPress] {
         &self.buffer
     }
+
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
 }
\ No newline at end of file
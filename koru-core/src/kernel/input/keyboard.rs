@@ -0,0 +1,302 @@
+//! The keyboard layout of each session, which tells what keys pressed with Shift or AltGr turn into.
+
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, RwLock};
+use keypress_localize::{Layout, DEFAULT_LAYOUT};
+use scheme_rs::exceptions::Exception;
+use scheme_rs::gc::{Gc, Trace};
+use scheme_rs::lists;
+use scheme_rs::records::{rtd, Record, RecordTypeDescriptor, SchemeCompatible};
+use scheme_rs::registry::bridge;
+use scheme_rs::symbols::Symbol;
+use scheme_rs::value::{UnpackedValue, Value};
+use crate::kernel::input::{ControlKey, KeyPress, KeyValue, ModifierKey};
+use crate::kernel::scheme_api::session::CURRENT_SESSION_ID;
+
+/// The layouts that can be picked by name, which start out as the layouts that come with the editor.
+static LAYOUTS: LazyLock<RwLock<HashMap<String, Arc<Layout>>>> = LazyLock::new(|| {
+    let layouts = Layout::builtin_names()
+        .filter_map(Layout::builtin)
+        .map(|layout| (layout.name().to_string(), Arc::new(layout)))
+        .collect();
+    RwLock::new(layouts)
+});
+
+/// The layout and dead keys setting used by sessions that haven't picked their own.
+///
+/// These are what the user config sets, since it is loaded outside of any session.
+static DEFAULTS: LazyLock<RwLock<Settings>> = LazyLock::new(|| {
+    let layout = registered(DEFAULT_LAYOUT)
+        .unwrap_or_else(|| Arc::new(Layout::new(DEFAULT_LAYOUT)));
+    RwLock::new(Settings { layout, dead_keys: false })
+});
+
+/// The settings that each session picked for itself, by session id.
+static SESSIONS: LazyLock<RwLock<HashMap<usize, SessionSettings>>> = LazyLock::new(|| {
+    RwLock::new(HashMap::new())
+});
+
+#[derive(Clone)]
+struct Settings {
+    layout: Arc<Layout>,
+    /// Whether dead keys are composed with the key after them.
+    dead_keys: bool,
+}
+
+#[derive(Default)]
+struct SessionSettings {
+    layout: Option<Arc<Layout>>,
+    dead_keys: Option<bool>,
+    /// A dead key that is waiting for the next key.
+    pending: Option<KeyPress>,
+}
+
+fn session_id() -> Option<usize> {
+    CURRENT_SESSION_ID.try_with(|id| *id).ok()
+}
+
+fn defaults() -> Settings {
+    DEFAULTS.read().expect("keyboard layout lock poisoned").clone()
+}
+
+/// Gets the settings of the current session, falling back on the defaults for the ones it hasn't picked.
+fn settings() -> Settings {
+    let defaults = defaults();
+    let Some(id) = session_id() else {
+        return defaults;
+    };
+    match SESSIONS.read().expect("keyboard layout lock poisoned").get(&id) {
+        Some(session) => Settings {
+            layout: session.layout.clone().unwrap_or(defaults.layout),
+            dead_keys: session.dead_keys.unwrap_or(defaults.dead_keys),
+        },
+        None => defaults,
+    }
+}
+
+/// Changes the settings of the current session, or the defaults when outside of a session.
+fn update(f: impl FnOnce(&mut Settings)) {
+    let Some(id) = session_id() else {
+        f(&mut DEFAULTS.write().expect("keyboard layout lock poisoned"));
+        return;
+    };
+    let mut settings = settings();
+    f(&mut settings);
+    let mut sessions = SESSIONS.write().expect("keyboard layout lock poisoned");
+    let session = sessions.entry(id).or_default();
+    session.layout = Some(settings.layout);
+    session.dead_keys = Some(settings.dead_keys);
+    session.pending = None;
+}
+
+/// Forgets the settings of a session that has ended.
+pub fn remove_session(session_id: usize) {
+    SESSIONS.write().expect("keyboard layout lock poisoned").remove(&session_id);
+}
+
+/// Gets the layout of the current session.
+pub fn current() -> Arc<Layout> {
+    settings().layout
+}
+
+/// Switches the layout of the current session, dropping any dead key that was waiting.
+pub fn set_current(layout: Arc<Layout>) {
+    update(|settings| settings.layout = layout);
+}
+
+/// Makes a layout pickable by its name, replacing any layout with the same name.
+pub fn register(layout: Layout) -> Arc<Layout> {
+    let layout = Arc::new(layout);
+    LAYOUTS.write().expect("keyboard layout lock poisoned")
+        .insert(layout.name().to_string(), layout.clone());
+    layout
+}
+
+pub fn registered(name: &str) -> Option<Arc<Layout>> {
+    LAYOUTS.read().expect("keyboard layout lock poisoned").get(name).cloned()
+}
+
+/// Puts dead keys together with the key pressed after them, if dead keys are turned on for the session.
+///
+/// Returns the keys to process, which is nothing while a dead key waits for the next key.
+/// A dead key followed by space makes the dead key on its own,
+/// and a dead key followed by a key it doesn't compose with is let through before that key.
+pub fn compose(keypress: KeyPress) -> Vec<KeyPress> {
+    let settings = settings();
+    let Some(id) = session_id().filter(|_| settings.dead_keys) else {
+        return vec![keypress];
+    };
+    let mut sessions = SESSIONS.write().expect("keyboard layout lock poisoned");
+    let session = sessions.entry(id).or_default();
+    let mut keys = Vec::new();
+    if let Some(dead) = session.pending.take() {
+        match (&dead.key, &keypress.key) {
+            (KeyValue::CharacterKey(dead_key), KeyValue::CharacterKey(key)) if keypress.modifiers.is_empty() => {
+                if let Some(composed) = settings.layout.compose(dead_key, key) {
+                    let key = KeyValue::CharacterKey(composed.into());
+                    return vec![KeyPress::new(key, ModifierKey::empty())];
+                }
+            }
+            (_, KeyValue::ControlKey(ControlKey::Space)) if keypress.modifiers.is_empty() => {
+                return vec![dead];
+            }
+            _ => {}
+        }
+        keys.push(dead);
+    }
+    match &keypress.key {
+        KeyValue::CharacterKey(key) if keypress.modifiers.is_empty() && settings.layout.is_dead_key(key) => {
+            session.pending = Some(keypress);
+        }
+        _ => keys.push(keypress),
+    }
+    keys
+}
+
+/// A layout being put together from Scheme.
+#[derive(Debug, Trace)]
+pub struct SchemeLayout {
+    #[trace(skip)]
+    layout: RwLock<Layout>,
+}
+
+impl SchemeCompatible for SchemeLayout {
+    fn rtd() -> Arc<RecordTypeDescriptor>
+    where
+        Self: Sized
+    {
+        rtd!(name: "&KeyboardLayout", sealed: true)
+    }
+}
+
+/// Reads a layout name from either a symbol or a string.
+fn name_from(value: &Value) -> Result<String, Exception> {
+    match value.clone().unpack() {
+        UnpackedValue::Symbol(name) => Ok(name.to_string()),
+        _ => {
+            let name: String = value.clone().try_into()?;
+            Ok(name)
+        }
+    }
+}
+
+fn character(value: &Value) -> Result<String, Exception> {
+    let key: String = value.clone().try_into()?;
+    if key.chars().count() != 1 {
+        return Err(Exception::error(format!("Expected a single character, got: {key}")));
+    }
+    Ok(key)
+}
+
+#[bridge(name = "keyboard-layout-set!", lib = "(koru-key)")]
+pub fn keyboard_layout_set(layout: &Value) -> Result<Vec<Value>, Exception> {
+    let layout = if let Ok(layout) = layout.try_to_rust_type::<SchemeLayout>() {
+        let layout = layout.layout.read().expect("keyboard layout lock poisoned").clone();
+        register(layout)
+    } else {
+        let name = name_from(layout)?;
+        registered(&name).ok_or_else(|| Exception::error(format!("No keyboard layout named {name}")))?
+    };
+    set_current(layout);
+    Ok(Vec::new())
+}
+
+#[bridge(name = "keyboard-layout", lib = "(koru-key)")]
+pub fn keyboard_layout() -> Result<Vec<Value>, Exception> {
+    Ok(vec![Value::from(Symbol::intern(current().name()))])
+}
+
+#[bridge(name = "keyboard-layouts", lib = "(koru-key)")]
+pub fn keyboard_layouts() -> Result<Vec<Value>, Exception> {
+    let mut names = LAYOUTS.read().expect("keyboard layout lock poisoned")
+        .keys()
+        .cloned()
+        .collect::<Vec<_>>();
+    names.sort();
+    let names = names.iter()
+        .map(|name| Value::from(Symbol::intern(name)))
+        .collect::<Vec<_>>();
+    Ok(vec![lists::slice_to_list(&names)])
+}
+
+#[bridge(name = "keyboard-layout-load", lib = "(koru-key)")]
+pub async fn keyboard_layout_load(path: &Value) -> Result<Vec<Value>, Exception> {
+    let path: String = path.clone().try_into()?;
+    let text = tokio::fs::read_to_string(&path).await
+        .map_err(|err| Exception::error(format!("Couldn't read {path}: {err}")))?;
+    let default_name = std::path::Path::new(&path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.clone());
+    let layout = Layout::parse(&default_name, &text)
+        .map_err(|err| Exception::error(format!("{path}: {err}")))?;
+    let layout = register(layout);
+    Ok(vec![Value::from(Symbol::intern(layout.name()))])
+}
+
+#[bridge(name = "keyboard-layout-create", lib = "(koru-key)")]
+pub fn keyboard_layout_create(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let Some((name, rest)) = args.split_first() else {
+        return Err(Exception::wrong_num_of_args(1, args.len()));
+    };
+    let name = name_from(name)?;
+    let mut layout = match rest.first() {
+        Some(base) => {
+            let base = name_from(base)?;
+            let base = registered(&base)
+                .ok_or_else(|| Exception::error(format!("No keyboard layout named {base}")))?;
+            (*base).clone()
+        }
+        None => Layout::new(&name),
+    };
+    layout.set_name(&name);
+    let layout = SchemeLayout { layout: RwLock::new(layout) };
+    Ok(vec![Value::from(Record::from_rust_type(layout))])
+}
+
+#[bridge(name = "keyboard-layout-shift-set!", lib = "(koru-key)")]
+pub fn keyboard_layout_shift_set(layout: &Value, key: &Value, shifted: &Value) -> Result<Vec<Value>, Exception> {
+    let layout: Gc<SchemeLayout> = layout.try_to_rust_type()?;
+    let key = character(key)?;
+    let shifted = character(shifted)?;
+    layout.layout.write().expect("keyboard layout lock poisoned").set_shift(&key, &shifted);
+    Ok(Vec::new())
+}
+
+#[bridge(name = "keyboard-layout-altgr-set!", lib = "(koru-key)")]
+pub fn keyboard_layout_altgr_set(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let [layout, key, value, rest @ ..] = args else {
+        return Err(Exception::wrong_num_of_args(3, args.len()));
+    };
+    let layout: Gc<SchemeLayout> = layout.try_to_rust_type()?;
+    let key = character(key)?;
+    let value = character(value)?;
+    let mut guard = layout.layout.write().expect("keyboard layout lock poisoned");
+    guard.set_altgr(&key, &value, false);
+    if let Some(shifted) = rest.first() {
+        guard.set_altgr(&key, &character(shifted)?, true);
+    }
+    Ok(Vec::new())
+}
+
+#[bridge(name = "keyboard-layout-dead-key-set!", lib = "(koru-key)")]
+pub fn keyboard_layout_dead_key_set(layout: &Value, dead: &Value, key: &Value, composed: &Value) -> Result<Vec<Value>, Exception> {
+    let layout: Gc<SchemeLayout> = layout.try_to_rust_type()?;
+    let dead = character(dead)?;
+    let key = character(key)?;
+    let composed = character(composed)?;
+    layout.layout.write().expect("keyboard layout lock poisoned").set_dead_key(&dead, &key, &composed);
+    Ok(Vec::new())
+}
+
+#[bridge(name = "keyboard-dead-keys-set!", lib = "(koru-key)")]
+pub fn keyboard_dead_keys_set(enabled: &Value) -> Result<Vec<Value>, Exception> {
+    let enabled = enabled.is_true();
+    update(|settings| settings.dead_keys = enabled);
+    Ok(Vec::new())
+}
+
+#[bridge(name = "keyboard-dead-keys?", lib = "(koru-key)")]
+pub fn keyboard_dead_keys() -> Result<Vec<Value>, Exception> {
+    Ok(vec![Value::from(settings().dead_keys)])
+}
@@ -0,0 +1,53 @@
+use crate::kernel::input::ModifierKey;
+
+#[derive(Eq, PartialEq, Copy, Clone, Hash, Debug)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+impl MouseButton {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MouseButton::Left => "left",
+            MouseButton::Right => "right",
+            MouseButton::Middle => "middle",
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Copy, Clone, Hash, Debug)]
+pub enum MouseEventKind {
+    Press(MouseButton),
+    Release(MouseButton),
+    /// The mouse moved while a button was held down
+    Drag(MouseButton),
+    /// The wheel was turned by a number of lines and columns.
+    /// Positive lines scroll towards the end of the buffer and positive columns scroll to the right.
+    Scroll {
+        lines: isize,
+        columns: isize,
+    },
+}
+
+/// Something the mouse did over the text of a pane.
+///
+/// The position is where the event happened in the pane's drawn text,
+/// as a row of the `StyledFile` and a display column counted from the start of that row,
+/// so the frontends take the gutter and how far they have scrolled into account.
+#[derive(Eq, PartialEq, Copy, Clone, Hash, Debug)]
+pub struct MouseEvent {
+    pub kind: MouseEventKind,
+    pub modifiers: ModifierKey,
+    /// The id of the pane that the mouse is over
+    pub pane: usize,
+    pub row: usize,
+    pub column: usize,
+}
+
+impl MouseEvent {
+    pub fn new(kind: MouseEventKind, modifiers: ModifierKey, pane: usize, row: usize, column: usize) -> Self {
+        Self { kind, modifiers, pane, row, column }
+    }
+}
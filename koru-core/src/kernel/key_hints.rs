@@ -0,0 +1,227 @@
+//! Shows which keys can finish the keys that are waiting in the key buffer.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{LazyLock, RwLock};
+use std::time::Duration;
+use log::error;
+use scheme_rs::exceptions::Exception;
+use scheme_rs::num::SimpleNumber;
+use scheme_rs::registry::bridge;
+use scheme_rs::value::Value;
+use crate::kernel;
+use crate::kernel::broker::{BackendMessage, MessageKind};
+use crate::kernel::completion::{segment, truncate};
+use crate::kernel::input::KeyPress;
+use crate::kernel::scheme_api::session::{SessionState, CURRENT_SESSION_ID};
+use crate::keymap::{KeyMap, KeyMapChild};
+use crate::styled_text::{ColorType, StyledFile, StyledText};
+
+/// How long the keys have to wait before the hints are shown, unless changed from Scheme.
+const DEFAULT_DELAY: Duration = Duration::from_millis(500);
+/// The gap between the columns of hints.
+const COLUMN_GAP: usize = 2;
+/// The widest that a column of hints gets.
+const MAX_COLUMN_WIDTH: usize = 40;
+/// The suffix that commands bound to keys usually have, which is left off of hints.
+const KEYPRESS_SUFFIX: &str = "-keypress";
+
+/// How long to wait before showing hints, or None if hints are turned off.
+static DELAY: LazyLock<RwLock<Option<Duration>>> = LazyLock::new(|| {
+    RwLock::new(Some(DEFAULT_DELAY))
+});
+
+/// Counts the keypresses, so a timer knows if another key was pressed while it waited.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+static SHOWN: AtomicBool = AtomicBool::new(false);
+
+/// A key that can come next, and what it does.
+struct Hint {
+    key: String,
+    action: String,
+    prefix: bool,
+}
+
+fn delay() -> Option<Duration> {
+    *DELAY.read().expect("key hints delay lock poisoned")
+}
+
+async fn send(message: BackendMessage) {
+    if let Err(err) = SessionState::send_message(MessageKind::BackEnd(message)).await {
+        error!("{}", err);
+    }
+}
+
+/// Hides the hints and waits to show them again if the key buffer still holds the start of a binding.
+///
+/// This is called after every keypress.
+pub async fn refresh() {
+    let generation = GENERATION.fetch_add(1, Ordering::Relaxed) + 1;
+    if SHOWN.swap(false, Ordering::Relaxed) {
+        send(BackendMessage::HideKeyHints).await;
+    }
+    let Some(delay) = delay() else {
+        return;
+    };
+    if SessionState::pending_keys().await.is_empty() {
+        return;
+    }
+    kernel::session_spawn(CURRENT_SESSION_ID.get(), async move {
+        tokio::time::sleep(delay).await;
+        if GENERATION.load(Ordering::Relaxed) != generation {
+            return;
+        }
+        let keys = SessionState::pending_keys().await;
+        let maps = SessionState::active_key_maps().await.into_iter()
+            .map(|(_, map)| map)
+            .collect::<Vec<_>>();
+        let hints = hints(&keys, &maps);
+        if hints.is_empty() {
+            return;
+        }
+        let (columns, lines) = SessionState::get_viewport().await;
+        let file = render(&keys, &hints, columns, lines);
+        // Another key may have been pressed while the hints were being put together
+        if GENERATION.load(Ordering::Relaxed) == generation {
+            SHOWN.store(true, Ordering::Relaxed);
+            send(BackendMessage::ShowKeyHints(file)).await;
+        }
+    });
+}
+
+/// Collects the keys that can come after the prefix, sorted by key.
+///
+/// The maps are in the order that keys are looked up in, so a key keeps what the first map binds it to.
+/// The first map is the special key map, which is only ever given the newest key,
+/// so its keys are listed whenever the prefix is the start of a binding in one of the other maps.
+fn hints(prefix: &[KeyPress], maps: &[KeyMap]) -> Vec<Hint> {
+    let Some((special, maps)) = maps.split_first() else {
+        return Vec::new();
+    };
+    let children = maps.iter()
+        .map(|map| map.children(prefix))
+        .collect::<Vec<_>>();
+    if children.iter().all(Vec::is_empty) {
+        return Vec::new();
+    }
+    let mut hints: HashMap<String, Hint> = HashMap::new();
+    for (key, child) in special.children(&[]).into_iter().chain(children.into_iter().flatten()) {
+        let key = key.to_string();
+        if hints.contains_key(&key) {
+            continue;
+        }
+        let hint = match child {
+            KeyMapChild::Command(command) => {
+                let name = command.name().to_string();
+                let action = name.strip_suffix(KEYPRESS_SUFFIX).unwrap_or(&name).to_string();
+                Hint { key: key.clone(), action, prefix: false }
+            }
+            KeyMapChild::Prefix(count) => {
+                let noun = if count == 1 { "binding" } else { "bindings" };
+                Hint { key: key.clone(), action: format!("+{count} {noun}"), prefix: true }
+            }
+        };
+        hints.insert(key, hint);
+    }
+    let mut hints = hints.into_values().collect::<Vec<_>>();
+    hints.sort_by(|a, b| {
+        a.key.to_lowercase().cmp(&b.key.to_lowercase()).then_with(|| a.key.cmp(&b.key))
+    });
+    hints
+}
+
+/// Draws the prefix on the first line and the hints in columns below it, filling each column before the next.
+///
+/// The hints take up at most a third of the lines, and the last hint says how many were left out if they don't fit.
+fn render(prefix: &[KeyPress], hints: &[Hint], columns: usize, lines: usize) -> StyledFile {
+    let mut file = StyledFile::new();
+    if columns == 0 {
+        return file;
+    }
+    let prefix = prefix.iter().map(ToString::to_string).collect::<Vec<_>>().join(" ");
+    let title = truncate(&format!(" {prefix} "), columns);
+    let padding = columns - title.chars().count();
+    file.push_line(vec![
+        segment(title, ColorType::Accent, ColorType::Surface1),
+        segment(" ".repeat(padding), ColorType::Text, ColorType::Surface1),
+    ]);
+
+    let key_width = hints.iter().map(|hint| hint.key.chars().count()).max().unwrap_or(0);
+    let widest = hints.iter()
+        .map(|hint| key_width + 3 + hint.action.chars().count())
+        .max()
+        .unwrap_or(0);
+    let column_width = (widest + COLUMN_GAP).min(MAX_COLUMN_WIDTH).min(columns);
+    let column_count = (columns / column_width).max(1);
+    let max_rows = (lines / 3).max(1);
+    let rows = hints.len().div_ceil(column_count).min(max_rows);
+    let shown = rows * column_count;
+    for row in 0..rows {
+        let mut line = Vec::new();
+        let mut used = 0;
+        for column in 0..column_count {
+            let index = column * rows + row;
+            if index >= hints.len() {
+                break;
+            }
+            let cell = if index + 1 == shown && hints.len() > shown {
+                vec![segment(fit(&format!("… {} more", hints.len() - index), column_width), ColorType::Subtext0, ColorType::Surface0)]
+            } else {
+                cell(&hints[index], key_width, column_width)
+            };
+            used += column_width;
+            line.extend(cell);
+        }
+        line.push(segment(" ".repeat(columns.saturating_sub(used)), ColorType::Text, ColorType::Surface0));
+        file.push_line(line);
+    }
+    file
+}
+
+/// Draws a hint as its key, an arrow and what the key does, padded out to the column width.
+fn cell(hint: &Hint, key_width: usize, width: usize) -> Vec<StyledText> {
+    let key = format!("{:>key_width$}", hint.key);
+    let key = if width == 0 { String::new() } else { truncate(&key, width) };
+    let room = width.saturating_sub(key.chars().count());
+    let arrow = " → ".chars().take(room).collect::<String>();
+    let room = room.saturating_sub(arrow.chars().count());
+    let action_color = if hint.prefix { ColorType::Subtext0 } else { ColorType::Text };
+    vec![
+        segment(key, ColorType::Accent, ColorType::Surface0),
+        segment(arrow, ColorType::Overlay0, ColorType::Surface0),
+        segment(fit(&hint.action, room), action_color, ColorType::Surface0),
+    ]
+}
+
+/// Cuts text down to the width or pads it out to the width.
+fn fit(text: &str, width: usize) -> String {
+    if width == 0 {
+        return String::new();
+    }
+    let text = truncate(text, width);
+    let padding = width - text.chars().count();
+    format!("{text}{}", " ".repeat(padding))
+}
+
+#[bridge(name = "key-hints-delay-set!", lib = "(koru-key)")]
+pub fn key_hints_delay_set(delay: &Value) -> Result<Vec<Value>, Exception> {
+    let delay = if delay.is_true() {
+        let milliseconds: SimpleNumber = delay.clone().try_into()?;
+        let milliseconds: usize = milliseconds.try_into()?;
+        Some(Duration::from_millis(milliseconds as u64))
+    } else {
+        None
+    };
+    *DELAY.write().expect("key hints delay lock poisoned") = delay;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "key-hints-delay", lib = "(koru-key)")]
+pub fn key_hints_delay() -> Result<Vec<Value>, Exception> {
+    let delay = match delay() {
+        Some(delay) => Value::from(SimpleNumber::from(delay.as_millis() as i64)),
+        None => Value::from(false),
+    };
+    Ok(vec![delay])
+}
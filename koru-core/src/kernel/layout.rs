@@ -0,0 +1,616 @@
+use std::collections::HashMap;
+use crop::Rope;
+use tokio::sync::RwLock;
+use scheme_rs::exceptions::Exception;
+use scheme_rs::gc::Gc;
+use scheme_rs::lists;
+use scheme_rs::num::SimpleNumber;
+use scheme_rs::registry::bridge;
+use scheme_rs::symbols::Symbol;
+use scheme_rs::value::Value;
+use crate::kernel::buffer::Cursor;
+use crate::kernel::scheme_api::major_mode::MajorMode;
+use crate::kernel::scheme_api::major_mode::text_edit;
+use crate::kernel::scheme_api::session::{Buffer, SessionState};
+use crate::styled_text::StyledFile;
+
+/// The smallest share of a split that either side can be resized to, as a percentage.
+const MIN_RATIO: usize = 10;
+
+/// How a split arranges its two sides.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SplitDirection {
+    /// The second side is below the first
+    Horizontal,
+    /// The second side is to the right of the first
+    Vertical,
+}
+
+impl SplitDirection {
+    fn from_symbol(symbol: &Symbol) -> Result<Self, Exception> {
+        if *symbol == Symbol::intern("horizontal") {
+            Ok(SplitDirection::Horizontal)
+        } else if *symbol == Symbol::intern("vertical") {
+            Ok(SplitDirection::Vertical)
+        } else {
+            Err(Exception::error(format!("Unknown split direction: {symbol}")))
+        }
+    }
+}
+
+/// A direction to move the focus in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FocusDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl FocusDirection {
+    fn from_symbol(symbol: &Symbol) -> Result<Self, Exception> {
+        if *symbol == Symbol::intern("left") {
+            Ok(FocusDirection::Left)
+        } else if *symbol == Symbol::intern("right") {
+            Ok(FocusDirection::Right)
+        } else if *symbol == Symbol::intern("up") {
+            Ok(FocusDirection::Up)
+        } else if *symbol == Symbol::intern("down") {
+            Ok(FocusDirection::Down)
+        } else {
+            Err(Exception::error(format!("Unknown direction: {symbol}")))
+        }
+    }
+}
+
+/// The cells that a pane is drawn in, from the top left of the area that buffers are drawn in.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct PaneArea {
+    pub x: usize,
+    pub y: usize,
+    pub columns: usize,
+    pub lines: usize,
+}
+
+/// A view of a buffer.
+#[derive(Debug, Clone, Default)]
+pub struct Pane {
+    /// The buffer that is shown, this is None until the first buffer is opened
+    buffer_name: Option<String>,
+    /// The cursors of the pane while another pane has the focus.
+    ///
+    /// The focused pane's cursors are the ones in the major mode of its buffer.
+    cursors: Option<Vec<Cursor>>,
+}
+
+impl Pane {
+    pub fn buffer_name(&self) -> Option<&str> {
+        self.buffer_name.as_deref()
+    }
+
+    pub fn cursors(&self) -> Option<&[Cursor]> {
+        self.cursors.as_deref()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Pane(usize),
+    Split {
+        direction: SplitDirection,
+        /// The percentage of the space that the first side gets
+        ratio: usize,
+        first: Box<Node>,
+        second: Box<Node>,
+    },
+}
+
+impl Node {
+    fn pane_ids(&self, ids: &mut Vec<usize>) {
+        match self {
+            Node::Pane(id) => ids.push(*id),
+            Node::Split { first, second, .. } => {
+                first.pane_ids(ids);
+                second.pane_ids(ids);
+            }
+        }
+    }
+
+    fn first_pane(&self) -> usize {
+        match self {
+            Node::Pane(id) => *id,
+            Node::Split { first, .. } => first.first_pane(),
+        }
+    }
+
+    fn contains(&self, pane: usize) -> bool {
+        match self {
+            Node::Pane(id) => *id == pane,
+            Node::Split { first, second, .. } => first.contains(pane) || second.contains(pane),
+        }
+    }
+
+    /// Replaces a pane with a split of it and a new pane.
+    fn split(&mut self, pane: usize, new_pane: usize, direction: SplitDirection) -> bool {
+        match self {
+            Node::Pane(id) if *id == pane => {
+                *self = Node::Split {
+                    direction,
+                    ratio: 50,
+                    first: Box::new(Node::Pane(pane)),
+                    second: Box::new(Node::Pane(new_pane)),
+                };
+                true
+            }
+            Node::Pane(_) => false,
+            Node::Split { first, second, .. } => {
+                first.split(pane, new_pane, direction) || second.split(pane, new_pane, direction)
+            }
+        }
+    }
+
+    /// Removes a pane, the other side of its split takes the place of the split.
+    ///
+    /// Returns the pane that should get the focus.
+    fn remove(&mut self, pane: usize) -> Option<usize> {
+        let Node::Split { first, second, .. } = self else {
+            return None;
+        };
+        let remaining = match (first.as_ref(), second.as_ref()) {
+            (Node::Pane(id), _) if *id == pane => second.as_ref().clone(),
+            (_, Node::Pane(id)) if *id == pane => first.as_ref().clone(),
+            _ => return first.remove(pane).or_else(|| second.remove(pane)),
+        };
+        *self = remaining;
+        Some(self.first_pane())
+    }
+
+    /// Changes the ratio of the innermost split that holds a pane, growing the side the pane is on.
+    fn resize(&mut self, pane: usize, delta: isize) -> bool {
+        let Node::Split { ratio, first, second, .. } = self else {
+            return false;
+        };
+        if first.resize(pane, delta) || second.resize(pane, delta) {
+            return true;
+        }
+        let delta = if first.contains(pane) {
+            delta
+        } else if second.contains(pane) {
+            -delta
+        } else {
+            return false;
+        };
+        *ratio = ratio.saturating_add_signed(delta).clamp(MIN_RATIO, 100 - MIN_RATIO);
+        true
+    }
+
+    fn areas(&self, area: PaneArea, areas: &mut Vec<(usize, PaneArea)>) {
+        match self {
+            Node::Pane(id) => areas.push((*id, area)),
+            Node::Split { direction, ratio, first, second } => {
+                let (first_area, second_area) = split_area(area, *direction, *ratio);
+                first.areas(first_area, areas);
+                second.areas(second_area, areas);
+            }
+        }
+    }
+
+    fn view(&self, views: &mut HashMap<usize, PaneView>) -> PaneLayout {
+        match self {
+            Node::Pane(id) => PaneLayout::Pane(Box::new(views.remove(id).unwrap_or_else(|| PaneView::empty(*id)))),
+            Node::Split { direction, ratio, first, second } => PaneLayout::Split {
+                direction: *direction,
+                ratio: *ratio,
+                first: Box::new(first.view(views)),
+                second: Box::new(second.view(views)),
+            },
+        }
+    }
+}
+
+/// Divides an area between the sides of a split, leaving a line or column between them for a separator.
+fn split_area(area: PaneArea, direction: SplitDirection, ratio: usize) -> (PaneArea, PaneArea) {
+    let total = match direction {
+        SplitDirection::Horizontal => area.lines,
+        SplitDirection::Vertical => area.columns,
+    };
+    let available = total.saturating_sub(1);
+    let first_size = (available * ratio + 50) / 100;
+    let second_size = available - first_size;
+    let second_start = (first_size + 1).min(total);
+    match direction {
+        SplitDirection::Horizontal => (
+            PaneArea { lines: first_size, ..area },
+            PaneArea { y: area.y + second_start, lines: second_size, ..area },
+        ),
+        SplitDirection::Vertical => (
+            PaneArea { columns: first_size, ..area },
+            PaneArea { x: area.x + second_start, columns: second_size, ..area },
+        ),
+    }
+}
+
+/// The panes of a session and how they are arranged.
+///
+/// There is always at least one pane, and exactly one of them has the focus.
+/// The focused pane shows the current buffer.
+#[derive(Debug, Clone)]
+pub struct Layout {
+    root: Node,
+    panes: HashMap<usize, Pane>,
+    focused: usize,
+    next_id: usize,
+}
+
+impl Layout {
+    pub fn new() -> Self {
+        Self {
+            root: Node::Pane(0),
+            panes: HashMap::from([(0, Pane::default())]),
+            focused: 0,
+            next_id: 1,
+        }
+    }
+
+    pub fn focused(&self) -> usize {
+        self.focused
+    }
+
+    pub fn pane(&self, id: usize) -> Option<&Pane> {
+        self.panes.get(&id)
+    }
+
+    /// Gets the ids of the panes from the top left to the bottom right.
+    pub fn pane_ids(&self) -> Vec<usize> {
+        let mut ids = Vec::new();
+        self.root.pane_ids(&mut ids);
+        ids
+    }
+
+    fn set_buffer(&mut self, id: usize, buffer_name: String) {
+        if let Some(pane) = self.panes.get_mut(&id) {
+            pane.buffer_name = Some(buffer_name);
+        }
+    }
+
+    fn set_cursors(&mut self, id: usize, cursors: Option<Vec<Cursor>>) {
+        if let Some(pane) = self.panes.get_mut(&id) {
+            pane.cursors = cursors;
+        }
+    }
+
+    /// Splits the focused pane, the new pane shows the same buffer.
+    ///
+    /// Returns the id of the new pane.
+    fn split(&mut self, direction: SplitDirection) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        let pane = self.panes.get(&self.focused).cloned().unwrap_or_default();
+        self.panes.insert(id, pane);
+        self.root.split(self.focused, id, direction);
+        id
+    }
+
+    /// Removes a pane, returning the pane that takes over the focus if it had it.
+    ///
+    /// The last pane can't be removed.
+    fn remove(&mut self, id: usize) -> Option<usize> {
+        let next = self.root.remove(id)?;
+        self.panes.remove(&id);
+        Some(next)
+    }
+
+    /// Grows the focused pane by a percentage of the split it is in, or shrinks it if the amount is negative.
+    ///
+    /// Returns false if there is only one pane.
+    fn resize(&mut self, delta: isize) -> bool {
+        self.root.resize(self.focused, delta)
+    }
+
+    /// Divides the area that buffers are drawn in between the panes.
+    pub fn areas(&self, columns: usize, lines: usize) -> Vec<(usize, PaneArea)> {
+        let mut areas = Vec::new();
+        self.root.areas(PaneArea { x: 0, y: 0, columns, lines }, &mut areas);
+        areas
+    }
+
+    /// Finds the pane next to the focused pane in a direction.
+    ///
+    /// Out of the panes on that side that line up with the focused pane, the closest one wins.
+    fn neighbor(&self, direction: FocusDirection, columns: usize, lines: usize) -> Option<usize> {
+        let areas = self.areas(columns, lines);
+        let (_, focused) = areas.iter().find(|(id, _)| *id == self.focused)?;
+        let overlaps = |start: usize, len: usize, other_start: usize, other_len: usize| {
+            start < other_start + other_len && other_start < start + len
+        };
+        areas.iter()
+            .filter(|(id, _)| *id != self.focused)
+            .filter_map(|(id, area)| {
+                let distance = match direction {
+                    FocusDirection::Left if area.x + area.columns <= focused.x
+                        && overlaps(area.y, area.lines, focused.y, focused.lines) => focused.x - area.x - area.columns,
+                    FocusDirection::Right if focused.x + focused.columns <= area.x
+                        && overlaps(area.y, area.lines, focused.y, focused.lines) => area.x - focused.x - focused.columns,
+                    FocusDirection::Up if area.y + area.lines <= focused.y
+                        && overlaps(area.x, area.columns, focused.x, focused.columns) => focused.y - area.y - area.lines,
+                    FocusDirection::Down if focused.y + focused.lines <= area.y
+                        && overlaps(area.x, area.columns, focused.x, focused.columns) => area.y - focused.y - focused.lines,
+                    _ => return None,
+                };
+                Some((distance, *id))
+            })
+            .min()
+            .map(|(_, id)| id)
+    }
+
+    /// Finds the pane after or before the focused one, wrapping around.
+    fn cycle(&self, forward: bool) -> usize {
+        let ids = self.pane_ids();
+        let index = ids.iter().position(|id| *id == self.focused).unwrap_or(0);
+        let next = if forward {
+            (index + 1) % ids.len()
+        } else {
+            (index + ids.len() - 1) % ids.len()
+        };
+        ids[next]
+    }
+
+    /// Arranges the drawn panes the same way as the layout.
+    pub fn view(&self, mut views: HashMap<usize, PaneView>) -> PaneLayout {
+        self.root.view(&mut views)
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What a frontend draws for a pane.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PaneView {
+    pub id: usize,
+    pub area: PaneArea,
+    pub text: StyledFile,
+    /// The line numbers, signs, and fold markers drawn to the left of the text, with a line for every row of the text
+    pub gutter: StyledFile,
+    /// How many columns the gutter takes up, the text is drawn in the rest of the pane
+    pub gutter_width: usize,
+    /// The status line drawn below the text, this is empty if there is no status line
+    pub status_line: StyledFile,
+    /// The row and display column that the main cursor is drawn at
+    pub cursor: (usize, usize),
+    pub focused: bool,
+}
+
+impl PaneView {
+    pub fn empty(id: usize) -> Self {
+        Self {
+            id,
+            area: PaneArea::default(),
+            text: StyledFile::new(),
+            gutter: StyledFile::new(),
+            gutter_width: 0,
+            status_line: StyledFile::new(),
+            cursor: (0, 0),
+            focused: false,
+        }
+    }
+}
+
+/// The drawn panes, arranged as they are in the layout.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PaneLayout {
+    Pane(Box<PaneView>),
+    Split {
+        direction: SplitDirection,
+        /// The percentage of the space that the first side gets
+        ratio: usize,
+        first: Box<PaneLayout>,
+        second: Box<PaneLayout>,
+    },
+}
+
+impl PaneLayout {
+    /// Gets the panes from the top left to the bottom right.
+    pub fn panes(&self) -> Vec<&PaneView> {
+        match self {
+            PaneLayout::Pane(view) => vec![view],
+            PaneLayout::Split { first, second, .. } => {
+                let mut panes = first.panes();
+                panes.extend(second.panes());
+                panes
+            }
+        }
+    }
+
+    pub fn focused(&self) -> Option<&PaneView> {
+        self.panes().into_iter().find(|view| view.focused)
+    }
+}
+
+impl Default for PaneLayout {
+    fn default() -> Self {
+        PaneLayout::Pane(Box::new(PaneView::empty(0)))
+    }
+}
+
+/// Gets the cursors of a buffer's major mode, if it has any.
+pub(crate) async fn buffer_cursors(buffer: &Buffer) -> Option<Vec<Cursor>> {
+    let major_mode: Gc<MajorMode> = buffer.get_major_mode().try_to_rust_type().ok()?;
+    let data = text_edit::get_data(&major_mode).await.ok()?;
+    Some(data.get_cursors().await)
+}
+
+async fn set_buffer_cursors(buffer: &Buffer, cursors: Vec<Cursor>) {
+    let Ok(major_mode) = buffer.get_major_mode().try_to_rust_type::<MajorMode>() else {
+        return;
+    };
+    if let Ok(data) = text_edit::get_data(&major_mode).await {
+        data.set_cursors(cursors).await;
+    }
+}
+
+/// Keeps cursors inside of a buffer that was edited from another pane since they were saved.
+pub(crate) fn clamp_cursors(cursors: &mut [Cursor], text: &Rope) {
+    let last_line = text.line_len().saturating_sub(1);
+    for cursor in cursors.iter_mut() {
+        if cursor.line() > last_line {
+            cursor.set_line(last_line);
+        }
+        let line_length = if text.line_len() == 0 {
+            0
+        } else {
+            text.line(cursor.line()).graphemes().count()
+        };
+        if cursor.column() > line_length {
+            cursor.set_column(line_length);
+        }
+    }
+}
+
+/// Keeps the cursors of the current buffer in the focused pane of a layout, for when the pane loses the focus.
+pub(crate) async fn save_focused_cursors(layout: &RwLock<Layout>) {
+    let cursors = match SessionState::get_current_buffer().await {
+        Some(buffer) => buffer_cursors(&buffer).await,
+        None => None,
+    };
+    let mut guard = layout.write().await;
+    let focused = guard.focused;
+    guard.set_cursors(focused, cursors);
+}
+
+/// Makes the buffer of the focused pane of a layout the current buffer and puts the pane's cursors back into it.
+pub(crate) async fn restore_focused_pane(layout: &RwLock<Layout>) {
+    let pane = {
+        let guard = layout.read().await;
+        guard.pane(guard.focused).cloned()
+    };
+    let Some(Pane { buffer_name: Some(buffer_name), cursors }) = pane else {
+        return;
+    };
+    SessionState::set_current_buffer(buffer_name).await;
+    let (Some(mut cursors), Some(buffer)) = (cursors, SessionState::get_current_buffer().await) else {
+        return;
+    };
+    clamp_cursors(&mut cursors, &buffer.get_handle().get_text().await);
+    set_buffer_cursors(&buffer, cursors).await;
+}
+
+/// Moves the focus to a pane and makes its buffer the current buffer.
+///
+/// The cursors of the pane that loses the focus are kept in it unless it was removed,
+/// and the cursors of the pane that gains the focus are put back into its buffer.
+pub(crate) async fn focus_pane(id: usize, keep_old: bool) {
+    let layout = SessionState::get_layout().await;
+    if keep_old {
+        save_focused_cursors(&layout).await;
+    }
+    layout.write().await.focused = id;
+    restore_focused_pane(&layout).await;
+}
+
+/// Records that the focused pane now shows a buffer.
+pub(crate) async fn show_buffer(buffer_name: String) {
+    let layout = SessionState::get_layout().await;
+    let mut guard = layout.write().await;
+    let focused = guard.focused;
+    guard.set_buffer(focused, buffer_name);
+}
+
+#[bridge(name = "pane-split", lib = "(koru-pane)")]
+pub async fn pane_split(direction: &Value) -> Result<Vec<Value>, Exception> {
+    let direction: Symbol = direction.clone().try_into()?;
+    let direction = SplitDirection::from_symbol(&direction)?;
+    let id = {
+        let layout = SessionState::get_layout().await;
+        let mut guard = layout.write().await;
+        guard.split(direction)
+    };
+    focus_pane(id, true).await;
+    Ok(vec![Value::from(id)])
+}
+
+#[bridge(name = "pane-close", lib = "(koru-pane)")]
+pub async fn pane_close() -> Result<Vec<Value>, Exception> {
+    let next = {
+        let layout = SessionState::get_layout().await;
+        let mut guard = layout.write().await;
+        let focused = guard.focused;
+        guard.remove(focused)
+    };
+    let Some(next) = next else {
+        return Err(Exception::error(String::from("Can't close the last pane")));
+    };
+    focus_pane(next, false).await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "pane-resize", lib = "(koru-pane)")]
+pub async fn pane_resize(delta: &Value) -> Result<Vec<Value>, Exception> {
+    let delta: SimpleNumber = delta.clone().try_into()?;
+    let delta: isize = delta.try_into()?;
+    let layout = SessionState::get_layout().await;
+    let resized = layout.write().await.resize(delta);
+    Ok(vec![Value::from(resized)])
+}
+
+#[bridge(name = "pane-focus", lib = "(koru-pane)")]
+pub async fn pane_focus(direction: &Value) -> Result<Vec<Value>, Exception> {
+    let direction: Symbol = direction.clone().try_into()?;
+    let direction = FocusDirection::from_symbol(&direction)?;
+    let (columns, lines) = SessionState::get_viewport().await;
+    let neighbor = {
+        let layout = SessionState::get_layout().await;
+        let guard = layout.read().await;
+        guard.neighbor(direction, columns, lines)
+    };
+    let Some(neighbor) = neighbor else {
+        return Ok(vec![Value::from(false)]);
+    };
+    focus_pane(neighbor, true).await;
+    Ok(vec![Value::from(true)])
+}
+
+#[bridge(name = "pane-focus-next", lib = "(koru-pane)")]
+pub async fn pane_focus_next() -> Result<Vec<Value>, Exception> {
+    let next = SessionState::get_layout().await.read().await.cycle(true);
+    focus_pane(next, true).await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "pane-focus-previous", lib = "(koru-pane)")]
+pub async fn pane_focus_previous() -> Result<Vec<Value>, Exception> {
+    let previous = SessionState::get_layout().await.read().await.cycle(false);
+    focus_pane(previous, true).await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "pane-current", lib = "(koru-pane)")]
+pub async fn pane_current() -> Result<Vec<Value>, Exception> {
+    let focused = SessionState::get_layout().await.read().await.focused();
+    Ok(vec![Value::from(focused)])
+}
+
+#[bridge(name = "pane-list", lib = "(koru-pane)")]
+pub async fn pane_list() -> Result<Vec<Value>, Exception> {
+    let ids = SessionState::get_layout().await.read().await.pane_ids();
+    let ids = ids.into_iter().map(Value::from).collect::<Vec<_>>();
+    Ok(vec![lists::slice_to_list(&ids)])
+}
+
+#[bridge(name = "pane-buffer", lib = "(koru-pane)")]
+pub async fn pane_buffer(id: &Value) -> Result<Vec<Value>, Exception> {
+    let id: SimpleNumber = id.clone().try_into()?;
+    let id: usize = id.try_into()?;
+    let layout = SessionState::get_layout().await;
+    let guard = layout.read().await;
+    let Some(pane) = guard.pane(id) else {
+        return Err(Exception::error(format!("Pane not found: {id}")));
+    };
+    match pane.buffer_name() {
+        Some(buffer_name) => Ok(vec![Value::from(buffer_name.to_string())]),
+        None => Ok(vec![Value::from(false)]),
+    }
+}
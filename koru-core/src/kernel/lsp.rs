@@ -0,0 +1,704 @@
+mod client;
+mod document;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex, RwLock};
+use crop::Rope;
+use log::warn;
+use scheme_rs::exceptions::Exception;
+use scheme_rs::lists::{self, List};
+use scheme_rs::num::SimpleNumber;
+use scheme_rs::registry::bridge;
+use scheme_rs::value::{UnpackedValue, Value};
+use serde_json::{json, Value as Json};
+use tokio::sync::OnceCell;
+use crate::kernel::buffer::{Diagnostic, Severity};
+use crate::kernel::scheme_api::major_mode::MajorMode;
+use crate::kernel::scheme_api::major_mode::text_edit::get_data;
+use crate::kernel::scheme_api::session::{Buffer, SessionState};
+
+pub use client::LanguageClient;
+pub use document::{position_of_byte, resolve_edits, DocumentSync};
+
+/// The owner of the diagnostics that come from language servers.
+pub const DIAGNOSTICS_OWNER: &str = "lsp";
+
+/// How to launch the language server for a language.
+#[derive(Clone)]
+pub struct ServerConfig {
+    pub command: String,
+    pub args: Vec<String>,
+    /// Files or directories that mark the root of a project, like `Cargo.toml` or `.git`.
+    pub root_markers: Vec<String>,
+}
+
+static SERVERS: LazyLock<RwLock<HashMap<String, ServerConfig>>> = LazyLock::new(|| {
+    RwLock::new(HashMap::new())
+});
+static FILE_TYPES: LazyLock<RwLock<HashMap<String, String>>> = LazyLock::new(|| {
+    RwLock::new(HashMap::new())
+});
+/// The language and project root that a client was started for.
+type ClientKey = (String, PathBuf);
+
+/// The client of a language and project root, which is empty until its server has started.
+type ClientCell = Arc<OnceCell<Arc<LanguageClient>>>;
+
+/// One client per language and project root.
+///
+/// The lock is only held to get or insert a cell, so that a server taking its time to start
+/// doesn't hold up the clients of other projects.
+static CLIENTS: LazyLock<Mutex<HashMap<ClientKey, ClientCell>>> = LazyLock::new(|| {
+    Mutex::new(HashMap::new())
+});
+
+/// Finds the project root of a file by walking up from it until a directory contains one of the root markers.
+///
+/// Falls back to the directory of the file.
+fn find_root(path: &Path, root_markers: &[String]) -> PathBuf {
+    let directory = path.parent().unwrap_or(path);
+    directory.ancestors()
+        .find(|ancestor| root_markers.iter().any(|marker| ancestor.join(marker).exists()))
+        .unwrap_or(directory)
+        .to_path_buf()
+}
+
+/// Gets the client for the project that a file is in, starting the server if it isn't running.
+async fn client_for(language: &str, path: &Path) -> Result<Arc<LanguageClient>, Exception> {
+    let Some(config) = SERVERS.read().expect("server lock poisoned").get(language).cloned() else {
+        return Err(Exception::error(format!("No language server configured for {language}")));
+    };
+    let root = find_root(path, &config.root_markers);
+    let key = (language.to_string(), root);
+
+    let cell = {
+        let mut clients = CLIENTS.lock().expect("client lock poisoned");
+        let cell = clients.entry(key.clone()).or_default();
+        // A server that has stopped is started again in a new cell
+        if cell.get().is_some_and(|client| !client.is_running()) {
+            *cell = ClientCell::default();
+        }
+        cell.clone()
+    };
+    // Callers for the same project wait for the one start, and a start that fails leaves the cell empty for the next one
+    let client = cell.get_or_try_init(|| LanguageClient::start(language, &config, &key.1)).await?;
+    Ok(client.clone())
+}
+
+/// Finds the open buffer for a file.
+async fn buffer_for_path(path: &Path) -> Option<Buffer> {
+    let buffers = {
+        let state = SessionState::get_state();
+        let guard = state.read().await;
+        let buffers = guard.get_buffers().await;
+        buffers.values().cloned().collect::<Vec<_>>()
+    };
+    let canonical = path.canonicalize().ok();
+    for buffer in buffers {
+        let Some(buffer_path) = buffer.get_handle().get_path().await else {
+            continue;
+        };
+        let buffer_path = PathBuf::from(buffer_path);
+        if buffer_path == path || Some(&buffer_path) == canonical.as_ref() {
+            return Some(buffer);
+        }
+    }
+    None
+}
+
+/// Applies a list of `TextEdit`s to an open buffer, moving the cursors of its major mode along with the text.
+async fn apply_buffer_edits(buffer: &Buffer, edits: &[Json]) {
+    let data = match buffer.get_major_mode().try_to_rust_type::<MajorMode>() {
+        Ok(major_mode) => get_data(&major_mode).await.ok(),
+        Err(_) => None,
+    };
+    let cursors = match &data {
+        Some(data) => data.get_cursors().await,
+        None => Vec::new(),
+    };
+    let cursors = buffer.get_handle().apply_lsp_edits(edits, cursors).await;
+    if let Some(data) = data {
+        data.set_cursors(cursors).await;
+    }
+}
+
+/// Gets the text of a file, preferring the open buffer over what is on disk.
+async fn text_for_path(path: &Path) -> Result<Rope, Exception> {
+    if let Some(buffer) = buffer_for_path(path).await {
+        return Ok(buffer.get_handle().get_text().await);
+    }
+    let text = tokio::fs::read_to_string(path).await
+        .map_err(|err| Exception::error(format!("Unable to read {}: {err}", path.display())))?;
+    Ok(Rope::from(text))
+}
+
+/// Replaces the diagnostics of an open buffer with the ones a server published.
+///
+/// Diagnostics for files that aren't open are dropped since the server sends them again when the file gets opened.
+pub async fn publish_diagnostics(language: &str, params: &Json) {
+    let Some(path) = params["uri"].as_str().and_then(document::uri_to_path) else {
+        return;
+    };
+    let Some(buffer) = buffer_for_path(&path).await else {
+        return;
+    };
+    let handle = buffer.get_handle();
+    let diagnostics = params["diagnostics"].as_array().map(Vec::as_slice).unwrap_or_default();
+    handle.set_diagnostics(DIAGNOSTICS_OWNER, |rope| {
+        diagnostics.iter()
+            .map(|diagnostic| Diagnostic {
+                range: document::byte_range(rope, &diagnostic["range"]),
+                severity: Severity::from_lsp(diagnostic["severity"].as_i64()),
+                message: diagnostic["message"].as_str().unwrap_or_default().to_string(),
+                source: diagnostic["source"].as_str().unwrap_or(language).to_string(),
+            })
+            .collect()
+    }).await;
+}
+
+/// Applies a `WorkspaceEdit` from a server.
+///
+/// Files that are open get edited through their buffer so the edits can be undone.
+/// Files that aren't open get edited on disk.
+/// Returns the number of files that were changed.
+pub async fn apply_workspace_edit(edit: &Json) -> Result<usize, Exception> {
+    let mut documents: Vec<(&str, &Json)> = Vec::new();
+    if let Some(changes) = edit["documentChanges"].as_array() {
+        for change in changes {
+            if let Some(uri) = change["textDocument"]["uri"].as_str() {
+                documents.push((uri, &change["edits"]));
+            } else if let Some(kind) = change["kind"].as_str() {
+                warn!("Skipping unsupported resource operation: {}", kind);
+            }
+        }
+    } else if let Some(changes) = edit["changes"].as_object() {
+        for (uri, edits) in changes {
+            documents.push((uri.as_str(), edits));
+        }
+    }
+
+    for (uri, edits) in &documents {
+        let Some(path) = document::uri_to_path(uri) else {
+            return Err(Exception::error(format!("Unable to edit non-file uri: {uri}")));
+        };
+        let edits = edits.as_array().map(Vec::as_slice).unwrap_or_default();
+        if let Some(buffer) = buffer_for_path(&path).await {
+            apply_buffer_edits(&buffer, edits).await;
+        } else {
+            let mut rope = text_for_path(&path).await?;
+            for (range, text) in document::resolve_edits(&rope, edits) {
+                rope.replace(range, &text);
+            }
+            tokio::fs::write(&path, rope.to_string()).await
+                .map_err(|err| Exception::error(format!("Unable to write {}: {err}", path.display())))?;
+        }
+    }
+    Ok(documents.len())
+}
+
+fn string_list(value: &Value) -> Result<Vec<String>, Exception> {
+    let list: List = value.try_into()?;
+    list.into_iter()
+        .map(String::try_from)
+        .collect()
+}
+
+fn number_arg(value: &Value) -> Result<usize, Exception> {
+    let number: SimpleNumber = value.clone().try_into()?;
+    number.try_into()
+}
+
+/// Reads the common `(buffer-name line column)` arguments.
+fn position_args(args: &[Value], expected: usize) -> Result<(String, usize, usize), Exception> {
+    let [buffer_name, line, column, ..] = args else {
+        return Err(Exception::wrong_num_of_args(expected, args.len()));
+    };
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    Ok((buffer_name, number_arg(line)?, number_arg(column)?))
+}
+
+/// Sends a request about a position in a buffer.
+async fn position_request(
+    buffer_name: &str,
+    line: usize,
+    column: usize,
+    method: &str,
+    mut params: Json
+) -> Result<Json, Exception> {
+    let handle = SessionState::get_buffer_handle(buffer_name).await?;
+    let Some((client, uri, position)) = handle.lsp_position(line, column).await else {
+        return Err(Exception::error(format!("Buffer is not attached to a language server: {buffer_name}")));
+    };
+    params["textDocument"] = json!({ "uri": uri });
+    params["position"] = position;
+    client.request(method, params).await
+}
+
+/// Turns a `Location`, `Location[]` or `LocationLink[]` into a list of `(path line column)` lists.
+async fn locations_to_list(result: &Json) -> Result<Value, Exception> {
+    let locations = match result {
+        Json::Array(locations) => locations.as_slice(),
+        Json::Null => &[],
+        location => std::slice::from_ref(location),
+    };
+    let mut values = Vec::with_capacity(locations.len());
+    for location in locations {
+        let (uri, range) = match location.get("targetUri") {
+            Some(uri) => (uri, &location["targetSelectionRange"]),
+            None => (&location["uri"], &location["range"]),
+        };
+        let Some(path) = uri.as_str().and_then(document::uri_to_path) else {
+            continue;
+        };
+        let rope = text_for_path(&path).await?;
+        let (line, column) = document::grid_of_position(&rope, &range["start"]);
+        values.push(lists::slice_to_list(&[
+            Value::from(path.to_string_lossy().to_string()),
+            Value::from(SimpleNumber::from(line)),
+            Value::from(SimpleNumber::from(column)),
+        ]));
+    }
+    Ok(lists::slice_to_list(&values))
+}
+
+/// Flattens the different shapes of hover contents into plain text.
+fn hover_text(contents: &Json) -> String {
+    match contents {
+        Json::String(text) => text.clone(),
+        Json::Array(parts) => parts.iter()
+            .map(hover_text)
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        Json::Object(part) => part.get("value")
+            .and_then(Json::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        _ => String::new(),
+    }
+}
+
+#[bridge(name = "lsp-server-set!", lib = "(koru-lsp)")]
+pub async fn lsp_server_set(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let Some((language, rest)) = args.split_first() else {
+        return Err(Exception::wrong_num_of_args(2, args.len()));
+    };
+    let Some((command, rest)) = rest.split_first() else {
+        return Err(Exception::wrong_num_of_args(2, args.len()));
+    };
+    let language: String = language.clone().try_into()?;
+    let command: String = command.clone().try_into()?;
+    let (server_args, rest) = match rest.split_first() {
+        Some((server_args, rest)) => (string_list(server_args)?, rest),
+        None => (Vec::new(), rest),
+    };
+    let root_markers = match rest.split_first() {
+        Some((root_markers, _)) => string_list(root_markers)?,
+        None => vec![String::from(".git")],
+    };
+
+    SERVERS.write().expect("server lock poisoned").insert(language, ServerConfig {
+        command,
+        args: server_args,
+        root_markers,
+    });
+    Ok(Vec::new())
+}
+
+#[bridge(name = "lsp-server-configured?", lib = "(koru-lsp)")]
+pub async fn lsp_server_configured(language: &Value) -> Result<Vec<Value>, Exception> {
+    let language: String = language.clone().try_into()?;
+    let configured = SERVERS.read().expect("server lock poisoned").contains_key(&language);
+    Ok(vec![Value::from(configured)])
+}
+
+#[bridge(name = "lsp-server-stop", lib = "(koru-lsp)")]
+pub async fn lsp_server_stop(language: &Value) -> Result<Vec<Value>, Exception> {
+    let language: String = language.clone().try_into()?;
+    let stopped = {
+        let mut clients = CLIENTS.lock().expect("client lock poisoned");
+        let keys = clients.keys()
+            .filter(|(client_language, _)| *client_language == language)
+            .cloned()
+            .collect::<Vec<_>>();
+        keys.into_iter()
+            .filter_map(|key| clients.remove(&key))
+            .filter_map(|cell| cell.get().cloned())
+            .collect::<Vec<_>>()
+    };
+    for client in stopped {
+        client.shutdown().await;
+    }
+    Ok(Vec::new())
+}
+
+#[bridge(name = "lsp-file-type-set!", lib = "(koru-lsp)")]
+pub async fn lsp_file_type_set(extension: &Value, language: &Value) -> Result<Vec<Value>, Exception> {
+    let extension: String = extension.clone().try_into()?;
+    let language: String = language.clone().try_into()?;
+    FILE_TYPES.write().expect("file type lock poisoned").insert(extension, language);
+    Ok(Vec::new())
+}
+
+#[bridge(name = "lsp-file-type", lib = "(koru-lsp)")]
+pub async fn lsp_file_type(extension: &Value) -> Result<Vec<Value>, Exception> {
+    let extension: String = extension.clone().try_into()?;
+    let language = FILE_TYPES.read().expect("file type lock poisoned").get(&extension).cloned();
+    match language {
+        Some(language) => Ok(vec![Value::from(language)]),
+        None => Ok(vec![Value::from(false)]),
+    }
+}
+
+#[bridge(name = "lsp-attach", lib = "(koru-lsp)")]
+pub async fn lsp_attach(buffer_name: &Value, language: &Value) -> Result<Vec<Value>, Exception> {
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let language: String = language.clone().try_into()?;
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    let Some(path) = handle.get_path().await else {
+        return Err(Exception::error(format!("Buffer has no associated path: {buffer_name}")));
+    };
+    let path = PathBuf::from(path);
+    let path = path.canonicalize().unwrap_or(path);
+    let client = client_for(&language, &path).await?;
+    handle.attach_lsp(client, &path).await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "lsp-detach", lib = "(koru-lsp)")]
+pub async fn lsp_detach(buffer_name: &Value) -> Result<Vec<Value>, Exception> {
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    handle.detach_lsp().await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "lsp-buffer-language", lib = "(koru-lsp)")]
+pub async fn lsp_buffer_language(buffer_name: &Value) -> Result<Vec<Value>, Exception> {
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let handle = SessionState::get_buffer_handle(&buffer_name).await?;
+    match handle.lsp_language().await {
+        Some(language) => Ok(vec![Value::from(language)]),
+        None => Ok(vec![Value::from(false)]),
+    }
+}
+
+#[bridge(name = "lsp-definition", lib = "(koru-lsp)")]
+pub async fn lsp_definition(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let (buffer_name, line, column) = position_args(args, 3)?;
+    let result = position_request(&buffer_name, line, column, "textDocument/definition", json!({})).await?;
+    Ok(vec![locations_to_list(&result).await?])
+}
+
+#[bridge(name = "lsp-references", lib = "(koru-lsp)")]
+pub async fn lsp_references(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let (buffer_name, line, column) = position_args(args, 3)?;
+    let params = json!({
+        "context": {
+            "includeDeclaration": true,
+        },
+    });
+    let result = position_request(&buffer_name, line, column, "textDocument/references", params).await?;
+    Ok(vec![locations_to_list(&result).await?])
+}
+
+#[bridge(name = "lsp-hover", lib = "(koru-lsp)")]
+pub async fn lsp_hover(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let (buffer_name, line, column) = position_args(args, 3)?;
+    let result = position_request(&buffer_name, line, column, "textDocument/hover", json!({})).await?;
+    let text = hover_text(&result["contents"]);
+    if text.is_empty() {
+        Ok(vec![Value::from(false)])
+    } else {
+        Ok(vec![Value::from(text)])
+    }
+}
+
+/// Turns a `CompletionList` or `CompletionItem[]` into a list of `(label text detail start)` lists.
+///
+/// The start is the column that the item's text edit replaces from, or false if the item has no text edit.
+fn completion_items_to_list(result: &Json, rope: &Rope) -> Value {
+    let items = match result {
+        Json::Array(items) => items.as_slice(),
+        Json::Object(list) => list.get("items").and_then(Json::as_array).map(Vec::as_slice).unwrap_or_default(),
+        _ => &[],
+    };
+    let values = items.iter()
+        .filter_map(|item| {
+            let label = item["label"].as_str()?;
+            let edit = &item["textEdit"];
+            // An InsertReplaceEdit has two ranges instead of one
+            let range = edit.get("range").or_else(|| edit.get("replace"));
+            let text = edit["newText"].as_str()
+                .or_else(|| item["insertText"].as_str())
+                .unwrap_or(label);
+            let detail = item["detail"].as_str().unwrap_or_default();
+            let start = match range {
+                Some(range) => Value::from(SimpleNumber::from(document::grid_of_position(rope, &range["start"]).1)),
+                None => Value::from(false),
+            };
+            Some(lists::slice_to_list(&[
+                Value::from(label.to_string()),
+                Value::from(text.to_string()),
+                Value::from(detail.to_string()),
+                start,
+            ]))
+        })
+        .collect::<Vec<_>>();
+    lists::slice_to_list(&values)
+}
+
+#[bridge(name = "lsp-completion", lib = "(koru-lsp)")]
+pub async fn lsp_completion(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let (buffer_name, line, column) = position_args(args, 3)?;
+    let result = position_request(&buffer_name, line, column, "textDocument/completion", json!({})).await?;
+    let rope = SessionState::get_buffer_handle(&buffer_name).await?.get_text().await;
+    Ok(vec![completion_items_to_list(&result, &rope)])
+}
+
+#[bridge(name = "lsp-rename", lib = "(koru-lsp)")]
+pub async fn lsp_rename(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let (buffer_name, line, column) = position_args(args, 4)?;
+    let Some(new_name) = args.get(3) else {
+        return Err(Exception::wrong_num_of_args(4, args.len()));
+    };
+    let new_name: String = new_name.clone().try_into()?;
+    let params = json!({ "newName": new_name });
+    let result = position_request(&buffer_name, line, column, "textDocument/rename", params).await?;
+    let changed = apply_workspace_edit(&result).await?;
+    Ok(vec![Value::from(SimpleNumber::from(changed))])
+}
+
+#[bridge(name = "lsp-format", lib = "(koru-lsp)")]
+pub async fn lsp_format(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let Some((buffer_name, rest)) = args.split_first() else {
+        return Err(Exception::wrong_num_of_args(1, args.len()));
+    };
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let tab_size = match rest.first() {
+        Some(tab_size) => number_arg(tab_size)?,
+        None => 4,
+    };
+    let insert_spaces = match rest.get(1).map(|value| value.clone().unpack()) {
+        Some(UnpackedValue::Boolean(insert_spaces)) => insert_spaces,
+        Some(other) => return Err(Exception::type_error("Boolean", other.type_name())),
+        None => true,
+    };
+
+    let buffer = SessionState::get_buffer(&buffer_name).await?;
+    let Some((client, uri)) = buffer.get_handle().lsp_document().await else {
+        return Err(Exception::error(format!("Buffer is not attached to a language server: {buffer_name}")));
+    };
+    let params = json!({
+        "textDocument": { "uri": uri },
+        "options": {
+            "tabSize": tab_size,
+            "insertSpaces": insert_spaces,
+        },
+    });
+    let result = client.request("textDocument/formatting", params).await?;
+    let edits = result.as_array().map(Vec::as_slice).unwrap_or_default();
+    apply_buffer_edits(&buffer, edits).await;
+    Ok(Vec::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use crop::Rope;
+    use scheme_rs::lists::List;
+    use scheme_rs::num::SimpleNumber;
+    use scheme_rs::value::Value;
+    use serde_json::{json, Value as Json};
+    use crate::kernel::buffer::{EditOperation, EditValue};
+    use crate::kernel::lsp::client::SyncKind;
+    use super::{apply_workspace_edit, client_for, locations_to_list, ServerConfig, SERVERS};
+    use super::document::path_to_uri;
+    use super::{position_of_byte, DocumentSync, LanguageClient};
+
+    const SOURCE: &str = "fn add(a: i32) -> i32 {\n    a + 1\n}\n\nfn main() {\n    add(add(1));\n}\n";
+
+    /// Runs the mock server under `tests/`, which needs `python3`.
+    fn mock_config() -> ServerConfig {
+        ServerConfig {
+            command: String::from("python3"),
+            args: vec![concat!(env!("CARGO_MANIFEST_DIR"), "/tests/lsp_mock.py").to_string()],
+            root_markers: Vec::new(),
+        }
+    }
+
+    /// Makes an empty project directory for a test.
+    fn project(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("koru-lsp-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    async fn start(root: &Path) -> Arc<LanguageClient> {
+        LanguageClient::start("mock", &mock_config(), root).await.unwrap()
+    }
+
+    /// Asks the mock server for its copy of a document.
+    async fn server_document(client: &LanguageClient, uri: &str) -> Json {
+        client.request("mock/document", json!({ "uri": uri })).await.unwrap()
+    }
+
+    /// Sends a request about the position of a byte in a file.
+    async fn request_at(client: &LanguageClient, path: &Path, byte: usize, method: &str, mut params: Json) -> Json {
+        params["textDocument"] = json!({ "uri": path_to_uri(path) });
+        params["position"] = position_of_byte(&Rope::from(SOURCE), byte);
+        client.request(method, params).await.unwrap()
+    }
+
+    fn number(value: &Value) -> usize {
+        let number: SimpleNumber = value.clone().try_into().unwrap();
+        number.try_into().unwrap()
+    }
+
+    /// Turns the list made by [`locations_to_list`] back into `(path line column)` tuples.
+    fn locations(value: Value) -> Vec<(String, usize, usize)> {
+        let list: List = (&value).try_into().unwrap();
+        list.into_iter()
+            .map(|location| {
+                let location: List = (&location).try_into().unwrap();
+                let [path, line, column] = location.as_slice() else {
+                    panic!("location should have a path, a line and a column");
+                };
+                (path.clone().try_into().unwrap(), number(line), number(column))
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn initialize_reads_the_server_capabilities() {
+        let root = project("initialize");
+        let client = start(&root).await;
+
+        assert!(client.is_running());
+        assert_eq!(client.root(), root);
+        assert_eq!(client.sync_kind(), SyncKind::Incremental);
+        assert_eq!(client.save_options(), Some(false));
+        assert!(client.supports("definitionProvider"));
+        assert!(client.supports("referencesProvider"));
+        assert!(client.supports("renameProvider"));
+        assert!(!client.supports("hoverProvider"));
+
+        client.shutdown().await;
+        assert!(!client.is_running());
+    }
+
+    #[tokio::test]
+    async fn document_changes_keep_the_server_in_sync() {
+        let root = project("sync");
+        let client = start(&root).await;
+        let path = root.join("main.rs");
+        let mut rope = Rope::from(SOURCE);
+        let mut document = DocumentSync::open(client.clone(), &path, &rope);
+
+        let opened = server_document(&client, document.uri()).await;
+        assert_eq!(opened["text"], SOURCE);
+        assert_eq!(opened["version"], 0);
+
+        // Characters outside the basic plane take two UTF-16 code units, so later columns on the line move by two
+        let edits = [
+            EditOperation::new(28, EditValue::Insert { text: String::from("🦀 é ") }),
+            EditOperation::new(40, EditValue::Replace { count: 1, text: String::from("2\n    ") }),
+            EditOperation::new(0, EditValue::Delete { count: 3 }),
+        ];
+        for edit in &edits {
+            document.edit(&rope, edit);
+            match &edit.value {
+                EditValue::Insert { text } => rope.insert(edit.byte_offset, text),
+                EditValue::Replace { count, text } => rope.replace(edit.byte_offset..edit.byte_offset + count, text),
+                EditValue::Delete { count } => rope.delete(edit.byte_offset..edit.byte_offset + count),
+                EditValue::Bulk(_) => unreachable!(),
+            }
+        }
+        document.flush(&rope);
+
+        let changed = server_document(&client, document.uri()).await;
+        assert_eq!(changed["text"], rope.to_string());
+        assert_eq!(changed["version"], 1);
+
+        // A bulk edit can't be described as ranges, so the whole text is sent instead
+        document.edit(&rope, &EditOperation::new(0, EditValue::Bulk(Vec::new())));
+        rope = Rope::from("fn main() {}\n");
+        document.flush(&rope);
+
+        let replaced = server_document(&client, document.uri()).await;
+        assert_eq!(replaced["text"], "fn main() {}\n");
+        assert_eq!(replaced["version"], 2);
+
+        let uri = document.uri().to_string();
+        drop(document);
+        assert_eq!(server_document(&client, &uri).await, Json::Null);
+        client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn definition_goes_to_the_first_occurrence() {
+        let root = project("definition");
+        let path = root.join("main.rs");
+        std::fs::write(&path, SOURCE).unwrap();
+        let client = start(&root).await;
+
+        let call = SOURCE.find("add(1)").unwrap();
+        let result = request_at(&client, &path, call, "textDocument/definition", json!({})).await;
+        let path = path.to_string_lossy().to_string();
+        assert_eq!(locations(locations_to_list(&result).await.unwrap()), vec![(path, 0, 3)]);
+        client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn references_list_every_occurrence() {
+        let root = project("references");
+        let path = root.join("main.rs");
+        std::fs::write(&path, SOURCE).unwrap();
+        let client = start(&root).await;
+
+        let params = json!({ "context": { "includeDeclaration": true } });
+        let result = request_at(&client, &path, 4, "textDocument/references", params).await;
+        let path = path.to_string_lossy().to_string();
+        assert_eq!(
+            locations(locations_to_list(&result).await.unwrap()),
+            vec![(path.clone(), 0, 3), (path.clone(), 5, 4), (path, 5, 8)]
+        );
+        client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn rename_edits_files_that_are_not_open() {
+        let root = project("rename");
+        let path = root.join("main.rs");
+        std::fs::write(&path, SOURCE).unwrap();
+        let client = start(&root).await;
+
+        let result = request_at(&client, &path, 4, "textDocument/rename", json!({ "newName": "sum" })).await;
+        assert_eq!(apply_workspace_edit(&result).await.unwrap(), 1);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), SOURCE.replace("add", "sum"));
+        client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn clients_are_shared_by_files_in_a_project() {
+        let root = project("shared");
+        SERVERS.write().unwrap().insert(String::from("mock-shared"), mock_config());
+
+        let (a, b) = (root.join("a.rs"), root.join("b.rs"));
+
+        // Both callers wait on the same start instead of starting a server each
+        let (first, second) = tokio::join!(client_for("mock-shared", &a), client_for("mock-shared", &b));
+        let (first, second) = (first.unwrap(), second.unwrap());
+        assert!(Arc::ptr_eq(&first, &second));
+
+        // A server that stopped is started again
+        first.shutdown().await;
+        let restarted = client_for("mock-shared", &a).await.unwrap();
+        assert!(!Arc::ptr_eq(&first, &restarted));
+        assert!(restarted.is_running());
+        restarted.shutdown().await;
+    }
+}
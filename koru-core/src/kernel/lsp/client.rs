@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use log::{debug, error, info, warn};
+use scheme_rs::exceptions::Exception;
+use serde_json::{json, Value as Json};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+use crate::kernel::lsp::document::path_to_uri;
+use crate::kernel::lsp::ServerConfig;
+
+/// How long to wait for a response before giving up on a request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Servers can take a while to index a project before they answer `initialize`.
+const INITIALIZE_TIMEOUT: Duration = Duration::from_secs(60);
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// JSON-RPC error code for requests that the client doesn't handle.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// How the server wants to be told about changes to documents.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SyncKind {
+    None,
+    Full,
+    Incremental,
+}
+
+type PendingRequests = Arc<Mutex<HashMap<i64, oneshot::Sender<Result<Json, String>>>>>;
+
+/// A connection to a language server process that talks over stdio.
+pub struct LanguageClient {
+    language: String,
+    root: PathBuf,
+    outgoing: UnboundedSender<Json>,
+    pending: PendingRequests,
+    next_id: AtomicI64,
+    running: Arc<AtomicBool>,
+    capabilities: RwLock<Json>,
+    child: tokio::sync::Mutex<Child>,
+}
+
+impl LanguageClient {
+    /// Launches the server and waits for it to finish initializing.
+    pub async fn start(language: &str, config: &ServerConfig, root: &Path) -> Result<Arc<Self>, Exception> {
+        let mut child = Command::new(&config.command)
+            .args(&config.args)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|err| Exception::error(format!("Unable to start language server '{}': {err}", config.command)))?;
+
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+
+        let (outgoing, receiver) = tokio::sync::mpsc::unbounded_channel();
+        let pending: PendingRequests = Arc::new(Mutex::new(HashMap::new()));
+        let running = Arc::new(AtomicBool::new(true));
+
+        tokio::spawn(write_messages(stdin, receiver));
+        tokio::spawn(read_messages(
+            language.to_string(),
+            stdout,
+            outgoing.clone(),
+            pending.clone(),
+            running.clone()
+        ));
+        tokio::spawn(log_stderr(language.to_string(), stderr));
+
+        let client = Arc::new(Self {
+            language: language.to_string(),
+            root: root.to_path_buf(),
+            outgoing,
+            pending,
+            next_id: AtomicI64::new(0),
+            running,
+            capabilities: RwLock::new(Json::Null),
+            child: tokio::sync::Mutex::new(child),
+        });
+        client.initialize().await?;
+        info!("Started {} language server for {}", language, root.display());
+        Ok(client)
+    }
+
+    async fn initialize(&self) -> Result<(), Exception> {
+        let root_uri = path_to_uri(&self.root);
+        let root_name = self.root.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.root.to_string_lossy().to_string());
+        let params = json!({
+            "processId": std::process::id(),
+            "clientInfo": {
+                "name": "koru",
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "rootPath": self.root.to_string_lossy(),
+            "rootUri": root_uri,
+            "workspaceFolders": [{
+                "uri": root_uri,
+                "name": root_name,
+            }],
+            "capabilities": {
+                "general": {
+                    "positionEncodings": ["utf-16"],
+                },
+                "workspace": {
+                    "applyEdit": true,
+                    "configuration": true,
+                    "workspaceFolders": true,
+                    "workspaceEdit": {
+                        "documentChanges": true,
+                    },
+                },
+                "textDocument": {
+                    "synchronization": {
+                        "didSave": true,
+                    },
+                    "hover": {
+                        "contentFormat": ["plaintext", "markdown"],
+                    },
+                    "completion": {
+                        "completionItem": {
+                            "snippetSupport": false,
+                        },
+                    },
+                    "definition": {
+                        "linkSupport": true,
+                    },
+                    "references": {},
+                    "rename": {},
+                    "formatting": {},
+                },
+            },
+        });
+        let result = self.request_with_timeout("initialize", params, INITIALIZE_TIMEOUT).await?;
+        *self.capabilities.write().unwrap() = result["capabilities"].clone();
+        self.notify("initialized", json!({}));
+        Ok(())
+    }
+
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Acquire)
+    }
+
+    pub fn sync_kind(&self) -> SyncKind {
+        let capabilities = self.capabilities.read().unwrap();
+        let sync = &capabilities["textDocumentSync"];
+        let kind = sync.as_i64().or_else(|| sync["change"].as_i64());
+        match kind {
+            Some(1) => SyncKind::Full,
+            Some(2) => SyncKind::Incremental,
+            _ => SyncKind::None,
+        }
+    }
+
+    /// Gets whether the server wants to hear about saves.
+    ///
+    /// Returns `Some(true)` if the server also wants the text of the document when it is saved.
+    pub fn save_options(&self) -> Option<bool> {
+        let capabilities = self.capabilities.read().unwrap();
+        let sync = &capabilities["textDocumentSync"];
+        if sync.is_number() {
+            return Some(false);
+        }
+        match &sync["save"] {
+            Json::Bool(true) => Some(false),
+            Json::Object(options) => Some(options.get("includeText").and_then(Json::as_bool).unwrap_or(false)),
+            _ => None,
+        }
+    }
+
+    /// Checks if the server advertised a capability like `hoverProvider`.
+    pub fn supports(&self, provider: &str) -> bool {
+        let capabilities = self.capabilities.read().unwrap();
+        match &capabilities[provider] {
+            Json::Bool(supported) => *supported,
+            Json::Null => false,
+            _ => true,
+        }
+    }
+
+    /// Sends a notification to the server.
+    ///
+    /// This doesn't wait for the message to be written so that it can be called while a buffer is locked.
+    pub fn notify(&self, method: &str, params: Json) {
+        let _ = self.outgoing.send(json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        }));
+    }
+
+    pub async fn request(&self, method: &str, params: Json) -> Result<Json, Exception> {
+        self.request_with_timeout(method, params, REQUEST_TIMEOUT).await
+    }
+
+    async fn request_with_timeout(&self, method: &str, params: Json, timeout: Duration) -> Result<Json, Exception> {
+        if !self.is_running() {
+            return Err(Exception::error(format!("The {} language server is not running", self.language)));
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, sender);
+        let _ = self.outgoing.send(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        }));
+
+        match tokio::time::timeout(timeout, receiver).await {
+            Ok(Ok(Ok(result))) => Ok(result),
+            Ok(Ok(Err(message))) => Err(Exception::error(format!("{method}: {message}"))),
+            Ok(Err(_)) => Err(Exception::error(format!("The {} language server exited", self.language))),
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&id);
+                self.notify("$/cancelRequest", json!({ "id": id }));
+                Err(Exception::error(format!("{method}: the {} language server took too long to respond", self.language)))
+            }
+        }
+    }
+
+    /// Asks the server to exit, killing it if it doesn't exit in time.
+    pub async fn shutdown(&self) {
+        if self.is_running() {
+            match self.request_with_timeout("shutdown", Json::Null, SHUTDOWN_TIMEOUT).await {
+                Ok(_) => self.notify("exit", Json::Null),
+                Err(err) => warn!("{}", err),
+            }
+        }
+        let mut child = self.child.lock().await;
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, child.wait()).await.is_err() {
+            let _ = child.kill().await;
+        }
+        self.running.store(false, Ordering::Release);
+    }
+}
+
+async fn write_messages(mut stdin: ChildStdin, mut receiver: UnboundedReceiver<Json>) {
+    while let Some(message) = receiver.recv().await {
+        let body = message.to_string();
+        let framed = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        if let Err(err) = stdin.write_all(framed.as_bytes()).await {
+            error!("Unable to write to language server: {}", err);
+            break;
+        }
+        if stdin.flush().await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Reads a message framed with a `Content-Length` header.
+///
+/// Returns None once the server closes its stdout.
+async fn read_message(reader: &mut BufReader<ChildStdout>) -> Option<Json> {
+    loop {
+        let mut content_length = None;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).await.ok()? == 0 {
+                return None;
+            }
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':')
+                && name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+        let Some(content_length) = content_length else {
+            warn!("Language server sent a message without a Content-Length");
+            continue;
+        };
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).await.ok()?;
+        match serde_json::from_slice(&body) {
+            Ok(message) => return Some(message),
+            Err(err) => warn!("Language server sent invalid JSON: {}", err),
+        }
+    }
+}
+
+async fn read_messages(
+    language: String,
+    stdout: ChildStdout,
+    outgoing: UnboundedSender<Json>,
+    pending: PendingRequests,
+    running: Arc<AtomicBool>,
+) {
+    let mut reader = BufReader::new(stdout);
+    while let Some(message) = read_message(&mut reader).await {
+        let method = message["method"].as_str();
+        let id = message.get("id").cloned();
+        match (method, id) {
+            (Some(method), Some(id)) => {
+                let response = match handle_server_request(method, &message["params"]).await {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err((code, message)) => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": code, "message": message },
+                    }),
+                };
+                let _ = outgoing.send(response);
+            }
+            (Some(method), None) => {
+                handle_notification(&language, method, &message["params"]).await;
+            }
+            (None, Some(id)) => {
+                let Some(id) = id.as_i64() else {
+                    continue;
+                };
+                let Some(sender) = pending.lock().unwrap().remove(&id) else {
+                    continue;
+                };
+                let result = match message.get("error") {
+                    Some(error) => Err(error["message"].as_str().unwrap_or("unknown error").to_string()),
+                    None => Ok(message.get("result").cloned().unwrap_or(Json::Null)),
+                };
+                let _ = sender.send(result);
+            }
+            (None, None) => {}
+        }
+    }
+    info!("The {} language server closed its connection", language);
+    running.store(false, Ordering::Release);
+    // Dropping the senders wakes up anything still waiting on a response
+    pending.lock().unwrap().clear();
+}
+
+async fn handle_server_request(method: &str, params: &Json) -> Result<Json, (i64, String)> {
+    match method {
+        "workspace/configuration" => {
+            // We don't have any settings to give, so every item gets the server's defaults
+            let items = params["items"].as_array().map(Vec::len).unwrap_or(0);
+            Ok(Json::Array(vec![Json::Null; items]))
+        }
+        "workspace/applyEdit" => {
+            match crate::kernel::lsp::apply_workspace_edit(&params["edit"]).await {
+                Ok(_) => Ok(json!({ "applied": true })),
+                Err(err) => Ok(json!({ "applied": false, "failureReason": err.to_string() })),
+            }
+        }
+        "window/workDoneProgress/create" | "client/registerCapability" | "client/unregisterCapability" => {
+            Ok(Json::Null)
+        }
+        _ => Err((METHOD_NOT_FOUND, format!("Unhandled method {method}"))),
+    }
+}
+
+async fn handle_notification(language: &str, method: &str, params: &Json) {
+    match method {
+        "textDocument/publishDiagnostics" => {
+            crate::kernel::lsp::publish_diagnostics(language, params).await;
+        }
+        "window/logMessage" | "window/showMessage" => {
+            let message = params["message"].as_str().unwrap_or_default();
+            match params["type"].as_i64() {
+                Some(1) => error!("[{}] {}", language, message),
+                Some(2) => warn!("[{}] {}", language, message),
+                Some(3) => info!("[{}] {}", language, message),
+                _ => debug!("[{}] {}", language, message),
+            }
+        }
+        _ => {
+            debug!("[{}] unhandled notification {}", language, method);
+        }
+    }
+}
+
+async fn log_stderr(language: String, stderr: ChildStderr) {
+    let mut lines = BufReader::new(stderr).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        debug!("[{}] {}", language, line);
+    }
+}
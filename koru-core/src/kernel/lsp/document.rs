@@ -0,0 +1,236 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crop::Rope;
+use serde_json::{json, Value as Json};
+use crate::kernel::buffer::{EditOperation, EditValue};
+use crate::kernel::lsp::client::{LanguageClient, SyncKind};
+
+const URI_PREFIX: &str = "file://";
+
+/// Turns an absolute path into a `file://` uri, percent encoding anything that isn't allowed in a uri path.
+pub fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from(URI_PREFIX);
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char);
+            }
+            _ => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    uri
+}
+
+/// Turns a `file://` uri back into a path.
+///
+/// Returns None if the uri is for some other scheme.
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix(URI_PREFIX)?;
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut iter = encoded.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let high = iter.next().and_then(|b| (b as char).to_digit(16));
+            let low = iter.next().and_then(|b| (b as char).to_digit(16));
+            match (high, low) {
+                (Some(high), Some(low)) => bytes.push((high * 16 + low) as u8),
+                _ => return None,
+            }
+        } else {
+            bytes.push(byte);
+        }
+    }
+    Some(PathBuf::from(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+/// Converts a byte offset into an lsp position, which counts columns in UTF-16 code units.
+pub fn position_of_byte(rope: &Rope, byte_offset: usize) -> Json {
+    let byte_offset = byte_offset.min(rope.byte_len());
+    let line = rope.line_of_byte(byte_offset);
+    let line_start = rope.byte_of_line(line);
+    let character = rope.byte_slice(line_start..byte_offset)
+        .chars()
+        .map(char::len_utf16)
+        .sum::<usize>();
+    json!({ "line": line, "character": character })
+}
+
+/// Converts an lsp position into a byte offset.
+///
+/// Positions past the end of a line get clamped to the end of the line
+/// and positions past the last line get clamped to the end of the rope.
+pub fn byte_of_position(rope: &Rope, position: &Json) -> usize {
+    let line = position["line"].as_u64().unwrap_or(0) as usize;
+    let character = position["character"].as_u64().unwrap_or(0) as usize;
+    if line >= rope.line_len() {
+        return rope.byte_len();
+    }
+    let mut byte_offset = rope.byte_of_line(line);
+    let mut units = 0;
+    for ch in rope.line(line).chars() {
+        if units >= character {
+            break;
+        }
+        units += ch.len_utf16();
+        byte_offset += ch.len_utf8();
+    }
+    byte_offset
+}
+
+/// Converts an lsp position into the line and grapheme column that cursors use.
+pub fn grid_of_position(rope: &Rope, position: &Json) -> (usize, usize) {
+    let byte_offset = byte_of_position(rope, position);
+    let line = rope.line_of_byte(byte_offset);
+    let line_start = rope.byte_of_line(line);
+    let column = rope.byte_slice(line_start..byte_offset).graphemes().count();
+    (line, column)
+}
+
+/// Converts an lsp range into a range of bytes.
+pub fn byte_range(rope: &Rope, range: &Json) -> std::ops::Range<usize> {
+    let start = byte_of_position(rope, &range["start"]);
+    let end = byte_of_position(rope, &range["end"]);
+    start..end.max(start)
+}
+
+/// Converts a list of lsp `TextEdit`s into byte ranges and their replacement text.
+///
+/// The edits are sorted from the end of the text to the start so that applying them in order
+/// doesn't move the ranges of the edits that come after.
+/// Edits at the same position keep the order the server gave them in.
+pub fn resolve_edits(rope: &Rope, edits: &[Json]) -> Vec<(std::ops::Range<usize>, String)> {
+    let mut resolved = edits.iter()
+        .rev()
+        .map(|edit| {
+            let text = edit["newText"].as_str().unwrap_or_default().to_string();
+            (byte_range(rope, &edit["range"]), text)
+        })
+        .collect::<Vec<_>>();
+    resolved.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+    resolved
+}
+
+/// Keeps a language server's copy of a buffer in sync with the buffer.
+///
+/// Edits are collected as they happen and sent in a single `textDocument/didChange` when the document is flushed.
+/// Dropping the document tells the server that the buffer was closed.
+pub struct DocumentSync {
+    client: Arc<LanguageClient>,
+    uri: String,
+    version: i64,
+    changes: Vec<Json>,
+    dirty: bool,
+    /// Set when an edit couldn't be described as a range, so the whole text has to be sent.
+    resync: bool,
+}
+
+impl DocumentSync {
+    /// Tells the server about the buffer with `textDocument/didOpen`.
+    pub fn open(client: Arc<LanguageClient>, path: &Path, rope: &Rope) -> Self {
+        let uri = path_to_uri(path);
+        client.notify("textDocument/didOpen", json!({
+            "textDocument": {
+                "uri": uri,
+                "languageId": client.language(),
+                "version": 0,
+                "text": rope.to_string(),
+            }
+        }));
+        Self {
+            client,
+            uri,
+            version: 0,
+            changes: Vec::new(),
+            dirty: false,
+            resync: false,
+        }
+    }
+
+    pub fn client(&self) -> &Arc<LanguageClient> {
+        &self.client
+    }
+
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    /// Records an edit to the buffer.
+    ///
+    /// This must be called before the edit is applied to the rope since the range of the edit is measured in the old text.
+    pub fn edit(&mut self, rope: &Rope, operation: &EditOperation) {
+        self.dirty = true;
+        if self.resync || self.client.sync_kind() != SyncKind::Incremental {
+            return;
+        }
+        let start = operation.byte_offset;
+        let (removed, text) = match &operation.value {
+            EditValue::Insert { text } => (0, text.as_str()),
+            EditValue::Delete { count } => (*count, ""),
+            EditValue::Replace { count, text } => (*count, text.as_str()),
+            EditValue::Bulk(_) => {
+                self.changes.clear();
+                self.resync = true;
+                return;
+            }
+        };
+        self.changes.push(json!({
+            "range": {
+                "start": position_of_byte(rope, start),
+                "end": position_of_byte(rope, start + removed),
+            },
+            "text": text,
+        }));
+    }
+
+    /// Sends the edits made since the last flush with `textDocument/didChange`.
+    pub fn flush(&mut self, rope: &Rope) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+        let changes = match self.client.sync_kind() {
+            SyncKind::None => return,
+            SyncKind::Incremental if !self.resync => std::mem::take(&mut self.changes),
+            _ => {
+                self.changes.clear();
+                self.resync = false;
+                vec![json!({ "text": rope.to_string() })]
+            }
+        };
+        self.version += 1;
+        self.client.notify("textDocument/didChange", json!({
+            "textDocument": {
+                "uri": self.uri,
+                "version": self.version,
+            },
+            "contentChanges": changes,
+        }));
+    }
+
+    /// Tells the server that the buffer was written to disk with `textDocument/didSave`.
+    pub fn save(&mut self, rope: &Rope) {
+        self.flush(rope);
+        let Some(include_text) = self.client.save_options() else {
+            return;
+        };
+        let mut params = json!({
+            "textDocument": {
+                "uri": self.uri,
+            },
+        });
+        if include_text {
+            params["text"] = Json::from(rope.to_string());
+        }
+        self.client.notify("textDocument/didSave", params);
+    }
+}
+
+impl Drop for DocumentSync {
+    fn drop(&mut self) {
+        self.client.notify("textDocument/didClose", json!({
+            "textDocument": {
+                "uri": self.uri,
+            },
+        }));
+    }
+}
@@ -0,0 +1,387 @@
+use std::time::{Duration, Instant};
+use crop::RopeSlice;
+use scheme_rs::exceptions::Exception;
+use scheme_rs::gc::Gc;
+use scheme_rs::lists;
+use scheme_rs::num::SimpleNumber;
+use scheme_rs::registry::bridge;
+use scheme_rs::symbols::Symbol;
+use scheme_rs::value::Value;
+use crate::kernel::buffer::{gutter, Cursor, CursorDirection, GridCursor};
+use crate::kernel::input::{ModifierKey, MouseButton, MouseEvent, MouseEventKind};
+use crate::kernel::layout;
+use crate::kernel::scheme_api::major_mode::text_edit::{self, TextEditData};
+use crate::kernel::scheme_api::major_mode::MajorMode;
+use crate::kernel::scheme_api::session::SessionState;
+
+/// The hook that is emitted when a mouse button is pressed over a pane.
+pub const MOUSE_PRESS_HOOK: &str = "mouse-press";
+/// The hook that is emitted when a mouse button is released.
+pub const MOUSE_RELEASE_HOOK: &str = "mouse-release";
+/// The hook that is emitted when the mouse moves while a button is held down.
+pub const MOUSE_DRAG_HOOK: &str = "mouse-drag";
+/// The hook that is emitted when the mouse wheel is turned over a pane.
+pub const MOUSE_SCROLL_HOOK: &str = "mouse-scroll";
+pub const MOUSE_HOOKS: [&str; 4] = [MOUSE_PRESS_HOOK, MOUSE_RELEASE_HOOK, MOUSE_DRAG_HOOK, MOUSE_SCROLL_HOOK];
+
+/// How long a press can come after the last one and still count as another click of it.
+const MULTI_CLICK_TIME: Duration = Duration::from_millis(400);
+/// How many rows the cursor moves for each line that the wheel scrolls.
+const SCROLL_ROWS: usize = 3;
+
+struct Press {
+    time: Instant,
+    button: MouseButton,
+    pane: usize,
+    position: (usize, usize),
+    clicks: usize,
+}
+
+/// Counts the clicks of presses that come quickly one after another at the same place.
+#[derive(Default)]
+pub struct Clicks {
+    last: Option<Press>,
+}
+
+impl Clicks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a press, returning how many clicks it makes.
+    ///
+    /// This counts up to three clicks and then starts over at one.
+    fn press(&mut self, button: MouseButton, pane: usize, position: (usize, usize)) -> usize {
+        let now = Instant::now();
+        let clicks = match &self.last {
+            Some(last) if last.button == button
+                && last.pane == pane
+                && last.position == position
+                && now.duration_since(last.time) <= MULTI_CLICK_TIME => last.clicks % 3 + 1,
+            _ => 1,
+        };
+        self.last = Some(Press { time: now, button, pane, position, clicks });
+        clicks
+    }
+
+    /// The clicks of the last press, which releases and drags belong to.
+    fn current(&self) -> usize {
+        self.last.as_ref().map_or(1, |press| press.clicks)
+    }
+}
+
+/// Handles a mouse event from a frontend.
+///
+/// Pressing a button or turning the wheel over a pane gives it the focus.
+/// Drags and releases only go to the focused pane, since that is where the press that started them went.
+///
+/// The event is passed to the callbacks of its hook,
+/// and the default behavior only happens when nothing has been added to the hook.
+pub async fn handle(event: MouseEvent, clicks: &mut Clicks) -> Result<(), Exception> {
+    let layout = SessionState::get_layout().await;
+    let (focused, buffer_name) = {
+        let guard = layout.read().await;
+        let buffer_name = guard.pane(event.pane).and_then(|pane| pane.buffer_name()).map(String::from);
+        (guard.focused(), buffer_name)
+    };
+    let Some(buffer_name) = buffer_name else {
+        return Ok(());
+    };
+    match event.kind {
+        MouseEventKind::Press(_) | MouseEventKind::Scroll { .. } if event.pane != focused => {
+            layout::focus_pane(event.pane, true).await;
+        }
+        MouseEventKind::Drag(_) | MouseEventKind::Release(_) if event.pane != focused => return Ok(()),
+        _ => {}
+    }
+    let Some(buffer) = SessionState::get_current_buffer().await else {
+        return Ok(());
+    };
+    let handle = buffer.get_handle();
+
+    // Wrapped rows depend on the size of the pane, which is what it is drawn at
+    let (columns, lines) = SessionState::get_viewport().await;
+    let area = layout.read().await.areas(columns, lines).into_iter()
+        .find_map(|(id, area)| (id == event.pane).then_some(area))
+        .unwrap_or_default();
+    let gutter_width = handle.gutter_width(gutter::line_numbers()).await;
+    handle.set_viewport_columns(area.columns.saturating_sub(gutter_width)).await;
+    let (line, column) = handle.logical_position(event.row, event.column).await;
+
+    let mut args = vec![
+        Value::from(event.pane),
+        Value::from(buffer_name),
+        Value::from(SimpleNumber::from(line)),
+        Value::from(SimpleNumber::from(column)),
+    ];
+    let (hook, clicks) = match event.kind {
+        MouseEventKind::Press(button) => (MOUSE_PRESS_HOOK, clicks.press(button, event.pane, (line, column))),
+        MouseEventKind::Release(_) => (MOUSE_RELEASE_HOOK, clicks.current()),
+        MouseEventKind::Drag(_) => (MOUSE_DRAG_HOOK, clicks.current()),
+        MouseEventKind::Scroll { .. } => (MOUSE_SCROLL_HOOK, 0),
+    };
+    match event.kind {
+        MouseEventKind::Press(button) | MouseEventKind::Release(button) | MouseEventKind::Drag(button) => {
+            args.push(Value::from(Symbol::intern(button.name())));
+            args.push(modifier_list(event.modifiers));
+            args.push(Value::from(SimpleNumber::from(clicks)));
+        }
+        MouseEventKind::Scroll { lines, columns } => {
+            args.push(Value::from(SimpleNumber::from(lines)));
+            args.push(Value::from(SimpleNumber::from(columns)));
+            args.push(modifier_list(event.modifiers));
+        }
+    }
+
+    let hook = Symbol::intern(hook);
+    let overridden = {
+        let state = SessionState::get_state();
+        let hooks = state.read().await.get_hooks().clone();
+        hooks.read().await.has_callbacks(&hook)
+    };
+    if overridden {
+        return SessionState::emit_hook_blocking(hook, &args).await;
+    }
+
+    match event.kind {
+        MouseEventKind::Press(MouseButton::Left) if event.modifiers.contains(ModifierKey::Alt) => {
+            toggle_cursor(line, column).await
+        }
+        MouseEventKind::Press(MouseButton::Left) => match clicks {
+            1 => place_cursor(line, column).await,
+            2 => select_word(line, column).await,
+            _ => select_line(line, column).await,
+        },
+        MouseEventKind::Drag(MouseButton::Left) => drag_to(line, column).await,
+        MouseEventKind::Scroll { lines, columns } => scroll(lines, columns).await,
+        _ => Ok(()),
+    }
+}
+
+/// Turns modifiers into a list of symbols for the mouse hooks.
+fn modifier_list(modifiers: ModifierKey) -> Value {
+    let names = [
+        (ModifierKey::Shift, "shift"),
+        (ModifierKey::Control, "control"),
+        (ModifierKey::Alt, "alt"),
+        (ModifierKey::Meta, "meta"),
+    ];
+    let symbols = names.into_iter()
+        .filter(|(modifier, _)| modifiers.contains(*modifier))
+        .map(|(_, name)| Value::from(Symbol::intern(name)))
+        .collect::<Vec<_>>();
+    lists::slice_to_list(&symbols)
+}
+
+/// Gets the cursors of the current buffer, which the mouse moves.
+///
+/// Buffers whose major mode doesn't keep cursors have nothing for the mouse to do.
+async fn current_data() -> Option<Gc<TextEditData>> {
+    let buffer = SessionState::get_current_buffer().await?;
+    let major_mode: Gc<MajorMode> = buffer.get_major_mode().try_to_rust_type().ok()?;
+    text_edit::get_data(&major_mode).await.ok()
+}
+
+fn grapheme_class(grapheme: &str) -> u8 {
+    if grapheme.chars().all(char::is_whitespace) {
+        0
+    } else if grapheme.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        1
+    } else {
+        2
+    }
+}
+
+/// Finds the columns of the word around a column, as a start and an end that is past the last grapheme.
+///
+/// Runs of whitespace and of punctuation count as words too.
+fn word_bounds(line: RopeSlice, column: usize) -> (usize, usize) {
+    let classes = line.graphemes()
+        .map(|grapheme| grapheme_class(&grapheme))
+        .collect::<Vec<_>>();
+    let Some(class) = classes.get(column).copied() else {
+        return (column, column);
+    };
+    let start = classes[..column].iter().rposition(|other| *other != class).map_or(0, |before| before + 1);
+    let end = classes[column..].iter().position(|other| *other != class).map_or(classes.len(), |after| column + after);
+    (start, end)
+}
+
+/// Finds the word around a position in the current buffer.
+async fn word_at(line: usize, column: usize) -> (usize, usize) {
+    let Some(buffer) = SessionState::get_current_buffer().await else {
+        return (column, column);
+    };
+    let text = buffer.get_handle().get_text().await;
+    if line >= text.line_len() {
+        return (column, column);
+    }
+    word_bounds(text.line(line), column)
+}
+
+fn cursor_at(line: usize, column: usize) -> Cursor {
+    Cursor::new_main(GridCursor::new(line, column))
+}
+
+/// Moves a position that is past the end of the current buffer or of its line back onto the text,
+/// the same as `TextEditData::set_cursor_position` does.
+async fn clamp_position(line: usize, column: usize) -> (usize, usize) {
+    let Some(buffer) = SessionState::get_current_buffer().await else {
+        return (line, column);
+    };
+    let text = buffer.get_handle().get_text().await;
+    if text.line_len() == 0 {
+        return (0, 0);
+    }
+    let line = line.min(text.line_len() - 1);
+    (line, column.min(text.line(line).graphemes().count()))
+}
+
+/// Replaces all of the cursors with one at a position.
+async fn place_cursor(line: usize, column: usize) -> Result<(), Exception> {
+    let (line, column) = clamp_position(line, column).await;
+    if let Some(data) = current_data().await {
+        data.set_cursors(vec![cursor_at(line, column)]).await;
+    }
+    Ok(())
+}
+
+/// Adds a cursor at a position and makes it the main cursor, or removes the cursor that is already there.
+///
+/// The last cursor is never removed.
+async fn toggle_cursor(line: usize, column: usize) -> Result<(), Exception> {
+    let (line, column) = clamp_position(line, column).await;
+    let Some(data) = current_data().await else {
+        return Ok(());
+    };
+    let mut cursors = data.get_cursors().await;
+    let existing = cursors.iter().position(|cursor| (cursor.line(), cursor.column()) == (line, column));
+    match existing {
+        Some(_) if cursors.len() == 1 => return Ok(()),
+        Some(index) => {
+            let removed = cursors.remove(index);
+            if removed.is_main() {
+                cursors[index.saturating_sub(1)].set_main();
+            }
+        }
+        None => {
+            cursors.iter_mut().for_each(Cursor::unset_main);
+            // The cursors are kept in the order that they are in the buffer
+            let index = cursors.iter()
+                .position(|cursor| (cursor.line(), cursor.column()) > (line, column))
+                .unwrap_or(cursors.len());
+            cursors.insert(index, cursor_at(line, column));
+        }
+    }
+    data.set_cursors(cursors).await;
+    Ok(())
+}
+
+/// Replaces all of the cursors with one that selects the word at a position.
+async fn select_word(line: usize, column: usize) -> Result<(), Exception> {
+    let (line, column) = clamp_position(line, column).await;
+    let Some(data) = current_data().await else {
+        return Ok(());
+    };
+    let (start, end) = word_at(line, column).await;
+    let mut cursor = cursor_at(line, start);
+    cursor.place_point_mark();
+    // Point marks include the grapheme under the cursor
+    cursor.set_column(end.saturating_sub(1).max(start));
+    data.set_cursors(vec![cursor]).await;
+    Ok(())
+}
+
+/// Replaces all of the cursors with one that selects the line at a position.
+async fn select_line(line: usize, column: usize) -> Result<(), Exception> {
+    let (line, column) = clamp_position(line, column).await;
+    if let Some(data) = current_data().await {
+        let mut cursor = cursor_at(line, column);
+        cursor.place_line_mark();
+        data.set_cursors(vec![cursor]).await;
+    }
+    Ok(())
+}
+
+/// Moves the main cursor to a position, first placing a point mark where it was if it has no mark.
+async fn drag_to(line: usize, column: usize) -> Result<(), Exception> {
+    let Some(data) = current_data().await else {
+        return Ok(());
+    };
+    let index = data.get_main_cursor_index().await;
+    if !data.get_cursor(index).await.is_mark_set() {
+        data.place_point_mark(index).await?;
+    }
+    data.set_cursor_position(index, line, column).await
+}
+
+/// Moves the main cursor by rows and columns for each line and column that the wheel scrolled.
+///
+/// The frontends keep the main cursor in view, so this scrolls the pane.
+async fn scroll(lines: isize, columns: isize) -> Result<(), Exception> {
+    let Some(data) = current_data().await else {
+        return Ok(());
+    };
+    let index = data.get_main_cursor_index().await;
+    for _ in 0..lines.unsigned_abs() * SCROLL_ROWS {
+        data.move_cursor_visual(index, lines < 0).await?;
+    }
+    let direction = if columns < 0 {
+        CursorDirection::Left { wrap: false }
+    } else {
+        CursorDirection::Right { wrap: false }
+    };
+    for _ in 0..columns.unsigned_abs() * SCROLL_ROWS {
+        data.move_cursor(index, direction, |_| Ok(false)).await?;
+    }
+    Ok(())
+}
+
+fn position_args(line: &Value, column: &Value) -> Result<(usize, usize), Exception> {
+    let line: SimpleNumber = line.clone().try_into()?;
+    let column: SimpleNumber = column.clone().try_into()?;
+    Ok((line.try_into()?, column.try_into()?))
+}
+
+#[bridge(name = "mouse-cursor-place!", lib = "(koru-mouse)")]
+pub async fn mouse_cursor_place(line: &Value, column: &Value) -> Result<Vec<Value>, Exception> {
+    let (line, column) = position_args(line, column)?;
+    place_cursor(line, column).await?;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "mouse-cursor-toggle!", lib = "(koru-mouse)")]
+pub async fn mouse_cursor_toggle(line: &Value, column: &Value) -> Result<Vec<Value>, Exception> {
+    let (line, column) = position_args(line, column)?;
+    toggle_cursor(line, column).await?;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "mouse-select-word!", lib = "(koru-mouse)")]
+pub async fn mouse_select_word(line: &Value, column: &Value) -> Result<Vec<Value>, Exception> {
+    let (line, column) = position_args(line, column)?;
+    select_word(line, column).await?;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "mouse-select-line!", lib = "(koru-mouse)")]
+pub async fn mouse_select_line(line: &Value, column: &Value) -> Result<Vec<Value>, Exception> {
+    let (line, column) = position_args(line, column)?;
+    select_line(line, column).await?;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "mouse-drag-to!", lib = "(koru-mouse)")]
+pub async fn mouse_drag_to(line: &Value, column: &Value) -> Result<Vec<Value>, Exception> {
+    let (line, column) = position_args(line, column)?;
+    drag_to(line, column).await?;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "mouse-scroll!", lib = "(koru-mouse)")]
+pub async fn mouse_scroll(lines: &Value, columns: &Value) -> Result<Vec<Value>, Exception> {
+    let lines: SimpleNumber = lines.clone().try_into()?;
+    let columns: SimpleNumber = columns.clone().try_into()?;
+    scroll(lines.try_into()?, columns.try_into()?).await?;
+    Ok(Vec::new())
+}
@@ -0,0 +1,628 @@
+mod sources;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, RwLock};
+use log::error;
+use scheme_rs::exceptions::Exception;
+use scheme_rs::gc::Gc;
+use scheme_rs::lists::{self, List};
+use scheme_rs::proc::Procedure;
+use scheme_rs::records::{rtd, Record, RecordTypeDescriptor, SchemeCompatible};
+use scheme_rs::registry::bridge;
+use scheme_rs::value::{UnpackedValue, Value};
+use tokio::sync::Mutex;
+use crate::kernel;
+use crate::kernel::broker::{BackendMessage, MessageKind};
+use crate::kernel::completion::{fuzzy_match, highlight_matches, segment, truncate, FuzzyMatch};
+use crate::kernel::input::{KeyPress, KeyValue};
+use crate::kernel::scheme_api::command::{Command, CommandTree};
+use crate::kernel::scheme_api::session::keymap::SchemeKeyMap;
+use crate::kernel::scheme_api::session::{SessionState, CURRENT_SESSION_ID};
+use crate::kernel::scheme_api::task;
+use crate::keymap::KeyMap;
+use crate::styled_text::{ColorType, StyledFile, StyledText};
+
+use sources::ProjectWalk;
+
+/// How many files the project walk gives back at a time.
+const WALK_BATCH: usize = 256;
+/// The narrowest that the picker gets, unless the screen is narrower.
+const MIN_WIDTH: usize = 20;
+const TAB_WIDTH: usize = 4;
+
+/// Something that can be picked.
+struct Item {
+    /// What is shown in the list and matched against the query.
+    label: String,
+    /// Extra information shown after the label.
+    detail: String,
+    /// What the callback and the preview are called with.
+    value: Value,
+}
+
+struct Picker {
+    /// Tells this picker apart from the ones before it, so a source that is still running stops once its picker is gone.
+    id: usize,
+    prompt: String,
+    query: String,
+    items: Vec<Item>,
+    /// The items that match the query, best first.
+    matches: Vec<(usize, FuzzyMatch)>,
+    selected: usize,
+    /// The first match that is visible.
+    scroll: usize,
+    /// How many matches fit in the picker when it was last drawn.
+    rows: usize,
+    callback: Procedure,
+    preview: Option<Procedure>,
+    /// The item that the preview was last made for, and its lines.
+    preview_lines: Option<(usize, Vec<String>)>,
+    /// Whether the source is still giving more items.
+    loading: bool,
+}
+
+static PICKER: LazyLock<Mutex<Option<Picker>>> = LazyLock::new(|| {
+    Mutex::new(None)
+});
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// The keys that drive the picker while it is open.
+static KEY_MAP: LazyLock<RwLock<Option<KeyMap>>> = LazyLock::new(|| {
+    RwLock::new(None)
+});
+
+/// Reads a list of items.
+///
+/// An item is either a string or a list of the label, the value, and the detail.
+/// The value defaults to the label and the detail defaults to nothing.
+fn items_from(value: &Value) -> Result<Vec<Item>, Exception> {
+    let list: List = value.try_into()?;
+    list.into_iter()
+        .map(|value| {
+            if let UnpackedValue::String(label) = value.clone().unpack() {
+                let label = label.to_string();
+                return Ok(Item { label: label.clone(), detail: String::new(), value });
+            }
+            let parts: List = (&value).try_into()?;
+            let parts = parts.as_slice();
+            let Some(label) = parts.first() else {
+                return Err(Exception::error("An item must have a label"));
+            };
+            let label: String = label.clone().try_into()?;
+            let value = match parts.get(1) {
+                Some(value) => value.clone(),
+                None => Value::from(label.clone()),
+            };
+            let detail = match parts.get(2) {
+                Some(detail) => detail.clone().try_into()?,
+                None => String::new(),
+            };
+            Ok(Item { label, detail, value })
+        })
+        .collect()
+}
+
+fn items_to_list(items: Vec<(String, Value, String)>) -> Value {
+    let items = items.into_iter()
+        .map(|(label, value, detail)| lists::slice_to_list(&[Value::from(label), value, Value::from(detail)]))
+        .collect::<Vec<_>>();
+    lists::slice_to_list(&items)
+}
+
+/// Cuts text down to a width and pads it out to exactly that width.
+fn fit(text: &str, width: usize) -> String {
+    if width == 0 {
+        return String::new();
+    }
+    let text = truncate(text, width);
+    format!("{text:<width$}")
+}
+
+impl Picker {
+    fn selected_item(&self) -> Option<usize> {
+        self.matches.get(self.selected).map(|(index, _)| *index)
+    }
+
+    /// Matches the items against the query.
+    ///
+    /// An empty query matches every item in the order the source gave them.
+    /// The selection goes back to the best match unless it should stay on the item that was selected.
+    fn rank(&mut self, keep_selection: bool) {
+        let selected = self.selected_item().filter(|_| keep_selection);
+        self.matches = if self.query.is_empty() {
+            (0..self.items.len())
+                .map(|index| (index, FuzzyMatch { score: 0, positions: Vec::new() }))
+                .collect()
+        } else {
+            let mut matches = self.items.iter()
+                .enumerate()
+                .filter_map(|(index, item)| fuzzy_match(&self.query, &item.label).map(|fuzzy| (index, fuzzy)))
+                .collect::<Vec<_>>();
+            // The sort is stable so equal scores keep the order of the source
+            matches.sort_by_key(|(_, fuzzy)| std::cmp::Reverse(fuzzy.score));
+            matches
+        };
+        self.selected = selected
+            .and_then(|selected| self.matches.iter().position(|(index, _)| *index == selected))
+            .unwrap_or(0);
+        if !keep_selection {
+            self.scroll = 0;
+        }
+    }
+
+    /// Gets what to call the preview with if the selected item hasn't been previewed yet.
+    fn preview_request(&self) -> Option<(usize, Procedure, Value)> {
+        let preview = self.preview.clone()?;
+        let index = self.selected_item()?;
+        if self.preview_lines.as_ref().is_some_and(|(previewed, _)| *previewed == index) {
+            return None;
+        }
+        Some((index, preview, self.items[index].value.clone()))
+    }
+
+    fn prompt_line(&self, width: usize) -> Vec<StyledText> {
+        let loading = if self.loading { "…" } else { "" };
+        let count = format!(" {}/{}{loading} ", self.matches.len(), self.items.len());
+        let count_width = count.chars().count();
+        let text_width = width.saturating_sub(count_width + 1);
+        let text = format!(" {}{}", self.prompt, self.query);
+        let text = if text_width == 0 {
+            String::new()
+        } else {
+            truncate(&text, text_width)
+        };
+        let padding = width.saturating_sub(text.chars().count() + 1 + count_width);
+        vec![
+            segment(text, ColorType::Text, ColorType::Surface1),
+            segment(String::from(" "), ColorType::Text, ColorType::Cursor),
+            segment(" ".repeat(padding), ColorType::Text, ColorType::Surface1),
+            segment(count, ColorType::Subtext0, ColorType::Surface1),
+        ]
+    }
+
+    /// Draws a match with its matched chars in the accent color and its detail after it.
+    fn match_line(&self, index: usize, width: usize) -> Vec<StyledText> {
+        let Some((item, fuzzy)) = self.matches.get(index) else {
+            return vec![segment(" ".repeat(width), ColorType::Text, ColorType::Surface0)];
+        };
+        let item = &self.items[*item];
+        let bg_color = if index == self.selected {
+            ColorType::Selection
+        } else {
+            ColorType::Surface0
+        };
+        let inner = width.saturating_sub(2);
+        let label = if inner == 0 { String::new() } else { truncate(&item.label, inner) };
+        let mut used = label.chars().count();
+        let mut line = vec![segment(String::from(" "), ColorType::Text, bg_color)];
+        line.extend(highlight_matches(&label, &fuzzy.positions, bg_color));
+        let room = inner.saturating_sub(used + 2);
+        if !item.detail.is_empty() && room > 0 {
+            let detail = truncate(&item.detail, room);
+            used += 2 + detail.chars().count();
+            line.push(segment(format!("  {detail}"), ColorType::Subtext0, bg_color));
+        }
+        line.push(segment(" ".repeat(width.saturating_sub(used + 1)), ColorType::Text, bg_color));
+        line
+    }
+
+    /// Draws the prompt with the matches below it, and the preview of the selected item beside them.
+    ///
+    /// The picker takes up most of the area that the panes are drawn in.
+    fn render(&mut self, columns: usize, lines: usize) -> StyledFile {
+        let width = (columns * 4 / 5).max(MIN_WIDTH).min(columns);
+        let height = (lines * 3 / 4).max(2).min(lines);
+        self.rows = height.saturating_sub(1).max(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + self.rows {
+            self.scroll = self.selected + 1 - self.rows;
+        }
+        let (list_width, preview_width) = if self.preview.is_some() && width >= MIN_WIDTH * 2 {
+            (width / 2, width - width / 2 - 1)
+        } else {
+            (width, 0)
+        };
+        let preview = self.preview_lines.as_ref()
+            .filter(|(index, _)| Some(*index) == self.selected_item())
+            .map(|(_, lines)| lines.as_slice())
+            .unwrap_or_default();
+
+        let mut file = StyledFile::new();
+        file.push_line(self.prompt_line(width));
+        for row in 0..height.saturating_sub(1) {
+            let mut line = self.match_line(self.scroll + row, list_width);
+            if preview_width > 0 {
+                let text = preview.get(row).map(String::as_str).unwrap_or_default();
+                line.push(segment(String::from("│"), ColorType::Overlay0, ColorType::Surface0));
+                line.push(segment(fit(text, preview_width), ColorType::Text, ColorType::Base));
+            }
+            file.push_line(line);
+        }
+        file
+    }
+}
+
+async fn send(message: BackendMessage) {
+    if let Err(err) = SessionState::send_message(MessageKind::BackEnd(message)).await {
+        error!("{}", err);
+    }
+}
+
+/// Draws the picker again, asking for the preview of the selected item first if it hasn't been previewed.
+///
+/// The picker is unlocked while the preview runs since it can call back into the picker.
+async fn redraw() {
+    let request = {
+        let guard = PICKER.lock().await;
+        let Some(picker) = guard.as_ref() else {
+            return;
+        };
+        picker.preview_request().map(|request| (picker.id, request))
+    };
+    if let Some((id, (index, preview, value))) = request {
+        let lines = match preview.call(&[value]).await {
+            Ok(values) => match values.first().map(|value| value.clone().unpack()) {
+                Some(UnpackedValue::String(text)) => text.to_string()
+                    .lines()
+                    .map(|line| line.replace('\t', &" ".repeat(TAB_WIDTH)))
+                    .collect(),
+                _ => Vec::new(),
+            },
+            Err(err) => {
+                error!("Picker preview failed: {}", err);
+                Vec::new()
+            }
+        };
+        let mut guard = PICKER.lock().await;
+        if let Some(picker) = guard.as_mut()
+            && picker.id == id {
+            picker.preview_lines = Some((index, lines));
+        }
+    }
+
+    let (columns, lines) = SessionState::get_viewport().await;
+    let file = {
+        let mut guard = PICKER.lock().await;
+        let Some(picker) = guard.as_mut() else {
+            return;
+        };
+        picker.render(columns, lines)
+    };
+    send(BackendMessage::ShowPicker(file)).await;
+}
+
+/// Calls a source for more items until it gives back an empty list or false.
+///
+/// The picker is drawn again after every batch, and the source stops being called once its picker is closed.
+async fn run_source(id: usize, source: Procedure) {
+    loop {
+        let items = source.call(&[]).await.and_then(|values| match values.first() {
+            Some(value) if value.is_true() => items_from(value),
+            _ => Ok(Vec::new()),
+        });
+        let items = match items {
+            Ok(items) => items,
+            Err(err) => {
+                error!("Picker source failed: {}", err);
+                Vec::new()
+            }
+        };
+        let done = items.is_empty();
+        {
+            let mut guard = PICKER.lock().await;
+            let Some(picker) = guard.as_mut().filter(|picker| picker.id == id) else {
+                return;
+            };
+            if done {
+                picker.loading = false;
+            } else {
+                picker.items.extend(items);
+                picker.rank(true);
+            }
+        }
+        redraw().await;
+        if done {
+            return;
+        }
+    }
+}
+
+/// Gets the picker's keys while a picker is open.
+pub async fn key_map() -> Option<KeyMap> {
+    if PICKER.lock().await.is_none() {
+        return None;
+    }
+    KEY_MAP.read().expect("picker key map lock poisoned").clone()
+}
+
+/// Has the picker's keys merge the key groups in again, after a group has changed.
+pub fn key_groups_changed() {
+    if let Some(key_map) = KEY_MAP.write().expect("picker key map lock poisoned").as_mut() {
+        key_map.invalidate_tree();
+    }
+}
+
+/// Changes the picker and draws it again.
+async fn update(change: impl FnOnce(&mut Picker)) {
+    {
+        let mut guard = PICKER.lock().await;
+        let Some(picker) = guard.as_mut() else {
+            return;
+        };
+        change(picker);
+    }
+    redraw().await;
+}
+
+async fn insert(text: &str) {
+    update(|picker| {
+        picker.query.push_str(text);
+        picker.rank(false);
+    }).await;
+}
+
+/// Moves the selection to the next or previous match, wrapping around at the ends.
+async fn move_selection(forward: bool) {
+    update(|picker| {
+        let count = picker.matches.len();
+        if count == 0 {
+            return;
+        }
+        picker.selected = if forward {
+            (picker.selected + 1) % count
+        } else {
+            (picker.selected + count - 1) % count
+        };
+    }).await;
+}
+
+#[bridge(name = "picker-open", lib = "(koru-picker)")]
+pub async fn picker_open(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let [prompt, source, callback, rest @ ..] = args else {
+        return Err(Exception::wrong_num_of_args(3, args.len()));
+    };
+    let prompt: String = prompt.clone().try_into()?;
+    let callback: Procedure = callback.clone().try_into()?;
+    let preview = match rest.first() {
+        Some(preview) if preview.is_true() => Some(preview.clone().try_into()?),
+        _ => None,
+    };
+    let (items, source) = match source.clone().unpack() {
+        UnpackedValue::Procedure(source) => (Vec::new(), Some(source)),
+        _ => (items_from(source)?, None),
+    };
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let mut picker = Picker {
+        id,
+        prompt,
+        query: String::new(),
+        items,
+        matches: Vec::new(),
+        selected: 0,
+        scroll: 0,
+        rows: 1,
+        callback,
+        preview,
+        preview_lines: None,
+        loading: source.is_some(),
+    };
+    picker.rank(false);
+    *PICKER.lock().await = Some(picker);
+    redraw().await;
+
+    if let Some(source) = source {
+        let handle = kernel::session_spawn(CURRENT_SESSION_ID.get(), async move {
+            run_source(id, source).await;
+            Ok(Vec::new())
+        });
+        task::TaskManager::new_emphemeral_task_rust(handle).await;
+    }
+    Ok(Vec::new())
+}
+
+#[bridge(name = "picker-active?", lib = "(koru-picker)")]
+pub async fn picker_active() -> Result<Vec<Value>, Exception> {
+    let active = PICKER.lock().await.is_some();
+    Ok(vec![Value::from(active)])
+}
+
+#[bridge(name = "picker-query", lib = "(koru-picker)")]
+pub async fn picker_query() -> Result<Vec<Value>, Exception> {
+    let query = PICKER.lock().await.as_ref()
+        .map(|picker| picker.query.clone())
+        .unwrap_or_default();
+    Ok(vec![Value::from(query)])
+}
+
+#[bridge(name = "picker-insert", lib = "(koru-picker)")]
+pub async fn picker_insert(text: &Value) -> Result<Vec<Value>, Exception> {
+    let text: String = text.clone().try_into()?;
+    insert(&text).await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "picker-insert-key", lib = "(koru-picker)")]
+pub async fn picker_insert_key(keys: &Value) -> Result<Vec<Value>, Exception> {
+    let keys: List = keys.try_into()?;
+    let [key] = keys.as_slice() else {
+        return Ok(vec![Value::from(false)]);
+    };
+    let key: Gc<KeyPress> = key.clone().try_to_rust_type()?;
+    if !key.modifiers.is_empty() {
+        return Ok(vec![Value::from(false)]);
+    }
+    let KeyValue::CharacterKey(text) = &key.key else {
+        return Ok(vec![Value::from(false)]);
+    };
+    insert(text).await;
+    Ok(vec![Value::from(true)])
+}
+
+#[bridge(name = "picker-delete-back", lib = "(koru-picker)")]
+pub async fn picker_delete_back() -> Result<Vec<Value>, Exception> {
+    update(|picker| {
+        if picker.query.pop().is_some() {
+            picker.rank(false);
+        }
+    }).await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "picker-next", lib = "(koru-picker)")]
+pub async fn picker_next() -> Result<Vec<Value>, Exception> {
+    move_selection(true).await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "picker-previous", lib = "(koru-picker)")]
+pub async fn picker_previous() -> Result<Vec<Value>, Exception> {
+    move_selection(false).await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "picker-accept", lib = "(koru-picker)")]
+pub async fn picker_accept() -> Result<Vec<Value>, Exception> {
+    let chosen = {
+        let mut guard = PICKER.lock().await;
+        let Some(index) = guard.as_ref().and_then(Picker::selected_item) else {
+            return Ok(vec![Value::from(false)]);
+        };
+        let picker = guard.take().expect("the picker was just checked");
+        (picker.callback, picker.items[index].value.clone())
+    };
+    send(BackendMessage::HidePicker).await;
+    let (callback, value) = chosen;
+    callback.call(&[value]).await?;
+    Ok(vec![Value::from(true)])
+}
+
+#[bridge(name = "picker-cancel", lib = "(koru-picker)")]
+pub async fn picker_cancel() -> Result<Vec<Value>, Exception> {
+    let was_open = PICKER.lock().await.take().is_some();
+    if was_open {
+        send(BackendMessage::HidePicker).await;
+    }
+    Ok(Vec::new())
+}
+
+#[bridge(name = "picker-key-map-set!", lib = "(koru-picker)")]
+pub async fn picker_key_map_set(key_map: &Value) -> Result<Vec<Value>, Exception> {
+    let key_map: Gc<SchemeKeyMap> = key_map.clone().try_to_rust_type()?;
+    let key_map = key_map.make_keymap().await.map_err(Exception::error)?;
+    *KEY_MAP.write().expect("picker key map lock poisoned") = Some(key_map);
+    Ok(Vec::new())
+}
+
+impl SchemeCompatible for ProjectWalk {
+    fn rtd() -> Arc<RecordTypeDescriptor>
+    where
+        Self: Sized
+    {
+        rtd!(name: "&ProjectWalk", sealed: true)
+    }
+}
+
+#[bridge(name = "picker-project-walk", lib = "(koru-picker)")]
+pub fn picker_project_walk() -> Result<Vec<Value>, Exception> {
+    Ok(vec![Value::from(Record::from_rust_type(ProjectWalk::new()))])
+}
+
+#[bridge(name = "picker-project-walk-next", lib = "(koru-picker)")]
+pub async fn picker_project_walk_next(walk: &Value) -> Result<Vec<Value>, Exception> {
+    let walk: Gc<ProjectWalk> = walk.clone().try_to_rust_type()?;
+    let files = walk.next_batch(WALK_BATCH).into_iter()
+        .map(Value::from)
+        .collect::<Vec<_>>();
+    Ok(vec![lists::slice_to_list(&files)])
+}
+
+#[bridge(name = "picker-buffer-items", lib = "(koru-picker)")]
+pub async fn picker_buffer_items() -> Result<Vec<Value>, Exception> {
+    let buffers = {
+        let state = SessionState::get_state();
+        let guard = state.read().await;
+        let buffers = guard.get_buffers().await;
+        buffers.iter()
+            .map(|(name, buffer)| (name.clone(), buffer.get_handle()))
+            .collect::<Vec<_>>()
+    };
+    let current = SessionState::current_focused_buffer().await.map(|(name, _)| name);
+    let directory = std::env::current_dir().ok();
+    let mut items = Vec::with_capacity(buffers.len());
+    for (name, handle) in buffers {
+        // Buffers of files in the project are labelled by their path in it
+        let label = directory.as_ref()
+            .and_then(|directory| std::path::Path::new(&name).strip_prefix(directory).ok())
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or_else(|| name.clone());
+        let detail = if handle.modified().await { "modified" } else { "" };
+        items.push((label, name, detail.to_string()));
+    }
+    // The current buffer goes last since switching to it does nothing
+    items.sort_by(|(a, a_name, _), (b, b_name, _)| {
+        let a_current = current.as_ref() == Some(a_name);
+        let b_current = current.as_ref() == Some(b_name);
+        a_current.cmp(&b_current).then_with(|| a.cmp(b))
+    });
+    let items = items.into_iter()
+        .map(|(label, name, detail)| (label, Value::from(name), detail))
+        .collect();
+    Ok(vec![items_to_list(items)])
+}
+
+#[bridge(name = "picker-command-items", lib = "(koru-picker)")]
+pub async fn picker_command_items() -> Result<Vec<Value>, Exception> {
+    let mut items = Vec::new();
+    for name in CommandTree::names("").await {
+        let Some(command) = CommandTree::lookup(&name).await else {
+            continue;
+        };
+        let description = command.description().to_string();
+        let command = Value::from(Record::from_rust_type((*command).clone()));
+        items.push((name, command, description));
+    }
+    Ok(vec![items_to_list(items)])
+}
+
+#[bridge(name = "picker-file-preview", lib = "(koru-picker)")]
+pub async fn picker_file_preview(path: &Value) -> Result<Vec<Value>, Exception> {
+    let path: String = path.clone().try_into()?;
+    let preview = tokio::task::spawn_blocking(move || sources::file_preview(&path)).await
+        .map_err(|err| Exception::error(err.to_string()))?;
+    Ok(vec![preview.map(Value::from).unwrap_or_else(|| Value::from(false))])
+}
+
+#[bridge(name = "picker-buffer-preview", lib = "(koru-picker)")]
+pub async fn picker_buffer_preview(buffer_name: &Value) -> Result<Vec<Value>, Exception> {
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let buffer = {
+        let state = SessionState::get_state();
+        let guard = state.read().await;
+        let buffers = guard.get_buffers().await;
+        buffers.get(&buffer_name).cloned()
+    };
+    let Some(buffer) = buffer else {
+        return Err(Exception::error(format!("Buffer '{buffer_name}' not found")));
+    };
+    let line = buffer.get_main_cursor().await
+        .map(|cursor| cursor.line())
+        .unwrap_or(0);
+    let rope = buffer.get_handle().get_text().await;
+    Ok(vec![Value::from(sources::buffer_preview(&rope, line))])
+}
+
+#[bridge(name = "picker-command-preview", lib = "(koru-picker)")]
+pub async fn picker_command_preview(command: &Value) -> Result<Vec<Value>, Exception> {
+    let command: Gc<Command> = command.clone().try_to_rust_type()?;
+    let mut preview = format!("{}\n\n{}", command.name(), command.description());
+    if !command.arguments().is_empty() {
+        let arguments = command.arguments().iter()
+            .map(<&str>::from)
+            .collect::<Vec<_>>();
+        preview.push_str(&format!("\n\nArguments: {}", arguments.join(" ")));
+    }
+    Ok(vec![Value::from(preview)])
+}
@@ -0,0 +1,144 @@
+use std::collections::VecDeque;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use scheme_rs::gc::Trace;
+use crop::Rope;
+
+/// The most bytes of a file that are read for its preview.
+const MAX_PREVIEW_BYTES: u64 = 64 * 1024;
+/// The most lines that a preview gives back.
+const MAX_PREVIEW_LINES: usize = 200;
+/// How many lines above the main cursor a buffer's preview starts at.
+const PREVIEW_CONTEXT: usize = 3;
+
+/// The names and extensions that the project's `.gitignore` leaves out.
+///
+/// Only plain names like `target/` and extensions like `*.log` are understood.
+#[derive(Debug, Default)]
+struct Ignored {
+    names: Vec<String>,
+    extensions: Vec<String>,
+}
+
+impl Ignored {
+    fn load(root: &Path) -> Self {
+        let mut ignored = Self::default();
+        let Ok(contents) = std::fs::read_to_string(root.join(".gitignore")) else {
+            return ignored;
+        };
+        for line in contents.lines() {
+            let line = line.trim().trim_matches('/');
+            if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+                continue;
+            }
+            if let Some(extension) = line.strip_prefix("*.")
+                && !extension.contains(['*', '?', '[', '/']) {
+                ignored.extensions.push(extension.to_string());
+            } else if !line.contains(['*', '?', '[', '/']) {
+                ignored.names.push(line.to_string());
+            }
+        }
+        ignored
+    }
+
+    fn contains(&self, path: &Path) -> bool {
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            return true;
+        };
+        if name.starts_with('.') || self.names.iter().any(|ignored| ignored == name) {
+            return true;
+        }
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| self.extensions.iter().any(|ignored| ignored == extension))
+    }
+}
+
+#[derive(Debug)]
+struct WalkState {
+    root: PathBuf,
+    ignored: Ignored,
+    /// The directories that haven't been read yet, shallowest first.
+    directories: VecDeque<PathBuf>,
+}
+
+/// Lists the files under the working directory a batch at a time, so a picker can show the first files before the rest are found.
+///
+/// Directories are read breadth first, so files closer to the top of the project come first.
+/// Hidden entries and the ones in the project's `.gitignore` are skipped.
+#[derive(Debug, Trace)]
+pub struct ProjectWalk {
+    #[trace(skip)]
+    state: Mutex<WalkState>,
+}
+
+impl ProjectWalk {
+    pub fn new() -> Self {
+        let root = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        let ignored = Ignored::load(&root);
+        Self {
+            state: Mutex::new(WalkState {
+                directories: VecDeque::from([root.clone()]),
+                root,
+                ignored,
+            }),
+        }
+    }
+
+    /// Gets the paths of the next files, relative to the working directory.
+    ///
+    /// Returns an empty list once every directory has been read.
+    pub fn next_batch(&self, size: usize) -> Vec<String> {
+        let mut state = self.state.lock().expect("project walk lock poisoned");
+        let mut files = Vec::new();
+        while files.len() < size {
+            let Some(directory) = state.directories.pop_front() else {
+                break;
+            };
+            let Ok(entries) = std::fs::read_dir(&directory) else {
+                continue;
+            };
+            let mut entries = entries
+                .filter_map(Result::ok)
+                .filter(|entry| !state.ignored.contains(&entry.path()))
+                .collect::<Vec<_>>();
+            entries.sort_by_key(|entry| entry.file_name());
+            for entry in entries {
+                let path = entry.path();
+                match entry.file_type() {
+                    Ok(file_type) if file_type.is_dir() => state.directories.push_back(path),
+                    Ok(_) => {
+                        let relative = path.strip_prefix(&state.root).unwrap_or(&path);
+                        files.push(relative.to_string_lossy().to_string());
+                    }
+                    Err(_) => {}
+                }
+            }
+        }
+        files
+    }
+}
+
+/// Reads the start of a file for previewing it.
+///
+/// Returns None for files that can't be read or that aren't text.
+pub fn file_preview(path: &str) -> Option<String> {
+    let file = std::fs::File::open(path).ok()?;
+    let mut bytes = Vec::new();
+    file.take(MAX_PREVIEW_BYTES).read_to_end(&mut bytes).ok()?;
+    if bytes.contains(&0) {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&bytes);
+    Some(text.lines().take(MAX_PREVIEW_LINES).collect::<Vec<_>>().join("\n"))
+}
+
+/// Gets the lines of a buffer around its main cursor for previewing it.
+pub fn buffer_preview(rope: &Rope, cursor_line: usize) -> String {
+    let start = cursor_line.saturating_sub(PREVIEW_CONTEXT).min(rope.line_len());
+    (start..rope.line_len().min(start + MAX_PREVIEW_LINES))
+        .map(|line| rope.line(line).to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::sync::{Arc, LazyLock};
 use scheme_rs::exceptions::Exception;
 use scheme_rs::gc::{Gc, Trace};
+use scheme_rs::lists;
 use scheme_rs::proc::Procedure;
 use scheme_rs::records::{rtd, Record, RecordTypeDescriptor, SchemeCompatible};
 use scheme_rs::registry::bridge;
@@ -76,7 +77,6 @@ impl CommandTree {
             let Some(child) = node.lookup_mut(&c) else {
                 unreachable!("We should have just inserted character '{c}'");
             };
-            child.insert(c);
             node = child;
         }
         node.set_command(command);
@@ -93,6 +93,30 @@ impl CommandTree {
         node.get_command()
     }
 
+    fn names_internal(&self, prefix: &str) -> Vec<String> {
+        let mut node = &self.root;
+        for c in prefix.chars() {
+            let Some(child) = node.lookup(&c) else {
+                return Vec::new();
+            };
+            node = child;
+        }
+        let mut names = Vec::new();
+        let mut stack = vec![(prefix.to_string(), node)];
+        while let Some((name, node)) = stack.pop() {
+            if node.contains_command() {
+                names.push(name.clone());
+            }
+            for (c, child) in node.children.iter() {
+                let mut name = name.clone();
+                name.push(*c);
+                stack.push((name, child));
+            }
+        }
+        names.sort();
+        names
+    }
+
     /// Inserts a new command into the global tree.
     pub async fn insert(command_name: &str, command: Gc<Command>) {
         let mut guard = COMMAND_TREE.write().await;
@@ -104,6 +128,12 @@ impl CommandTree {
         let guard = COMMAND_TREE.read().await;
         guard.get_internal(command_name)
     }
+
+    /// Gets the names of all the commands that start with a prefix, in alphabetical order.
+    pub async fn names(prefix: &str) -> Vec<String> {
+        let guard = COMMAND_TREE.read().await;
+        guard.names_internal(prefix)
+    }
 }
 
 
@@ -115,6 +145,8 @@ pub enum ArgumentDef {
     KeyPress,
     KeySequence,
     Boolean,
+    /// The name of an open buffer
+    Buffer,
     Variable(Box<ArgumentDef>),
 }
 
@@ -127,6 +159,7 @@ impl From<ArgumentDef> for &str {
             ArgumentDef::KeyPress => "key-press",
             ArgumentDef::KeySequence => "key-sequence",
             ArgumentDef::Boolean => "boolean",
+            ArgumentDef::Buffer => "buffer",
             ArgumentDef::Variable(x) => {
                 match x.as_ref() {
                     ArgumentDef::Text => "variable:text",
@@ -135,6 +168,7 @@ impl From<ArgumentDef> for &str {
                     ArgumentDef::KeyPress => "variable:key-press",
                     ArgumentDef::KeySequence => "variable:key-sequence",
                     ArgumentDef::Boolean => "variable:boolean",
+                    ArgumentDef::Buffer => "variable:buffer",
                     _ => unreachable!("invalid variable arg")
                 }
             }
@@ -151,6 +185,7 @@ impl From<&ArgumentDef> for &str {
             ArgumentDef::KeyPress => "key-press",
             ArgumentDef::KeySequence => "key-sequence",
             ArgumentDef::Boolean => "boolean",
+            ArgumentDef::Buffer => "buffer",
             ArgumentDef::Variable(x) => {
                 match x.as_ref() {
                     ArgumentDef::Text => "variable:text",
@@ -159,6 +194,7 @@ impl From<&ArgumentDef> for &str {
                     ArgumentDef::KeyPress => "variable:key-press",
                     ArgumentDef::KeySequence => "variable:key-sequence",
                     ArgumentDef::Boolean => "variable:boolean",
+                    ArgumentDef::Buffer => "variable:buffer",
                     _ => unreachable!("invalid variable arg")
                 }
             }
@@ -176,12 +212,14 @@ impl TryFrom<&str> for ArgumentDef {
             "key-press" => Ok(ArgumentDef::KeyPress),
             "key-sequence" => Ok(ArgumentDef::KeySequence),
             "boolean" => Ok(ArgumentDef::Boolean),
+            "buffer" => Ok(ArgumentDef::Buffer),
             "variable:text" => Ok(ArgumentDef::Variable(Box::new(ArgumentDef::Text))),
             "variable:number" => Ok(ArgumentDef::Variable(Box::new(ArgumentDef::Number))),
             "variable:path" => Ok(ArgumentDef::Variable(Box::new(ArgumentDef::Path))),
             "variable:key-press" => Ok(ArgumentDef::Variable(Box::new(ArgumentDef::KeyPress))),
             "variable:key-sequence" => Ok(ArgumentDef::Variable(Box::new(ArgumentDef::KeySequence))),
             "variable:boolean" => Ok(ArgumentDef::Variable(Box::new(ArgumentDef::Boolean))),
+            "variable:buffer" => Ok(ArgumentDef::Variable(Box::new(ArgumentDef::Buffer))),
             _ => {
                 Err(format!("Unknown argument: {}", value))
             },
@@ -238,6 +276,14 @@ impl Command {
         &self.function
     }
 
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    pub fn arguments(&self) -> &[ArgumentDef] {
+        &self.arguments
+    }
+
     /// Executes the command for an arg list of strings.
     /// This parses the args according to the argument definition.
     pub async fn execute(&self, args: &[String]) -> Result<Vec<Value>, Exception> {
@@ -292,6 +338,12 @@ impl Command {
                         return Err(Exception::error("Value not convertible to boolean"))
                     }
                 }
+                ArgumentDef::Buffer => {
+                    let Some(arg) = args.next() else {
+                        return Err(Exception::error("Not enough arguments for command"))
+                    };
+                    function_args.push(Value::from(arg.to_string()));
+                }
                 ArgumentDef::Variable(x) => {
                     while let Some(arg) = args.next() {
                         match x.as_ref() {
@@ -323,6 +375,9 @@ impl Command {
                                     return Err(Exception::error("Value not convertible to boolean"))
                                 }
                             }
+                            ArgumentDef::Buffer => {
+                                function_args.push(Value::from(arg.to_string()));
+                            }
                             ArgumentDef::Variable(_) => {
                                 unreachable!("It should not be possible to have a variable variable arg def")
                             }
@@ -392,6 +447,15 @@ pub fn command_description(command: &Value) -> Result<Vec<Value>, Exception> {
     Ok(vec![Value::from(command.description.clone())])
 }
 
+#[bridge(name = "command-arguments", lib = "(koru-command)")]
+pub fn command_arguments(command: &Value) -> Result<Vec<Value>, Exception> {
+    let command: Gc<Command> = command.clone().try_to_rust_type()?;
+    let arguments = command.arguments.iter()
+        .map(|argument| Value::from(Symbol::intern(argument.into())))
+        .collect::<Vec<_>>();
+    Ok(vec![lists::slice_to_list(&arguments)])
+}
+
 #[bridge(name = "command-create", lib = "(koru-command)")]
 pub async fn command_create(args: &[Value]) -> Result<Vec<Value>, Exception> {
     let Some((name, rest)) = args.split_first() else {
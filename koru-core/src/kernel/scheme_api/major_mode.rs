@@ -11,7 +11,10 @@ use scheme_rs::registry::bridge;
 use scheme_rs::symbols::Symbol;
 use scheme_rs::value::Value;
 use tokio::sync::RwLock;
+use scheme_rs::num::SimpleNumber;
 use crate::kernel::buffer::Cursor;
+use crate::kernel::scheme_api::session::SessionState;
+use crate::styled_text::{ColorType, StyledText, TextAttribute, TextChunk};
 
 #[derive(Debug, Trace)]
 pub struct MajorMode {
@@ -21,6 +24,8 @@ pub struct MajorMode {
     get_main_cursor: Procedure,
     gain_focus: Procedure,
     lose_focus: Procedure,
+    /// Computes the indentation of new lines, see `major-mode-indent-set!`
+    indent: RwLock<Option<Procedure>>,
 }
 
 impl MajorMode {
@@ -39,6 +44,7 @@ impl MajorMode {
             get_main_cursor,
             gain_focus,
             lose_focus,
+            indent: RwLock::new(None),
         }
     }
 
@@ -54,6 +60,10 @@ impl MajorMode {
         self.lose_focus.clone()
     }
 
+    pub async fn indent(&self) -> Option<Procedure> {
+        self.indent.read().await.clone()
+    }
+
     pub async fn get_main_cursor(&self, self_value: Value) -> Result<Cursor, Exception> {
         let cursor: Gc<Cursor> = self.get_main_cursor.call(&[self_value]).await
             .map(|values| {
@@ -126,6 +136,17 @@ pub async fn major_mode_set_data(args: &[Value]) -> Result<Vec<Value>, Exception
     Ok(Vec::new())
 }
 
+#[bridge(name = "major-mode-indent-set!", lib = "(major-mode)")]
+pub async fn major_mode_set_indent(mode: &Value, indent: &Value) -> Result<Vec<Value>, Exception> {
+    let mode: Gc<MajorMode> = mode.clone().try_to_rust_type()?;
+    let indent: Option<Procedure> = if indent.is_true() {
+        Some(indent.clone().try_into()?)
+    } else {
+        None
+    };
+    *mode.indent.write().await = indent;
+    Ok(Vec::new())
+}
 
 /*
 #[bridge(name = "major-mode-append-line", lib = "(major-mode)")]
@@ -150,29 +171,60 @@ pub async fn append_line(args: &[Value]) -> Result<Vec<Value>, Exception> {
         Ok(vec![])
     }
 }*/
-/*
 #[bridge(name = "write-line-number", lib = "(major-mode)")]
-pub fn write_line_number(args: &[Value]) -> Result<Vec<Value>, Exception> {
+pub async fn write_line_number(args: &[Value]) -> Result<Vec<Value>, Exception> {
     let Some((current_line, rest)) = args.split_first() else {
         return Err(Exception::wrong_num_of_args(3, args.len()));
     };
     let Some((max_lines, rest)) = rest.split_first() else {
         return Err(Exception::wrong_num_of_args(3, args.len()));
     };
-    let Some((separator, _)) = rest.split_first() else {
+    let Some((separator, rest)) = rest.split_first() else {
         return Err(Exception::wrong_num_of_args(3, args.len()));
     };
-    let current_line: Arc<Number> = current_line.clone().try_into()?;
-    let current_line = current_line.checked_add(&Number::FixedInteger(1)).unwrap();
-    let max_lines: Arc<Number> = max_lines.clone().try_into()?;
+    let current_line: SimpleNumber = current_line.clone().try_into()?;
+    let current_line: usize = current_line.try_into()?;
+    let max_lines: SimpleNumber = max_lines.clone().try_into()?;
+    let max_lines: usize = max_lines.try_into()?;
     let separator: char = separator.clone().try_into()?;
-    let current_line = current_line.to_string();
-    let max_lines = max_lines.to_string();
-    let needed_padding = max_lines.chars().count();
-    
-    let mut string = format!("{: >1$}", current_line, needed_padding);
+    let needed_padding = max_lines.to_string().chars().count();
+
+    // With a buffer, the number gets a sign column for the worst diagnostic on the line
+    let severity = match rest.first() {
+        Some(buffer_name) => {
+            let buffer_name: String = buffer_name.clone().try_into()?;
+            let handle = {
+                let state = SessionState::get_state();
+                let guard = state.read().await;
+                let buffers = guard.get_buffers().await;
+                buffers.get(&buffer_name).map(|buffer| buffer.get_handle())
+            };
+            let Some(handle) = handle else {
+                return Err(Exception::error(format!("Buffer not found: {buffer_name}")));
+            };
+            Some(handle.line_severity(current_line).await)
+        }
+        None => None,
+    };
+
+    let mut string = match severity {
+        Some(Some(severity)) => String::from(severity.sign()),
+        Some(None) => String::from(" "),
+        None => String::new(),
+    };
+    string.push_str(&format!("{: >1$}", current_line + 1, needed_padding));
     string.push(separator);
-    
-    Ok(vec![Value::from(string)])
+
+    let text = match severity.flatten() {
+        Some(severity) => StyledText::Style {
+            fg_color: severity.color(),
+            bg_color: ColorType::Base,
+            attribute: TextAttribute::empty(),
+            text: TextChunk::from(string),
+        },
+        None => StyledText::None {
+            text: TextChunk::from(string),
+        },
+    };
+    Ok(vec![Value::from(Record::from_rust_type(text))])
 }
-*/
@@ -11,7 +11,7 @@ use tokio::sync::Mutex;
 use crate::kernel::buffer::{BufferHandle, Cursor, CursorDirection, Cursors, GridCursor};
 use crate::kernel::input::{KeyPress, KeyValue};
 use crate::kernel::scheme_api::major_mode::{MajorMode};
-use crate::kernel::scheme_api::session::SessionState;
+use crate::kernel::scheme_api::session::{register_name, SessionState, CLIPBOARD_REGISTER};
 
 #[derive(Debug, Trace)]
 struct TextEditDataInternal {
@@ -50,6 +50,13 @@ impl TextEditData {
         Ok(())
     }
     
+    pub async fn move_cursor_visual(&self, index: usize, up: bool) -> Result<(), Exception> {
+        let handle = self.get_buffer_handle().await?;
+        let new_cursor = handle.move_cursor_visual(self.internal.lock().await.cursors[index], up).await?;
+        self.internal.lock().await.cursors[index] = new_cursor;
+        Ok(())
+    }
+
     pub async fn scan(&self, cursor: usize) -> Result<String, Exception> {
         let handle = self.get_buffer_handle().await?;
         let character = handle.scan(self.internal.lock().await.cursors[cursor]).await;
@@ -104,6 +111,25 @@ impl TextEditData {
         (cursor.line(), cursor.column())
     }
 
+    /// Moves a cursor to a line and column, clamping them to the buffer.
+    pub async fn set_cursor_position(&self, index: usize, line: usize, column: usize) -> Result<(), Exception> {
+        let handle = self.get_buffer_handle().await?;
+        let text = handle.get_text().await;
+        let line = line.min(text.line_len().saturating_sub(1));
+        let column = if text.line_len() == 0 {
+            0
+        } else {
+            column.min(text.line(line).graphemes().count())
+        };
+        let mut guard = self.internal.lock().await;
+        let Some(cursor) = guard.cursors.get_mut(index) else {
+            return Err(Exception::error(format!("Cursor index out of bounds: {index}")));
+        };
+        cursor.set_line(line);
+        cursor.set_column(column);
+        Ok(())
+    }
+
     pub async fn add_cursor(&self, line: usize, column: usize) {
         //trace!("line: {line}, column: {column}");
         let mut index = 0;
@@ -219,7 +245,7 @@ pub async fn text_edit_draw(major_mode: &Value) -> Result<Vec<Value>, Exception>
         let mut guard = state.write().await;
         let mut buffers = guard.get_buffers_mut().await;
         let buffer = buffers.get_mut(&buffer_name).ok_or(Exception::error(String::from("Buffer does not exist")))?;
-        buffer.render_styled_text().await;
+        buffer.render_styled_text(&data.internal.lock().await.cursors).await;
         buffer.clone()
     };
 
@@ -261,6 +287,26 @@ pub async fn move_cursor_down(args: &[Value]) -> Result<Vec<Value>, Exception> {
     Ok(Vec::new())
 }
 
+#[bridge(name = "text-edit-move-cursor-visual-up", lib = "(text-edit)")]
+pub async fn move_cursor_visual_up(major_mode: &Value, cursor_index: &Value) -> Result<Vec<Value>, Exception> {
+    let major_mode: Gc<MajorMode> = major_mode.clone().try_to_rust_type()?;
+    let data = get_data(&major_mode).await?;
+    let cursor_index: SimpleNumber = cursor_index.clone().try_into()?;
+    let cursor_index = cursor_index.try_into()?;
+    data.move_cursor_visual(cursor_index, true).await?;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "text-edit-move-cursor-visual-down", lib = "(text-edit)")]
+pub async fn move_cursor_visual_down(major_mode: &Value, cursor_index: &Value) -> Result<Vec<Value>, Exception> {
+    let major_mode: Gc<MajorMode> = major_mode.clone().try_to_rust_type()?;
+    let data = get_data(&major_mode).await?;
+    let cursor_index: SimpleNumber = cursor_index.clone().try_into()?;
+    let cursor_index = cursor_index.try_into()?;
+    data.move_cursor_visual(cursor_index, false).await?;
+    Ok(Vec::new())
+}
+
 #[bridge(name = "text-edit-move-cursor-left", lib = "(text-edit)")]
 pub async fn move_cursor_left(args: &[Value]) -> Result<Vec<Value>, Exception> {
     let Some((major_mode, rest)) = args.split_first() else {
@@ -601,6 +647,33 @@ pub async fn get_cursor_position(args: &[Value]) -> Result<Vec<Value>, Exception
     Ok(vec![pair])
 }
 
+#[bridge(name = "text-edit-cursor-set-position", lib = "(text-edit)")]
+pub async fn set_cursor_position(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let Some((major_mode, rest)) = args.split_first() else {
+        return Err(Exception::wrong_num_of_args(4, args.len()))
+    };
+    let Some((cursor_index, rest)) = rest.split_first() else {
+        return Err(Exception::wrong_num_of_args(4, args.len()))
+    };
+    let Some((row, rest)) = rest.split_first() else {
+        return Err(Exception::wrong_num_of_args(4, args.len()))
+    };
+    let Some((col, _)) = rest.split_first() else {
+        return Err(Exception::wrong_num_of_args(4, args.len()))
+    };
+    let cursor_index: SimpleNumber = cursor_index.clone().try_into()?;
+    let cursor_index: usize = cursor_index.try_into()?;
+    let row: SimpleNumber = row.clone().try_into()?;
+    let row: usize = row.try_into()?;
+    let col: SimpleNumber = col.clone().try_into()?;
+    let col: usize = col.try_into()?;
+    let major_mode: Gc<MajorMode> = major_mode.clone().try_to_rust_type()?;
+    let data = get_data(&major_mode).await?;
+    data.set_cursor_position(cursor_index, row, col).await?;
+
+    Ok(Vec::new())
+}
+
 #[bridge(name = "text-edit-cursor-create", lib = "(text-edit)")]
 pub async fn create_cursor(args: &[Value]) -> Result<Vec<Value>, Exception> {
     let Some((major_mode, rest)) = args.split_first() else {
@@ -766,6 +839,75 @@ pub async fn insert_text(args: &[Value]) -> Result<Vec<Value>, Exception> {
     }
 }
 
+/// Gets the indentation for a new line from the major mode's indent procedure.
+///
+/// Returns None if there is no indent procedure or it returned `#f`.
+async fn computed_indentation(mode_value: &Value, major_mode: &Gc<MajorMode>, handle: &BufferHandle, line: usize) -> Result<Option<String>, Exception> {
+    let Some(indent) = major_mode.indent().await else {
+        return Ok(None);
+    };
+    let args = [
+        mode_value.clone(),
+        Value::from(handle.get_name().await),
+        Value::from(line),
+    ];
+    let result = indent.call(&args).await?;
+    let Some(column) = result.first().filter(|column| column.is_true()) else {
+        return Ok(None);
+    };
+    let column: SimpleNumber = column.clone().try_into()?;
+    let column: usize = column.try_into()?;
+    Ok(Some(handle.indent().await.text_for_column(column)))
+}
+
+#[bridge(name = "text-edit-insert-newline", lib = "(text-edit)")]
+pub async fn insert_newline(major_mode: &Value, cursor_index: &Value) -> Result<Vec<Value>, Exception> {
+    let mode_value = major_mode.clone();
+    let major_mode: Gc<MajorMode> = major_mode.clone().try_to_rust_type()?;
+    let cursor_index: SimpleNumber = cursor_index.clone().try_into()?;
+    let cursor_index: usize = cursor_index.try_into()?;
+    let data = get_data(&major_mode).await?;
+    let handle: BufferHandle = data.get_buffer_handle().await?;
+
+    // The newline and its indentation get undone together
+    handle.start_transaction().await;
+    let result = async {
+        insert_text_at_cursor(major_mode.clone(), cursor_index, String::from("\n")).await?;
+        let line = data.get_cursor(cursor_index).await.line();
+        let indentation = match computed_indentation(&mode_value, &major_mode, &handle, line).await? {
+            Some(indentation) => indentation,
+            None => handle.line_indentation(line - 1).await,
+        };
+        if !indentation.is_empty() {
+            insert_text_at_cursor(major_mode.clone(), cursor_index, indentation).await?;
+        }
+        Ok::<(), Exception>(())
+    }.await;
+    handle.end_transaction().await;
+    result?;
+    Ok(Vec::new())
+}
+
+async fn shift_cursor_lines(major_mode: &Value, dedent: bool) -> Result<Vec<Value>, Exception> {
+    let major_mode: Gc<MajorMode> = major_mode.clone().try_to_rust_type()?;
+    let data = get_data(&major_mode).await?;
+    let cursors = data.get_cursors().await;
+    let handle: BufferHandle = data.get_buffer_handle().await?;
+    let new_cursors = handle.shift_lines(cursors, dedent).await;
+    data.set_cursors(new_cursors).await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "text-edit-indent-cursors", lib = "(text-edit)")]
+pub async fn indent_cursors(major_mode: &Value) -> Result<Vec<Value>, Exception> {
+    shift_cursor_lines(major_mode, false).await
+}
+
+#[bridge(name = "text-edit-dedent-cursors", lib = "(text-edit)")]
+pub async fn dedent_cursors(major_mode: &Value) -> Result<Vec<Value>, Exception> {
+    shift_cursor_lines(major_mode, true).await
+}
+
 #[bridge(name = "text-edit-delete-before-cursor", lib = "(text-edit)")]
 pub async fn delete_text_back(args: &[Value]) -> Result<Vec<Value>, Exception> {
     let Some((major_mode, rest)) = args.split_first() else {
@@ -806,19 +948,49 @@ pub async fn delete_text_forward(args: &[Value]) -> Result<Vec<Value>, Exception
     Ok(Vec::new())
 }
 
+/// Parses the optional register name that the region commands take after the cursor index.
+fn optional_register(rest: &[Value]) -> Result<Option<String>, Exception> {
+    match rest.first() {
+        Some(register) => Ok(Some(register_name(register)?)),
+        None => Ok(None),
+    }
+}
+
+/// Stores the text in the cursor's region into the registers.
+async fn yank_region(data: &TextEditData, cursor_index: usize, register: Option<&str>) -> Result<(), Exception> {
+    let handle: BufferHandle = data.get_buffer_handle().await?;
+    let cursor = data.get_cursor(cursor_index).await;
+    let Some(text) = handle.region_text(cursor).await else {
+        return Ok(());
+    };
+    let registers = SessionState::get_registers().await;
+    let mut guard = registers.write().await;
+    guard.yank(register, cursor_index, text);
+    if register == Some(CLIPBOARD_REGISTER) {
+        let contents = guard.get(register)
+            .map(|entry| entry.values().join("\n"))
+            .unwrap_or_default();
+        drop(guard);
+        SessionState::set_clipboard(contents).await?;
+    }
+    Ok(())
+}
+
 #[bridge(name = "text-edit-delete-region-cursor", lib = "(text-edit)")]
 pub async fn delete_text_region(args: &[Value]) -> Result<Vec<Value>, Exception> {
     let Some((major_mode, rest)) = args.split_first() else {
         return Err(Exception::wrong_num_of_args(2, args.len()))
     };
-    let Some((cursor_index, _)) = rest.split_first() else {
+    let Some((cursor_index, rest)) = rest.split_first() else {
         return Err(Exception::wrong_num_of_args(2, args.len()));
     };
 
     let major_mode: Gc<MajorMode> = major_mode.clone().try_to_rust_type()?;
     let cursor_index: SimpleNumber = cursor_index.clone().try_into()?;
     let cursor_index: usize = cursor_index.try_into()?;
+    let register = optional_register(rest)?;
     let data = get_data(&major_mode).await?;
+    yank_region(&data, cursor_index, register.as_deref()).await?;
     let cursors = data.get_cursors().await;
     let handle: BufferHandle = data.get_buffer_handle().await?;
     let new_cursors = handle.delete_region(cursor_index, cursors).await?;
@@ -826,6 +998,50 @@ pub async fn delete_text_region(args: &[Value]) -> Result<Vec<Value>, Exception>
     Ok(Vec::new())
 }
 
+#[bridge(name = "text-edit-copy-region-cursor", lib = "(text-edit)")]
+pub async fn copy_text_region(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let Some((major_mode, rest)) = args.split_first() else {
+        return Err(Exception::wrong_num_of_args(2, args.len()))
+    };
+    let Some((cursor_index, rest)) = rest.split_first() else {
+        return Err(Exception::wrong_num_of_args(2, args.len()));
+    };
+
+    let major_mode: Gc<MajorMode> = major_mode.clone().try_to_rust_type()?;
+    let cursor_index: SimpleNumber = cursor_index.clone().try_into()?;
+    let cursor_index: usize = cursor_index.try_into()?;
+    let register = optional_register(rest)?;
+    let data = get_data(&major_mode).await?;
+    yank_region(&data, cursor_index, register.as_deref()).await?;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "text-edit-paste-at-cursor", lib = "(text-edit)")]
+pub async fn paste_text(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let Some((major_mode, rest)) = args.split_first() else {
+        return Err(Exception::wrong_num_of_args(2, args.len()))
+    };
+    let Some((cursor_index, rest)) = rest.split_first() else {
+        return Err(Exception::wrong_num_of_args(2, args.len()));
+    };
+
+    let major_mode: Gc<MajorMode> = major_mode.clone().try_to_rust_type()?;
+    let cursor_index: SimpleNumber = cursor_index.clone().try_into()?;
+    let cursor_index: usize = cursor_index.try_into()?;
+    let register = optional_register(rest)?;
+    let text = {
+        let registers = SessionState::get_registers().await;
+        let guard = registers.read().await;
+        guard.get(register.as_deref())
+            .and_then(|entry| entry.get(cursor_index))
+    };
+    let Some(text) = text else {
+        return Ok(vec![Value::from(false)]);
+    };
+    insert_text_at_cursor(major_mode, cursor_index, text).await?;
+    Ok(vec![Value::from(true)])
+}
+
 #[bridge(name = "text-edit-replace-text", lib = "(text-edit)")]
 pub async fn replace_text(args: &[Value]) -> Result<Vec<Value>, Exception> {
     let Some((major_mode, rest)) = args.split_first() else {
@@ -38,16 +38,14 @@ impl MinorModeManager {
         }
     }
 
-    pub async fn add_minor_mode(&mut self, minor_mode: Value) -> Result<(), Exception> {
+    /// Adds a minor mode, giving back false if one with the same name was already added.
+    ///
+    /// The mode's gain focus callback isn't called here, since the caller is usually holding the buffer table.
+    pub async fn add_minor_mode(&mut self, minor_mode: Value) -> Result<bool, Exception> {
         let minor_mode_value: Gc<MinorMode> = minor_mode.try_to_rust_type()?;
         let mut guard = self.metadata.write().await;
         if guard.map.contains_key(&minor_mode_value.name) {
-            return Ok(());
-        }
-        {
-            let mm: Gc<MinorMode> = minor_mode.try_to_rust_type()?;
-            let gain_focus = mm.gain_focus();
-            gain_focus.call(&[minor_mode.clone()]).await?;
+            return Ok(false);
         }
         if let Some(index) = guard.free_list.pop_front() {
             self.minor_modes[index] = Some(minor_mode);
@@ -57,7 +55,7 @@ impl MinorModeManager {
             guard.map.insert(name, index);
             self.minor_modes.push(Some(minor_mode));
         }
-        Ok(())
+        Ok(true)
     }
 
     pub async fn remove_minor_mode(&mut self, minor_mode_name: Symbol) -> Option<String> {
@@ -114,6 +112,10 @@ impl MinorMode {
         }
     }
 
+    pub fn name(&self) -> Symbol {
+        self.name
+    }
+
     pub fn gain_focus(&self) -> Procedure {
         self.gain_focus.clone()
     }
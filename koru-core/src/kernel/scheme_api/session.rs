@@ -1,12 +1,18 @@
 mod buffer;
-mod keymap;
+mod command_bar;
+mod history;
+pub(crate) mod keymap;
+mod registers;
 
 use std::ops::DerefMut;
 use std::ops::Deref;
 pub use buffer::*;
+pub use registers::*;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, LazyLock};
+use std::time::Duration;
 use crop::Rope;
 use log::error;
 use scheme_rs::exceptions::Exception;
@@ -19,17 +25,23 @@ use scheme_rs::symbols::Symbol;
 use scheme_rs::value::{UnpackedValue, Value};
 use tokio::sync::{RwLock};
 use tokio::task_local;
-use keypress_localize::KeyboardRegion;
+use keypress_localize::Layout as KeyboardLayout;
 use crate::kernel;
 use crate::kernel::broker::{BackendMessage, BrokerClient, MessageKind};
-use crate::kernel::buffer::{BufferHandle};
-use crate::kernel::input::{KeyBuffer, KeyPress, KeyValue};
+use crate::kernel::buffer::{BufferHandle, TextBufferTable};
+use crate::kernel::completion;
+use crate::kernel::mouse;
+use crate::kernel::picker;
+use crate::kernel::bindings::{self, KeyMapSource};
+use crate::kernel::layout::{self, Layout};
+use crate::kernel::tabs::Tabs;
+use crate::kernel::input::{keyboard, KeyBuffer, KeyPress, KeyValue};
 use crate::kernel::scheme_api::command::{Command, CommandTree};
 use crate::kernel::scheme_api::major_mode::MajorMode;
 use crate::kernel::scheme_api::minor_mode::MinorMode;
 use crate::kernel::scheme_api::session::keymap::SchemeKeyMap;
 use crate::kernel::scheme_api::task;
-use crate::keymap::KeyMap;
+use crate::keymap::{KeyMap, Resolution};
 use crate::styled_text::{ColorType, StyledFile, StyledText, TextAttribute, TextChunk};
 
 pub struct Hooks {
@@ -64,6 +76,11 @@ impl Hooks {
         }
     }
 
+    /// Checks if anything has been added to a hook.
+    pub fn has_callbacks(&self, hook_name: &Symbol) -> bool {
+        self.hooks.get(hook_name).is_some_and(|hooks| !hooks.is_empty())
+    }
+
     pub async fn execute_hook(&self, hook_name: &Symbol, args: &[Value]) -> Result<(), Exception> {
         let Some(hooks) = self.hooks.get(hook_name) else {
             panic!("Unknown hook {}", hook_name);
@@ -90,6 +107,12 @@ impl KeyPressCommandResult {
 pub struct CommandBar {
     buffer: String,
     cursor: usize,
+    /// The candidates of the last completion, until the command bar is changed some other way.
+    completion: Option<command_bar::CommandBarCompletion>,
+    /// The history that the prompt that is shown uses, if any.
+    history_name: Option<String>,
+    history: history::CommandHistory,
+    navigation: Option<history::HistoryNavigation>,
 }
 
 impl CommandBar {
@@ -97,19 +120,35 @@ impl CommandBar {
         Self {
             buffer: String::new(),
             cursor: 0,
+            completion: None,
+            history_name: None,
+            history: history::CommandHistory::load(),
+            navigation: None,
         }
     }
 
+    /// Ends completing and stepping through the history when the command bar is changed.
+    fn edited(&mut self) {
+        self.completion = None;
+        self.navigation = None;
+    }
+
     pub fn cursor_left(&mut self) {
+        self.edited();
         self.cursor = self.cursor.saturating_sub(1);
     }
     pub fn cursor_right(&mut self) {
+        self.edited();
         if self.cursor != self.buffer.chars().count() {
             self.cursor = self.cursor.saturating_add(1);
         }
     }
 
     pub fn delete_backward(&mut self) {
+        self.edited();
+        if self.cursor == 0 {
+            return;
+        }
         let chars = self.buffer.chars()
             .take(self.cursor - 1)
             .chain(self.buffer.chars().skip(self.cursor))
@@ -119,6 +158,7 @@ impl CommandBar {
     }
 
     pub fn delete_forward(&mut self) {
+        self.edited();
         let chars = self.buffer.chars()
         .take(self.cursor)
         .chain(self.buffer.chars().skip(self.cursor + 1))
@@ -126,9 +166,22 @@ impl CommandBar {
         self.buffer = chars;
     }
 
+    /// Takes the text out of the command bar, adding it to the history of the prompt.
     pub fn take(&mut self) -> String {
+        self.edited();
+        self.cursor = 0;
+        let text = std::mem::take(&mut self.buffer);
+        if let Some(name) = &self.history_name {
+            self.history.record(name, &text);
+        }
+        text
+    }
+
+    /// Empties the command bar without adding what was in it to the history.
+    pub fn clear(&mut self) {
+        self.edited();
         self.cursor = 0;
-        std::mem::take(&mut self.buffer)
+        self.buffer.clear();
     }
 
     pub fn get(&self) -> String {
@@ -183,6 +236,7 @@ impl CommandBar {
     }
 
     pub fn insert(&mut self, value: &str) {
+        self.edited();
         let index = self.buffer.chars()
             .take(self.cursor)
             .map(char::len_utf8)
@@ -214,12 +268,20 @@ pub struct SessionState {
     broker_client: Arc<RwLock<BrokerClient>>,
     active_sessions: Arc<RwLock<Vec<usize>>>,
     command_bar: Arc<RwLock<CommandBar>>,
+    registers: Arc<RwLock<Registers>>,
+    tabs: Arc<RwLock<Tabs>>,
+    /// The columns and lines of the area that all the panes are drawn in.
+    viewport: Arc<RwLock<(usize, usize)>>,
 }
 
 impl SessionState {
     pub fn new() -> Self {
         let mut hooks = Hooks::new();
         hooks.add_new_hook_kind(Symbol::intern("buffer-open"));
+        hooks.add_new_hook_kind(Symbol::intern(completion::COMPLETION_END_HOOK));
+        for hook in mouse::MOUSE_HOOKS {
+            hooks.add_new_hook_kind(Symbol::intern(hook));
+        }
 
         let hooks = Arc::new(RwLock::new(hooks));
         let (sender, receiver) = tokio::sync::mpsc::channel(100);
@@ -235,6 +297,10 @@ impl SessionState {
             broker_client: Arc::new(RwLock::new(BrokerClient::new(0, sender, receiver))),
             active_sessions: Arc::new(RwLock::new(Vec::new())),
             command_bar: Arc::new(RwLock::new(CommandBar::new())),
+            registers: Arc::new(RwLock::new(Registers::new())),
+            tabs: Arc::new(RwLock::new(Tabs::new())),
+            // The frontend tells the real size when it connects
+            viewport: Arc::new(RwLock::new((80, 24))),
         }
     }
 
@@ -252,6 +318,7 @@ impl SessionState {
             state.read().await.active_sessions.clone()
         };
         active_sessions.write().await.remove(session_id);
+        keyboard::remove_session(session_id);
     }
 
     pub async fn quit_session() {
@@ -292,6 +359,11 @@ impl SessionState {
         Ok(())
     }
 
+    /// Sends the text to the focused client to put into the system clipboard.
+    pub async fn set_clipboard(text: String) -> Result<(), Exception> {
+        SessionState::send_message(MessageKind::BackEnd(BackendMessage::SetClipboard(text))).await
+    }
+
     pub async fn get_buffers(&self) -> impl Deref<Target = HashMap<String, Buffer>> {
         self.buffers.read().await
     }
@@ -303,6 +375,52 @@ impl SessionState {
         self.buffers.write().await.insert(name.to_string(), Buffer::new(handle));
     }
 
+    /// Opens a file into the editor and runs the `buffer-open` hook.
+    ///
+    /// If the file is already open then the existing buffer is used instead.
+    /// Returns the name of the buffer.
+    pub async fn open_file(path: &str) -> Result<String, Exception> {
+        let buffers = SessionState::get_state().read().await.buffers.clone();
+        if let Ok(path) = PathBuf::from(path).canonicalize() {
+            let name = path.to_string_lossy().to_string();
+            if buffers.read().await.contains_key(&name) {
+                return Ok(name);
+            }
+        }
+        let handle = TextBufferTable::open(path.to_string()).await
+            .map_err(|err| Exception::error(err.to_string()))?;
+        let name = handle.get_name().await;
+        buffers.write().await.insert(name.clone(), Buffer::new(handle));
+        Self::buffer_opened_hook(&name).await;
+        Ok(name)
+    }
+
+    /// Creates a new buffer in the editor and runs the `buffer-open` hook.
+    pub async fn create_buffer(name: &str, contents: &str) -> Result<String, Exception> {
+        let handle = TextBufferTable::create(name.to_string(), contents).await
+            .map_err(|err| Exception::error(err.to_string()))?;
+        let buffers = SessionState::get_state().read().await.buffers.clone();
+        buffers.write().await.insert(name.to_string(), Buffer::new(handle));
+        Self::buffer_opened_hook(name).await;
+        Ok(name.to_string())
+    }
+
+    async fn buffer_opened_hook(buffer_name: &str) {
+        let file_ext = Path::new(buffer_name).extension()
+            .map(|ext| ext.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let args = &[Value::from(buffer_name.to_string()), Value::from(file_ext)];
+
+        let result = SessionState::emit_hook_blocking(Symbol::intern("buffer-open"), args).await;
+
+        match result {
+            Ok(_) => {}
+            Err(err) => {
+                error!("{}", err);
+            }
+        }
+    }
+
     pub fn get_hooks(&self) -> &Arc<RwLock<Hooks>> {
         &self.hooks
     }
@@ -315,6 +433,43 @@ impl SessionState {
         command_bar
     }
 
+    /// Gets the layout of the current tab.
+    pub async fn get_layout() -> Arc<RwLock<Layout>> {
+        let tabs = SessionState::get_tabs().await;
+        let guard = tabs.read().await;
+        guard.current_layout()
+    }
+
+    pub(crate) async fn get_tabs() -> Arc<RwLock<Tabs>> {
+        let state = SessionState::get_state();
+        let guard = state.read().await;
+        guard.tabs.clone()
+    }
+
+    /// Gets the columns and lines of the area that the panes are drawn in.
+    pub async fn get_viewport() -> (usize, usize) {
+        let viewport = SessionState::get_state().read().await.viewport.clone();
+        let guard = viewport.read().await;
+        *guard
+    }
+
+    /// Records the size of the area that the panes are drawn in.
+    ///
+    /// Returns true if the size changed.
+    pub async fn set_viewport(columns: usize, lines: usize) -> bool {
+        let viewport = SessionState::get_state().read().await.viewport.clone();
+        let mut guard = viewport.write().await;
+        let changed = *guard != (columns, lines);
+        *guard = (columns, lines);
+        changed
+    }
+
+    pub async fn get_registers() -> Arc<RwLock<Registers>> {
+        let state = SessionState::get_state();
+        let guard = state.read().await;
+        guard.registers.clone()
+    }
+
     /**
     Reads from a string a command to execute.
     Then executes the command from the name, doing nothing if the command is not valid.
@@ -396,6 +551,12 @@ impl SessionState {
             error!("Unknown command: {}", command_name);
             return;
         };
+        // Commands run from scripts or key bindings go in the same history as the ones typed into the command bar
+        {
+            let command_bar = Self::get_command_bar().await;
+            command_bar.write().await.history.record(history::COMMAND_HISTORY, command_bar_string);
+        }
+        history::save_history().await;
 
         match command.execute(&command_args).await {
             Err(err) => {
@@ -450,6 +611,7 @@ impl SessionState {
             }
         }
         *current_buffer.write().await = Some(buffer_name.clone());
+        layout::show_buffer(buffer_name.clone()).await;
         if different_buffer {
             let buffer = {
                 let result = buffers.read().await.get(&buffer_name).cloned();
@@ -522,6 +684,22 @@ impl SessionState {
         }
     }
 
+    /// Gets an open buffer by its name.
+    pub async fn get_buffer(buffer_name: &str) -> Result<Buffer, Exception> {
+        let buffer = {
+            let state = Self::get_state();
+            let guard = state.read().await;
+            let buffers = guard.get_buffers().await;
+            buffers.get(buffer_name).cloned()
+        };
+        buffer.ok_or_else(|| Exception::error(format!("Buffer not found: {buffer_name}")))
+    }
+
+    /// Gets the handle of an open buffer by its name.
+    pub async fn get_buffer_handle(buffer_name: &str) -> Result<BufferHandle, Exception> {
+        Ok(Self::get_buffer(buffer_name).await?.get_handle())
+    }
+
     pub async fn add_keybinding(&self, keys: Vec<KeyPress>, command: Gc<Command>) {
         self.main_key_map.write().await.add_binding(keys, command);
     }
@@ -549,91 +727,239 @@ impl SessionState {
         guard.remove(&keymap_name);
     }
 
+    /// Has every key map merge the key groups into its bindings again, after a group has changed.
+    pub async fn key_groups_changed() {
+        {
+            let state = Self::get_state();
+            let guard = state.read().await;
+            guard.special_key_map.write().await.invalidate_tree();
+            guard.main_key_map.write().await.invalidate_tree();
+            guard.key_maps.write().await.values_mut().for_each(KeyMap::invalidate_tree);
+        }
+        picker::key_groups_changed();
+    }
+
     /// Returns: `true` if a mapping has been found, `false` if a mapping was not found
     async fn try_process_keypress(keys: &[KeyPress], map: &KeyMap) -> KeyPressCommandResult {
+        match map.lookup(keys) {
+            Some(command) => Self::run_key_command(keys, &command).await,
+            None => KeyPressCommandResult::new(false, false),
+        }
+    }
+
+    /// Calls the command with the keys that it was looked up with.
+    async fn run_key_command(keys: &[KeyPress], command: &Gc<Command>) -> KeyPressCommandResult {
         let mut flush = false;
         let mut found = false;
-        if let Some(command) = map.lookup(&keys).cloned() {
-            let proc = command.command().clone();
-            let args = keys.iter().map(|press| {
-                Value::from(Record::from_rust_type((*press).clone()))
-            }).collect::<Vec<Value>>();
-
-            let list = lists::slice_to_list(&args);
-
-            match proc.call(&[list]).await {
-                Ok(value) => {
-                    if value.is_empty() {
-                        flush = true;
-                        found = true;
-                    } else if !value.is_empty() {
-                        let unpack = value[0].clone().unpack();
-                        match unpack {
-                            UnpackedValue::Boolean(b) => {
-                                if b {
-                                    found = true;
-                                    flush = true;
-                                } else {
-                                    found = false;
-                                    flush = false;
-                                }
-                            }
-                            _ => {
-                                flush = true;
+        let proc = command.command().clone();
+        let args = keys.iter().map(|press| {
+            Value::from(Record::from_rust_type((*press).clone()))
+        }).collect::<Vec<Value>>();
+
+        let list = lists::slice_to_list(&args);
+
+        match proc.call(&[list]).await {
+            Ok(value) => {
+                if value.is_empty() {
+                    flush = true;
+                    found = true;
+                } else if !value.is_empty() {
+                    let unpack = value[0].clone().unpack();
+                    match unpack {
+                        UnpackedValue::Boolean(b) => {
+                            if b {
                                 found = true;
+                                flush = true;
+                            } else {
+                                found = false;
+                                flush = false;
                             }
                         }
+                        _ => {
+                            flush = true;
+                            found = true;
+                        }
                     }
-                },
-                Err(e) => {
-                    let key_string = keys.iter()
-                        .map(|press| press.to_string())
-                        .collect::<Vec<String>>();
-                    let key_string = key_string.join(" ");
-                    error!("{key_string}:\n{e}");
                 }
+            },
+            Err(e) => {
+                let key_string = keys.iter()
+                    .map(|press| press.to_string())
+                    .collect::<Vec<String>>();
+                let key_string = key_string.join(" ");
+                error!("{key_string}:\n{e}");
             }
-        } else {
-            found = false;
-            flush = false;
         }
         KeyPressCommandResult::new(flush, found)
     }
 
     pub async fn process_keypress(keypress: KeyPress) {
-        let keypress = keypress.canonicalize(SessionState::get_keyboard_region());
-        let (key_buffer, main_map, maps, special) = {
+        let keypress = keypress.canonicalize(&SessionState::get_keyboard_layout());
+        // A dead key waits for the key after it, so there can be no keys or two of them to process
+        for keypress in keyboard::compose(keypress) {
+            Self::process_composed_keypress(keypress).await;
+        }
+    }
+
+    async fn process_composed_keypress(keypress: KeyPress) {
+        // Keys that are being read for a command aren't run
+        if bindings::read_key(&keypress).await {
+            return;
+        }
+        // An open picker takes every key
+        if let Some(picker_map) = picker::key_map().await {
+            Self::try_process_keypress(&[keypress], &picker_map).await;
+            return;
+        }
+        let special = {
             let state = Self::get_state();
             let guard = state.read().await;
-            (guard.key_buffer.clone(), guard.main_key_map.read().await.clone(), guard.key_maps.read().await.clone(), guard.special_key_map.read().await.clone())
+            guard.special_key_map.read().await.clone()
         };
-        let result = Self::try_process_keypress(&vec![keypress.clone()], &special).await;
+        let result = Self::try_process_keypress(std::slice::from_ref(&keypress), &special).await;
         if result.found {
             return;
         }
 
-        Self::add_to_key_buffer(keypress).await;
-        let mut clear_key_buffer = false;
-        let keys = key_buffer.read().await.get().to_vec();
-        let result = Self::try_process_keypress(&keys, &main_map).await;
-        if result.found && result.flush {
-            clear_key_buffer = true;
-        }
-        if !result.found {
-            for (_, keymap) in maps.iter() {
-                let result = Self::try_process_keypress(&keys, keymap).await;
-                if result.found {
-                    if result.flush {
-                        clear_key_buffer = true;
+        Self::process_buffered_keypresses(vec![keypress]).await;
+    }
+
+    /// Gets the key maps that the keys in the key buffer are looked up in, which is every key map but the special one.
+    async fn buffered_key_maps() -> Vec<KeyMap> {
+        let state = Self::get_state();
+        let guard = state.read().await;
+        let mut maps = vec![guard.main_key_map.read().await.clone()];
+        maps.extend(guard.key_maps.read().await.values().cloned());
+        maps
+    }
+
+    /// Adds the keys to the key buffer one at a time, running what the keys in the key buffer are bound to.
+    ///
+    /// Keys that are the start of a longer binding wait in the key buffer for the rest of it,
+    /// for as long as the timeout of the first key map that they are waiting in.
+    /// If a key doesn't go on with any binding that the keys before it started,
+    /// the keys before it are resolved as if they ran out of time and the key is looked up again on its own.
+    async fn process_buffered_keypresses(keypresses: Vec<KeyPress>) {
+        let mut queue = VecDeque::from(keypresses);
+        while let Some(keypress) = queue.pop_front() {
+            Self::add_to_key_buffer(keypress).await;
+            let keys = Self::pending_keys().await;
+            // A command can change the key maps, so they are fetched again for every key
+            let maps = Self::buffered_key_maps().await;
+            let mut result = KeyPressCommandResult::new(false, false);
+            let mut waiting = None;
+            for map in &maps {
+                match map.resolve(&keys) {
+                    Resolution::Bound(command) | Resolution::Default(command) => {
+                        result = Self::run_key_command(&keys, &command).await;
+                        if result.found {
+                            break;
+                        }
+                    }
+                    Resolution::Prefix(_) => {
+                        waiting.get_or_insert(map.get_timeout());
+                    }
+                    Resolution::Unbound => {}
+                }
+            }
+
+            if result.found {
+                if result.flush {
+                    Self::flush_key_buffer().await;
+                }
+            } else if let Some(timeout) = waiting {
+                if let Some(timeout) = timeout {
+                    Self::start_key_sequence_timer(timeout).await;
+                }
+            } else {
+                // Nothing can come of the keys anymore, so they don't get in the way of the next ones
+                Self::flush_key_buffer().await;
+                if let Some((last, prefix)) = keys.split_last()
+                    && !prefix.is_empty() {
+                    queue.push_front(last.clone());
+                    for key in Self::resolve_key_prefix(prefix, &maps).await.into_iter().rev() {
+                        queue.push_front(key);
                     }
-                    break;
                 }
             }
         }
+    }
 
-        if clear_key_buffer {
-            Self::flush_key_buffer().await;
+    /// Runs what keys that stopped waiting for the rest of a binding do on their own.
+    ///
+    /// The longest start of the keys that is bound runs.
+    /// If none of it is bound, then the first key goes to the default command of the first key map that the keys were waiting in.
+    /// Returns the keys after the ones that were used up, which are to be looked up again.
+    async fn resolve_key_prefix(keys: &[KeyPress], maps: &[KeyMap]) -> Vec<KeyPress> {
+        for length in (1..=keys.len()).rev() {
+            for map in maps {
+                if let Resolution::Bound(command) | Resolution::Prefix(Some(command)) = map.resolve(&keys[..length])
+                    && Self::run_key_command(&keys[..length], &command).await.found {
+                    return keys[length..].to_vec();
+                }
+            }
+        }
+        let default = maps.iter()
+            .filter(|map| matches!(map.resolve(keys), Resolution::Prefix(_)))
+            .find_map(KeyMap::get_default);
+        if let Some(default) = default {
+            Self::run_key_command(&keys[..1], &default).await;
         }
+        keys[1..].to_vec()
+    }
+
+    /// Waits for the timeout and then has the session resolve the keys in the key buffer,
+    /// unless the key buffer changes before then.
+    async fn start_key_sequence_timer(timeout: Duration) {
+        let generation = Self::get_key_buffer().await.read().await.generation();
+        kernel::session_spawn(CURRENT_SESSION_ID.get(), async move {
+            tokio::time::sleep(timeout).await;
+            // The keys are resolved by the session, so that their commands don't run alongside a keypress
+            let message = MessageKind::BackEnd(BackendMessage::KeySequenceTimeout(generation));
+            if let Err(err) = SessionState::send_message(message).await {
+                error!("{}", err);
+            }
+        });
+    }
+
+    /// Runs what the keys in the key buffer do on their own, since they ran out of time waiting for the rest of a binding.
+    ///
+    /// Returns false without doing anything if the key buffer has changed since it had the generation.
+    pub async fn key_sequence_timeout(generation: usize) -> bool {
+        let keys = {
+            let key_buffer = Self::get_key_buffer().await;
+            let guard = key_buffer.read().await;
+            if guard.generation() != generation || guard.is_empty() {
+                return false;
+            }
+            guard.get().to_vec()
+        };
+        Self::flush_key_buffer().await;
+        let maps = Self::buffered_key_maps().await;
+        let rest = Self::resolve_key_prefix(&keys, &maps).await;
+        Self::process_buffered_keypresses(rest).await;
+        true
+    }
+
+    /// Gets the keys waiting in the key buffer.
+    pub async fn pending_keys() -> Vec<KeyPress> {
+        let key_buffer = Self::get_key_buffer().await;
+        key_buffer.read().await.get().to_vec()
+    }
+
+    /// Gets the key maps in the order that keys are looked up in.
+    ///
+    /// The special key map comes first, then the main key map, and then the other key maps.
+    pub async fn active_key_maps() -> Vec<(KeyMapSource, KeyMap)> {
+        let state = Self::get_state();
+        let guard = state.read().await;
+        let mut maps = vec![
+            (KeyMapSource::Special, guard.special_key_map.read().await.clone()),
+            (KeyMapSource::Main, guard.main_key_map.read().await.clone()),
+        ];
+        maps.extend(guard.key_maps.read().await.iter()
+            .map(|(name, map)| (KeyMapSource::Named(*name), map.clone())));
+        maps
     }
 
     async fn get_key_buffer() -> Arc<RwLock<KeyBuffer>> {
@@ -702,23 +1028,19 @@ impl SessionState {
         STATE.clone()
     }
 
-    pub fn set_keyboard_region(new_region: KeyboardRegion) {
-        unsafe {
-            KEYBOARD_REGION = new_region;
-        }
+    /// Switches the keyboard layout of the current session.
+    pub fn set_keyboard_layout(layout: Arc<KeyboardLayout>) {
+        keyboard::set_current(layout);
     }
 
-    pub fn get_keyboard_region() -> KeyboardRegion {
-        unsafe {
-            KEYBOARD_REGION
-        }
+    /// Gets the keyboard layout of the current session.
+    pub fn get_keyboard_layout() -> Arc<KeyboardLayout> {
+        keyboard::current()
     }
 }
 
 static STATE: LazyLock<Arc<RwLock<SessionState>>> = LazyLock::new(|| Arc::new(RwLock::new(SessionState::new())));
 
-static mut KEYBOARD_REGION: KeyboardRegion = KeyboardRegion::EnglishUS;
-
 #[bridge(name = "create-hook", lib = "(koru-session)")]
 pub async fn create_hook(args: &[Value]) -> Result<Vec<Value>, Exception> {
     let Some((hook_name, _)) = args.split_first() else {
@@ -833,16 +1155,21 @@ pub async fn add_minor_mode(args: &[Value]) -> Result<Vec<Value>, Exception> {
         return Err(Exception::wrong_num_of_args(2, args.len()))
     };
     let buffer_name: String = buffer_name.clone().try_into()?;
-    let _: Gc<MinorMode> = minor_mode.try_to_rust_type()?;
+    let mode: Gc<MinorMode> = minor_mode.try_to_rust_type()?;
 
-    let state = SessionState::get_state();
-    let guard = state.read().await;
-    let mut buffer_guard = guard.buffers.write().await;
-    let Some(buffer) = buffer_guard.get_mut(&buffer_name) else {
-        return Err(Exception::error(format!("Buffer not found: {buffer_name}")));
+    let added = {
+        let state = SessionState::get_state();
+        let guard = state.read().await;
+        let mut buffer_guard = guard.buffers.write().await;
+        let Some(buffer) = buffer_guard.get_mut(&buffer_name) else {
+            return Err(Exception::error(format!("Buffer not found: {buffer_name}")));
+        };
+        buffer.add_minor_mode(minor_mode.clone()).await?
     };
-
-    buffer.add_minor_mode(minor_mode.clone()).await?;
+    // The buffers have to be unlocked first as the callback is free to look at them
+    if added {
+        mode.gain_focus().call(std::slice::from_ref(minor_mode)).await?;
+    }
 
     Ok(Vec::new())
 }
@@ -859,6 +1186,26 @@ pub async fn get_minor_mode(minor_mode_name: &Value) -> Result<Vec<Value>, Excep
     Ok(vec![minor_mode])
 }
 
+#[bridge(name = "buffer-minor-modes", lib = "(koru-buffer)")]
+pub async fn buffer_minor_modes(buffer_name: &Value) -> Result<Vec<Value>, Exception> {
+    let buffer_name: String = buffer_name.clone().try_into()?;
+    let buffer = {
+        let state = SessionState::get_state();
+        let guard = state.read().await;
+        let buffers = guard.buffers.read().await;
+        buffers.get(&buffer_name).cloned()
+    };
+    let Some(buffer) = buffer else {
+        return Err(Exception::error(format!("Buffer not found: {buffer_name}")));
+    };
+    let mut names = Vec::new();
+    for minor_mode in buffer.get_minor_modes() {
+        let minor_mode: Gc<MinorMode> = minor_mode.try_to_rust_type()?;
+        names.push(Value::from(minor_mode.name()));
+    }
+    Ok(vec![lists::slice_to_list(&names)])
+}
+
 #[bridge(name = "current-major-mode", lib = "(koru-buffer)")]
 pub async fn get_current_major_mode() -> Result<Vec<Value>, Exception> {
     let Some(buffer) = SessionState::get_current_buffer().await else {
@@ -1048,6 +1395,14 @@ pub async fn flush_keybuffer() -> Result<Vec<Value>, Exception> {
 #[bridge(name = "buffer-change-focus", lib = "(koru-buffer)")]
 pub async fn change_current_buffer(name: &Value) -> Result<Vec<Value>, Exception> {
     let name: String = name.clone().try_into()?;
+    let exists = {
+        let state = SessionState::get_state();
+        let guard = state.read().await;
+        guard.get_buffers().await.contains_key(&name)
+    };
+    if !exists {
+        return Err(Exception::error(format!("Buffer '{name}' not found")));
+    }
     SessionState::set_current_buffer(name).await;
     Ok(Vec::new())
 }
@@ -1084,9 +1439,17 @@ pub async fn command_bar_delete_forward() -> Result<Vec<Value>, Exception> {
 pub async fn command_bar_take() -> Result<Vec<Value>, Exception> {
     let command_buffer = SessionState::get_command_bar().await;
     let string = command_buffer.write().await.take();
+    history::save_history().await;
     Ok(vec![Value::from(string)])
 }
 
+#[bridge(name = "command-bar-clear", lib = "(koru-session)")]
+pub async fn command_bar_clear() -> Result<Vec<Value>, Exception> {
+    let command_buffer = SessionState::get_command_bar().await;
+    command_buffer.write().await.clear();
+    Ok(Vec::new())
+}
+
 #[bridge(name = "command-bar-get", lib = "(koru-session)")]
 pub async fn command_bar_get() -> Result<Vec<Value>, Exception> {
     let command_buffer = SessionState::get_command_bar().await;
@@ -1139,7 +1502,20 @@ pub async fn command_bar_insert_key(key_seq: &Value) -> Result<Vec<Value>, Excep
 }
 
 #[bridge(name = "command-bar-show", lib = "(koru-session)")]
-pub async fn command_bar_show() -> Result<Vec<Value>, Exception> {
+pub async fn command_bar_show(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let history_name = match args.first() {
+        Some(name) => {
+            let name: Symbol = name.clone().try_into()?;
+            Some(name.to_str().to_string())
+        }
+        None => None,
+    };
+    {
+        let command_buffer = SessionState::get_command_bar().await;
+        let mut guard = command_buffer.write().await;
+        guard.history_name = history_name;
+        guard.navigation = None;
+    }
     SessionState::send_message(MessageKind::BackEnd(BackendMessage::ShowCommandBar)).await?;
     Ok(Vec::new())
 }
@@ -1155,7 +1531,7 @@ pub async fn command_bar_update(args: &[Value]) -> Result<Vec<Value>, Exception>
     let command_buffer = SessionState::get_command_bar().await;
     let mut string = command_buffer.read().await.style();
     let Some((prefix, rest)) = args.split_first() else {
-        SessionState::send_message(MessageKind::BackEnd(BackendMessage::UpdateCommandBar(string))).await?;
+        command_bar::send_update(string).await?;
         return Ok(Vec::new());
     };
 
@@ -1175,7 +1551,7 @@ pub async fn command_bar_update(args: &[Value]) -> Result<Vec<Value>, Exception>
     };
     string.prepend_segment(0, prefix);
     let Some((suffix, _)) = rest.split_first() else {
-        SessionState::send_message(MessageKind::BackEnd(BackendMessage::UpdateCommandBar(string))).await?;
+        command_bar::send_update(string).await?;
         return Ok(Vec::new());
     };
     let suffix = if let Ok(suffix) = suffix.clone().try_into() {
@@ -1194,11 +1570,18 @@ pub async fn command_bar_update(args: &[Value]) -> Result<Vec<Value>, Exception>
     };
     string.append_segment(0, suffix);
 
-    SessionState::send_message(MessageKind::BackEnd(BackendMessage::UpdateCommandBar(string))).await?;
+    command_bar::send_update(string).await?;
     
     Ok(Vec::new())
 }
 
+#[bridge(name = "message-bar-set", lib = "(koru-session)")]
+pub async fn message_bar_set(message: &Value) -> Result<Vec<Value>, Exception> {
+    let message: String = message.clone().try_into()?;
+    SessionState::send_message(MessageKind::BackEnd(BackendMessage::UpdateMessageBar(message))).await?;
+    Ok(Vec::new())
+}
+
 #[bridge(name = "command-execute", lib = "(koru-session)")]
 pub async fn command_bar_execute(string: &Value) -> Result<Vec<Value>, Exception> {
     let string: String = string.clone().try_into()?;
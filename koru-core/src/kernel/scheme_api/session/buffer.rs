@@ -5,13 +5,17 @@ use scheme_rs::value::{Value};
 use crate::kernel::buffer::{BufferHandle, Cursor};
 use crate::kernel::scheme_api::major_mode::MajorMode;
 use crate::kernel::scheme_api::minor_mode::{MinorModeManager};
-use crate::styled_text::StyledFile;
+use crate::styled_text::{StyledFile, DEFAULT_TAB_WIDTH};
 
 #[derive(Clone)]
 pub struct Buffer {
     major_mode: Value,
     handle: BufferHandle,
     styled_text: StyledFile,
+    /// The display columns where the rows of each wrapped line start, None if the buffer isn't wrapped
+    row_starts: Option<Vec<Vec<usize>>>,
+    /// How many columns apart the tab stops of the buffer were when it was last rendered
+    tab_width: usize,
     minor_modes: MinorModeManager,
 }
 
@@ -21,6 +25,8 @@ impl Buffer {
             major_mode: Value::undefined(),
             handle,
             styled_text: StyledFile::default(),
+            row_starts: None,
+            tab_width: DEFAULT_TAB_WIDTH,
             minor_modes: MinorModeManager::new(),
         }
     }
@@ -35,7 +41,7 @@ impl Buffer {
         Ok(())
     }
 
-    pub async fn add_minor_mode(&mut self, minor_mode: Value) -> Result<(), Exception> {
+    pub async fn add_minor_mode(&mut self, minor_mode: Value) -> Result<bool, Exception> {
         self.minor_modes.add_minor_mode(minor_mode).await
     }
 
@@ -55,14 +61,23 @@ impl Buffer {
         self.handle.clone()
     }
 
-    pub async fn render_styled_text(&mut self) {
-        let text = self.handle.draw().await;
+    /// Draws the text of the buffer around the main cursor.
+    pub async fn render_styled_text(&mut self, cursors: &[Cursor]) {
+        let focus = cursors.iter()
+            .find(|cursor| cursor.is_main())
+            .map(|cursor| (cursor.line(), cursor.column()));
+        let text = self.handle.draw(focus).await;
         self.styled_text = text;
+        self.row_starts = self.handle.row_starts().await;
+        self.tab_width = self.handle.tab_width().await;
     }
 
     pub fn get_styled_text(&self, cursors: &[Cursor]) -> StyledFile {
-        let file = self.styled_text.clone();
-        file.place_cursors(cursors)
+        let file = self.styled_text.clone().place_cursors(cursors, self.tab_width);
+        match &self.row_starts {
+            Some(row_starts) => file.wrap(row_starts),
+            None => file,
+        }
     }
     pub fn get_major_mode(&self) -> Value {
         self.major_mode.clone()
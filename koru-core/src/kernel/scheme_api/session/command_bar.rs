@@ -0,0 +1,402 @@
+use std::path::PathBuf;
+use scheme_rs::exceptions::Exception;
+use scheme_rs::registry::bridge;
+use scheme_rs::value::Value;
+use crate::kernel::broker::{BackendMessage, MessageKind};
+use crate::kernel::input::KeyPress;
+use crate::kernel::scheme_api::command::{ArgumentDef, CommandTree};
+use crate::kernel::scheme_api::session::{CommandBar, SessionState};
+use crate::styled_text::{display_width, ColorType, StyledFile, StyledText, TextAttribute, TextChunk};
+
+/// Shown before each candidate in the command bar.
+const SEPARATOR: &str = "  ";
+
+/// Text that the word before the command bar's cursor can be completed to.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+struct BarCandidate {
+    text: String,
+    /// False for candidates that can be completed further, like directories.
+    finished: bool,
+}
+
+impl BarCandidate {
+    fn new(text: String, finished: bool) -> Self {
+        Self { text, finished }
+    }
+}
+
+/// The candidates that completing again cycles through.
+pub struct CommandBarCompletion {
+    /// The char index of the start of the word being completed.
+    start: usize,
+    /// Whether the word was typed in quotes.
+    quoted: bool,
+    candidates: Vec<BarCandidate>,
+    selected: Option<usize>,
+}
+
+impl CommandBarCompletion {
+    /// Lists the candidates in a number of columns, keeping the selected candidate visible.
+    fn listing(&self, columns: usize) -> Vec<StyledText> {
+        let widths = self.candidates.iter()
+            .map(|candidate| display_width(&candidate.text) + SEPARATOR.len())
+            .collect::<Vec<_>>();
+        let width = |first: usize, last: usize| widths[first..=last].iter().sum::<usize>();
+
+        let selected = self.selected.unwrap_or(0);
+        let mut first = 0;
+        while first < selected && width(first, selected) > columns {
+            first += 1;
+        }
+        let mut last = selected;
+        while last + 1 < self.candidates.len() && width(first, last + 1) <= columns {
+            last += 1;
+        }
+        if width(first, last) > columns {
+            return Vec::new();
+        }
+
+        let mut segments = Vec::new();
+        for (index, candidate) in self.candidates.iter().enumerate().take(last + 1).skip(first) {
+            segments.push(segment(String::from(SEPARATOR), ColorType::Text, ColorType::Base));
+            if Some(index) == self.selected {
+                segments.push(segment(candidate.text.clone(), ColorType::Text, ColorType::Selection));
+            } else {
+                segments.push(segment(candidate.text.clone(), ColorType::Subtext0, ColorType::Base));
+            }
+        }
+        segments
+    }
+}
+
+fn segment(text: String, fg_color: ColorType, bg_color: ColorType) -> StyledText {
+    StyledText::Style {
+        text: TextChunk::from(text),
+        fg_color,
+        bg_color,
+        attribute: TextAttribute::empty(),
+    }
+}
+
+/// The word before the cursor, with its quotes and escapes removed.
+struct Word {
+    start: usize,
+    text: String,
+    quoted: bool,
+}
+
+impl Word {
+    fn new(start: usize) -> Self {
+        Self {
+            start,
+            text: String::new(),
+            quoted: false,
+        }
+    }
+}
+
+/// Splits the text before the cursor into words the same way that commands are read,
+/// giving the words before the word that the cursor is in and that word.
+fn split_words(text: &[char]) -> (Vec<String>, Word) {
+    let mut words = Vec::new();
+    let mut word: Option<Word> = None;
+    let mut quote = None;
+    let mut escaped = false;
+    for (index, ch) in text.iter().copied().enumerate() {
+        if escaped {
+            escaped = false;
+            let ch = match ch {
+                'n' => '\n',
+                't' => '\t',
+                ch => ch,
+            };
+            word.get_or_insert_with(|| Word::new(index)).text.push(ch);
+            continue;
+        }
+        match ch {
+            '\\' => {
+                escaped = true;
+                word.get_or_insert_with(|| Word::new(index));
+            }
+            '"' | '\'' if quote == Some(ch) => {
+                quote = None;
+                if let Some(word) = word.take() {
+                    words.push(word.text);
+                }
+            }
+            '"' | '\'' if quote.is_none() => {
+                quote = Some(ch);
+                word.get_or_insert_with(|| Word::new(index)).quoted = true;
+            }
+            ch if ch.is_whitespace() && quote.is_none() => {
+                if let Some(word) = word.take() {
+                    words.push(word.text);
+                }
+            }
+            ch => word.get_or_insert_with(|| Word::new(index)).text.push(ch),
+        }
+    }
+    (words, word.unwrap_or_else(|| Word::new(text.len())))
+}
+
+/// Writes a candidate so that it is read back as the same word.
+///
+/// Candidates with spaces, quotes, or backslashes in them are put in double quotes.
+/// The quotes are left open for candidates that aren't finished so that completing can continue.
+fn render(text: &str, quoted: bool, finished: bool) -> String {
+    let quoted = quoted || text.chars().any(|ch| ch.is_whitespace() || matches!(ch, '"' | '\'' | '\\'));
+    if !quoted {
+        return text.to_string();
+    }
+    let mut rendered = String::from('"');
+    for ch in text.chars() {
+        match ch {
+            '"' | '\'' | '\\' => {
+                rendered.push('\\');
+                rendered.push(ch);
+            }
+            '\n' => rendered.push_str("\\n"),
+            '\t' => rendered.push_str("\\t"),
+            ch => rendered.push(ch),
+        }
+    }
+    if finished {
+        rendered.push('"');
+    }
+    rendered
+}
+
+fn common_prefix(candidates: &[BarCandidate]) -> String {
+    let Some((first, rest)) = candidates.split_first() else {
+        return String::new();
+    };
+    let mut prefix = first.text.chars().collect::<Vec<_>>();
+    for candidate in rest {
+        let length = prefix.iter()
+            .zip(candidate.text.chars())
+            .take_while(|(a, b)| **a == *b)
+            .count();
+        prefix.truncate(length);
+    }
+    prefix.into_iter().collect()
+}
+
+/// Lists the entries of the directory in the word, relative to the working directory.
+async fn paths(word: &str) -> Vec<BarCandidate> {
+    let (directory, name) = match word.rfind('/') {
+        Some(slash) => word.split_at(slash + 1),
+        None => ("", word),
+    };
+    let path = if let Some(rest) = directory.strip_prefix("~/") {
+        let Some(home) = std::env::var_os("HOME") else {
+            return Vec::new();
+        };
+        PathBuf::from(home).join(rest)
+    } else if directory.is_empty() {
+        PathBuf::from(".")
+    } else {
+        PathBuf::from(directory)
+    };
+
+    let Ok(mut entries) = tokio::fs::read_dir(&path).await else {
+        return Vec::new();
+    };
+    let show_hidden = name.starts_with('.');
+    let mut candidates = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if file_name.starts_with('.') && !show_hidden || !file_name.starts_with(name) {
+            continue;
+        }
+        let is_directory = entry.file_type().await.is_ok_and(|file_type| file_type.is_dir());
+        if is_directory {
+            candidates.push(BarCandidate::new(format!("{directory}{file_name}/"), false));
+        } else {
+            candidates.push(BarCandidate::new(format!("{directory}{file_name}"), true));
+        }
+    }
+    candidates
+}
+
+async fn buffer_names(word: &str) -> Vec<BarCandidate> {
+    let state = SessionState::get_state();
+    let guard = state.read().await;
+    let buffers = guard.get_buffers().await;
+    buffers.keys()
+        .filter(|name| name.starts_with(word))
+        .map(|name| BarCandidate::new(name.clone(), true))
+        .collect()
+}
+
+/// Completes the last key of a key string, keeping its modifiers.
+///
+/// The modifiers are offered too when no key has been started.
+fn key_names(word: &str) -> Vec<BarCandidate> {
+    let split = word.rfind('-').map_or(0, |dash| dash + 1);
+    let (modifiers, name) = word.split_at(split);
+    let mut candidates = KeyPress::key_names()
+        .filter(|key| key.starts_with(name))
+        .map(|key| BarCandidate::new(format!("{modifiers}{key}"), true))
+        .collect::<Vec<_>>();
+    if name.is_empty() {
+        for modifier in ["A-", "C-", "M-", "S-"] {
+            if !modifiers.split_inclusive('-').any(|existing| existing == modifier) {
+                candidates.push(BarCandidate::new(format!("{modifiers}{modifier}"), false));
+            }
+        }
+    }
+    candidates
+}
+
+/// Gets what the word can be completed to from the words before it.
+///
+/// The first word is completed to a command name,
+/// and the words after it to what the command's argument at that position takes.
+async fn candidates(words: &[String], word: &str) -> Vec<BarCandidate> {
+    let Some((name, arguments)) = words.split_first() else {
+        return CommandTree::names(word).await
+            .into_iter()
+            .map(|name| BarCandidate::new(name, true))
+            .collect();
+    };
+    let Some(command) = CommandTree::lookup(name).await else {
+        return Vec::new();
+    };
+    let definitions = command.arguments();
+    let definition = definitions.get(arguments.len())
+        .or_else(|| definitions.last().filter(|definition| matches!(definition, ArgumentDef::Variable(_))));
+    let definition = match definition {
+        Some(ArgumentDef::Variable(definition)) => definition.as_ref(),
+        Some(definition) => definition,
+        None => return Vec::new(),
+    };
+
+    let mut candidates = match definition {
+        ArgumentDef::Path => paths(word).await,
+        ArgumentDef::Buffer => buffer_names(word).await,
+        ArgumentDef::Boolean => ["true", "false"].into_iter()
+            .filter(|boolean| boolean.starts_with(word))
+            .map(|boolean| BarCandidate::new(boolean.to_string(), true))
+            .collect(),
+        ArgumentDef::KeyPress => key_names(word),
+        ArgumentDef::KeySequence => {
+            let split = word.rfind(' ').map_or(0, |space| space + 1);
+            let (keys, last) = word.split_at(split);
+            key_names(last).into_iter()
+                .map(|candidate| BarCandidate::new(format!("{keys}{}", candidate.text), candidate.finished))
+                .collect()
+        }
+        ArgumentDef::Text | ArgumentDef::Number | ArgumentDef::Variable(_) => Vec::new(),
+    };
+    candidates.sort();
+    candidates.dedup();
+    candidates
+}
+
+impl CommandBar {
+    /// Replaces the text from a char index up to the cursor, leaving the cursor after the new text.
+    fn replace_before_cursor(&mut self, start: usize, text: &str) {
+        let before = self.buffer.chars().take(start).collect::<String>();
+        let after = self.buffer.chars().skip(self.cursor).collect::<String>();
+        self.buffer = format!("{before}{text}{after}");
+        self.cursor = start + text.chars().count();
+        self.navigation = None;
+    }
+}
+
+/// Sends the command bar to the frontends with the candidates that are being completed,
+/// or what is being searched for in the history, after it.
+pub(super) async fn send_update(mut command_bar: StyledFile) -> Result<(), Exception> {
+    let (columns, _) = SessionState::get_viewport().await;
+    let used = command_bar.lines()
+        .first()
+        .map_or(0, |line| line.iter().map(|segment| segment.text().width()).sum());
+    let (listing, search) = {
+        let bar = SessionState::get_command_bar().await;
+        let guard = bar.read().await;
+        let listing = guard.completion.as_ref()
+            .map(|completion| completion.listing(columns.saturating_sub(used)))
+            .unwrap_or_default();
+        (listing, guard.search_query().map(str::to_string))
+    };
+    for segment in listing {
+        command_bar.append_segment(0, segment);
+    }
+    if let Some(query) = search {
+        command_bar.append_segment(0, segment(format!("  reverse search: {query}"), ColorType::Subtext0, ColorType::Base));
+    }
+    SessionState::send_message(MessageKind::BackEnd(BackendMessage::UpdateCommandBar(command_bar))).await
+}
+
+/// Completes the word before the command bar's cursor.
+///
+/// A single candidate is inserted and finished with a space.
+/// When there are more, the text they all start with is inserted and they are listed after the command bar,
+/// then completing again cycles through them, forwards or backwards.
+pub async fn complete(forward: bool) {
+    let command_bar = SessionState::get_command_bar().await;
+
+    {
+        let mut guard = command_bar.write().await;
+        let bar = &mut *guard;
+        if let Some(completion) = bar.completion.as_mut() {
+            let count = completion.candidates.len();
+            let selected = match (completion.selected, forward) {
+                (None, true) => 0,
+                (None, false) => count - 1,
+                (Some(selected), true) => (selected + 1) % count,
+                (Some(selected), false) => (selected + count - 1) % count,
+            };
+            completion.selected = Some(selected);
+            let candidate = &completion.candidates[selected];
+            let text = render(&candidate.text, completion.quoted, candidate.finished);
+            let start = completion.start;
+            bar.replace_before_cursor(start, &text);
+            return;
+        }
+    }
+
+    let before_cursor = {
+        let guard = command_bar.read().await;
+        guard.buffer.chars().take(guard.cursor).collect::<Vec<_>>()
+    };
+    let (words, word) = split_words(&before_cursor);
+    let candidates = candidates(&words, &word.text).await;
+
+    let mut guard = command_bar.write().await;
+    match candidates.as_slice() {
+        [] => {}
+        [candidate] => {
+            let mut text = render(&candidate.text, word.quoted, candidate.finished);
+            let next = guard.buffer.chars().nth(guard.cursor);
+            if candidate.finished && !next.is_some_and(char::is_whitespace) {
+                text.push(' ');
+            }
+            guard.replace_before_cursor(word.start, &text);
+        }
+        _ => {
+            let prefix = common_prefix(&candidates);
+            if prefix.chars().count() > word.text.chars().count() {
+                guard.replace_before_cursor(word.start, &render(&prefix, word.quoted, false));
+            }
+            guard.completion = Some(CommandBarCompletion {
+                start: word.start,
+                quoted: word.quoted,
+                candidates,
+                selected: None,
+            });
+        }
+    }
+}
+
+#[bridge(name = "command-bar-complete", lib = "(koru-session)")]
+pub async fn command_bar_complete() -> Result<Vec<Value>, Exception> {
+    complete(true).await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "command-bar-complete-previous", lib = "(koru-session)")]
+pub async fn command_bar_complete_previous() -> Result<Vec<Value>, Exception> {
+    complete(false).await;
+    Ok(Vec::new())
+}
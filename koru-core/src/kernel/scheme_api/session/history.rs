@@ -0,0 +1,293 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use log::error;
+use scheme_rs::exceptions::Exception;
+use scheme_rs::lists;
+use scheme_rs::registry::bridge;
+use scheme_rs::symbols::Symbol;
+use scheme_rs::value::Value;
+use crate::kernel::scheme_api::session::{CommandBar, SessionState};
+
+/// How many entries each history holds before the oldest gets dropped.
+const HISTORY_CAPACITY: usize = 100;
+/// The file in koru's state directory that the histories are saved in.
+const HISTORY_FILE: &str = "history";
+/// The history that executed commands are added to.
+pub const COMMAND_HISTORY: &str = "command";
+
+/// What has been entered into each kind of prompt of the command bar, like commands, searches, or file paths.
+///
+/// Each history is oldest first and holds an entry only once.
+#[derive(Default)]
+pub struct CommandHistory {
+    histories: HashMap<String, VecDeque<String>>,
+    /// Whether an entry was added since the histories were last saved.
+    unsaved: bool,
+}
+
+/// Gets the path of the history file, which is kept in `$XDG_STATE_HOME/koru`, or `~/.local/state/koru` if that isn't set.
+///
+/// Returns None if neither of them can be found, in which case the histories aren't saved.
+fn history_path() -> Option<PathBuf> {
+    let state_home = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("state")))?;
+    Some(state_home.join("koru").join(HISTORY_FILE))
+}
+
+/// Escapes an entry so that it fits on one line of the history file.
+fn escape(entry: &str) -> String {
+    let mut escaped = String::with_capacity(entry.len());
+    for ch in entry.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+fn unescape(entry: &str) -> String {
+    let mut unescaped = String::with_capacity(entry.len());
+    let mut chars = entry.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            unescaped.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some(ch) => unescaped.push(ch),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+impl CommandHistory {
+    /// Reads the histories from the history file.
+    ///
+    /// The histories start out empty if the file doesn't exist or can't be read.
+    pub fn load() -> Self {
+        let mut history = Self::default();
+        let Some(path) = history_path() else {
+            return history;
+        };
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    error!("Failed to read the command history: {}", err);
+                }
+                return history;
+            }
+        };
+        for line in contents.lines() {
+            let Some((name, entry)) = line.split_once('\t') else {
+                continue;
+            };
+            history.record(name, &unescape(entry));
+        }
+        history.unsaved = false;
+        history
+    }
+
+    /// Gets the entries of a history, oldest first.
+    pub fn entries(&self, name: &str) -> std::collections::vec_deque::Iter<'_, String> {
+        self.histories.get(name)
+            .map(VecDeque::iter)
+            .unwrap_or_default()
+    }
+
+    /// Adds an entry as the newest of a history, moving it there if it is already in the history.
+    ///
+    /// Blank entries aren't added.
+    pub fn record(&mut self, name: &str, entry: &str) {
+        if entry.trim().is_empty() {
+            return;
+        }
+        let history = self.histories.entry(name.to_string()).or_default();
+        history.retain(|existing| existing != entry);
+        history.push_back(entry.to_string());
+        while history.len() > HISTORY_CAPACITY {
+            history.pop_front();
+        }
+        self.unsaved = true;
+    }
+
+    /// Gets what to write to the history file if an entry was added since it was last written.
+    fn take_unsaved(&mut self) -> Option<String> {
+        if !self.unsaved {
+            return None;
+        }
+        self.unsaved = false;
+        let mut names = self.histories.keys().collect::<Vec<_>>();
+        names.sort();
+        let mut contents = String::new();
+        for name in names {
+            for entry in &self.histories[name] {
+                contents.push_str(name);
+                contents.push('\t');
+                contents.push_str(&escape(entry));
+                contents.push('\n');
+            }
+        }
+        Some(contents)
+    }
+}
+
+/// Where the command bar is while stepping through the entries of its history.
+pub struct HistoryNavigation {
+    /// What was typed before the history was stepped into, which the entries are matched against.
+    draft: String,
+    /// The index of the entry that is shown.
+    index: usize,
+    /// Whether entries containing the draft are matched instead of entries starting with it.
+    search: bool,
+}
+
+impl HistoryNavigation {
+    fn matches(&self, entry: &str) -> bool {
+        if self.search {
+            entry.contains(&self.draft)
+        } else {
+            entry.starts_with(&self.draft)
+        }
+    }
+}
+
+impl CommandBar {
+    fn show_entry(&mut self, entry: String) {
+        self.completion = None;
+        self.cursor = entry.chars().count();
+        self.buffer = entry;
+    }
+
+    /// Shows the next older entry of the prompt's history that matches what was typed.
+    ///
+    /// Searching matches entries that contain what was typed, otherwise they have to start with it.
+    fn history_older(&mut self, search: bool) {
+        let Some(name) = self.history_name.clone() else {
+            return;
+        };
+        let navigation = match self.navigation.take() {
+            Some(navigation) if navigation.search == search => navigation,
+            _ => HistoryNavigation {
+                draft: self.buffer.clone(),
+                index: self.history.entries(&name).count(),
+                search,
+            },
+        };
+        let older = self.history.entries(&name)
+            .take(navigation.index)
+            .enumerate()
+            .rev()
+            .find(|(_, entry)| **entry != self.buffer && navigation.matches(entry))
+            .map(|(index, entry)| (index, entry.clone()));
+        match older {
+            Some((index, entry)) => {
+                self.show_entry(entry);
+                self.navigation = Some(HistoryNavigation { index, ..navigation });
+            }
+            None => self.navigation = Some(navigation),
+        }
+    }
+
+    /// Shows the next newer entry that matches what was typed,
+    /// or what was typed once there are no newer entries.
+    fn history_newer(&mut self) {
+        let (Some(name), Some(navigation)) = (self.history_name.clone(), self.navigation.take()) else {
+            return;
+        };
+        let newer = self.history.entries(&name)
+            .enumerate()
+            .skip(navigation.index + 1)
+            .find(|(_, entry)| **entry != self.buffer && navigation.matches(entry))
+            .map(|(index, entry)| (index, entry.clone()));
+        match newer {
+            Some((index, entry)) => {
+                self.show_entry(entry);
+                self.navigation = Some(HistoryNavigation { index, ..navigation });
+            }
+            None => self.show_entry(navigation.draft),
+        }
+    }
+
+    /// Gets what is being searched for in the history.
+    pub(super) fn search_query(&self) -> Option<&str> {
+        self.navigation.as_ref()
+            .filter(|navigation| navigation.search)
+            .map(|navigation| navigation.draft.as_str())
+    }
+}
+
+/// Writes the histories to the history file if an entry was added to them.
+pub(super) async fn save_history() {
+    let contents = {
+        let command_bar = SessionState::get_command_bar().await;
+        let mut guard = command_bar.write().await;
+        guard.history.take_unsaved()
+    };
+    let (Some(contents), Some(path)) = (contents, history_path()) else {
+        return;
+    };
+    if let Some(directory) = path.parent()
+        && let Err(err) = tokio::fs::create_dir_all(directory).await {
+        error!("Failed to create the command history directory: {}", err);
+        return;
+    }
+    if let Err(err) = tokio::fs::write(path, contents).await {
+        error!("Failed to save the command history: {}", err);
+    }
+}
+
+#[bridge(name = "command-bar-history-previous", lib = "(koru-session)")]
+pub async fn command_bar_history_previous() -> Result<Vec<Value>, Exception> {
+    let command_bar = SessionState::get_command_bar().await;
+    command_bar.write().await.history_older(false);
+    Ok(Vec::new())
+}
+
+#[bridge(name = "command-bar-history-next", lib = "(koru-session)")]
+pub async fn command_bar_history_next() -> Result<Vec<Value>, Exception> {
+    let command_bar = SessionState::get_command_bar().await;
+    command_bar.write().await.history_newer();
+    Ok(Vec::new())
+}
+
+#[bridge(name = "command-bar-history-search", lib = "(koru-session)")]
+pub async fn command_bar_history_search() -> Result<Vec<Value>, Exception> {
+    let command_bar = SessionState::get_command_bar().await;
+    command_bar.write().await.history_older(true);
+    Ok(Vec::new())
+}
+
+#[bridge(name = "command-bar-history", lib = "(koru-session)")]
+pub async fn command_bar_history(name: &Value) -> Result<Vec<Value>, Exception> {
+    let name: Symbol = name.clone().try_into()?;
+    let command_bar = SessionState::get_command_bar().await;
+    let guard = command_bar.read().await;
+    let entries = guard.history.entries(&name.to_str())
+        .rev()
+        .map(|entry| Value::from(entry.clone()))
+        .collect::<Vec<_>>();
+    Ok(vec![lists::slice_to_list(&entries)])
+}
+
+#[bridge(name = "command-bar-history-add", lib = "(koru-session)")]
+pub async fn command_bar_history_add(name: &Value, entry: &Value) -> Result<Vec<Value>, Exception> {
+    let name: Symbol = name.clone().try_into()?;
+    let entry: String = entry.clone().try_into()?;
+    {
+        let command_bar = SessionState::get_command_bar().await;
+        command_bar.write().await.history.record(&name.to_str(), &entry);
+    }
+    save_history().await;
+    Ok(Vec::new())
+}
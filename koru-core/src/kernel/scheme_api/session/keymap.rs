@@ -1,26 +1,39 @@
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 use scheme_rs::exceptions::Exception;
 use scheme_rs::gc::{Gc, Trace};
+use scheme_rs::lists;
+use scheme_rs::num::SimpleNumber;
 use scheme_rs::records::{rtd, Record, RecordTypeDescriptor, SchemeCompatible};
 use scheme_rs::registry::bridge;
-use scheme_rs::value::Value;
+use scheme_rs::symbols::Symbol;
+use scheme_rs::value::{UnpackedValue, Value};
 use tokio::sync::RwLock;
+use crate::kernel::bindings::keys_from;
 use crate::kernel::input::KeyPress;
 use crate::kernel::scheme_api::command::Command;
-use crate::keymap::KeyMap;
+use crate::kernel::scheme_api::session::SessionState;
+use crate::keymap::{self, KeyMap};
 
 #[derive(Debug, Trace)]
 pub struct SchemeKeyMap {
     mapping: RwLock<HashMap<String, Gc<Command>>>,
+    /// Key groups bound to commands, in the order they were bound.
+    groups: RwLock<Vec<(Symbol, Gc<Command>)>>,
     default: Option<Gc<Command>>,
+    /// How long the start of a longer binding waits for the rest of it, or None to wait for the next key.
+    #[trace(skip)]
+    timeout: RwLock<Option<Duration>>,
 }
 
 impl SchemeKeyMap {
     pub fn new(default: Option<Gc<Command>>) -> Self {
         Self {
             mapping: RwLock::new(HashMap::new()),
-            default
+            groups: RwLock::new(Vec::new()),
+            default,
+            timeout: RwLock::new(None),
         }
     }
 
@@ -32,6 +45,22 @@ impl SchemeKeyMap {
         self.mapping.write().await.remove(key);
     }
 
+    pub async fn add_group_binding(&self, group: Symbol, command: Gc<Command>) {
+        let mut groups = self.groups.write().await;
+        match groups.iter_mut().find(|(name, _)| *name == group) {
+            Some((_, bound)) => *bound = command,
+            None => groups.push((group, command)),
+        }
+    }
+
+    pub async fn remove_group_binding(&self, group: Symbol) {
+        self.groups.write().await.retain(|(name, _)| *name != group);
+    }
+
+    pub async fn set_timeout(&self, timeout: Option<Duration>) {
+        *self.timeout.write().await = timeout;
+    }
+
     pub async fn make_keymap(&self) -> Result<KeyMap, String> {
         let mut guard = self.mapping.write().await;
         let mut key_map = KeyMap::new_sparse();
@@ -46,9 +75,13 @@ impl SchemeKeyMap {
             };
             key_map.add_binding(vec, value);
         }
+        for (group, command) in self.groups.write().await.drain(..) {
+            key_map.add_group_binding(group, command);
+        }
         if let Some(default) = &self.default {
             key_map.set_default(default.clone());
         }
+        key_map.set_timeout(*self.timeout.read().await);
         Ok(key_map)
     }
 }
@@ -84,8 +117,12 @@ pub async fn keymap_insert(args: &[Value]) -> Result<Vec<Value>, Exception> {
         return Err(Exception::wrong_num_of_args(3, args.len()));
     };
     let keymap: Gc<SchemeKeyMap> = keymap.clone().try_to_rust_type()?;
-    let key_sequence: String = key_sequence.clone().try_into()?;
     let command: Gc<Command> = command.clone().try_to_rust_type()?;
+    if let UnpackedValue::Symbol(group) = key_sequence.clone().unpack() {
+        keymap.add_group_binding(group, command).await;
+        return Ok(vec![]);
+    }
+    let key_sequence: String = key_sequence.clone().try_into()?;
 
     keymap.add_binding(&key_sequence, command).await;
     Ok(vec![])
@@ -100,8 +137,105 @@ pub async fn keymap_delete(args: &[Value]) -> Result<Vec<Value>, Exception> {
         return Err(Exception::wrong_num_of_args(2, args.len()));
     };
     let keymap: Gc<SchemeKeyMap> = keymap.clone().try_to_rust_type()?;
+    if let UnpackedValue::Symbol(group) = key_sequence.clone().unpack() {
+        keymap.remove_group_binding(group).await;
+        return Ok(vec![]);
+    }
     let key_sequence: String = key_sequence.clone().try_into()?;
 
     keymap.remove_binding(&key_sequence).await;
     Ok(vec![])
-}
\ No newline at end of file
+}
+
+#[bridge(name = "key-map-timeout-set!", lib = "(koru-key)")]
+pub async fn keymap_timeout_set(keymap: &Value, milliseconds: &Value) -> Result<Vec<Value>, Exception> {
+    let keymap: Gc<SchemeKeyMap> = keymap.clone().try_to_rust_type()?;
+    let timeout = if milliseconds.is_true() {
+        let milliseconds: SimpleNumber = milliseconds.clone().try_into()?;
+        let milliseconds: usize = milliseconds.try_into()?;
+        Some(Duration::from_millis(milliseconds as u64))
+    } else {
+        None
+    };
+    keymap.set_timeout(timeout).await;
+    Ok(Vec::new())
+}
+
+fn sequence_to_string(keys: &[KeyPress]) -> String {
+    keys.iter().map(ToString::to_string).collect::<Vec<_>>().join(" ")
+}
+
+#[bridge(name = "key-group-set!", lib = "(koru-key)")]
+pub async fn key_group_set(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let Some((name, sequences)) = args.split_first() else {
+        return Err(Exception::wrong_num_of_args(1, args.len()));
+    };
+    let name: Symbol = name.clone().try_into()?;
+    let sequences = sequences.iter()
+        .map(keys_from)
+        .collect::<Result<Vec<_>, _>>()?;
+    keymap::set_key_group(name, sequences);
+    SessionState::key_groups_changed().await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "key-group-add!", lib = "(koru-key)")]
+pub async fn key_group_add(name: &Value, keys: &Value) -> Result<Vec<Value>, Exception> {
+    let name: Symbol = name.clone().try_into()?;
+    keymap::add_to_key_group(name, keys_from(keys)?);
+    SessionState::key_groups_changed().await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "key-group-remove!", lib = "(koru-key)")]
+pub async fn key_group_remove(name: &Value, keys: &Value) -> Result<Vec<Value>, Exception> {
+    let name: Symbol = name.clone().try_into()?;
+    keymap::remove_from_key_group(name, &keys_from(keys)?);
+    SessionState::key_groups_changed().await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "key-group-delete!", lib = "(koru-key)")]
+pub async fn key_group_delete(name: &Value) -> Result<Vec<Value>, Exception> {
+    let name: Symbol = name.clone().try_into()?;
+    keymap::remove_key_group(name);
+    SessionState::key_groups_changed().await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "key-group", lib = "(koru-key)")]
+pub fn key_group(name: &Value) -> Result<Vec<Value>, Exception> {
+    let name: Symbol = name.clone().try_into()?;
+    let Some(sequences) = keymap::key_group(name) else {
+        return Ok(vec![Value::from(false)]);
+    };
+    let sequences = sequences.iter()
+        .map(|keys| Value::from(sequence_to_string(keys)))
+        .collect::<Vec<_>>();
+    Ok(vec![lists::slice_to_list(&sequences)])
+}
+
+#[bridge(name = "key-groups", lib = "(koru-key)")]
+pub fn key_groups() -> Result<Vec<Value>, Exception> {
+    let mut names = keymap::key_group_names();
+    names.sort_by_key(ToString::to_string);
+    let names = names.into_iter().map(Value::from).collect::<Vec<_>>();
+    Ok(vec![lists::slice_to_list(&names)])
+}
+
+#[bridge(name = "key-groups-of", lib = "(koru-key)")]
+pub fn key_groups_of(keys: &Value) -> Result<Vec<Value>, Exception> {
+    let mut names = keymap::key_groups_of(&keys_from(keys)?);
+    names.sort_by_key(ToString::to_string);
+    let names = names.into_iter().map(Value::from).collect::<Vec<_>>();
+    Ok(vec![lists::slice_to_list(&names)])
+}
+
+#[bridge(name = "key-in-group?", lib = "(koru-key)")]
+pub fn key_in_group(keys: &Value, name: &Value) -> Result<Vec<Value>, Exception> {
+    let keys = keys_from(keys)?;
+    let name: Symbol = name.clone().try_into()?;
+    let found = keymap::key_group(name)
+        .is_some_and(|sequences| sequences.contains(&keys));
+    Ok(vec![Value::from(found)])
+}
@@ -0,0 +1,323 @@
+use std::collections::{HashMap, VecDeque};
+use scheme_rs::exceptions::Exception;
+use scheme_rs::lists;
+use scheme_rs::lists::List;
+use scheme_rs::proc::Procedure;
+use scheme_rs::registry::bridge;
+use scheme_rs::value::{UnpackedValue, Value};
+use crate::kernel::broker::{BackendMessage, MessageKind};
+use crate::kernel::scheme_api::session::SessionState;
+
+/// The name of the register that always holds the most recent kill.
+pub const UNNAMED_REGISTER: &str = "\"";
+/// The name of the register that mirrors the system clipboard.
+pub const CLIPBOARD_REGISTER: &str = "+";
+/// How many kills the kill ring holds before the oldest gets dropped.
+const KILL_RING_CAPACITY: usize = 60;
+
+/// A single yank or kill.
+///
+/// There is one slot per cursor that was active when the entry was begun.
+/// Slots get filled in one at a time as each cursor yanks its region.
+#[derive(Debug, Clone, Default)]
+pub struct RegisterEntry {
+    values: Vec<Option<String>>,
+}
+
+impl RegisterEntry {
+    pub fn new(cursor_count: usize) -> Self {
+        Self {
+            values: vec![None; cursor_count.max(1)],
+        }
+    }
+
+    pub fn from_values(values: Vec<String>) -> Self {
+        Self {
+            values: values.into_iter().map(Some).collect(),
+        }
+    }
+
+    fn set(&mut self, cursor_index: usize, text: String) {
+        if cursor_index >= self.values.len() {
+            self.values.resize(cursor_index + 1, None);
+        }
+        self.values[cursor_index] = Some(text);
+    }
+
+    /// Gets the text to paste at a cursor.
+    ///
+    /// If there is an entry for each cursor then the cursor gets its own entry.
+    /// Otherwise, cursors past the end of the entry get the last value,
+    /// meaning a single value gets repeated for every cursor.
+    pub fn get(&self, cursor_index: usize) -> Option<String> {
+        let values = self.values();
+        values.get(cursor_index)
+            .or(values.last())
+            .cloned()
+    }
+
+    /// The value of every slot, in cursor order.
+    ///
+    /// Slots that haven't been filled in are empty strings, so every value stays at the index of its cursor.
+    pub fn values(&self) -> Vec<String> {
+        self.values.iter()
+            .map(|value| value.clone().unwrap_or_default())
+            .collect()
+    }
+}
+
+/// Named registers and the kill ring.
+pub struct Registers {
+    named: HashMap<String, RegisterEntry>,
+    kill_ring: VecDeque<RegisterEntry>,
+    /// The index of the kill ring entry that pasting uses.
+    kill_ring_index: usize,
+    /// Procedures waiting on the contents of the system clipboard.
+    clipboard_requests: Vec<Procedure>,
+}
+
+impl Registers {
+    pub fn new() -> Self {
+        Self {
+            named: HashMap::new(),
+            kill_ring: VecDeque::new(),
+            kill_ring_index: 0,
+            clipboard_requests: Vec::new(),
+        }
+    }
+
+    /// Starts a new kill ring entry with a slot for each cursor.
+    ///
+    /// Every copy or delete command begins its own entry before its cursors yank into it.
+    pub fn begin_entry(&mut self, cursor_count: usize) {
+        self.kill_ring.push_front(RegisterEntry::new(cursor_count));
+        self.kill_ring.truncate(KILL_RING_CAPACITY);
+        self.kill_ring_index = 0;
+    }
+
+    /// Stores the text yanked by a cursor in the newest kill ring entry.
+    ///
+    /// An entry is begun if the kill ring is empty.
+    /// If a register name is given then the register gets the same entry.
+    pub fn yank(&mut self, register: Option<&str>, cursor_index: usize, text: String) {
+        if self.kill_ring.is_empty() {
+            self.begin_entry(cursor_index + 1);
+        }
+        self.kill_ring_index = 0;
+        let entry = self.kill_ring.front_mut().expect("kill ring was just pushed to");
+        entry.set(cursor_index, text);
+        let entry = entry.clone();
+
+        if let Some(register) = register.filter(|register| *register != UNNAMED_REGISTER) {
+            self.named.insert(register.to_string(), entry);
+        }
+    }
+
+    /// Replaces the contents of a register.
+    ///
+    /// Setting the unnamed register pushes a new entry on the kill ring.
+    pub fn set(&mut self, register: &str, values: Vec<String>) {
+        let entry = RegisterEntry::from_values(values);
+        if register == UNNAMED_REGISTER {
+            self.kill_ring.push_front(entry);
+            self.kill_ring.truncate(KILL_RING_CAPACITY);
+            self.kill_ring_index = 0;
+        } else {
+            self.named.insert(register.to_string(), entry);
+        }
+    }
+
+    /// Gets a register, using the current kill ring entry when there is no register name.
+    pub fn get(&self, register: Option<&str>) -> Option<&RegisterEntry> {
+        match register {
+            Some(register) if register != UNNAMED_REGISTER => self.named.get(register),
+            _ => self.kill_ring.get(self.kill_ring_index),
+        }
+    }
+
+    pub fn clear(&mut self, register: &str) {
+        if register == UNNAMED_REGISTER {
+            self.kill_ring.clear();
+            self.kill_ring_index = 0;
+        } else {
+            self.named.remove(register);
+        }
+    }
+
+    /// Moves which kill ring entry gets pasted by the amount given, wrapping around at either end.
+    pub fn rotate_kill_ring(&mut self, amount: isize) {
+        if self.kill_ring.is_empty() {
+            return;
+        }
+        let len = self.kill_ring.len() as isize;
+        self.kill_ring_index = (self.kill_ring_index as isize + amount).rem_euclid(len) as usize;
+    }
+
+    pub fn kill_ring_len(&self) -> usize {
+        self.kill_ring.len()
+    }
+
+    pub fn add_clipboard_request(&mut self, callback: Procedure) {
+        self.clipboard_requests.push(callback);
+    }
+
+    /// Stores what a client read from the system clipboard in the clipboard register.
+    ///
+    /// Returns the procedures that were waiting on the clipboard.
+    pub fn receive_clipboard(&mut self, contents: Option<String>) -> Vec<Procedure> {
+        if let Some(contents) = contents {
+            self.named.insert(CLIPBOARD_REGISTER.to_string(), RegisterEntry::from_values(vec![contents]));
+        }
+        std::mem::take(&mut self.clipboard_requests)
+    }
+}
+
+impl Default for Registers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads a register name from either a string, symbol, or char.
+pub fn register_name(value: &Value) -> Result<String, Exception> {
+    match value.clone().unpack() {
+        UnpackedValue::String(string) => Ok(string.to_string()),
+        UnpackedValue::Symbol(symbol) => Ok(symbol.to_string()),
+        UnpackedValue::Character(c) => Ok(c.to_string()),
+        other => Err(Exception::type_error("String, Symbol, or Char", other.type_name())),
+    }
+}
+
+fn entry_to_list(entry: &RegisterEntry) -> Value {
+    let values = entry.values()
+        .into_iter()
+        .map(Value::from)
+        .collect::<Vec<_>>();
+    lists::slice_to_list(&values)
+}
+
+#[bridge(name = "register-get", lib = "(koru-register)")]
+pub async fn register_get(register: &Value) -> Result<Vec<Value>, Exception> {
+    let register = register_name(register)?;
+    let registers = SessionState::get_registers().await;
+    let guard = registers.read().await;
+    match guard.get(Some(&register)) {
+        Some(entry) => Ok(vec![entry_to_list(entry)]),
+        None => Ok(vec![Value::from(false)]),
+    }
+}
+
+#[bridge(name = "register-set!", lib = "(koru-register)")]
+pub async fn register_set(register: &Value, values: &Value) -> Result<Vec<Value>, Exception> {
+    let register = register_name(register)?;
+    let values = match values.clone().unpack() {
+        UnpackedValue::String(string) => vec![string.to_string()],
+        UnpackedValue::Pair(_) | UnpackedValue::Null => {
+            let list: List = values.try_into()?;
+            list.into_iter()
+                .map(String::try_from)
+                .collect::<Result<Vec<_>, _>>()?
+        }
+        other => return Err(Exception::type_error("String or List", other.type_name())),
+    };
+    if register == CLIPBOARD_REGISTER {
+        SessionState::set_clipboard(values.join("\n")).await?;
+    }
+    let registers = SessionState::get_registers().await;
+    registers.write().await.set(&register, values);
+    Ok(Vec::new())
+}
+
+#[bridge(name = "register-clear!", lib = "(koru-register)")]
+pub async fn register_clear(register: &Value) -> Result<Vec<Value>, Exception> {
+    let register = register_name(register)?;
+    let registers = SessionState::get_registers().await;
+    registers.write().await.clear(&register);
+    Ok(Vec::new())
+}
+
+#[bridge(name = "kill-ring-rotate", lib = "(koru-register)")]
+pub async fn kill_ring_rotate(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let amount = match args.first() {
+        Some(amount) => {
+            let amount: isize = amount.clone().try_into()?;
+            amount
+        }
+        None => 1,
+    };
+    let registers = SessionState::get_registers().await;
+    registers.write().await.rotate_kill_ring(amount);
+    Ok(Vec::new())
+}
+
+#[bridge(name = "kill-ring-begin-entry", lib = "(koru-register)")]
+pub async fn kill_ring_begin_entry(cursor_count: &Value) -> Result<Vec<Value>, Exception> {
+    let cursor_count: usize = cursor_count.clone().try_into()?;
+    let registers = SessionState::get_registers().await;
+    registers.write().await.begin_entry(cursor_count);
+    Ok(Vec::new())
+}
+
+#[bridge(name = "kill-ring-length", lib = "(koru-register)")]
+pub async fn kill_ring_length() -> Result<Vec<Value>, Exception> {
+    let registers = SessionState::get_registers().await;
+    let len = registers.read().await.kill_ring_len();
+    Ok(vec![Value::from(len)])
+}
+
+#[bridge(name = "clipboard-set!", lib = "(koru-register)")]
+pub async fn clipboard_set(text: &Value) -> Result<Vec<Value>, Exception> {
+    let text: String = text.clone().try_into()?;
+    SessionState::set_clipboard(text.clone()).await?;
+    let registers = SessionState::get_registers().await;
+    registers.write().await.set(CLIPBOARD_REGISTER, vec![text]);
+    Ok(Vec::new())
+}
+
+#[bridge(name = "clipboard-request", lib = "(koru-register)")]
+pub async fn clipboard_request(callback: &Value) -> Result<Vec<Value>, Exception> {
+    let callback: Procedure = callback.clone().try_into()?;
+    {
+        let registers = SessionState::get_registers().await;
+        registers.write().await.add_clipboard_request(callback);
+    }
+    SessionState::send_message(MessageKind::BackEnd(BackendMessage::RequestClipboard)).await?;
+    Ok(Vec::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yanks_fill_the_entry_that_was_begun() {
+        let mut registers = Registers::new();
+        registers.begin_entry(2);
+        registers.yank(None, 0, "one".to_string());
+        registers.yank(None, 1, "two".to_string());
+        assert_eq!(registers.kill_ring_len(), 1);
+        assert_eq!(registers.get(None).unwrap().values(), vec!["one", "two"]);
+    }
+
+    #[test]
+    fn each_command_begins_its_own_entry() {
+        let mut registers = Registers::new();
+        registers.begin_entry(2);
+        registers.yank(None, 1, "first".to_string());
+        registers.begin_entry(2);
+        registers.yank(None, 0, "second".to_string());
+        assert_eq!(registers.kill_ring_len(), 2);
+        assert_eq!(registers.get(None).unwrap().values(), vec!["second", ""]);
+        registers.rotate_kill_ring(1);
+        assert_eq!(registers.get(None).unwrap().values(), vec!["", "first"]);
+    }
+
+    #[test]
+    fn yank_into_an_empty_kill_ring_begins_an_entry() {
+        let mut registers = Registers::new();
+        registers.yank(Some("a"), 1, "text".to_string());
+        assert_eq!(registers.kill_ring_len(), 1);
+        assert_eq!(registers.get(Some("a")).unwrap().values(), vec!["", "text"]);
+        assert_eq!(registers.get(None).unwrap().get(3), Some("text".to_string()));
+    }
+}
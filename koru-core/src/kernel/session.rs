@@ -1,16 +1,19 @@
+use std::collections::HashMap;
 use std::error::Error;
-use std::ffi::OsStr;
-use std::path::PathBuf;
 use log::error;
 use scheme_rs::gc::Gc;
 use scheme_rs::runtime::Runtime;
-use scheme_rs::symbols::Symbol;
 use scheme_rs::value::Value;
+use crate::kernel::buffer::{gutter, Cursor, Diagnostic};
+use crate::kernel::completion;
+use crate::kernel::key_hints;
+use crate::kernel::mouse::{self, Clicks};
+use crate::kernel::layout::{self, Layout, PaneView};
 use crate::kernel::broker::{BackendMessage, BrokerClient, GeneralMessage, Message, MessageKind};
-use crate::kernel::buffer::TextBufferTable;
 use crate::kernel::scheme_api::major_mode::MajorMode;
 use crate::kernel::scheme_api::session::SessionState;
 use crate::kernel::scheme_api::theme;
+use crate::kernel::status_line::{self, StatusLinePane};
 use crate::KoruArgs;
 use crate::styled_text::StyledFile;
 
@@ -18,6 +21,14 @@ use crate::styled_text::StyledFile;
 pub struct Session {
     broker_client: BrokerClient,
     client_ids: Vec<usize>,
+    /// The client that last sent input, this is where clipboard requests go.
+    focused_client: Option<usize>,
+    /// The diagnostic shown in the message bar for the line under the main cursor.
+    diagnostic_message: Option<String>,
+    /// The tab bar that was last sent to the clients.
+    tab_bar: Option<StyledFile>,
+    /// The presses of the mouse, for counting double and triple clicks.
+    clicks: Clicks,
 }
 
 impl Session {
@@ -28,6 +39,10 @@ impl Session {
         Self {
             broker_client,
             client_ids: vec![],
+            focused_client: None,
+            diagnostic_message: None,
+            tab_bar: None,
+            clicks: Clicks::new(),
         }
     }
     
@@ -59,6 +74,9 @@ impl Session {
         self.broker_client.send_async(MessageKind::General(GeneralMessage::SetUiAttrs(values)), id).await?;*/
         
         self.client_ids.push(id);
+        self.focused_client = Some(id);
+        // The tabs live in the session, so a client that connects gets sent the tab bar with the next draw
+        self.tab_bar = None;
         
         for definition in theme::all_color_definitions().await {
             self.broker_client.send_async(MessageKind::General(GeneralMessage::SetColorDef(definition)), id).await?;
@@ -68,31 +86,11 @@ impl Session {
     }
 
     async fn open_files(&self, name: &str) -> Result<String, Box<dyn Error>> {
-        let handle = TextBufferTable::open(name.to_string()).await?;
-        let out = handle.get_name().await;
-        {
-            let state = SessionState::get_state();
-            let mut guard = state.write().await;
-            guard.add_buffer(&out, handle).await;
-        }
-        let path = PathBuf::from(&out);
-        let file_ext = path.extension().unwrap().to_string_lossy().to_string();
-        self.buffer_opened_hook(&out, &file_ext).await;
-        Ok(out)
+        Ok(SessionState::open_file(name).await.map_err(|err| err.to_string())?)
     }
     
     async fn create_buffer(&self, name: &str) -> Result<String, Box<dyn Error>> {
-        let out = name.to_string();
-        let handle = TextBufferTable::create(name.to_string(), "").await?;
-        {
-            let state = SessionState::get_state();
-            let mut guard = state.write().await;
-            guard.add_buffer(name, handle).await;
-        }
-        let path = PathBuf::from(&out);
-        let file_ext = path.extension().unwrap_or_else(|| OsStr::new("")).to_string_lossy().to_string();
-        self.buffer_opened_hook(name, &file_ext).await;
-        Ok(out)
+        Ok(SessionState::create_buffer(name, "").await.map_err(|err| err.to_string())?)
     }
     
     async fn notify_clients(&mut self, msg: MessageKind) {
@@ -106,40 +104,145 @@ impl Session {
             }
         }
         for client in dead_clients.into_iter().rev() {
-            self.client_ids.remove(client);
+            let client = self.client_ids.remove(client);
+            if self.focused_client == Some(client) {
+                self.focused_client = self.client_ids.first().copied();
+            }
         }
     }
 
-    async fn buffer_opened_hook(&self, file_name: &str, file_ext: &str) {
-        let args = &[Value::from(file_name.to_string()), Value::from(file_ext.to_string())];
-
-        let result = SessionState::emit_hook_blocking(Symbol::intern("buffer-open"), args).await;
-
-        match result {
+    async fn notify_focused_client(&mut self, msg: MessageKind) {
+        let Some(client) = self.focused_client else {
+            return;
+        };
+        match self.broker_client.send_async(msg, client).await {
             Ok(_) => {}
-            Err(err) => {
-                error!("{}", err);
+            Err(e) => {
+                error!("Failure sending to focused client: {}", e);
+            }
+        }
+    }
+
+    async fn receive_clipboard(&mut self, contents: Option<String>) {
+        let callbacks = {
+            let registers = SessionState::get_registers().await;
+            registers.write().await.receive_clipboard(contents.clone())
+        };
+        let arg = match contents {
+            Some(contents) => Value::from(contents),
+            None => Value::from(false),
+        };
+        for callback in callbacks {
+            match callback.call(std::slice::from_ref(&arg)).await {
+                Ok(_) => {}
+                Err(err) => {
+                    error!("{}", err);
+                }
             }
         }
     }
 
-    async fn send_draw(&mut self, buffer_name: &str) -> Result<(), Box<dyn Error>> {
+    /// Draws every pane of the layout.
+    async fn send_draw(&mut self) -> Result<(), Box<dyn Error>> {
+        let tab_bar = SessionState::get_tabs().await.read().await.bar().await;
+        if self.tab_bar.as_ref() != Some(&tab_bar) {
+            self.tab_bar = Some(tab_bar.clone());
+            self.notify_clients(MessageKind::General(GeneralMessage::UpdateTabBar(tab_bar))).await;
+        }
+
+        let layout = SessionState::get_layout().await.read().await.clone();
+        let (columns, lines) = SessionState::get_viewport().await;
+        let mut areas = layout.areas(columns, lines);
+        // The focused pane goes last so that a buffer shown in more than one pane is left at its size for moving the cursors
+        areas.sort_by_key(|(id, _)| *id == layout.focused());
 
+        let mut views = HashMap::new();
+        for (id, area) in areas {
+            let focused = id == layout.focused();
+            let mut view = match layout.pane(id).and_then(|pane| pane.buffer_name()) {
+                Some(buffer_name) => self.draw_pane(buffer_name, &layout, id, focused, area.columns).await?,
+                None => PaneView::empty(id),
+            };
+            view.area = area;
+            view.focused = focused;
+            views.insert(id, view);
+        }
+        let layout = layout.view(views);
+        self.notify_clients(MessageKind::General(GeneralMessage::Draw(layout))).await;
+        Ok(())
+    }
+
+    /// Draws a buffer in a pane.
+    ///
+    /// The focused pane is drawn by the buffer's major mode,
+    /// the other panes are drawn with the cursors that they had when they lost the focus.
+    ///
+    /// The text is drawn in what is left of the pane's columns next to the gutter.
+    async fn draw_pane(
+        &mut self,
+        buffer_name: &str,
+        layout: &Layout,
+        id: usize,
+        focused: bool,
+        columns: usize,
+    ) -> Result<PaneView, Box<dyn Error>> {
         let mut buffer = {
             let state = SessionState::get_state();
             let guard = state.read().await;
-            guard.get_buffers().await.get(buffer_name).unwrap().clone()
+            let buffers = guard.get_buffers().await;
+            match buffers.get(buffer_name) {
+                Some(buffer) => buffer.clone(),
+                None => return Ok(PaneView::empty(id)),
+            }
         };
+        let handle = buffer.get_handle();
+        let line_numbers = gutter::line_numbers();
+        let gutter_width = handle.gutter_width(line_numbers).await;
+        handle.set_viewport_columns(columns.saturating_sub(gutter_width)).await;
 
-        buffer.render_styled_text().await;
-        let major_mode = buffer.get_major_mode();
-        let major_mode: Gc<MajorMode> = major_mode.try_to_rust_type().unwrap();
-        let draw = major_mode.draw();
-        let out = draw.call(&[buffer.get_major_mode()]).await.unwrap();
-        let styled_file: Gc<StyledFile> = out[0].clone().try_to_rust_type().unwrap();
-        let styled_file = (*styled_file).clone();
-        self.notify_clients(MessageKind::General(GeneralMessage::Draw(styled_file))).await;
-        Ok(())
+        let (text, main_cursor) = if focused {
+            let major_mode = buffer.get_major_mode();
+            let major_mode: Gc<MajorMode> = major_mode.try_to_rust_type().unwrap();
+            let draw = major_mode.draw();
+            let out = draw.call(&[buffer.get_major_mode()]).await.unwrap();
+            let styled_file: Gc<StyledFile> = out[0].clone().try_to_rust_type().unwrap();
+            ((*styled_file).clone(), buffer.get_main_cursor().await.ok())
+        } else {
+            let saved = layout.pane(id).and_then(|pane| pane.cursors()).map(<[Cursor]>::to_vec);
+            let mut cursors = match saved {
+                Some(cursors) => cursors,
+                None => layout::buffer_cursors(&buffer).await.unwrap_or_default(),
+            };
+            layout::clamp_cursors(&mut cursors, &handle.get_text().await);
+            buffer.render_styled_text(&cursors).await;
+            let main_cursor = cursors.iter().find(|cursor| cursor.is_main()).copied();
+            (buffer.get_styled_text(&cursors), main_cursor)
+        };
+
+        let (cursor, logical_cursor) = match main_cursor {
+            Some(cursor) => (
+                handle.visual_position(cursor.line(), cursor.column()).await,
+                (cursor.line(), cursor.column()),
+            ),
+            None => ((0, 0), (0, 0)),
+        };
+        let gutter = handle.draw_gutter(line_numbers, logical_cursor.0).await;
+        let status_line = status_line::draw(StatusLinePane {
+            id,
+            buffer_name,
+            cursor: logical_cursor,
+            focused,
+        }, columns).await;
+        Ok(PaneView {
+            id,
+            area: Default::default(),
+            text,
+            gutter,
+            gutter_width,
+            status_line,
+            cursor,
+            focused,
+        })
     }
 
     
@@ -153,7 +256,7 @@ impl Session {
 
         let files = KoruArgs::get_files();
 
-        let buffer = if let Some(files) = files {
+        if let Some(files) = files {
             let buffer_name = if !files.is_empty() {
                 let first_file = files[0].clone();
                 let files = &files[1..];
@@ -176,17 +279,10 @@ impl Session {
                     String::new()
                 })
             };
-            let focused_buffer = {
-                SessionState::set_current_buffer(buffer_name).await;
-                SessionState::current_focused_buffer().await.unwrap().0
-            };
-            focused_buffer
-        } else {
-            SessionState::current_focused_buffer().await.unwrap().0
-        };
-
+            SessionState::set_current_buffer(buffer_name).await;
+        }
 
-        match self.send_draw(&buffer).await {
+        match self.send_draw().await {
             Ok(_) => {}
             Err(e) => {
                 error!("Failure sending draw: {}", e);
@@ -194,33 +290,52 @@ impl Session {
         }
         loop {
             let message = self.broker_client.recv_async().await;
+            if let Some(Message { kind: MessageKind::General(GeneralMessage::KeyEvent(_) | GeneralMessage::MouseEvent(_)), .. }) = &message {
+                self.focused_client = message.as_ref().map(Message::source);
+            }
             match message {
                 Some(Message { kind: MessageKind::General(GeneralMessage::FlushKeyBuffer), ..}) => {
                     SessionState::flush_key_buffer().await;
                 }
                 Some(Message { kind: MessageKind::General(GeneralMessage::KeyEvent(press)), .. }) => {
-                    let focused_buffer = {
-                        SessionState::process_keypress(press).await;
-                        SessionState::current_focused_buffer().await.unwrap().0
-                    };
-                    self.send_draw(&focused_buffer).await.unwrap();
+                    SessionState::process_keypress(press).await;
+                    self.keys_processed().await;
+                }
+                Some(Message { kind: MessageKind::General(GeneralMessage::MouseEvent(event)), .. }) => {
+                    if let Err(err) = mouse::handle(event, &mut self.clicks).await {
+                        error!("{}", err);
+                    }
+                    match self.send_draw().await {
+                        Ok(_) => {}
+                        Err(e) => {
+                            error!("Failure sending draw: {}", e);
+                        }
+                    }
                 }
                 Some(Message { kind: MessageKind::General(GeneralMessage::RequestMainCursor), ..}) => {
-                    let main_cursor = {
-                        let Some((_, buffer)) = SessionState::current_focused_buffer().await else {
-                            continue
-                        };
-                        buffer.get_main_cursor().await
-                    };
-                    let main_cursor = match main_cursor {
-                        Ok(cursor) => cursor,
-                        Err(err) => {
-                            error!("{}", err);
-                            continue;
+                    self.send_main_cursor().await;
+                }
+                Some(Message { kind: MessageKind::General(GeneralMessage::ViewportSize(columns, lines)), ..}) => {
+                    if !SessionState::set_viewport(columns, lines).await {
+                        continue;
+                    }
+                    // The panes get divided up again and wrapped lines depend on their width
+                    match self.send_draw().await {
+                        Ok(_) => {}
+                        Err(e) => {
+                            error!("Failure sending draw: {}", e);
                         }
-                    };
-
-                    self.notify_clients(MessageKind::General(GeneralMessage::MainCursorPosition(main_cursor.line(), main_cursor.column()))).await;
+                    }
+                    self.send_main_cursor().await;
+                }
+                Some(Message { kind: MessageKind::General(GeneralMessage::ClipboardContents(contents)), ..}) => {
+                    self.receive_clipboard(contents).await;
+                    match self.send_draw().await {
+                        Ok(_) => {}
+                        Err(e) => {
+                            error!("Failure sending draw: {}", e);
+                        }
+                    }
                 }
                 Some(Message { kind: MessageKind::BackEnd(message), ..}) => {
                     self.handle_backend_message(message).await;
@@ -235,6 +350,49 @@ impl Session {
         // TODO: add a way to send error to the frontend
     }
 
+    /// Catches the completion and key hints up with the keys that were run, and draws the panes again.
+    async fn keys_processed(&mut self) {
+        let (focused_buffer, buffer) = SessionState::current_focused_buffer().await.unwrap();
+        if let Ok(cursor) = buffer.get_main_cursor().await {
+            completion::refresh(&focused_buffer, cursor.line(), cursor.column()).await;
+        }
+        key_hints::refresh().await;
+        self.send_draw().await.unwrap();
+    }
+
+    /// Sends the row and column that the main cursor is drawn at.
+    async fn send_main_cursor(&mut self) {
+        let Some((_, buffer)) = SessionState::current_focused_buffer().await else {
+            return;
+        };
+        let main_cursor = match buffer.get_main_cursor().await {
+            Ok(cursor) => cursor,
+            Err(err) => {
+                error!("{}", err);
+                return;
+            }
+        };
+
+        let handle = buffer.get_handle();
+        let (row, column) = handle.visual_position(main_cursor.line(), main_cursor.column()).await;
+        self.notify_clients(MessageKind::General(GeneralMessage::MainCursorPosition(row, column))).await;
+        let (_, diagnostics) = handle.line_diagnostics(main_cursor.line()).await;
+        self.show_diagnostic(diagnostics.first()).await;
+    }
+
+    /// Shows a diagnostic in the message bar, only clearing the bar if it was showing a diagnostic before.
+    async fn show_diagnostic(&mut self, diagnostic: Option<&Diagnostic>) {
+        let message = diagnostic.map(|diagnostic| {
+            format!("{}: {} [{}]", diagnostic.severity.name(), diagnostic.message, diagnostic.source)
+        });
+        if message == self.diagnostic_message {
+            return;
+        }
+        let text = message.clone().unwrap_or_default();
+        self.diagnostic_message = message;
+        self.notify_clients(MessageKind::General(GeneralMessage::UpdateMessageBar(text))).await;
+    }
+
     async fn handle_backend_message(&mut self, message: BackendMessage) {
         match message {
             BackendMessage::ShowCommandBar => {
@@ -246,6 +404,43 @@ impl Session {
             BackendMessage::UpdateCommandBar(text) => {
                 self.notify_clients(MessageKind::General(GeneralMessage::UpdateCommandBar(text))).await;
             }
+            BackendMessage::UpdateMessageBar(text) => {
+                self.notify_clients(MessageKind::General(GeneralMessage::UpdateMessageBar(text))).await;
+            }
+            BackendMessage::SetClipboard(text) => {
+                self.notify_focused_client(MessageKind::General(GeneralMessage::SetClipboard(text))).await;
+            }
+            BackendMessage::RequestClipboard => {
+                self.notify_focused_client(MessageKind::General(GeneralMessage::RequestClipboard)).await;
+            }
+            BackendMessage::ShowPopup(popup) => {
+                self.notify_clients(MessageKind::General(GeneralMessage::ShowPopup(popup))).await;
+            }
+            BackendMessage::HidePopup => {
+                self.notify_clients(MessageKind::General(GeneralMessage::HidePopup)).await;
+            }
+            BackendMessage::ShowPicker(picker) => {
+                self.notify_clients(MessageKind::General(GeneralMessage::ShowPicker(picker))).await;
+            }
+            BackendMessage::HidePicker => {
+                self.notify_clients(MessageKind::General(GeneralMessage::HidePicker)).await;
+            }
+            BackendMessage::ShowKeyHints(hints) => {
+                self.notify_clients(MessageKind::General(GeneralMessage::ShowKeyHints(hints))).await;
+            }
+            BackendMessage::HideKeyHints => {
+                self.notify_clients(MessageKind::General(GeneralMessage::HideKeyHints)).await;
+            }
+            BackendMessage::Redraw => {
+                if let Err(e) = self.send_draw().await {
+                    error!("Failure sending draw: {}", e);
+                }
+            }
+            BackendMessage::KeySequenceTimeout(generation) => {
+                if SessionState::key_sequence_timeout(generation).await {
+                    self.keys_processed().await;
+                }
+            }
             BackendMessage::Quit => {
                 self.notify_clients(MessageKind::General(GeneralMessage::Quit)).await;
             }
@@ -0,0 +1,156 @@
+use std::sync::{LazyLock, RwLock};
+use log::error;
+use scheme_rs::exceptions::Exception;
+use scheme_rs::gc::Gc;
+use scheme_rs::lists::List;
+use scheme_rs::num::SimpleNumber;
+use scheme_rs::proc::Procedure;
+use scheme_rs::registry::bridge;
+use scheme_rs::value::{UnpackedValue, Value};
+use crate::kernel::broker::{BackendMessage, MessageKind};
+use crate::kernel::scheme_api::session::SessionState;
+use crate::styled_text::{ColorType, StyledFile, StyledText, TextAttribute, TextChunk};
+
+/// The procedure that builds the status line of each pane, there are no status lines without one.
+static STATUS_LINE: LazyLock<RwLock<Option<Procedure>>> = LazyLock::new(|| {
+    RwLock::new(None)
+});
+
+/// What a status line is built for.
+pub struct StatusLinePane<'a> {
+    pub id: usize,
+    pub buffer_name: &'a str,
+    /// The line and column of the pane's main cursor
+    pub cursor: (usize, usize),
+    pub focused: bool,
+}
+
+/// Builds the status line of a pane that is `width` columns wide.
+///
+/// Returns an empty file if there is no status line procedure or if it fails.
+pub async fn draw(pane: StatusLinePane<'_>, width: usize) -> StyledFile {
+    let mut file = StyledFile::new();
+    let procedure = STATUS_LINE.read().expect("status line lock poisoned").clone();
+    let Some(procedure) = procedure else {
+        return file;
+    };
+    let args = [
+        Value::from(pane.id),
+        Value::from(pane.buffer_name.to_string()),
+        Value::from(SimpleNumber::from(pane.cursor.0)),
+        Value::from(SimpleNumber::from(pane.cursor.1)),
+        Value::from(pane.focused),
+    ];
+    let sections = procedure.call(&args).await.and_then(|values| match values.first() {
+        Some(value) => sections_from(value),
+        None => Ok(Default::default()),
+    });
+    match sections {
+        Ok(sections) => file.push_line(arrange(sections, width)),
+        Err(err) => error!("Status line failed: {}", err),
+    }
+    file
+}
+
+fn fill(width: usize) -> StyledText {
+    StyledText::Style {
+        text: TextChunk::from(" ".repeat(width)),
+        fg_color: ColorType::Text,
+        bg_color: ColorType::Surface0,
+        attribute: TextAttribute::empty(),
+    }
+}
+
+/// Reads a segment, which is either a string or a styled text.
+///
+/// Segments without a style get the colors of the status line.
+fn segment_from(value: &Value) -> Result<StyledText, Exception> {
+    if let UnpackedValue::String(text) = value.clone().unpack() {
+        return Ok(fill(0).with_text(TextChunk::from(text.to_string())));
+    }
+    let text: Gc<StyledText> = value.clone().try_to_rust_type()?;
+    match &*text {
+        StyledText::None { text } => Ok(fill(0).with_text(text.clone())),
+        text => Ok(text.clone()),
+    }
+}
+
+/// Reads the left, center and right sections, any of which can be left out from the end.
+fn sections_from(value: &Value) -> Result<[Vec<StyledText>; 3], Exception> {
+    let list: List = value.try_into()?;
+    let mut sections: [Vec<StyledText>; 3] = Default::default();
+    for (section, value) in sections.iter_mut().zip(list) {
+        let segments: List = (&value).try_into()?;
+        *section = segments.into_iter()
+            .map(|segment| segment_from(&segment))
+            .collect::<Result<_, _>>()?;
+    }
+    Ok(sections)
+}
+
+fn width_of(segments: &[StyledText]) -> usize {
+    segments.iter().map(|segment| segment.text().width()).sum()
+}
+
+/// Cuts off the segments that don't fit in `width` columns.
+fn truncate(segments: Vec<StyledText>, width: usize) -> Vec<StyledText> {
+    let mut remaining = width;
+    let mut truncated = Vec::new();
+    for segment in segments {
+        if remaining == 0 {
+            break;
+        }
+        let segment_width = segment.text().width();
+        if segment_width <= remaining {
+            remaining -= segment_width;
+            truncated.push(segment);
+        } else {
+            let (before, _) = segment.split_at_width(remaining);
+            remaining = 0;
+            truncated.push(before);
+        }
+    }
+    truncated
+}
+
+/// Lays out the sections across the width of a pane.
+///
+/// The left and right sections win over the center when there isn't enough room.
+/// The center section is centered in the pane if it fits, otherwise it is pushed away from the longer side.
+fn arrange([left, center, right]: [Vec<StyledText>; 3], width: usize) -> Vec<StyledText> {
+    let left = truncate(left, width);
+    let left_width = width_of(&left);
+    let right = truncate(right, width - left_width);
+    let right_width = width_of(&right);
+    let center = truncate(center, width - left_width - right_width);
+    let center_width = width_of(&center);
+    let center_start = ((width - center_width) / 2).clamp(left_width, width - right_width - center_width);
+
+    let mut line = left;
+    line.push(fill(center_start - left_width));
+    line.extend(center);
+    line.push(fill(width - right_width - center_start - center_width));
+    line.extend(right);
+    line
+}
+
+#[bridge(name = "status-line-set!", lib = "(koru-status-line)")]
+pub async fn status_line_set(procedure: &Value) -> Result<Vec<Value>, Exception> {
+    let procedure: Procedure = procedure.clone().try_into()?;
+    *STATUS_LINE.write().expect("status line lock poisoned") = Some(procedure);
+    SessionState::send_message(MessageKind::BackEnd(BackendMessage::Redraw)).await?;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "status-line-clear!", lib = "(koru-status-line)")]
+pub async fn status_line_clear() -> Result<Vec<Value>, Exception> {
+    *STATUS_LINE.write().expect("status line lock poisoned") = None;
+    SessionState::send_message(MessageKind::BackEnd(BackendMessage::Redraw)).await?;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "status-line-refresh", lib = "(koru-status-line)")]
+pub async fn status_line_refresh() -> Result<Vec<Value>, Exception> {
+    SessionState::send_message(MessageKind::BackEnd(BackendMessage::Redraw)).await?;
+    Ok(Vec::new())
+}
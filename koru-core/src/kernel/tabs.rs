@@ -0,0 +1,262 @@
+use std::path::Path;
+use std::sync::Arc;
+use scheme_rs::exceptions::Exception;
+use scheme_rs::lists;
+use scheme_rs::num::SimpleNumber;
+use scheme_rs::registry::bridge;
+use scheme_rs::value::{UnpackedValue, Value};
+use tokio::sync::RwLock;
+use crate::kernel::layout::{self, Layout};
+use crate::kernel::scheme_api::session::SessionState;
+use crate::styled_text::{ColorType, StyledFile, StyledText, TextAttribute, TextChunk};
+
+/// A page of panes.
+#[derive(Debug, Clone)]
+pub struct Tab {
+    /// The name set by the user, tabs without one are named after the buffer of their focused pane
+    name: Option<String>,
+    layout: Arc<RwLock<Layout>>,
+}
+
+impl Tab {
+    fn new(name: Option<String>) -> Self {
+        Self {
+            name,
+            layout: Arc::new(RwLock::new(Layout::new())),
+        }
+    }
+
+    /// Gets the name that the tab is shown with.
+    pub async fn label(&self) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+        let layout = self.layout.read().await;
+        let buffer_name = layout.pane(layout.focused()).and_then(|pane| pane.buffer_name());
+        match buffer_name {
+            Some(buffer_name) => Path::new(buffer_name)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| buffer_name.to_string()),
+            None => String::from("[No Name]"),
+        }
+    }
+}
+
+/// The tabs of a session.
+///
+/// There is always at least one tab, the layout of the current tab is the one that gets drawn.
+#[derive(Debug, Clone)]
+pub struct Tabs {
+    tabs: Vec<Tab>,
+    current: usize,
+}
+
+impl Tabs {
+    pub fn new() -> Self {
+        Self {
+            tabs: vec![Tab::new(None)],
+            current: 0,
+        }
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn len(&self) -> usize {
+        self.tabs.len()
+    }
+
+    pub fn current_layout(&self) -> Arc<RwLock<Layout>> {
+        self.tabs[self.current].layout.clone()
+    }
+
+    /// Adds a tab after the current one, returning its index.
+    fn insert(&mut self, name: Option<String>) -> usize {
+        let index = self.current + 1;
+        self.tabs.insert(index, Tab::new(name));
+        index
+    }
+
+    /// Removes a tab, returning the index of the tab that should become the current tab.
+    ///
+    /// The last tab can't be removed.
+    fn remove(&mut self, index: usize) -> Option<usize> {
+        if self.tabs.len() == 1 || index >= self.tabs.len() {
+            return None;
+        }
+        self.tabs.remove(index);
+        if self.current > index {
+            self.current -= 1;
+        }
+        Some(index.min(self.tabs.len() - 1))
+    }
+
+    /// Moves the current tab to another index, keeping it current.
+    fn move_current(&mut self, index: usize) {
+        let index = index.min(self.tabs.len() - 1);
+        let tab = self.tabs.remove(self.current);
+        self.tabs.insert(index, tab);
+        self.current = index;
+    }
+
+    fn index(&self, value: &Value) -> Result<usize, Exception> {
+        let index: SimpleNumber = value.clone().try_into()?;
+        let index: usize = index.try_into()?;
+        if index >= self.tabs.len() {
+            return Err(Exception::error(format!("Tab not found: {index}")));
+        }
+        Ok(index)
+    }
+
+    pub async fn labels(&self) -> Vec<String> {
+        let mut labels = Vec::new();
+        for tab in &self.tabs {
+            labels.push(tab.label().await);
+        }
+        labels
+    }
+
+    /// Draws the tab bar, which is empty when there is only one tab.
+    pub async fn bar(&self) -> StyledFile {
+        let mut file = StyledFile::new();
+        if self.tabs.len() == 1 {
+            return file;
+        }
+        let mut line = Vec::new();
+        for (index, label) in self.labels().await.into_iter().enumerate() {
+            let (fg_color, bg_color) = if index == self.current {
+                (ColorType::Text, ColorType::Surface1)
+            } else {
+                (ColorType::Subtext0, ColorType::Surface0)
+            };
+            line.push(StyledText::Style {
+                text: TextChunk::from(format!(" {}: {label} ", index + 1)),
+                fg_color,
+                bg_color,
+                attribute: TextAttribute::empty(),
+            });
+        }
+        file.push_line(line);
+        file
+    }
+}
+
+impl Default for Tabs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Makes another tab the current tab.
+///
+/// Like moving the focus between panes, the focused pane of the tab being left keeps its cursors unless the tab was removed.
+async fn switch_tab(index: usize, keep_old: bool) {
+    let tabs = SessionState::get_tabs().await;
+    if keep_old {
+        let layout = tabs.read().await.current_layout();
+        layout::save_focused_cursors(&layout).await;
+    }
+    let layout = {
+        let mut guard = tabs.write().await;
+        guard.current = index;
+        guard.current_layout()
+    };
+    layout::restore_focused_pane(&layout).await;
+}
+
+#[bridge(name = "tab-new", lib = "(koru-tab)")]
+pub async fn tab_new(args: &[Value]) -> Result<Vec<Value>, Exception> {
+    let name = match args.first() {
+        Some(name) => Some(String::try_from(name.clone())?),
+        None => None,
+    };
+    let index = SessionState::get_tabs().await.write().await.insert(name);
+    let buffer_name = SessionState::current_focused_buffer().await.map(|(buffer_name, _)| buffer_name);
+    switch_tab(index, true).await;
+    // The new tab starts out showing the buffer that was current
+    if let Some(buffer_name) = buffer_name {
+        layout::show_buffer(buffer_name).await;
+    }
+    Ok(vec![Value::from(index)])
+}
+
+#[bridge(name = "tab-close", lib = "(koru-tab)")]
+pub async fn tab_close() -> Result<Vec<Value>, Exception> {
+    let next = {
+        let tabs = SessionState::get_tabs().await;
+        let mut guard = tabs.write().await;
+        let current = guard.current;
+        guard.remove(current)
+    };
+    let Some(next) = next else {
+        return Err(Exception::error(String::from("Can't close the last tab")));
+    };
+    switch_tab(next, false).await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "tab-rename", lib = "(koru-tab)")]
+pub async fn tab_rename(name: &Value) -> Result<Vec<Value>, Exception> {
+    let name = match name.clone().unpack() {
+        UnpackedValue::Boolean(false) => None,
+        UnpackedValue::String(_) => Some(String::try_from(name.clone())?),
+        other => return Err(Exception::type_error("#f or String", other.type_name())),
+    };
+    let tabs = SessionState::get_tabs().await;
+    let mut guard = tabs.write().await;
+    let current = guard.current;
+    guard.tabs[current].name = name;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "tab-move", lib = "(koru-tab)")]
+pub async fn tab_move(index: &Value) -> Result<Vec<Value>, Exception> {
+    let index: SimpleNumber = index.clone().try_into()?;
+    let index: usize = index.try_into()?;
+    SessionState::get_tabs().await.write().await.move_current(index);
+    Ok(Vec::new())
+}
+
+#[bridge(name = "tab-switch", lib = "(koru-tab)")]
+pub async fn tab_switch(index: &Value) -> Result<Vec<Value>, Exception> {
+    let index = SessionState::get_tabs().await.read().await.index(index)?;
+    switch_tab(index, true).await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "tab-next", lib = "(koru-tab)")]
+pub async fn tab_next() -> Result<Vec<Value>, Exception> {
+    let next = {
+        let tabs = SessionState::get_tabs().await;
+        let guard = tabs.read().await;
+        (guard.current + 1) % guard.len()
+    };
+    switch_tab(next, true).await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "tab-previous", lib = "(koru-tab)")]
+pub async fn tab_previous() -> Result<Vec<Value>, Exception> {
+    let previous = {
+        let tabs = SessionState::get_tabs().await;
+        let guard = tabs.read().await;
+        (guard.current + guard.len() - 1) % guard.len()
+    };
+    switch_tab(previous, true).await;
+    Ok(Vec::new())
+}
+
+#[bridge(name = "tab-current", lib = "(koru-tab)")]
+pub async fn tab_current() -> Result<Vec<Value>, Exception> {
+    let current = SessionState::get_tabs().await.read().await.current();
+    Ok(vec![Value::from(current)])
+}
+
+#[bridge(name = "tab-list", lib = "(koru-tab)")]
+pub async fn tab_list() -> Result<Vec<Value>, Exception> {
+    let labels = SessionState::get_tabs().await.read().await.labels().await;
+    let labels = labels.into_iter().map(Value::from).collect::<Vec<_>>();
+    Ok(vec![lists::slice_to_list(&labels)])
+}
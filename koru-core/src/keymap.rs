@@ -1,141 +1,309 @@
 use std::collections::HashMap;
 use std::marker::PhantomData;
+use std::sync::{Arc, LazyLock, OnceLock, RwLock};
+use std::time::Duration;
 use scheme_rs::gc::Gc;
+use scheme_rs::symbols::Symbol;
 use crate::kernel::input::KeyPress;
 use crate::kernel::scheme_api::command::Command;
 
+/// The key groups by name, each with the key sequences that belong to it.
+///
+/// Key maps bind groups by name and merge their keys in when they are first looked up in,
+/// so a key map has to be told with [`KeyMap::invalidate_tree`] when a group that it binds changes.
+static KEY_GROUPS: LazyLock<RwLock<HashMap<Symbol, Vec<Vec<KeyPress>>>>> = LazyLock::new(|| {
+    RwLock::new(HashMap::new())
+});
+
+/// Replaces the key sequences of a group, creating the group if it doesn't exist.
+pub fn set_key_group(name: Symbol, sequences: Vec<Vec<KeyPress>>) {
+    KEY_GROUPS.write().expect("key groups lock poisoned").insert(name, sequences);
+}
+
+/// Adds a key sequence to a group, creating the group if it doesn't exist.
+pub fn add_to_key_group(name: Symbol, keys: Vec<KeyPress>) {
+    let mut groups = KEY_GROUPS.write().expect("key groups lock poisoned");
+    let sequences = groups.entry(name).or_default();
+    if !sequences.contains(&keys) {
+        sequences.push(keys);
+    }
+}
+
+/// Takes a key sequence out of a group.
+pub fn remove_from_key_group(name: Symbol, keys: &[KeyPress]) {
+    if let Some(sequences) = KEY_GROUPS.write().expect("key groups lock poisoned").get_mut(&name) {
+        sequences.retain(|sequence| sequence != keys);
+    }
+}
+
+pub fn remove_key_group(name: Symbol) {
+    KEY_GROUPS.write().expect("key groups lock poisoned").remove(&name);
+}
+
+/// Gets the key sequences of a group, or None if there is no such group.
+pub fn key_group(name: Symbol) -> Option<Vec<Vec<KeyPress>>> {
+    KEY_GROUPS.read().expect("key groups lock poisoned").get(&name).cloned()
+}
+
+pub fn key_group_names() -> Vec<Symbol> {
+    KEY_GROUPS.read().expect("key groups lock poisoned").keys().copied().collect()
+}
+
+/// Finds the groups that the key sequence belongs to.
+pub fn key_groups_of(keys: &[KeyPress]) -> Vec<Symbol> {
+    KEY_GROUPS.read().expect("key groups lock poisoned")
+        .iter()
+        .filter(|(_, sequences)| sequences.iter().any(|sequence| sequence == keys))
+        .map(|(name, _)| *name)
+        .collect()
+}
+
 #[derive(Debug, Clone)]
 enum KeyMapNode {
     Node {
         children: HashMap<KeyPress, KeyMapNode>,
+        /// The command bound to the keys leading here, which runs if no longer binding is finished.
+        command: Option<Gc<Command>>,
     },
     Leaf {
         command: Gc<Command>,
     }
 }
 
+impl KeyMapNode {
+    fn empty() -> Self {
+        KeyMapNode::Node {
+            children: HashMap::new(),
+            command: None,
+        }
+    }
+}
+
+/// What pressing a key does after a prefix, as listed by [`KeyMap::children`].
+#[derive(Debug, Clone)]
+pub enum KeyMapChild {
+    /// The key finishes a binding.
+    Command(Gc<Command>),
+    /// The key starts a longer binding, which can be finished by this many keys.
+    Prefix(usize),
+}
+
+/// How a key map looks up a sequence of keys, as told by [`KeyMap::resolve`].
+#[derive(Debug, Clone)]
+pub enum Resolution {
+    /// The keys are bound to a command.
+    Bound(Gc<Command>),
+    /// Nothing is bound to the keys, so they go to the key map's default command.
+    Default(Gc<Command>),
+    /// The keys are the start of a longer binding.
+    ///
+    /// If the keys are also bound on their own, that command runs when no longer binding is finished.
+    Prefix(Option<Gc<Command>>),
+    /// The keys aren't bound and there is no default command.
+    Unbound,
+}
+
 #[derive(Debug, Clone)]
 pub struct KeyMap {
     keys_to_command: KeyMapNode,
+    /// Groups bound to commands, in the order they were bound.
+    groups: Vec<(Symbol, Gc<Command>)>,
     default: Option<Gc<Command>>,
+    /// How long the start of a longer binding waits for the rest of it, or None to wait for the next key.
+    timeout: Option<Duration>,
+    /// The bindings with the keys of the bound groups merged in, built the first time they are needed.
+    ///
+    /// Clones share it, so that it is only built once for the key map that they were cloned from.
+    merged: Arc<OnceLock<KeyMapNode>>,
 }
 
 impl KeyMap {
     pub fn new_sparse() -> Self {
         Self {
-            keys_to_command: KeyMapNode::Node {
-                children: HashMap::new(),
-            },
+            keys_to_command: KeyMapNode::empty(),
+            groups: Vec::new(),
             default: None,
+            timeout: None,
+            merged: Arc::default(),
         }
     }
 
     pub fn new(default: Gc<Command>) -> Self {
         Self {
-            keys_to_command: KeyMapNode::Node {
-                children: HashMap::new(),
-            },
+            keys_to_command: KeyMapNode::empty(),
+            groups: Vec::new(),
             default: Some(default),
+            timeout: None,
+            merged: Arc::default(),
         }
     }
 
-    pub fn lookup(&self, mut keys: &[KeyPress]) -> Option<&Gc<Command>> {
-        let mut node = &self.keys_to_command;
-        while keys.len() > 0 {
-            match node {
-                KeyMapNode::Node {
-                    children,
-                    ..
-                } => {
-                    node = match children.get(&keys[0]) {
-                        Some(node) => node,
-                        None => break,
-                    };
-                    keys = &keys[1..];
-                }
-                KeyMapNode::Leaf { .. } => {
-                    return None
-                }
-            }
+    /// Gets the bindings with the keys of the bound groups added in.
+    ///
+    /// Keys bound on their own win over groups, and groups bound first win over later ones.
+    fn tree(&self) -> &KeyMapNode {
+        if self.groups.is_empty() {
+            return &self.keys_to_command;
         }
-        match node {
-            KeyMapNode::Node {
-                ..
-            } => {
-                if let Some(default) = &self.default {
-                    Some(default)
-                } else {
-                    None
+        self.merged.get_or_init(|| {
+            let mut tree = self.keys_to_command.clone();
+            let groups = KEY_GROUPS.read().expect("key groups lock poisoned");
+            for (name, command) in &self.groups {
+                for keys in groups.get(name).into_iter().flatten() {
+                    bind(&mut tree, keys, command, false);
                 }
             }
-            KeyMapNode::Leaf { command } => {
-                Some(command)
-            }
+            tree
+        })
+    }
+
+    /// Drops the merged bindings so that they are built again the next time they are needed.
+    ///
+    /// This has to be called when a key group changes, since the key map can't tell.
+    pub fn invalidate_tree(&mut self) {
+        self.merged = Arc::default();
+    }
+
+    /// Gets the command that the keys run right away.
+    ///
+    /// Keys that are the start of a longer binding don't run anything yet, even if they are bound on their own.
+    pub fn lookup(&self, keys: &[KeyPress]) -> Option<Gc<Command>> {
+        match self.resolve(keys) {
+            Resolution::Bound(command) | Resolution::Default(command) => Some(command),
+            Resolution::Prefix(_) | Resolution::Unbound => None,
         }
+    }
 
+    /// Lists the keys that can come after a prefix, in no particular order.
+    ///
+    /// Returns nothing if the prefix isn't the start of a longer binding.
+    pub fn children(&self, prefix: &[KeyPress]) -> Vec<(KeyPress, KeyMapChild)> {
+        let mut node = self.tree();
+        for key in prefix {
+            let KeyMapNode::Node { children, .. } = node else {
+                return Vec::new();
+            };
+            let Some(child) = children.get(key) else {
+                return Vec::new();
+            };
+            node = child;
+        }
+        let KeyMapNode::Node { children, .. } = node else {
+            return Vec::new();
+        };
+        children.iter()
+            .map(|(key, child)| {
+                let child = match child {
+                    KeyMapNode::Leaf { command } => KeyMapChild::Command(command.clone()),
+                    KeyMapNode::Node { children, command: Some(command) } if children.is_empty() => {
+                        KeyMapChild::Command(command.clone())
+                    }
+                    KeyMapNode::Node { children, .. } => KeyMapChild::Prefix(children.len()),
+                };
+                (key.clone(), child)
+            })
+            .collect()
     }
 
-    pub fn add_binding(&mut self, mut keys: Vec<KeyPress>, command: Gc<Command>) {
-        // Reversing keys for faster popping
-        keys.reverse();
-        let mut node = &mut self.keys_to_command;
-        while keys.len() > 0 {
+    /// Tells how the keys are looked up.
+    pub fn resolve(&self, keys: &[KeyPress]) -> Resolution {
+        let mut node = self.tree();
+        let mut consumed = 0;
+        for key in keys {
             match node {
-                KeyMapNode::Node {
-                    children,
-                    ..
-                } => {
-                    if !children.contains_key(keys.last().unwrap()) {
-                        if keys.len() == 1 {
-                            children.insert(keys.last().unwrap().clone(), KeyMapNode::Leaf { command: command.clone() });
-                        } else {
-                            children.insert(keys.last().unwrap().clone(), KeyMapNode::Node { children: HashMap::new(), });
-                        }
+                KeyMapNode::Node { children, .. } => match children.get(key) {
+                    Some(child) => {
+                        node = child;
+                        consumed += 1;
+                    }
+                    None => break,
+                },
+                KeyMapNode::Leaf { .. } => return Resolution::Unbound,
+            }
+        }
+        let whole = consumed == keys.len() && consumed > 0;
+        match (node, &self.default) {
+            (KeyMapNode::Leaf { command }, _) => Resolution::Bound(command.clone()),
+            (KeyMapNode::Node { children, command }, _) if whole && !children.is_empty() => Resolution::Prefix(command.clone()),
+            (KeyMapNode::Node { command: Some(command), .. }, _) if whole => Resolution::Bound(command.clone()),
+            (KeyMapNode::Node { .. }, Some(default)) => Resolution::Default(default.clone()),
+            (KeyMapNode::Node { .. }, None) => Resolution::Unbound,
+        }
+    }
+
+    /// Lists every binding with its keys, in no particular order.
+    pub fn bindings(&self) -> Vec<(Vec<KeyPress>, Gc<Command>)> {
+        fn collect(node: &KeyMapNode, keys: &mut Vec<KeyPress>, bindings: &mut Vec<(Vec<KeyPress>, Gc<Command>)>) {
+            match node {
+                KeyMapNode::Leaf { command } => bindings.push((keys.clone(), command.clone())),
+                KeyMapNode::Node { children, command } => {
+                    if let Some(command) = command {
+                        bindings.push((keys.clone(), command.clone()));
+                    }
+                    for (key, child) in children {
+                        keys.push(key.clone());
+                        collect(child, keys, bindings);
+                        keys.pop();
                     }
-                    node = children.get_mut(keys.last().unwrap()).unwrap();
-                    keys.pop();
-                }
-                KeyMapNode::Leaf { .. } => {
-                    let mut map = HashMap::new();
-                    map.insert(keys.last().unwrap().clone(), KeyMapNode::Node { children: HashMap::new() });
-
-                    *node = KeyMapNode::Node {
-                        children: map,
-                    };
-                    let KeyMapNode::Node { children, .. } = node else {
-                        unreachable!("we should be this value because we just set it to this value");
-                    };
-
-                    node = children.get_mut(keys.last().unwrap()).unwrap();
-                    keys.pop();
                 }
             }
         }
-        *node = KeyMapNode::Leaf { command }
+        let mut bindings = Vec::new();
+        collect(self.tree(), &mut Vec::new(), &mut bindings);
+        bindings
+    }
+
+    /// Binds the keys, keeping any shorter or longer bindings that start with the same keys.
+    pub fn add_binding(&mut self, keys: Vec<KeyPress>, command: Gc<Command>) {
+        bind(&mut self.keys_to_command, &keys, &command, true);
+        self.invalidate_tree();
     }
 
+    /// Removes the binding of the keys, keeping the longer bindings that start with them.
+    /// If the keys are only the start of longer bindings, then those are all removed.
+    ///
     /// TODO: make it so that this can actually clear out keypresses if there is no dependencies
-    pub fn remove_binding(&mut self, mut keys: &[KeyPress]) {
+    pub fn remove_binding(&mut self, keys: &[KeyPress]) {
+        let Some((last, keys)) = keys.split_last() else {
+            return;
+        };
+        self.invalidate_tree();
         let mut node = &mut self.keys_to_command;
-        while keys.len() > 0 {
-            match node {
-                KeyMapNode::Node {
-                    children,
-                    ..
-                } => {
-                    let Some(new_node) = children.get_mut(&keys[0]) else {
-                        return;
-                    };
-                    keys = &keys[1..];
-                    node = new_node;
+        for key in keys {
+            let KeyMapNode::Node { children, .. } = node else {
+                return;
+            };
+            let Some(new_node) = children.get_mut(key) else {
+                return;
+            };
+            node = new_node;
+        }
+        if let KeyMapNode::Node { children, .. } = node {
+            match children.get_mut(last) {
+                Some(KeyMapNode::Node { children: longer, command }) if command.is_some() && !longer.is_empty() => {
+                    *command = None;
                 }
-                KeyMapNode::Leaf { .. } => {
-                    *node = KeyMapNode::Node { children: HashMap::new() }
+                _ => {
+                    children.remove(last);
                 }
             }
         }
     }
 
+    /// Binds every key sequence of a group, replacing what the group was bound to before.
+    pub fn add_group_binding(&mut self, group: Symbol, command: Gc<Command>) {
+        match self.groups.iter_mut().find(|(name, _)| *name == group) {
+            Some((_, bound)) => *bound = command,
+            None => self.groups.push((group, command)),
+        }
+        self.invalidate_tree();
+    }
+
+    pub fn remove_group_binding(&mut self, group: Symbol) {
+        self.groups.retain(|(name, _)| *name != group);
+        self.invalidate_tree();
+    }
+
     pub fn get_default(&self) -> Option<Gc<Command>> {
         self.default.clone()
     }
@@ -146,4 +314,40 @@ impl KeyMap {
     pub fn remove_default(&mut self) {
         self.default = None;
     }
+
+    pub fn get_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+}
+
+/// Binds the keys in the tree, replacing what they are bound to only if `replace` is set.
+///
+/// A binding that the keys start with turns into the start of the keys, so it still runs if they aren't finished.
+fn bind(tree: &mut KeyMapNode, keys: &[KeyPress], command: &Gc<Command>, replace: bool) {
+    let mut node = tree;
+    for key in keys {
+        if let KeyMapNode::Leaf { command: shorter } = node {
+            *node = KeyMapNode::Node {
+                children: HashMap::new(),
+                command: Some(shorter.clone()),
+            };
+        }
+        let KeyMapNode::Node { children, .. } = node else {
+            unreachable!("leaves on the way are turned into nodes");
+        };
+        node = children.entry(key.clone()).or_insert_with(KeyMapNode::empty);
+    }
+    let longer = matches!(node, KeyMapNode::Node { children, .. } if !children.is_empty());
+    if longer {
+        if let KeyMapNode::Node { command: bound, .. } = node
+            && (replace || bound.is_none()) {
+            *bound = Some(command.clone());
+        }
+    } else if replace || matches!(node, KeyMapNode::Node { command: None, .. }) {
+        *node = KeyMapNode::Leaf { command: command.clone() };
+    }
 }
@@ -12,8 +12,35 @@ use scheme_rs::records::{rtd, Record, RecordTypeDescriptor, SchemeCompatible};
 use scheme_rs::registry::bridge;
 use scheme_rs::value::Value;
 use unicode_normalization::char::is_combining_mark;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 use crate::kernel::buffer::Cursor;
 
+/// How many columns a tab reaches to when a buffer doesn't set its own tab width.
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
+/// Gets how many columns a grapheme takes up when it is drawn starting at a display column.
+///
+/// Tabs reach the next multiple of `tab_width`, and line endings take up nothing.
+/// Wide characters like CJK ideographs and emoji presentation sequences take up two columns.
+pub fn grapheme_width(grapheme: &str, column: usize, tab_width: usize) -> usize {
+    if grapheme == "\t" {
+        let tab_width = tab_width.max(1);
+        return tab_width - column % tab_width;
+    }
+    if grapheme.ends_with('\n') {
+        return 0;
+    }
+    // Every grapheme takes up at least one column so that there is somewhere to draw a cursor on it
+    grapheme.width().max(1)
+}
+
+/// Gets how many columns some text takes up when it is drawn.
+pub fn display_width(text: &str) -> usize {
+    text.graphemes(true)
+        .fold(0, |column, grapheme| column + grapheme_width(grapheme, column, DEFAULT_TAB_WIDTH))
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Trace)]
 pub struct TextChunk {
     #[trace(skip)]
@@ -40,6 +67,29 @@ impl TextChunk {
     pub fn end(&self) -> usize {
         self.end
     }
+
+    /// Gets how many columns the chunk takes up when it is drawn.
+    pub fn width(&self) -> usize {
+        self.graphemes()
+            .fold(0, |column, grapheme| column + grapheme_width(&grapheme, column, DEFAULT_TAB_WIDTH))
+    }
+
+    /// Splits the chunk into the graphemes that fit in `width` columns and the rest.
+    pub fn split_at_width(self, width: usize) -> (TextChunk, TextChunk) {
+        let mut column = 0;
+        let mut length = 0;
+        for grapheme in self.graphemes() {
+            let grapheme_width = grapheme_width(&grapheme, column, DEFAULT_TAB_WIDTH);
+            if column + grapheme_width > width {
+                break;
+            }
+            column += grapheme_width;
+            length += grapheme.len();
+        }
+        let middle = self.start + length;
+        let rest = TextChunk::new(self.rope.clone(), middle, self.end);
+        (TextChunk::new(self.rope, self.start, middle), rest)
+    }
 }
 
 impl SchemeCompatible for TextChunk {
@@ -157,6 +207,16 @@ impl TryFrom<&str> for ColorType {
             "Selection" => ColorType::Selection,
             "Cursor" => ColorType::Cursor,
             "SecondaryCursor" => ColorType::SecondaryCursor,
+            "Type" => ColorType::Type,
+            "Interface" => ColorType::Interface,
+            "Function" => ColorType::Function,
+            "Method" => ColorType::Method,
+            "Macro" => ColorType::Macro,
+            "Keyword" => ColorType::Keyword,
+            "Comment" => ColorType::Comment,
+            "String" => ColorType::String,
+            "Literal" => ColorType::Literal,
+            "Operator" => ColorType::Operator,
             "Pink" => ColorType::Pink,
             "Red" => ColorType::Red,
             "Lime" => ColorType::Lime,
@@ -192,14 +252,30 @@ impl SchemeCompatible for ColorType {
     }
 }
 
+/// Styling for a range of text.
+///
+/// A color of `None` leaves the color up to highlights underneath this one.
 #[derive(Debug, Clone, Eq, PartialEq, Trace)]
 pub struct Highlight {
-    pub fg_color: ColorType,
-    pub bg_color: ColorType,
+    pub fg_color: Option<ColorType>,
+    pub bg_color: Option<ColorType>,
     #[trace(skip)]
     pub attribute: TextAttribute,
 }
 
+impl Highlight {
+    /// Lays this highlight over another one.
+    ///
+    /// Colors that this highlight sets win, while text attributes from both get combined.
+    pub fn over(&self, under: &Highlight) -> Highlight {
+        Highlight {
+            fg_color: self.fg_color.or(under.fg_color),
+            bg_color: self.bg_color.or(under.bg_color),
+            attribute: self.attribute | under.attribute,
+        }
+    }
+}
+
 impl SchemeCompatible for Highlight {
     fn rtd() -> Arc<RecordTypeDescriptor>
     where
@@ -209,28 +285,28 @@ impl SchemeCompatible for Highlight {
     }
 }
 
+/// Reads a color that may be left unset with `#f`.
+fn optional_color(value: &Value) -> Result<Option<ColorType>, Exception> {
+    if !value.is_true() {
+        return Ok(None);
+    }
+    let color: String = value.clone().try_into()?;
+    match color.as_str().try_into() {
+        Ok(color) => Ok(Some(color)),
+        Err(msg) => Err(Exception::error(msg)),
+    }
+}
+
 #[bridge(name = "highlight-create", lib = "(styled-text)")]
 pub fn highlight_create(args: &[Value]) -> Result<Vec<Value>, Exception> {
     let Some((fg_color, rest)) = args.split_first() else {
         return Err(Exception::wrong_num_of_args(2, args.len()))
     };
-    let fg_color: String = fg_color.clone().try_into()?;
     let Some((bg_color, rest)) = rest.split_first() else {
         return Err(Exception::wrong_num_of_args(2, args.len()));
     };
-    let bg_color: String = bg_color.clone().try_into()?;
-    let fg_color = match fg_color.as_str().try_into()  {
-        Ok(color) => color,
-        Err(msg) => {
-            return Err(Exception::error(msg));
-        }
-    };
-    let bg_color = match bg_color.as_str().try_into()  {
-        Ok(color) => color,
-        Err(msg) => {
-            return Err(Exception::error(msg));
-        }
-    };
+    let fg_color = optional_color(fg_color)?;
+    let bg_color = optional_color(bg_color)?;
 
     let mut attributes = TextAttribute::empty();
 
@@ -269,6 +345,43 @@ pub enum StyledText {
     }
 }
 
+impl StyledText {
+    pub fn text(&self) -> &TextChunk {
+        match self {
+            StyledText::None { text } | StyledText::Style { text, .. } => text,
+        }
+    }
+
+    /// Makes a segment with the same style as this one but different text.
+    pub fn with_text(&self, text: TextChunk) -> StyledText {
+        match self {
+            StyledText::None { .. } => StyledText::None { text },
+            StyledText::Style { fg_color, bg_color, attribute, .. } => StyledText::Style {
+                fg_color: *fg_color,
+                bg_color: *bg_color,
+                attribute: *attribute,
+                text,
+            },
+        }
+    }
+
+    /// Splits the text into what fits in `width` columns and the rest, keeping the style on both.
+    pub fn split_at_width(self, width: usize) -> (StyledText, StyledText) {
+        match self {
+            StyledText::None { text } => {
+                let (before, after) = text.split_at_width(width);
+                (StyledText::None { text: before }, StyledText::None { text: after })
+            }
+            StyledText::Style { fg_color, bg_color, attribute, text } => {
+                let (before, after) = text.split_at_width(width);
+                (
+                    StyledText::Style { fg_color, bg_color, attribute, text: before },
+                    StyledText::Style { fg_color, bg_color, attribute, text: after },
+                )
+            }
+        }
+    }
+}
 
 impl SchemeCompatible for StyledText {
     fn rtd() -> Arc<RecordTypeDescriptor>
@@ -439,15 +552,39 @@ impl Selection {
 #[derive(Debug, Clone, Eq, PartialEq, Trace)]
 pub struct StyledFile {
     lines: Vec<Vec<StyledText>>,
+    /// The display column that every line begins at, when only a window of the columns was drawn
+    column_start: usize,
+    /// The column and display column of the first grapheme drawn on lines whose start was left out
+    #[trace(skip)]
+    line_starts: HashMap<usize, (usize, usize)>,
 }
 
 impl StyledFile {
     pub fn new() -> Self {
         Self {
             lines: Vec::new(),
+            column_start: 0,
+            line_starts: HashMap::new(),
         }
     }
 
+    /// Gets the display column that the lines begin at.
+    ///
+    /// This is 0 unless only the columns around the main cursor of long lines were drawn.
+    /// Frontends subtract it from their horizontal scroll.
+    pub fn column_start(&self) -> usize {
+        self.column_start
+    }
+
+    pub(crate) fn set_column_start(&mut self, column_start: usize) {
+        self.column_start = column_start;
+    }
+
+    /// Records that a line's text starts part way through the line.
+    pub(crate) fn set_line_start(&mut self, line: usize, column: usize, display_column: usize) {
+        self.line_starts.insert(line, (column, display_column));
+    }
+
     pub fn lines(&self) -> &Vec<Vec<StyledText>> {
         &self.lines
     }
@@ -473,6 +610,20 @@ impl StyledFile {
         }
         self.lines[line].push(text);
     }
+
+    /// Gets the text that is drawn on a line before the first segment with a background color.
+    ///
+    /// Frontends use this to find where the main cursor is drawn, since the major mode can draw things like line numbers before the text.
+    pub fn text_before_background(&self, line: usize, color: ColorType) -> Option<String> {
+        let mut before = String::new();
+        for segment in self.lines.get(line)? {
+            match segment {
+                StyledText::Style { bg_color, .. } if *bg_color == color => return Some(before),
+                StyledText::None { text } | StyledText::Style { text, .. } => before.push_str(&text.to_string()),
+            }
+        }
+        None
+    }
 }
 
 impl StyledFile {
@@ -508,6 +659,8 @@ impl StyledFile {
         line_index: usize,
         current_line: &mut Vec<StyledText>,
         column_index: &mut usize,
+        display_column: &mut usize,
+        tab_width: usize,
         text: TextChunk,
         fg_color: ColorType,
         bg_color: ColorType,
@@ -596,6 +749,7 @@ impl StyledFile {
 
             // Increment column for the space we just added
             *column_index += 1;
+            *display_column += 1;
             prev_column = *column_index;
         }
 
@@ -619,6 +773,43 @@ impl StyledFile {
             let is_selected = active_selections.iter()
                 .any(|sel| sel.contains(pos));
 
+            // Tabs are drawn as the spaces up to the next tab stop so that frontends don't have to know the tab width
+            if grapheme == "\t" {
+                if start < current_pos_bytes {
+                    let prev_pos = Position::new(line_index, prev_column);
+                    let prev_selected = active_selections.iter()
+                        .any(|sel| sel.contains(prev_pos));
+
+                    current_line.push(StyledText::Style {
+                        fg_color,
+                        bg_color: if prev_selected { ColorType::Selection } else { bg_color },
+                        attribute,
+                        text: TextChunk::new(text.rope.clone(), start, current_pos_bytes),
+                    });
+                }
+
+                let (tab_bg, tab_attribute) = match cursor_at_pos {
+                    Some(cursor_idx) if cursors[cursor_idx].is_main() => (ColorType::Cursor, TextAttribute::empty()),
+                    Some(_) => (ColorType::SecondaryCursor, TextAttribute::empty()),
+                    None if is_selected => (ColorType::Selection, attribute),
+                    None => (bg_color, attribute),
+                };
+                let width = grapheme_width(&grapheme, *display_column, tab_width);
+                current_line.push(StyledText::Style {
+                    fg_color,
+                    bg_color: tab_bg,
+                    attribute: tab_attribute,
+                    text: TextChunk::from(" ".repeat(width)),
+                });
+
+                start = next_pos_bytes;
+                current_pos_bytes = next_pos_bytes;
+                *column_index += 1;
+                *display_column += width;
+                prev_column = *column_index;
+                continue;
+            }
+
             // Determine if we need to flush accumulated text
             if let Some(cursor_idx) = cursor_at_pos {
                 // Flush text before cursor if any
@@ -662,6 +853,7 @@ impl StyledFile {
                     start = current_pos_bytes;
                     current_pos_bytes = next_pos_bytes;
                     *column_index += 1;
+                    *display_column += 1;
                     prev_column = current_column + 1;
                     continue;
                 } else {
@@ -706,6 +898,7 @@ impl StyledFile {
 
             current_pos_bytes = next_pos_bytes;
             *column_index += 1;
+            *display_column += grapheme_width(&grapheme, *display_column, tab_width);
         }
 
         // Push any remaining text
@@ -750,14 +943,19 @@ impl StyledFile {
 
     /// Place cursors and their selections into the styled text.
     /// Cursors can be in arbitrary order.
-    pub fn place_cursors(self, cursors: &[Cursor]) -> Self {
+    ///
+    /// Tabs are expanded to spaces, so the text that comes out is laid out in display columns.
+    pub fn place_cursors(self, cursors: &[Cursor], tab_width: usize) -> Self {
         let selection_map = Self::build_selection_map(cursors);
 
         let mut lines = Vec::new();
 
         for (line_index, line) in self.lines.into_iter().enumerate() {
             let mut current_line = Vec::new();
-            let mut column_index = 0;
+            let (mut column_index, mut display_column) = self.line_starts.get(&line_index)
+                .copied()
+                .unwrap_or((0, 0));
+            let line_display_start = display_column;
 
             for segment in line {
                 let (fg_color, bg_color, attribute, text) = match segment {
@@ -775,6 +973,8 @@ impl StyledFile {
                     line_index,
                     &mut current_line,
                     &mut column_index,
+                    &mut display_column,
+                    tab_width,
                     text,
                     fg_color,
                     bg_color,
@@ -782,10 +982,95 @@ impl StyledFile {
                 );
             }
 
+            if self.column_start > line_display_start {
+                current_line = Self::drop_columns(current_line, self.column_start - line_display_start);
+            }
             lines.push(current_line);
         }
 
-        Self { lines }
+        Self {
+            lines,
+            column_start: self.column_start,
+            line_starts: HashMap::new(),
+        }
+    }
+
+    /// Drops the first `count` display columns of a line, but not its line break.
+    ///
+    /// A wide grapheme that is cut in half leaves spaces for the part that is still drawn.
+    fn drop_columns(line: Vec<StyledText>, count: usize) -> Vec<StyledText> {
+        let mut dropped = 0;
+        let mut kept = Vec::with_capacity(line.len());
+        for segment in line {
+            if dropped >= count {
+                kept.push(segment);
+                continue;
+            }
+            let mut skipped_bytes = 0;
+            for grapheme in segment.text().graphemes() {
+                if dropped >= count || grapheme.ends_with('\n') {
+                    break;
+                }
+                dropped += grapheme_width(&grapheme, dropped, DEFAULT_TAB_WIDTH);
+                skipped_bytes += grapheme.len();
+            }
+            if dropped > count {
+                kept.push(segment.with_text(TextChunk::from(" ".repeat(dropped - count))));
+                dropped = count;
+            }
+            let text = segment.text();
+            if text.start() + skipped_bytes < text.end() {
+                let rest = TextChunk::new(text.rope.clone(), text.start() + skipped_bytes, text.end());
+                kept.push(segment.with_text(rest));
+            }
+        }
+        kept
+    }
+
+    /// Splits each line into the rows that it wraps into.
+    ///
+    /// `row_starts` has the display columns where the rows of each line start.
+    /// Lines without more than one row are left as they are.
+    pub fn wrap(self, row_starts: &[Vec<usize>]) -> Self {
+        let mut lines = Vec::with_capacity(self.lines.len());
+        for (line_index, line) in self.lines.into_iter().enumerate() {
+            let Some(starts) = row_starts.get(line_index).filter(|starts| starts.len() > 1) else {
+                lines.push(line);
+                continue;
+            };
+            let mut next_starts = starts[1..].iter().copied().peekable();
+            let mut row = Vec::new();
+            let mut column = 0;
+            for mut segment in line {
+                loop {
+                    let width = segment.text().width();
+                    match next_starts.peek() {
+                        Some(&start) if start < column + width => {
+                            let (before, after) = segment.split_at_width(start - column);
+                            if before.text().start() != before.text().end() {
+                                row.push(before);
+                            }
+                            lines.push(std::mem::take(&mut row));
+                            next_starts.next();
+                            column = start;
+                            segment = after;
+                        }
+                        _ => {
+                            column += width;
+                            row.push(segment);
+                            break;
+                        }
+                    }
+                }
+            }
+            lines.push(row);
+        }
+
+        Self {
+            lines,
+            column_start: self.column_start,
+            line_starts: HashMap::new(),
+        }
     }
 }
 
@@ -821,7 +1106,9 @@ impl From<Rope> for StyledFile {
         }]);
 
         Self {
-            lines
+            lines,
+            column_start: 0,
+            line_starts: HashMap::new(),
         }
     }
 }
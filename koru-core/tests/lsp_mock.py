@@ -0,0 +1,162 @@
+#!/usr/bin/env python3
+"""A tiny language server that talks over stdio, for testing the LSP client.
+
+It keeps its own copy of every open document by applying the changes it is sent,
+and answers definition, references and rename by looking for the word under the cursor.
+The copy of a document can be fetched with the `mock/document` request to check that it is in sync.
+"""
+
+import json
+import re
+import sys
+from urllib.parse import unquote, urlparse
+
+WORD = re.compile(r"\w+")
+
+documents = {}
+
+
+class UnhandledMethod(Exception):
+    pass
+
+
+def read_message():
+    length = None
+    while True:
+        line = sys.stdin.buffer.readline()
+        if not line:
+            return None
+        line = line.decode("ascii").strip()
+        if not line:
+            break
+        name, _, value = line.partition(":")
+        if name.lower() == "content-length":
+            length = int(value)
+    return json.loads(sys.stdin.buffer.read(length))
+
+
+def send(message):
+    message["jsonrpc"] = "2.0"
+    body = json.dumps(message).encode("utf-8")
+    sys.stdout.buffer.write(b"Content-Length: %d\r\n\r\n" % len(body))
+    sys.stdout.buffer.write(body)
+    sys.stdout.buffer.flush()
+
+
+def text_of(uri):
+    if uri in documents:
+        return documents[uri]["text"]
+    with open(unquote(urlparse(uri).path), encoding="utf-8") as file:
+        return file.read()
+
+
+def index_of(text, position):
+    """Turns a line and a column counted in UTF-16 code units into an index into the text."""
+    lines = text.split("\n")
+    index = sum(len(line) + 1 for line in lines[:position["line"]])
+    line = lines[position["line"]] if position["line"] < len(lines) else ""
+    units = 0
+    for column, char in enumerate(line):
+        if units >= position["character"]:
+            return index + column
+        units += 2 if ord(char) > 0xFFFF else 1
+    return index + len(line)
+
+
+def position_of(text, index):
+    before = text[:index]
+    line = before.count("\n")
+    column = before[before.rfind("\n") + 1:]
+    return {"line": line, "character": len(column.encode("utf-16-le")) // 2}
+
+
+def range_of(text, start, end):
+    return {"start": position_of(text, start), "end": position_of(text, end)}
+
+
+def word_at(text, position):
+    index = index_of(text, position)
+    for match in WORD.finditer(text):
+        if match.start() <= index <= match.end():
+            return match.group()
+    return None
+
+
+def occurrences(text, word):
+    return [match for match in WORD.finditer(text) if match.group() == word]
+
+
+def request(method, params):
+    if method == "initialize":
+        return {
+            "capabilities": {
+                "textDocumentSync": {"openClose": True, "change": 2, "save": {"includeText": False}},
+                "definitionProvider": True,
+                "referencesProvider": True,
+                "renameProvider": True,
+            },
+            "serverInfo": {"name": "lsp-mock"},
+        }
+    if method == "shutdown":
+        return None
+    if method == "mock/document":
+        return documents.get(params["uri"])
+
+    uri = params["textDocument"]["uri"]
+    text = text_of(uri)
+    word = word_at(text, params["position"])
+    if word is None:
+        return None
+    matches = occurrences(text, word)
+    if method == "textDocument/definition":
+        # The first occurrence stands in for where the word is defined
+        match = matches[0]
+        return {"uri": uri, "range": range_of(text, match.start(), match.end())}
+    if method == "textDocument/references":
+        return [{"uri": uri, "range": range_of(text, match.start(), match.end())} for match in matches]
+    if method == "textDocument/rename":
+        edits = [{"range": range_of(text, match.start(), match.end()), "newText": params["newName"]} for match in matches]
+        return {"changes": {uri: edits}}
+    raise UnhandledMethod(method)
+
+
+def notify(method, params):
+    if method == "textDocument/didOpen":
+        document = params["textDocument"]
+        documents[document["uri"]] = {"text": document["text"], "version": document["version"]}
+    elif method == "textDocument/didChange":
+        document = documents[params["textDocument"]["uri"]]
+        for change in params["contentChanges"]:
+            text = document["text"]
+            if "range" in change:
+                start = index_of(text, change["range"]["start"])
+                end = index_of(text, change["range"]["end"])
+                document["text"] = text[:start] + change["text"] + text[end:]
+            else:
+                document["text"] = change["text"]
+        document["version"] = params["textDocument"]["version"]
+    elif method == "textDocument/didClose":
+        documents.pop(params["textDocument"]["uri"], None)
+    elif method == "exit":
+        sys.exit(0)
+
+
+def main():
+    while True:
+        message = read_message()
+        if message is None:
+            return
+        method = message.get("method")
+        if method is None:
+            continue
+        if "id" not in message:
+            notify(method, message.get("params"))
+            continue
+        try:
+            send({"id": message["id"], "result": request(method, message.get("params"))})
+        except UnhandledMethod:
+            send({"id": message["id"], "error": {"code": -32601, "message": "Unhandled method %s" % method}})
+
+
+if __name__ == "__main__":
+    main()
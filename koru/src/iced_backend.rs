@@ -2,6 +2,7 @@ mod styled_text;
 mod buffer_state;
 mod colors;
 
+use std::collections::HashMap;
 use std::error::Error;
 use std::sync::mpsc::{Receiver, Sender};
 use futures::future::BoxFuture;
@@ -9,20 +10,22 @@ use futures::SinkExt;
 use iced::{Element, Task};
 use iced::keyboard::Key;
 use iced::keyboard::key::Named;
-use iced::widget::{column, scrollable, text};
+use iced::mouse::ScrollDelta;
+use iced::widget::{column, container, horizontal_rule, mouse_area, row, scrollable, stack, text, vertical_rule};
 use iced_core::keyboard::Modifiers;
-use iced_core::{Alignment, Length};
+use iced_core::{Alignment, Length, Padding};
 use iced_core::text::{Fragment, Span, Wrapping};
 use iced_futures::Subscription;
 use koru_core::kernel::broker::{BrokerClient, BrokerMessage, GeneralMessage, Message, MessageKind};
 use koru_core::kernel::client::{ClientConnectingMessage, ClientConnectingResponse};
-use koru_core::kernel::input::{ControlKey, KeyBuffer, KeyPress, KeyValue, ModifierKey};
+use koru_core::kernel::input::{ControlKey, KeyBuffer, KeyPress, KeyValue, ModifierKey, MouseButton, MouseEvent, MouseEventKind};
+use koru_core::kernel::layout::{PaneLayout, PaneView, SplitDirection};
 use buffer_state::BufferState;
 
 use iced_core::window::Id as WindowId;
 use tabled::Table;
 use koru_core::{KoruLogger, LogEntry};
-use koru_core::styled_text::{ColorValue, StyledFile};
+use koru_core::styled_text::{display_width, ColorType, ColorValue, StyledFile};
 use crate::crash_logs::CrashLog;
 use crate::iced_backend::colors::ColorDefinitions;
 
@@ -35,9 +38,18 @@ pub enum UiMessage {
     ConnectToSession,
     BrokerMessage(Message),
     KeyPress(KeyPress),
+    /// The modifier keys that are held down changed, which mouse events are sent with
+    ModifiersChanged(ModifierKey),
+    /// The mouse moved to a row and column of a pane's text, counted from the top left of what is shown
+    MouseMoved(usize, usize, usize),
+    MousePressed(usize, MouseButton),
+    MouseReleased(usize, MouseButton),
+    /// The wheel scrolled a pane by lines and columns
+    MouseScrolled(usize, isize, isize),
     CloseEvent(WindowId),
     CloseRequest(WindowId),
     CrashLog(Vec<CrashLog>),
+    ClipboardContents(Option<String>),
     Shutdown,
 }
 
@@ -78,10 +90,30 @@ enum AppInitializationState {
 struct App {
     initialization_state: AppInitializationState,
     session_address: Option<usize>,
+    message_bar: String,
     command_bar: StyledFile,
     hide_command_bar: bool,
+    /// The tab bar, which is hidden while it is empty
+    tab_bar: StyledFile,
+    /// The popup that is drawn at the main cursor, like the candidates of a completion.
+    popup: Option<StyledFile>,
+    /// The picker that is drawn in the middle of the buffer area, over the panes.
+    picker: Option<StyledFile>,
+    /// The keys that can come after the ones waiting in the key buffer, drawn along the bottom of the buffer area.
+    key_hints: Option<StyledFile>,
     key_buffer: KeyBuffer,
-    buffer_state: BufferState,
+    /// How the panes are arranged, as last drawn by the session
+    panes: PaneLayout,
+    /// The ui's state for each pane, by pane id
+    buffer_states: HashMap<usize, BufferState>,
+    /// The size of the buffer area that was last sent to the session, as columns and lines.
+    viewport: Option<(usize, usize)>,
+    /// The modifier keys that are held down, for mouse events
+    modifiers: ModifierKey,
+    /// The pane, row and column that the mouse was last over
+    mouse_position: Option<(usize, usize, usize)>,
+    /// The pane and button of a press that hasn't been released yet, which the mouse drags with
+    mouse_held: Option<(usize, MouseButton)>,
 }
 
 impl App {
@@ -96,14 +128,31 @@ impl App {
                 client_connection: (client_connector, client_receiver),
             },
             session_address: None,
+            message_bar: String::new(),
             command_bar: StyledFile::new(),
             hide_command_bar: true,
+            tab_bar: StyledFile::new(),
+            popup: None,
+            picker: None,
+            key_hints: None,
             key_buffer: KeyBuffer::new(),
-            buffer_state: BufferState::default(),
+            panes: PaneLayout::default(),
+            buffer_states: HashMap::new(),
+            viewport: None,
+            modifiers: ModifierKey::empty(),
+            mouse_position: None,
+            mouse_held: None,
         }
     }
 
-    fn send_client_messages(&mut self, messages: Vec<MessageKind>) -> Task<UiMessage> {
+    fn send_client_messages(&mut self, mut messages: Vec<MessageKind>) -> Task<UiMessage> {
+        // The panes and their wrapped lines depend on the size of the buffer area, so a new size goes ahead of everything else
+        if let Some(viewport) = self.measure_viewport(&self.panes) {
+            if self.viewport != Some(viewport) {
+                self.viewport = Some(viewport);
+                messages.insert(0, MessageKind::General(GeneralMessage::ViewportSize(viewport.0, viewport.1)));
+            }
+        }
         match &self.initialization_state {
             AppInitializationState::Initialized(client) => {
                 let destination = self.session_address.unwrap();
@@ -122,6 +171,21 @@ impl App {
         }
     }
 
+    /// Sends a mouse event at the position that the mouse was last over.
+    fn send_mouse(&mut self, kind: MouseEventKind) -> Task<UiMessage> {
+        let Some((pane, row, column)) = self.mouse_position else {
+            return Task::none();
+        };
+        let Some(state) = self.buffer_states.get(&pane) else {
+            return Task::none();
+        };
+        let event = MouseEvent::new(kind, self.modifiers, pane, row + state.line_offset, column + state.column_offset);
+        self.send_client_messages(vec![
+            MessageKind::General(GeneralMessage::MouseEvent(event)),
+            MessageKind::General(GeneralMessage::RequestMainCursor)
+        ])
+    }
+
     fn setup_client_stream(&mut self) -> Task<UiMessage> {
         let state = std::mem::replace(&mut self.initialization_state, AppInitializationState::Blank);
         match state {
@@ -204,6 +268,31 @@ impl App {
                     MessageKind::General(GeneralMessage::RequestMainCursor)
                 ])
             }
+            UiMessage::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers;
+                Task::none()
+            }
+            UiMessage::MouseMoved(pane, row, column) => {
+                if self.mouse_position == Some((pane, row, column)) {
+                    return Task::none();
+                }
+                self.mouse_position = Some((pane, row, column));
+                match self.mouse_held {
+                    Some((held_pane, button)) if held_pane == pane => self.send_mouse(MouseEventKind::Drag(button)),
+                    _ => Task::none(),
+                }
+            }
+            UiMessage::MousePressed(pane, button) => {
+                self.mouse_held = Some((pane, button));
+                self.send_mouse(MouseEventKind::Press(button))
+            }
+            UiMessage::MouseReleased(_, button) => {
+                self.mouse_held = None;
+                self.send_mouse(MouseEventKind::Release(button))
+            }
+            UiMessage::MouseScrolled(_, lines, columns) => {
+                self.send_mouse(MouseEventKind::Scroll { lines, columns })
+            }
             UiMessage::CloseEvent(window_id) => {
                 self.send_client_messages(vec![
                     MessageKind::Broker(BrokerMessage::Shutdown)
@@ -223,6 +312,11 @@ impl App {
                 }
                 Task::done(UiMessage::Nop)
             }
+            UiMessage::ClipboardContents(contents) => {
+                self.send_client_messages(vec![
+                    MessageKind::General(GeneralMessage::ClipboardContents(contents))
+                ])
+            }
             UiMessage::Shutdown => {
                 iced::window::get_oldest().and_then(|x| {
                     iced::window::close(x)
@@ -244,14 +338,14 @@ impl App {
                 }
                 Task::none()
             }
-            MessageKind::General(GeneralMessage::Draw(styled_file)) => {
-                self.buffer_state.text = styled_file;
+            MessageKind::General(GeneralMessage::Draw(panes)) => {
+                self.set_panes(panes);
                 self.send_client_messages(vec![
                     MessageKind::General(GeneralMessage::RequestMainCursor)
                 ])
             }
-            MessageKind::General(GeneralMessage::UpdateMessageBar(_message_bar)) => {
-                //self.message_bar = message_bar;
+            MessageKind::General(GeneralMessage::UpdateMessageBar(message_bar)) => {
+                self.message_bar = message_bar;
                 Task::none()
             }
             MessageKind::General(GeneralMessage::FlushKeyBuffer) => {
@@ -259,9 +353,12 @@ impl App {
                 Task::none()
             }
             MessageKind::General(GeneralMessage::MainCursorPosition(line, col)) => {
-                self.buffer_state.col = col;
-                self.buffer_state.line = line;
-                self.buffer_state.scroll_view();
+                let focused = self.panes.focused().map(|pane| pane.id);
+                if let Some(state) = focused.and_then(|id| self.buffer_states.get_mut(&id)) {
+                    state.col = col;
+                    state.line = line;
+                    state.scroll_view();
+                }
                 Task::none()
             }
             MessageKind::General(GeneralMessage::ShowCommandBar) => {
@@ -276,6 +373,40 @@ impl App {
                 self.command_bar = text;
                 Task::none()
             }
+            MessageKind::General(GeneralMessage::UpdateTabBar(tab_bar)) => {
+                self.tab_bar = tab_bar;
+                Task::none()
+            }
+            MessageKind::General(GeneralMessage::ShowPopup(popup)) => {
+                self.popup = Some(popup);
+                Task::none()
+            }
+            MessageKind::General(GeneralMessage::HidePopup) => {
+                self.popup = None;
+                Task::none()
+            }
+            MessageKind::General(GeneralMessage::ShowPicker(picker)) => {
+                self.picker = Some(picker);
+                Task::none()
+            }
+            MessageKind::General(GeneralMessage::HidePicker) => {
+                self.picker = None;
+                Task::none()
+            }
+            MessageKind::General(GeneralMessage::ShowKeyHints(key_hints)) => {
+                self.key_hints = Some(key_hints);
+                Task::none()
+            }
+            MessageKind::General(GeneralMessage::HideKeyHints) => {
+                self.key_hints = None;
+                Task::none()
+            }
+            MessageKind::General(GeneralMessage::SetClipboard(text)) => {
+                iced::clipboard::write(text)
+            }
+            MessageKind::General(GeneralMessage::RequestClipboard) => {
+                iced::clipboard::read().map(UiMessage::ClipboardContents)
+            }
             MessageKind::General(GeneralMessage::SetColorDef(definition)) => {
                 let (key, value) = definition.to_tuple();
                 match value {
@@ -315,7 +446,7 @@ impl App {
         match &self.initialization_state {
             AppInitializationState::Initialized(_) => {
                 let command_bar: Element<UiMessage> = if self.hide_command_bar {
-                    styled_text::rich_simple(Vec::new())
+                    styled_text::rich_simple(self.message_bar.lines().take(1).map(String::from).collect())
                         .font(iced::font::Font::MONOSPACE)
                         .into()
                 } else {
@@ -323,10 +454,40 @@ impl App {
                         .font(iced::font::Font::MONOSPACE)
                         .into()
                 };
+                let buffer = self.view_panes(&self.panes);
+                let buffer: Element<UiMessage> = match &self.key_hints {
+                    Some(key_hints) => {
+                        let key_hints = styled_text::rich(key_hints.lines(), 0, 0, |_| {})
+                            .font(iced::font::Font::MONOSPACE);
+                        stack!(
+                            buffer,
+                            container(key_hints).width(Length::Fill).align_bottom(Length::Fill)
+                        ).into()
+                    }
+                    None => buffer,
+                };
+                let buffer: Element<UiMessage> = match &self.picker {
+                    Some(picker) => {
+                        let picker = styled_text::rich(picker.lines(), 0, 0, |_| {})
+                            .font(iced::font::Font::MONOSPACE);
+                        stack!(
+                            buffer,
+                            container(picker).center(Length::Fill)
+                        ).into()
+                    }
+                    None => buffer,
+                };
+                if self.tab_bar.line_count() == 0 {
+                    return column!(
+                        buffer,
+                        command_bar
+                    ).into();
+                }
+                let tab_bar = styled_text::rich(self.tab_bar.lines(), 0, 0, |_| {})
+                    .font(iced::font::Font::MONOSPACE);
                 column!(
-                    styled_text::rich(&self.buffer_state.text.lines(), self.buffer_state.line_offset, self.buffer_state.column_offset, self.buffer_state.text_metrics_callback())
-                        .font(iced::font::Font::MONOSPACE)
-                        .height(Length::Fill),
+                    tab_bar,
+                    buffer,
                     command_bar
                 ).into()
             }
@@ -361,7 +522,167 @@ impl App {
         }
     }
 
-    fn on_key_press_handler(key: Key, mods: Modifiers) -> Option<UiMessage> {
+    /// Updates the state of the panes from a newly drawn layout, forgetting the panes that were closed.
+    fn set_panes(&mut self, panes: PaneLayout) {
+        let mut buffer_states = HashMap::new();
+        for pane in panes.panes() {
+            let mut state = self.buffer_states.remove(&pane.id).unwrap_or_default();
+            state.text = pane.text.clone();
+            (state.line, state.col) = pane.cursor;
+            state.scroll_view();
+            buffer_states.insert(pane.id, state);
+        }
+        self.buffer_states = buffer_states;
+        self.panes = panes;
+    }
+
+    /// Adds up the size of the buffer area from the sizes that the panes were last drawn at.
+    ///
+    /// The panes are laid out by the gui, so this is None until every pane has been drawn once.
+    fn measure_viewport(&self, panes: &PaneLayout) -> Option<(usize, usize)> {
+        match panes {
+            PaneLayout::Pane(view) => {
+                let metrics = *self.buffer_states.get(&view.id)?.text_metrics.lock().expect("lock poisoned");
+                // The gutter is drawn beside the measured text and the status line below it
+                Some((metrics.max_columns? + view.gutter_width, metrics.line_count + view.status_line.line_count()))
+            }
+            PaneLayout::Split { direction, first, second, .. } => {
+                let (first_columns, first_lines) = self.measure_viewport(first)?;
+                let (second_columns, second_lines) = self.measure_viewport(second)?;
+                // The separator takes up a line or column between the sides
+                match direction {
+                    SplitDirection::Horizontal => Some((first_columns.max(second_columns), first_lines + 1 + second_lines)),
+                    SplitDirection::Vertical => Some((first_columns + 1 + second_columns, first_lines.max(second_lines))),
+                }
+            }
+        }
+    }
+
+    /// Gets the size of a character from any pane that has been drawn, for sizing the separators.
+    fn char_size(&self) -> Option<(f32, f32)> {
+        self.buffer_states.values().find_map(|state| {
+            let metrics = *state.text_metrics.lock().expect("lock poisoned");
+            Some((metrics.char_width?, metrics.line_height))
+        })
+    }
+
+    /// Builds the panes into rows and columns the same way as they are split in the layout.
+    fn view_panes<'a>(&'a self, panes: &'a PaneLayout) -> Element<'a, UiMessage> {
+        match panes {
+            PaneLayout::Pane(view) => self.view_pane(view),
+            PaneLayout::Split { direction, ratio, first, second } => {
+                let (char_width, line_height) = self.char_size().unwrap_or((1.0, 1.0));
+                let first = container(self.view_panes(first));
+                let second = container(self.view_panes(second));
+                let first_portion = *ratio as u16;
+                let second_portion = 100u16.saturating_sub(first_portion);
+                match direction {
+                    SplitDirection::Horizontal => column!(
+                        first.height(Length::FillPortion(first_portion)),
+                        horizontal_rule(line_height),
+                        second.height(Length::FillPortion(second_portion)),
+                    ).into(),
+                    SplitDirection::Vertical => row!(
+                        first.width(Length::FillPortion(first_portion)),
+                        vertical_rule(char_width),
+                        second.width(Length::FillPortion(second_portion)),
+                    ).into(),
+                }
+            }
+        }
+    }
+
+    fn view_pane<'a>(&'a self, view: &'a PaneView) -> Element<'a, UiMessage> {
+        let Some(state) = self.buffer_states.get(&view.id) else {
+            return container(text("")).height(Length::Fill).width(Length::Fill).into();
+        };
+        // The drawn text can start partway into long lines
+        let column_offset = state.column_offset.saturating_sub(state.text.column_start());
+        let buffer = styled_text::rich(state.text.lines(), state.line_offset, column_offset, state.text_metrics_callback())
+            .font(iced::font::Font::MONOSPACE)
+            .width(Length::Fill)
+            .height(Length::Fill);
+        let buffer = Self::mouse_area(view.id, state, buffer);
+        let buffer: Element<'a, UiMessage> = match (&self.popup, self.popup_padding(state)) {
+            (Some(popup), Some(padding)) if view.focused => {
+                let popup = styled_text::rich(popup.lines(), 0, 0, |_| {})
+                    .font(iced::font::Font::MONOSPACE);
+                stack!(
+                    buffer,
+                    container(popup).padding(padding)
+                ).into()
+            }
+            _ => buffer.into(),
+        };
+        // The gutter scrolls down with the text but never sideways
+        let buffer: Element<'a, UiMessage> = if view.gutter_width == 0 {
+            buffer
+        } else {
+            let gutter = styled_text::rich(view.gutter.lines(), state.line_offset, 0, |_| {})
+                .font(iced::font::Font::MONOSPACE)
+                .height(Length::Fill);
+            row!(
+                gutter,
+                buffer
+            ).into()
+        };
+        if view.status_line.line_count() == 0 {
+            return buffer;
+        }
+        let status_line = styled_text::rich(view.status_line.lines(), 0, 0, |_| {})
+            .font(iced::font::Font::MONOSPACE);
+        column!(
+            buffer,
+            status_line
+        ).into()
+    }
+
+    /// Sends what the mouse does over the text of a pane, with positions as the rows and columns of the text.
+    fn mouse_area<'a>(id: usize, state: &BufferState, buffer: impl Into<Element<'a, UiMessage>>) -> Element<'a, UiMessage> {
+        let metrics = *state.text_metrics.lock().expect("lock poisoned");
+        let char_width = metrics.char_width.unwrap_or(1.0).max(1.0);
+        let line_height = metrics.line_height.max(1.0);
+        mouse_area(buffer)
+            .on_move(move |point| UiMessage::MouseMoved(id, (point.y / line_height) as usize, (point.x / char_width) as usize))
+            .on_press(UiMessage::MousePressed(id, MouseButton::Left))
+            .on_release(UiMessage::MouseReleased(id, MouseButton::Left))
+            .on_right_press(UiMessage::MousePressed(id, MouseButton::Right))
+            .on_right_release(UiMessage::MouseReleased(id, MouseButton::Right))
+            .on_middle_press(UiMessage::MousePressed(id, MouseButton::Middle))
+            .on_middle_release(UiMessage::MouseReleased(id, MouseButton::Middle))
+            .on_scroll(move |delta| {
+                // Positive deltas scroll towards the start of the text
+                let (lines, columns) = match delta {
+                    ScrollDelta::Lines { x, y } => (-y, -x),
+                    ScrollDelta::Pixels { x, y } => (-y / line_height, -x / char_width),
+                };
+                UiMessage::MouseScrolled(id, scroll_cells(lines), scroll_cells(columns))
+            })
+            .into()
+    }
+
+    /// Finds where to draw the popup in the focused pane so that it is below the main cursor, or above it if there isn't enough room below.
+    fn popup_padding(&self, state: &BufferState) -> Option<Padding> {
+        let popup = self.popup.as_ref()?;
+        let metrics = *state.text_metrics.lock().expect("lock poisoned");
+        let char_width = metrics.char_width?;
+        let before_cursor = state.text.text_before_background(state.line, ColorType::Cursor)?;
+        let cursor_column = (display_width(&before_cursor) + state.text.column_start())
+            .checked_sub(state.column_offset)?;
+        let cursor_line = state.line.checked_sub(state.line_offset)?;
+
+        let below = metrics.line_count.saturating_sub(cursor_line + 1);
+        let top = if popup.line_count() <= below || cursor_line < popup.line_count() {
+            cursor_line + 1
+        } else {
+            cursor_line - popup.line_count()
+        };
+        Some(Padding::ZERO
+            .top(top as f32 * metrics.line_height)
+            .left(cursor_column as f32 * char_width))
+    }
+
+    fn modifier_keys(mods: Modifiers) -> ModifierKey {
         let mut modifiers = ModifierKey::empty();
         if mods.logo() {
             modifiers |= ModifierKey::Meta;
@@ -375,6 +696,20 @@ impl App {
         if mods.alt() {
             modifiers |= ModifierKey::Alt;
         }
+        modifiers
+    }
+
+    fn on_modifiers_changed(event: iced::Event, _: iced::event::Status, _: WindowId) -> Option<UiMessage> {
+        match event {
+            iced::Event::Keyboard(iced::keyboard::Event::ModifiersChanged(mods)) => {
+                Some(UiMessage::ModifiersChanged(Self::modifier_keys(mods)))
+            }
+            _ => None,
+        }
+    }
+
+    fn on_key_press_handler(key: Key, mods: Modifiers) -> Option<UiMessage> {
+        let modifiers = Self::modifier_keys(mods);
         let key = match key {
             Key::Character(c) => {
                 KeyValue::CharacterKey(c.to_string().into_boxed_str())
@@ -437,12 +772,24 @@ impl App {
     fn subscription(&self) -> Subscription<UiMessage> {
         Subscription::batch([
             iced::keyboard::on_key_press(Self::on_key_press_handler),
+            iced::event::listen_with(Self::on_modifiers_changed),
             iced::window::close_events().map(UiMessage::CloseEvent),
             iced::window::close_requests().map(UiMessage::CloseRequest),
         ])
     }
 }
 
+/// Rounds a scroll to whole cells, scrolling by at least one so that the small steps of touchpads aren't lost.
+fn scroll_cells(amount: f32) -> isize {
+    if amount == 0.0 {
+        0
+    } else if amount.abs() < 1.0 {
+        amount.signum() as isize
+    } else {
+        amount.round() as isize
+    }
+}
+
 pub fn true_main(
     client_connector: Sender<ClientConnectingMessage>,
     client_receiver: Receiver<ClientConnectingResponse>,
@@ -2,26 +2,31 @@ mod events;
 mod input;
 mod components;
 mod buffer_state;
+mod clipboard;
 pub mod colors;
 
+use std::collections::HashMap;
 use std::error::Error;
 use std::sync::mpsc::{Receiver, Sender};
 use std::time::Duration;
 use tabled::Table;
 use tuirealm::{Application, AttrValue, Attribute, EventListenerCfg, PollStrategy, Sub, SubClause, SubEventClause, Update};
+use tuirealm::event::{KeyModifiers, MouseButton as TuiMouseButton, MouseEvent as TuiMouseEvent, MouseEventKind as TuiMouseEventKind};
 use tuirealm::props::{Color, Layout};
-use tuirealm::ratatui::layout::{Constraint, Direction};
+use tuirealm::ratatui::layout::{Constraint, Direction, Position, Rect};
 use tuirealm::ratatui::style::Styled;
+use tuirealm::ratatui::text::Span;
 use tuirealm::ratatui::widgets::Clear;
 use tuirealm::terminal::{CrosstermTerminalAdapter, TerminalBridge};
 use koru_core::kernel::broker::{BrokerClient, BrokerMessage, GeneralMessage, Message, MessageKind};
 use koru_core::kernel::client::{ClientConnectingMessage, ClientConnectingResponse};
-use koru_core::kernel::input::{KeyBuffer, KeyPress};
+use koru_core::kernel::input::{KeyBuffer, KeyPress, ModifierKey, MouseButton, MouseEvent, MouseEventKind};
+use koru_core::kernel::layout::{PaneArea, PaneLayout, SplitDirection};
 use crate::tuirealm_backend::components::TextView;
 use crate::tuirealm_backend::events::BrokerPort;
 use buffer_state::BufferState;
 use koru_core::KoruLogger;
-use koru_core::styled_text::{ColorType, ColorValue, StyledFile};
+use koru_core::styled_text::{display_width, ColorType, ColorValue, StyledFile, StyledText};
 use crate::crash_logs::CrashLog;
 use crate::tuirealm_backend::colors::ColorDefinitions;
 
@@ -30,14 +35,21 @@ pub enum UiMessage {
     RegisterBrokerClient(BrokerClient),
     BrokerMessage(Message),
     KeyPress(KeyPress),
+    Mouse(TuiMouseEvent),
     Redraw,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 enum Id {
     Input,
-    Buffer,
+    Pane(usize),
+    Gutter(usize),
+    StatusLine(usize),
+    TabBar,
     MessageBar,
+    Popup,
+    Picker,
+    KeyHints,
 }
 
 struct App {
@@ -50,49 +62,309 @@ struct App {
     message_bar: String,
     command_bar: StyledFile,
     show_command_bar: bool,
+    /// The tab bar, which is hidden while it is empty
+    tab_bar: StyledFile,
+    /// The popup that is drawn at the main cursor, like the candidates of a completion.
+    popup: Option<StyledFile>,
+    /// The picker that is drawn in the middle of the buffer area, over the panes and the popup.
+    picker: Option<StyledFile>,
+    /// The keys that can come after the ones waiting in the key buffer, drawn along the bottom of the buffer area.
+    key_hints: Option<StyledFile>,
     key_buffer: KeyBuffer,
-    buffer_state: BufferState,
+    /// How the panes are arranged, as last drawn by the session
+    panes: PaneLayout,
+    /// The ui's state for each pane, by pane id
+    buffer_states: HashMap<usize, BufferState>,
+    /// The ids of the panes that have a mounted text view
+    mounted_panes: Vec<usize>,
+    /// Where each pane and its text were last drawn on the screen, by pane id
+    pane_rects: Vec<(usize, Rect, Rect)>,
+    /// The pane that the last mouse press was in, which drags and releases go to
+    mouse_pane: Option<usize>,
+    /// The size of the buffer area that was last sent to the session, as columns and lines.
+    viewport: Option<(usize, usize)>,
+    /// The last text we put in the system clipboard.
+    /// This is sent back when the clipboard is requested and it can't be read, like when running over SSH.
+    clipboard: Option<String>,
 }
 
 impl App {
     pub fn view(&mut self, app: &mut Application<Id, UiMessage, UiMessage>) {
-        let bg_color = match ColorDefinitions::get(&ColorType::Base) {
-            ColorValue::Rgb { r, g, b } => {
-                Color::Rgb(r, g, b)
-            }
-            ColorValue::Ansi(index) => Color::Indexed(index),
-        };
+        let bg_color = color(&ColorType::Base);
+        // The frame only picks up a new terminal size when drawing, which is too late for dividing up the panes
+        let _ = self.terminal.raw_mut().autoresize();
         let total_area = self.terminal.raw_mut().get_frame().area();
-        self.buffer_state.line_count = (total_area.height - 1) as usize;
-        self.buffer_state.column_count = total_area.width as usize;
+        let tab_bar_height = self.tab_bar.line_count().min(1) as u16;
+        let buffer_size = Rect::new(0, 0, total_area.width, total_area.height.saturating_sub(1 + tab_bar_height));
+        self.send_viewport(buffer_size.width as usize, buffer_size.height as usize);
+
+        self.mount_panes(app);
+        let mut pane_areas = Vec::new();
+        let mut pane_rects = Vec::new();
+        for pane in self.panes.panes() {
+            let Some(state) = self.buffer_states.get(&pane.id) else {
+                continue;
+            };
+            let id = Id::Pane(pane.id);
+            app.attr(&id, Attribute::Text, TextView::lines(&state.text, state.line_offset, state.line_count)).expect("Invalid attribute");
+            app.attr(&id, Attribute::Custom("ColumnOffset"), AttrValue::Number(state.column_offset.saturating_sub(state.text.column_start()) as isize)).expect("Invalid attribute");
+            app.attr(&id, Attribute::Custom("Background"), AttrValue::Color(bg_color)).expect("Invalid attribute");
+            let area = to_rect(pane.area);
+            let status_height = (pane.status_line.line_count() as u16).min(area.height);
+            let gutter_width = (pane.gutter_width.min(u16::MAX as usize) as u16).min(area.width);
+            let (gutter_area, text_area, status_area) = (
+                Rect { width: gutter_width, height: area.height - status_height, ..area },
+                Rect { x: area.x + gutter_width, width: area.width - gutter_width, height: area.height - status_height, ..area },
+                Rect { y: area.bottom() - status_height, height: status_height, ..area },
+            );
+            pane_areas.push((id, text_area));
+            // The panes are placed below the tab bar
+            let on_screen = |area: Rect| Rect { y: area.y + tab_bar_height, ..area };
+            pane_rects.push((pane.id, on_screen(area), on_screen(text_area)));
 
-        app.attr(&Id::Buffer, Attribute::Text, TextView::lines(&self.buffer_state.text, self.buffer_state.line_offset, self.buffer_state.line_count)).expect("Invalid attribute");
-        app.attr(&Id::Buffer, Attribute::Custom("ColumnOffset"), AttrValue::Number(self.buffer_state.column_offset as isize)).expect("Invalid attribute");
-        app.attr(&Id::Buffer, Attribute::Custom("Background"), AttrValue::Color(bg_color)).expect("Invalid attribute");
+            // The gutter scrolls down with the text but never sideways
+            let id = Id::Gutter(pane.id);
+            app.attr(&id, Attribute::Text, TextView::lines(&pane.gutter, state.line_offset, state.line_count)).expect("Invalid attribute");
+            app.attr(&id, Attribute::Custom("ColumnOffset"), AttrValue::Number(0)).expect("Invalid attribute");
+            app.attr(&id, Attribute::Custom("Background"), AttrValue::Color(bg_color)).expect("Invalid attribute");
+            pane_areas.push((id, gutter_area));
+
+            let id = Id::StatusLine(pane.id);
+            app.attr(&id, Attribute::Text, TextView::lines(&pane.status_line, 0, status_height as usize)).expect("Invalid attribute");
+            app.attr(&id, Attribute::Custom("ColumnOffset"), AttrValue::Number(0)).expect("Invalid attribute");
+            app.attr(&id, Attribute::Custom("Background"), AttrValue::Color(color(&ColorType::Surface0))).expect("Invalid attribute");
+            pane_areas.push((id, status_area));
+        }
+        self.pane_rects = pane_rects;
+        let mut separators = Vec::new();
+        pane_separators(&self.panes, &mut separators);
+        let separator_color = color(&ColorType::Overlay0);
+
+        app.attr(&Id::TabBar, Attribute::Text, TextView::lines(&self.tab_bar, 0, 1)).expect("Invalid attribute");
+        app.attr(&Id::TabBar, Attribute::Custom("ColumnOffset"), AttrValue::Number(0)).expect("Invalid attribute");
+        app.attr(&Id::TabBar, Attribute::Custom("Background"), AttrValue::Color(color(&ColorType::Surface0))).expect("Invalid attribute");
 
         app.attr(&Id::MessageBar, Attribute::Custom("ColumnOffset"), AttrValue::Number(0)).expect("Invalid attribute");
         app.attr(&Id::MessageBar, Attribute::Custom("Background"), AttrValue::Color(bg_color)).expect("Invalid attribute");
         if self.show_command_bar {
             app.attr(&Id::MessageBar, Attribute::Text, TextView::lines(&self.command_bar, 0, 1)).expect("Invalid attribute");
         } else {
-            app.attr(&Id::MessageBar, Attribute::Text, TextView::lines(&StyledFile::new(), 0, 1)).expect("Invalid attribute");
+            app.attr(&Id::MessageBar, Attribute::Text, TextView::lines(&StyledFile::from(self.message_bar.clone()), 0, 1)).expect("Invalid attribute");
+        }
+
+        if let Some(popup) = &self.popup {
+            app.attr(&Id::Popup, Attribute::Text, TextView::lines(popup, 0, popup.line_count())).expect("Invalid attribute");
+            app.attr(&Id::Popup, Attribute::Custom("ColumnOffset"), AttrValue::Number(0)).expect("Invalid attribute");
+            app.attr(&Id::Popup, Attribute::Custom("Background"), AttrValue::Color(bg_color)).expect("Invalid attribute");
+        }
+        let popup_area = self.popup.as_ref().and_then(|popup| self.popup_area(popup, buffer_size));
+        if let Some(picker) = &self.picker {
+            app.attr(&Id::Picker, Attribute::Text, TextView::lines(picker, 0, picker.line_count())).expect("Invalid attribute");
+            app.attr(&Id::Picker, Attribute::Custom("ColumnOffset"), AttrValue::Number(0)).expect("Invalid attribute");
+            app.attr(&Id::Picker, Attribute::Custom("Background"), AttrValue::Color(color(&ColorType::Surface0))).expect("Invalid attribute");
         }
+        let picker_area = self.picker.as_ref().and_then(|picker| picker_area(picker, buffer_size));
+        if let Some(key_hints) = &self.key_hints {
+            app.attr(&Id::KeyHints, Attribute::Text, TextView::lines(key_hints, 0, key_hints.line_count())).expect("Invalid attribute");
+            app.attr(&Id::KeyHints, Attribute::Custom("ColumnOffset"), AttrValue::Number(0)).expect("Invalid attribute");
+            app.attr(&Id::KeyHints, Attribute::Custom("Background"), AttrValue::Color(color(&ColorType::Surface0))).expect("Invalid attribute");
+        }
+        let key_hints_area = self.key_hints.as_ref().and_then(|key_hints| key_hints_area(key_hints, buffer_size));
 
         self.terminal.draw(|frame| {
 
             let layout = Layout::default()
                 .direction(Direction::Vertical)
                 .constraints(&[
+                    Constraint::Length(tab_bar_height),
                     Constraint::Min(0),
                     Constraint::Length(1)
                 ])
                 .chunks(frame.area());
 
-            app.view(&Id::Buffer, frame, layout[0]);
-            app.view(&Id::MessageBar, frame, layout[1]);
+            let buffer_area = layout[1];
+            // The panes are placed from the top left of the buffer area
+            let place = |area: Rect| Rect { x: area.x + buffer_area.x, y: area.y + buffer_area.y, ..area };
+            if tab_bar_height > 0 {
+                app.view(&Id::TabBar, frame, layout[0]);
+            }
+            for (id, area) in &pane_areas {
+                let area = place(*area).intersection(buffer_area);
+                if !area.is_empty() {
+                    app.view(id, frame, area);
+                }
+            }
+            let buffer = frame.buffer_mut();
+            for (area, direction) in &separators {
+                let symbol = match direction {
+                    SplitDirection::Horizontal => "─",
+                    SplitDirection::Vertical => "│",
+                };
+                // The layout is from before a resize until the session draws it again
+                let area = place(*area).intersection(buffer_area);
+                if area.is_empty() {
+                    continue;
+                }
+                for position in area.positions() {
+                    buffer[position].set_symbol(symbol).set_fg(separator_color).set_bg(bg_color);
+                }
+            }
+            app.view(&Id::MessageBar, frame, layout[2]);
+            if let Some(popup_area) = popup_area {
+                let popup_area = place(popup_area);
+                frame.render_widget(Clear, popup_area);
+                app.view(&Id::Popup, frame, popup_area);
+            }
+            if let Some(key_hints_area) = key_hints_area {
+                let key_hints_area = place(key_hints_area);
+                frame.render_widget(Clear, key_hints_area);
+                app.view(&Id::KeyHints, frame, key_hints_area);
+            }
+            if let Some(picker_area) = picker_area {
+                let picker_area = place(picker_area);
+                frame.render_widget(Clear, picker_area);
+                app.view(&Id::Picker, frame, picker_area);
+            }
             
         }).unwrap();
     }
+
+    /// Mounts the text views for every pane in the layout and unmounts the ones of closed panes.
+    fn mount_panes(&mut self, app: &mut Application<Id, UiMessage, UiMessage>) {
+        let ids = self.panes.panes().iter().map(|pane| pane.id).collect::<Vec<_>>();
+        for id in self.mounted_panes.iter().filter(|id| !ids.contains(id)) {
+            let _ = app.umount(&Id::Pane(*id));
+            let _ = app.umount(&Id::Gutter(*id));
+            let _ = app.umount(&Id::StatusLine(*id));
+        }
+        for id in &ids {
+            for view_id in [Id::Pane(*id), Id::Gutter(*id), Id::StatusLine(*id)] {
+                if !app.mounted(&view_id) {
+                    app.mount(view_id, Box::from(TextView::new()), vec![]).expect("Failed to mount textview");
+                }
+            }
+        }
+        self.mounted_panes = ids;
+    }
+
+    /// Updates the state of the panes from a newly drawn layout, forgetting the panes that were closed.
+    fn set_panes(&mut self, panes: PaneLayout) {
+        let mut buffer_states = HashMap::new();
+        for pane in panes.panes() {
+            let mut state = self.buffer_states.remove(&pane.id).unwrap_or_default();
+            state.text = pane.text.clone();
+            state.line_count = pane.area.lines.saturating_sub(pane.status_line.line_count());
+            state.column_count = pane.area.columns.saturating_sub(pane.gutter_width);
+            (state.line, state.col) = pane.cursor;
+            state.scroll_view();
+            buffer_states.insert(pane.id, state);
+        }
+        self.buffer_states = buffer_states;
+        self.panes = panes;
+    }
+
+    /// Works out which pane a mouse event is over and where it is in the pane's text.
+    ///
+    /// Presses and the wheel go to the pane under the mouse, while drags and releases go to the pane the press was in
+    /// and are kept inside of its text.
+    fn resolve_mouse(&mut self, event: TuiMouseEvent) -> Option<MouseEvent> {
+        let (x, y) = (event.column, event.row);
+        let kind = match event.kind {
+            TuiMouseEventKind::Down(button) => MouseEventKind::Press(mouse_button(button)),
+            TuiMouseEventKind::Up(button) => MouseEventKind::Release(mouse_button(button)),
+            TuiMouseEventKind::Drag(button) => MouseEventKind::Drag(mouse_button(button)),
+            TuiMouseEventKind::ScrollDown => MouseEventKind::Scroll { lines: 1, columns: 0 },
+            TuiMouseEventKind::ScrollUp => MouseEventKind::Scroll { lines: -1, columns: 0 },
+            TuiMouseEventKind::ScrollRight => MouseEventKind::Scroll { lines: 0, columns: 1 },
+            TuiMouseEventKind::ScrollLeft => MouseEventKind::Scroll { lines: 0, columns: -1 },
+            TuiMouseEventKind::Moved => return None,
+        };
+        let pane = match kind {
+            MouseEventKind::Drag(_) | MouseEventKind::Release(_) => self.mouse_pane?,
+            _ => self.pane_rects.iter()
+                .find(|(_, area, _)| area.contains(Position::new(x, y)))
+                .map(|(id, _, _)| *id)?,
+        };
+        if let MouseEventKind::Press(_) = kind {
+            self.mouse_pane = Some(pane);
+        }
+        let (_, _, text_area) = self.pane_rects.iter().find(|(id, _, _)| *id == pane)?;
+        let state = self.buffer_states.get(&pane)?;
+        let row = y.clamp(text_area.top(), text_area.bottom().saturating_sub(1)).saturating_sub(text_area.top());
+        let column = x.saturating_sub(text_area.left()).min(text_area.width.saturating_sub(1));
+
+        let mut modifiers = ModifierKey::empty();
+        if event.modifiers.contains(KeyModifiers::SHIFT) {
+            modifiers |= ModifierKey::Shift;
+        }
+        if event.modifiers.contains(KeyModifiers::CONTROL) {
+            modifiers |= ModifierKey::Control;
+        }
+        if event.modifiers.contains(KeyModifiers::ALT) {
+            modifiers |= ModifierKey::Alt;
+        }
+        Some(MouseEvent::new(
+            kind,
+            modifiers,
+            pane,
+            row as usize + state.line_offset,
+            column as usize + state.column_offset,
+        ))
+    }
+
+    fn focused_state(&mut self) -> Option<&mut BufferState> {
+        let id = self.panes.focused()?.id;
+        self.buffer_states.get_mut(&id)
+    }
+
+    /// Tells the session the size of the buffer area if it changed, since the panes are divided up from it.
+    fn send_viewport(&mut self, columns: usize, lines: usize) {
+        let viewport = (columns, lines);
+        let Some(session_address) = self.session_address else {
+            return;
+        };
+        if self.viewport == Some(viewport) {
+            return;
+        }
+        self.viewport = Some(viewport);
+        let mut client = self.broker_client.clone();
+        koru_core::spawn_task(async move {
+            match client.send_async(
+                MessageKind::General(GeneralMessage::ViewportSize(viewport.0, viewport.1)),
+                session_address).await {
+                Ok(..) => {}
+                Err(e) => println!("Error sending viewport size: {}", e),
+            }
+        });
+    }
+
+    /// Places a popup below the main cursor, or above it if there isn't enough room below.
+    ///
+    /// The area is from the top left of the buffer area.
+    fn popup_area(&self, popup: &StyledFile, buffer_size: Rect) -> Option<Rect> {
+        let buffer_height = buffer_size.height;
+        let pane = self.panes.focused()?;
+        let state = self.buffer_states.get(&pane.id)?;
+        let before_cursor = state.text.text_before_background(state.line, ColorType::Cursor)?;
+        let cursor_x = (display_width(&before_cursor) + state.text.column_start())
+            .checked_sub(state.column_offset)? + pane.area.x + pane.gutter_width;
+        let cursor_x = cursor_x.min(u16::MAX as usize) as u16;
+        let cursor_y = (state.line.checked_sub(state.line_offset)? + pane.area.y).min(u16::MAX as usize) as u16;
+        if popup.line_count() == 0 || cursor_y >= buffer_height {
+            return None;
+        }
+
+        let width = file_width(popup).min(buffer_size.width as usize) as u16;
+        let below = buffer_height - cursor_y - 1;
+        let height = popup.line_count().min(below.max(cursor_y) as usize) as u16;
+        let y = if height <= below {
+            cursor_y + 1
+        } else {
+            cursor_y - height
+        };
+        let x = cursor_x.min(buffer_size.width - width);
+        Some(Rect::new(x, y, width, height))
+    }
     
     pub fn handle_broker_message(&mut self, msg: Message) -> Result<(), Box<dyn Error>> {
         match msg.kind {
@@ -115,9 +387,9 @@ impl App {
                 });
                 Ok(())
             }
-            MessageKind::General(GeneralMessage::Draw(file)) => {
+            MessageKind::General(GeneralMessage::Draw(panes)) => {
                 self.redraw = true;
-                self.buffer_state.text = file;
+                self.set_panes(panes);
                 Ok(())
             }
             MessageKind::General(GeneralMessage::UpdateMessageBar(bar)) => {
@@ -130,9 +402,11 @@ impl App {
                 Ok(())
             }
             MessageKind::General(GeneralMessage::MainCursorPosition(line, col)) => {
-                self.buffer_state.line = line;
-                self.buffer_state.col = col;
-                self.buffer_state.scroll_view();
+                if let Some(state) = self.focused_state() {
+                    state.line = line;
+                    state.col = col;
+                    state.scroll_view();
+                }
                 self.redraw = true;
                 Ok(())
             }
@@ -147,9 +421,66 @@ impl App {
                 Ok(())
             }
             MessageKind::General(GeneralMessage::UpdateCommandBar(text)) => {
+                self.redraw = true;
                 self.command_bar = text;
                 Ok(())
             }
+            MessageKind::General(GeneralMessage::UpdateTabBar(tab_bar)) => {
+                self.redraw = true;
+                self.tab_bar = tab_bar;
+                Ok(())
+            }
+            MessageKind::General(GeneralMessage::ShowPopup(popup)) => {
+                self.redraw = true;
+                self.popup = Some(popup);
+                Ok(())
+            }
+            MessageKind::General(GeneralMessage::HidePopup) => {
+                self.redraw = true;
+                self.popup = None;
+                Ok(())
+            }
+            MessageKind::General(GeneralMessage::ShowPicker(picker)) => {
+                self.redraw = true;
+                self.picker = Some(picker);
+                Ok(())
+            }
+            MessageKind::General(GeneralMessage::HidePicker) => {
+                self.redraw = true;
+                self.picker = None;
+                Ok(())
+            }
+            MessageKind::General(GeneralMessage::ShowKeyHints(key_hints)) => {
+                self.redraw = true;
+                self.key_hints = Some(key_hints);
+                Ok(())
+            }
+            MessageKind::General(GeneralMessage::HideKeyHints) => {
+                self.redraw = true;
+                self.key_hints = None;
+                Ok(())
+            }
+            MessageKind::General(GeneralMessage::SetClipboard(text)) => {
+                clipboard::set_clipboard(&text)?;
+                self.clipboard = Some(text);
+                Ok(())
+            }
+            MessageKind::General(GeneralMessage::RequestClipboard) => {
+                let mut client = self.broker_client.clone();
+                let session_address = self.session_address.unwrap();
+                let cached = self.clipboard.clone();
+                // Reading the clipboard runs a tool that can take a while, so it doesn't hold up the UI
+                std::thread::spawn(move || {
+                    let contents = clipboard::get_clipboard().or(cached);
+                    match client.send(
+                        MessageKind::General(GeneralMessage::ClipboardContents(contents)),
+                        session_address) {
+                        Ok(..) => {}
+                        Err(e) => println!("Error sending clipboard contents: {}", e),
+                    }
+                });
+                Ok(())
+            }
             MessageKind::Broker(BrokerMessage::Crash) => {
                 self.crashed = true;
                 self.quit = true;
@@ -196,6 +527,26 @@ impl Update<UiMessage> for App {
                 });
                 None
             }
+            Some(UiMessage::Mouse(event)) => {
+                let event = self.resolve_mouse(event)?;
+                let mut client = self.broker_client.clone();
+                let session_address = self.session_address?;
+                koru_core::spawn_task(async move {
+                    match client.send_async(
+                        MessageKind::General(GeneralMessage::MouseEvent(event)),
+                        session_address).await {
+                        Ok(..) => {}
+                        Err(e) => println!("Error sending mouse event: {}", e),
+                    }
+                    match client.send_async(
+                        MessageKind::General(GeneralMessage::RequestMainCursor),
+                        session_address).await {
+                        Ok(..) => {}
+                        Err(e) => println!("Error sending request main cursor: {}", e),
+                    }
+                });
+                None
+            }
             Some(UiMessage::Redraw) => {
                 self.redraw = true;
                 None
@@ -205,6 +556,76 @@ impl Update<UiMessage> for App {
     }
 }
 
+fn color(color_type: &ColorType) -> Color {
+    match ColorDefinitions::get(color_type) {
+        ColorValue::Rgb { r, g, b } => Color::Rgb(r, g, b),
+        ColorValue::Ansi(index) => Color::Indexed(index),
+    }
+}
+
+fn mouse_button(button: TuiMouseButton) -> MouseButton {
+    match button {
+        TuiMouseButton::Left => MouseButton::Left,
+        TuiMouseButton::Right => MouseButton::Right,
+        TuiMouseButton::Middle => MouseButton::Middle,
+    }
+}
+
+/// Gets how many columns the widest line of a file takes up.
+fn file_width(file: &StyledFile) -> usize {
+    file.lines().iter()
+        .map(|line| line.iter().map(|segment| match segment {
+            StyledText::None { text } | StyledText::Style { text, .. } => Span::raw(text.to_string()).width(),
+        }).sum::<usize>())
+        .max()
+        .unwrap_or(0)
+}
+
+/// Places a picker in the middle of the buffer area.
+///
+/// The area is from the top left of the buffer area.
+fn picker_area(picker: &StyledFile, buffer_size: Rect) -> Option<Rect> {
+    if picker.line_count() == 0 || buffer_size.is_empty() {
+        return None;
+    }
+    let width = file_width(picker).min(buffer_size.width as usize) as u16;
+    let height = picker.line_count().min(buffer_size.height as usize) as u16;
+    Some(Rect::new((buffer_size.width - width) / 2, (buffer_size.height - height) / 2, width, height))
+}
+
+/// Places the key hints along the bottom of the buffer area.
+///
+/// The area is from the top left of the buffer area.
+fn key_hints_area(key_hints: &StyledFile, buffer_size: Rect) -> Option<Rect> {
+    if key_hints.line_count() == 0 || buffer_size.is_empty() {
+        return None;
+    }
+    let height = key_hints.line_count().min(buffer_size.height as usize) as u16;
+    Some(Rect::new(0, buffer_size.height - height, buffer_size.width, height))
+}
+
+fn to_rect(area: PaneArea) -> Rect {
+    let clamp = |value: usize| value.min(u16::MAX as usize) as u16;
+    Rect::new(clamp(area.x), clamp(area.y), clamp(area.columns), clamp(area.lines))
+}
+
+/// Collects the cells between the sides of each split, returning the area that the layout covers.
+fn pane_separators(panes: &PaneLayout, separators: &mut Vec<(Rect, SplitDirection)>) -> Rect {
+    match panes {
+        PaneLayout::Pane(view) => to_rect(view.area),
+        PaneLayout::Split { direction, first, second, .. } => {
+            let first = pane_separators(first, separators);
+            let second = pane_separators(second, separators);
+            let separator = match direction {
+                SplitDirection::Horizontal => Rect::new(first.x, first.bottom(), first.width, second.y.saturating_sub(first.bottom())),
+                SplitDirection::Vertical => Rect::new(first.right(), first.y, second.x.saturating_sub(first.right()), first.height),
+            };
+            separators.push((separator, *direction));
+            first.union(second)
+        }
+    }
+}
+
 fn init_app(broker_client: &mut BrokerClient) -> Application<Id, UiMessage, UiMessage> {
     let mut app = Application::init(
         EventListenerCfg::default()
@@ -243,12 +664,6 @@ pub async fn real_main(
         ]
     )?;
     
-    application.mount(
-        Id::Buffer,
-        Box::from(TextView::new()),
-        vec![]
-    ).expect("Failed to mount textview");
-
     application.mount(
         Id::MessageBar,
         Box::from(TextView::new()),
@@ -256,6 +671,30 @@ pub async fn real_main(
             Sub::new(SubEventClause::Any, SubClause::Always),
         ]
     ).expect("Failed to mount messsagebar");
+
+    application.mount(
+        Id::TabBar,
+        Box::from(TextView::new()),
+        vec![]
+    ).expect("Failed to mount tab bar");
+
+    application.mount(
+        Id::Popup,
+        Box::from(TextView::new()),
+        vec![]
+    ).expect("Failed to mount popup");
+
+    application.mount(
+        Id::Picker,
+        Box::from(TextView::new()),
+        vec![]
+    ).expect("Failed to mount picker");
+
+    application.mount(
+        Id::KeyHints,
+        Box::from(TextView::new()),
+        vec![]
+    ).expect("Failed to mount key hints");
     
     let mut app = App {
         crashed: false,
@@ -267,12 +706,23 @@ pub async fn real_main(
         message_bar: String::new(),
         command_bar: StyledFile::new(),
         show_command_bar: false,
+        tab_bar: StyledFile::new(),
+        popup: None,
+        picker: None,
+        key_hints: None,
         key_buffer: KeyBuffer::new(),
-        buffer_state: BufferState::default(),
+        panes: PaneLayout::default(),
+        buffer_states: HashMap::new(),
+        mounted_panes: Vec::new(),
+        pane_rects: Vec::new(),
+        mouse_pane: None,
+        viewport: None,
+        clipboard: None,
     };
 
     let _ = app.terminal.enter_alternate_screen()?;
     let _ = app.terminal.enable_raw_mode()?;
+    let _ = app.terminal.enable_mouse_capture()?;
     
     while !app.quit {
         match application.tick(PollStrategy::TryFor(Duration::from_millis(16))) {
@@ -295,6 +745,7 @@ pub async fn real_main(
         }
     }
 
+    app.terminal.disable_mouse_capture()?;
     app.terminal.disable_raw_mode()?;
     app.terminal.leave_alternate_screen()?;
 
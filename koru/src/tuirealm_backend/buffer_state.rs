@@ -24,6 +24,10 @@ impl BufferState {
 
 
     pub fn scroll_view(&mut self) {
+        // A pane can be squeezed down to nothing when the terminal is small
+        if self.line_count == 0 || self.column_count == 0 {
+            return;
+        }
         self.scroll_horizontal();
         self.scroll_vertical();
     }
@@ -0,0 +1,141 @@
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// How long a clipboard tool gets to answer before giving up on it.
+const READ_TIMEOUT: Duration = Duration::from_millis(500);
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(input: &[u8]) -> String {
+    let mut output = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let bytes = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let group = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (group >> (18 - i * 6)) & 0x3F;
+                output.push(BASE64_ALPHABET[index as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+/// Builds the OSC 52 escape sequence that tells the terminal to set the system clipboard.
+///
+/// When running inside of tmux the sequence gets wrapped so that tmux passes it through to the outer terminal.
+fn osc52_sequence(text: &str) -> String {
+    let sequence = format!("\x1b]52;c;{}\x07", base64_encode(text.as_bytes()));
+    if std::env::var_os("TMUX").is_some() {
+        format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+    } else {
+        sequence
+    }
+}
+
+/// Sets the system clipboard through the terminal.
+///
+/// This works over SSH since the terminal emulator is the one that sets the clipboard.
+pub fn set_clipboard(text: &str) -> std::io::Result<()> {
+    let mut stdout = std::io::stdout();
+    stdout.write_all(osc52_sequence(text).as_bytes())?;
+    stdout.flush()
+}
+
+/// Lists the command line tools that can read the system clipboard, in the order they are tried.
+///
+/// The X and Wayland tools are only tried if there is a display for them to connect to.
+fn read_commands() -> Vec<&'static [&'static str]> {
+    let mut commands: Vec<&'static [&'static str]> = Vec::new();
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        commands.push(&["wl-paste", "--no-newline"]);
+    }
+    if std::env::var_os("DISPLAY").is_some() {
+        commands.push(&["xclip", "-selection", "clipboard", "-o"]);
+        commands.push(&["xsel", "--clipboard", "--output"]);
+    }
+    if cfg!(target_os = "macos") {
+        commands.push(&["pbpaste"]);
+    }
+    commands
+}
+
+/// Runs a tool and gets what it prints, killing it if it doesn't finish in time.
+fn read_output(command: &[&str]) -> Option<String> {
+    let (program, args) = command.split_first()?;
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    let mut stdout = child.stdout.take()?;
+    // The output is read on its own thread so a tool that prints a lot can't fill the pipe and hang
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut text = String::new();
+        let _ = sender.send(stdout.read_to_string(&mut text).map(|_| text));
+    });
+    let text = receiver.recv_timeout(READ_TIMEOUT);
+    if text.is_err() {
+        let _ = child.kill();
+    }
+    let status = child.wait().ok()?;
+    match text {
+        Ok(Ok(text)) if status.success() => Some(text),
+        _ => None,
+    }
+}
+
+/// Reads the system clipboard with the usual command line tools for it.
+///
+/// Terminals don't reliably answer OSC 52 queries, so the clipboard can't be read through the terminal.
+/// Returns None if none of the tools are installed or can reach the clipboard, like when running over SSH.
+pub fn get_clipboard() -> Option<String> {
+    read_commands().into_iter().find_map(read_output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::base64_encode;
+
+    #[test]
+    fn encodes_empty_input() {
+        assert_eq!(base64_encode(b""), "");
+    }
+
+    #[test]
+    fn encodes_whole_groups_without_padding() {
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn pads_a_group_of_two_bytes() {
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"fooba"), "Zm9vYmE=");
+    }
+
+    #[test]
+    fn pads_a_group_of_one_byte() {
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"foob"), "Zm9vYg==");
+    }
+
+    #[test]
+    fn encodes_non_ascii_text() {
+        assert_eq!(base64_encode("é".as_bytes()), "w6k=");
+        assert_eq!(base64_encode("日本".as_bytes()), "5pel5pys");
+        assert_eq!(base64_encode(&[0xff, 0xfe, 0xfd]), "//79");
+    }
+}
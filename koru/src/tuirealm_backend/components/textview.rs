@@ -105,7 +105,8 @@ impl MockComponent for TextView {
 
         let text = Text::from(lines);
         let paragraph = Paragraph::new(text)
-            .style(tuirealm::ratatui::style::Style::default().bg(bg_color));
+            .style(tuirealm::ratatui::style::Style::default().bg(bg_color))
+            .scroll((0, column_offset as u16));
 
         frame.render_widget(paragraph, area)
     }
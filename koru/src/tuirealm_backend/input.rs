@@ -35,12 +35,13 @@ impl Component<UiMessage, UiMessage> for Input {
             Event::User(msg) => {
                 Some(msg)
             },
+            Event::Mouse(mouse_event) => Some(UiMessage::Mouse(mouse_event)),
             Event::Keyboard(key_event) => {
                 let key = match key_event.code {
                     Key::Esc => KeyValue::ControlKey(ControlKey::Escape),
                     Key::Enter => KeyValue::ControlKey(ControlKey::Enter),
                     Key::Backspace => KeyValue::ControlKey(ControlKey::Backspace),
-                    Key::Tab => KeyValue::ControlKey(ControlKey::Tab),
+                    Key::Tab | Key::BackTab => KeyValue::ControlKey(ControlKey::Tab),
                     Key::Char(' ') => KeyValue::ControlKey(ControlKey::Space),
                     Key::Delete => KeyValue::ControlKey(ControlKey::Delete),
                     Key::Left => KeyValue::ControlKey(ControlKey::Left),
@@ -98,7 +99,7 @@ impl Component<UiMessage, UiMessage> for Input {
                     _ => return None,
                 };
                 let mut modifiers = ModifierKey::empty();
-                if key_event.modifiers.contains(KeyModifiers::SHIFT) {
+                if key_event.modifiers.contains(KeyModifiers::SHIFT) || key_event.code == Key::BackTab {
                     modifiers |= ModifierKey::Shift;
                 }
                 if key_event.modifiers.contains(KeyModifiers::CONTROL) {
@@ -0,0 +1,98 @@
+(library (scheme completion)
+  (export completion-setup
+    completion-complete
+    completion-complete-keypress)
+  (import (rnrs)
+    (koru-buffer)
+    (koru-command)
+    (koru-session)
+    (koru-lsp)
+    (koru-completion)
+    (scheme koru)
+    (scheme text-edit-mode))
+
+  ;; The keys that drive the popup while a completion is active
+  (define next-keys '("C-n" "DOWN"))
+  (define previous-keys '("C-p" "UP"))
+  (define accept-keys '("TAB" "ENTER"))
+
+  ;; Only buffers that are attached to a language server are asked
+  (define (completion-lsp buffer-name prefix line column)
+    (if (lsp-buffer-language buffer-name)
+      (lsp-completion buffer-name line column)
+      '()))
+
+  (define completion-next-keypress
+    (command-create
+      'completion-next-keypress
+      "Selects the next completion candidate"
+      (lambda (keys) (completion-next))
+      #t
+      'key-sequence))
+
+  (define completion-previous-keypress
+    (command-create
+      'completion-previous-keypress
+      "Selects the previous completion candidate"
+      (lambda (keys) (completion-previous))
+      #t
+      'key-sequence))
+
+  ;; The accepted text replaces what was typed before each cursor
+  (define completion-accept-keypress
+    (command-create
+      'completion-accept-keypress
+      "Inserts the selected completion candidate at each cursor"
+      (lambda (keys)
+        (let ((accepted (completion-accept)))
+          (when accepted
+            (let ((text (car accepted))
+                  (delete-count (cadr accepted))
+                  (cursor-count (text-edit-mode-cursor-count)))
+              (for i from 0 to (- cursor-count 1)
+                (for j from 1 to delete-count
+                  (command-apply text-edit-mode-delete-before-cursor i))
+                (command-apply text-edit-mode-insert-at-cursor i text))))))
+      #t
+      'key-sequence))
+
+  (define (bind-keys keys command)
+    (for-each (lambda (key) (add-special-key-binding key command)) keys))
+
+  (define (unbind-keys keys)
+    (for-each remove-special-key-binding keys))
+
+  (define (completion-end-hook)
+    (unbind-keys next-keys)
+    (unbind-keys previous-keys)
+    (unbind-keys accept-keys))
+
+  (define completion-complete
+    (command-create
+      'completion-complete
+      "Shows the completion candidates for the text before the main cursor"
+      (lambda ()
+        (let* ((position (text-edit-mode-cursor-position (text-edit-mode-main-cursor-index)))
+               (count (completion-start (current-buffer-name) (car position) (cdr position))))
+          (if (> count 0)
+            (begin
+              (bind-keys next-keys completion-next-keypress)
+              (bind-keys previous-keys completion-previous-keypress)
+              (bind-keys accept-keys completion-accept-keypress))
+            (message-bar-set "No completions"))))))
+
+  (define completion-complete-keypress
+    (command-create
+      'completion-complete-keypress
+      "Shows the completion candidates for the text before the main cursor in response to a keypress"
+      (lambda (keys) (command-apply completion-complete))
+      #t
+      'key-sequence))
+
+  ;; Registers the built in sources, the language server's candidates are ranked first
+  (define (completion-setup)
+    (completion-source-add! "lsp" completion-lsp 30)
+    (completion-source-add! "snippets" completion-snippets 20)
+    (completion-source-add! "buffer-words" completion-buffer-words 10)
+    (completion-source-add! "file-paths" completion-file-paths 10)
+    (add-hook 'completion-end 'completion completion-end-hook)))
@@ -0,0 +1,36 @@
+(library (scheme diagnostics)
+  (export diagnostics-show-list)
+  (import (rnrs)
+    (koru-buffer)
+    (koru-command)
+    (koru-session)
+    (koru-diagnostics))
+
+  (define (position->string position)
+    (string-append (number->string (+ (car position) 1))
+      ":" (number->string (+ (cadr position) 1))))
+
+  ;; Each diagnostic is (start end severity message source)
+  (define (diagnostic->string buffer-name diagnostic)
+    (string-append buffer-name
+      ":" (position->string (car diagnostic))
+      ": " (caddr diagnostic)
+      ": " (cadddr diagnostic)
+      " [" (car (cddddr diagnostic)) "]"))
+
+  (define diagnostics-show-list
+    (command-create
+      'diagnostics-show-list
+      "Lists the diagnostics of the current buffer in the *diagnostics* buffer"
+      (lambda ()
+        (let* ((buffer-name (current-buffer-name))
+               (diagnostics (diagnostics-get buffer-name)))
+          (if (null? diagnostics)
+            (message-bar-set "No diagnostics")
+            (buffer-change-focus
+              (buffer-create "*diagnostics*"
+                (fold-left
+                  (lambda (text diagnostic)
+                    (string-append text (diagnostic->string buffer-name diagnostic) "\n"))
+                  ""
+                  diagnostics)))))))))
@@ -0,0 +1,178 @@
+(library (scheme key-help)
+  (export describe-key
+    describe-key-keypress
+    where-is
+    where-is-keypress
+    describe-bindings
+    describe-bindings-keypress)
+  (import (rnrs)
+    (koru-buffer)
+    (koru-command)
+    (koru-key)
+    (koru-picker)
+    (koru-session))
+
+  (define (key-map-label source)
+    (string-append "the " (symbol->string source) " key map"))
+
+  (define (command-label command)
+    (symbol->string (command-name command)))
+
+  ;; Each entry of key-lookup is (source how command), where command is #f if nothing runs
+  (define (runs? entry)
+    (memq (cadr entry) '(bound default)))
+
+  (define (find-entry predicate entries)
+    (cond
+      ((null? entries) #f)
+      ((predicate (car entries)) (car entries))
+      (else (find-entry predicate (cdr entries)))))
+
+  (define (shadowed-note entries)
+    (let ((other (find-entry runs? entries)))
+      (if other
+        (string-append ", which keeps " (command-label (caddr other))
+          " in " (key-map-label (car other)) " from running")
+        "")))
+
+  (define (describe-keys keys keys-string)
+    (let* ((entries (key-lookup keys))
+           (winner (find-entry runs? entries))
+           ;; A prefix that is bound on its own runs when the keys after it don't finish a longer binding
+           (fallback (find-entry (lambda (entry) (and (eq? (cadr entry) 'prefix) (caddr entry))) entries)))
+      (cond
+        ((and winner (eq? (car winner) 'special))
+          (string-append keys-string " runs " (command-label (caddr winner))
+            " from the special key map, which gets every key before the other key maps"
+            (shadowed-note (cdr (memq winner entries)))))
+        ((and winner (eq? (cadr winner) 'default))
+          (string-append keys-string " runs " (command-label (caddr winner))
+            ", the default of " (key-map-label (car winner)) ", since nothing in it is bound to " keys-string
+            (shadowed-note (cdr (memq winner entries)))))
+        (winner
+          (string-append keys-string " runs " (command-label (caddr winner))
+            " from " (key-map-label (car winner))
+            (shadowed-note (cdr (memq winner entries)))))
+        (fallback
+          (string-append keys-string " is the start of longer bindings, and runs " (command-label (caddr fallback))
+            " from " (key-map-label (car fallback)) " if none of them are finished"))
+        ((find-entry (lambda (entry) (eq? (cadr entry) 'prefix)) entries)
+          (string-append keys-string " is the start of longer bindings"))
+        (else
+          (string-append keys-string " isn't bound in any active key map")))))
+
+  (define describe-key
+    (command-create
+      'describe-key
+      "Tells which key map and command the next keys run"
+      (lambda ()
+        (message-bar-set "Describe key: press the keys to describe")
+        (key-sequence-read
+          (lambda (keys)
+            (message-bar-set (describe-keys keys (key-sequence->string keys))))))))
+
+  (define describe-key-keypress
+    (command-create
+      'describe-key-keypress
+      "Tells which key map and command the next keys run in response to a keypress"
+      (lambda (keys) (command-apply describe-key))
+      #t
+      'key-sequence))
+
+  ;; Each binding is (source keys command standing other), where keys is #f for a default command
+  (define (binding-keys binding)
+    (or (cadr binding) "any unbound key"))
+
+  (define (binding-flag binding)
+    (let ((standing (cadddr binding))
+          (other (car (cddddr binding))))
+      (case standing
+        ((shadowed) (string-append "shadowed by " (key-map-label other)))
+        ((conflict) (string-append "shadows a binding in " (key-map-label other)))
+        (else ""))))
+
+  (define (where-is-entry binding)
+    (let ((flag (binding-flag binding)))
+      (string-append (binding-keys binding) " in " (key-map-label (car binding))
+        (if (string=? flag "") "" (string-append " (" flag ")")))))
+
+  (define (join strings separator)
+    (if (null? strings)
+      ""
+      (fold-left (lambda (text string) (string-append text separator string)) (car strings) (cdr strings))))
+
+  (define (where-is-message name)
+    (let ((bindings (filter
+                      (lambda (binding) (string=? (command-label (caddr binding)) name))
+                      (key-bindings))))
+      (if (null? bindings)
+        (string-append name " isn't bound to any key")
+        (string-append name " is on " (join (map where-is-entry bindings) ", ")))))
+
+  (define where-is
+    (command-create
+      'where-is
+      "Lists the keys that run a command"
+      (lambda (name) (message-bar-set (where-is-message name)))
+      'text))
+
+  ;; Commands bound to keys are usually hidden from the command bar, so the items come from the bindings
+  (define (bound-command-items)
+    (let loop ((bindings (key-bindings)) (names '()) (items '()))
+      (if (null? bindings)
+        (reverse items)
+        (let* ((command (caddr (car bindings)))
+               (name (command-label command)))
+          (if (member name names)
+            (loop (cdr bindings) names items)
+            (loop (cdr bindings) (cons name names)
+              (cons (list name name (command-description command)) items)))))))
+
+  (define where-is-keypress
+    (command-create
+      'where-is-keypress
+      "Picks a bound command and lists the keys that run it in response to a keypress"
+      (lambda (keys)
+        (picker-open "Where is: "
+          (bound-command-items)
+          (lambda (name) (message-bar-set (where-is-message name)))))
+      #t
+      'key-sequence))
+
+  (define (pad text width)
+    (if (< (string-length text) width)
+      (string-append text (make-string (- width (string-length text)) #\space))
+      (string-append text " ")))
+
+  (define (binding-line binding)
+    (let ((flag (binding-flag binding)))
+      (string-append "  " (pad (binding-keys binding) 24)
+        (pad (command-label (caddr binding)) 40)
+        (if (string=? flag "") "" (string-append "[" flag "]"))
+        "\n")))
+
+  ;; The bindings come grouped by key map, so a heading goes before the first binding of each key map
+  (define (bindings->string bindings)
+    (let loop ((bindings bindings) (source #f) (text "Key bindings, in the order keys are looked up in\n"))
+      (if (null? bindings)
+        text
+        (let* ((binding (car bindings))
+               (heading (if (eq? (car binding) source)
+                          ""
+                          (string-append "\n" (symbol->string (car binding)) "\n"))))
+          (loop (cdr bindings) (car binding) (string-append text heading (binding-line binding)))))))
+
+  (define describe-bindings
+    (command-create
+      'describe-bindings
+      "Lists the bindings of every active key map in the *bindings* buffer"
+      (lambda ()
+        (buffer-change-focus (buffer-create "*bindings*" (bindings->string (key-bindings)))))))
+
+  (define describe-bindings-keypress
+    (command-create
+      'describe-bindings-keypress
+      "Lists the bindings of every active key map in the *bindings* buffer in response to a keypress"
+      (lambda (keys) (command-apply describe-bindings))
+      #t
+      'key-sequence)))
@@ -0,0 +1,98 @@
+(library (scheme lsp-mode)
+  (export lsp-mode-create
+    lsp-mode-file-open-hook
+    lsp-goto-definition
+    lsp-show-hover
+    lsp-show-references
+    lsp-rename-symbol
+    lsp-format-buffer)
+  (import (rnrs)
+    (minor-mode)
+    (koru-buffer)
+    (koru-command)
+    (koru-session)
+    (koru-task)
+    (koru-lsp)
+    (scheme text-edit-mode))
+
+  ;; The document lives on the buffer so there is nothing to do on focus changes
+  (define (lsp-mode-gain-focus minor-mode) '())
+  (define (lsp-mode-lose-focus minor-mode) '())
+
+  (define (lsp-mode-create buffer-name language)
+    (minor-mode-create 'lsp-mode lsp-mode-gain-focus lsp-mode-lose-focus language))
+
+  ;; Starting a server can take a while so it is done in the background
+  (define (lsp-mode-file-open-hook buffer-name file-ext)
+    (let ((language (lsp-file-type file-ext)))
+      (when (and language (lsp-server-configured? language))
+        (minor-mode-add buffer-name (lsp-mode-create buffer-name language))
+        (spawn-ephemeral-task (lambda () (lsp-attach buffer-name language))))))
+
+  (define (main-cursor-position)
+    (text-edit-mode-cursor-position (text-edit-mode-main-cursor-index)))
+
+  (define (location->string location)
+    (string-append (car location)
+      ":" (number->string (+ (cadr location) 1))
+      ":" (number->string (+ (caddr location) 1))))
+
+  (define (goto-location location)
+    (let ((buffer-name (buffer-from-path (car location))))
+      (buffer-change-focus buffer-name)
+      (command-apply text-edit-mode-cursor-set-position
+        (text-edit-mode-main-cursor-index)
+        (cadr location)
+        (caddr location))))
+
+  (define lsp-goto-definition
+    (command-create
+      'lsp-goto-definition
+      "Jumps to the definition of the symbol under the main cursor"
+      (lambda ()
+        (let* ((position (main-cursor-position))
+               (locations (lsp-definition (current-buffer-name) (car position) (cdr position))))
+          (if (null? locations)
+            (message-bar-set "No definition found")
+            (goto-location (car locations)))))))
+
+  (define lsp-show-hover
+    (command-create
+      'lsp-show-hover
+      "Shows information about the symbol under the main cursor"
+      (lambda ()
+        (let* ((position (main-cursor-position))
+               (text (lsp-hover (current-buffer-name) (car position) (cdr position))))
+          (message-bar-set (if text text "No information available"))))))
+
+  (define lsp-show-references
+    (command-create
+      'lsp-show-references
+      "Lists the references to the symbol under the main cursor in the *references* buffer"
+      (lambda ()
+        (let* ((position (main-cursor-position))
+               (locations (lsp-references (current-buffer-name) (car position) (cdr position))))
+          (if (null? locations)
+            (message-bar-set "No references found")
+            (buffer-change-focus
+              (buffer-create "*references*"
+                (fold-left
+                  (lambda (text location) (string-append text (location->string location) "\n"))
+                  ""
+                  locations))))))))
+
+  (define lsp-rename-symbol
+    (command-create
+      'lsp-rename-symbol
+      "Renames the symbol under the main cursor across the project"
+      (lambda (new-name)
+        (let* ((position (main-cursor-position))
+               (files (lsp-rename (current-buffer-name) (car position) (cdr position) new-name)))
+          (message-bar-set (string-append "Renamed in " (number->string files) " files"))))
+      'text))
+
+  (define lsp-format-buffer
+    (command-create
+      'lsp-format-buffer
+      "Formats the current buffer with its language server"
+      (lambda () (lsp-format (current-buffer-name))))))
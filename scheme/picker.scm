@@ -0,0 +1,152 @@
+(library (scheme picker)
+  (export picker-setup
+    picker-files
+    picker-files-keypress
+    picker-buffers
+    picker-buffers-keypress
+    picker-commands
+    picker-commands-keypress)
+  (import (rnrs)
+    (koru-buffer)
+    (koru-command)
+    (koru-key)
+    (koru-session)
+    (koru-picker))
+
+  (define picker-insert-keypress
+    (command-create
+      'picker-insert-keypress
+      "Types into the picker's query"
+      (lambda (keys) (picker-insert-key keys))
+      #t
+      'key-sequence))
+
+  (define picker-insert-space-keypress
+    (command-create
+      'picker-insert-space-keypress
+      "Types a space into the picker's query"
+      (lambda (keys) (picker-insert " "))
+      #t
+      'key-sequence))
+
+  (define picker-delete-back-keypress
+    (command-create
+      'picker-delete-back-keypress
+      "Deletes the last character of the picker's query"
+      (lambda (keys) (picker-delete-back))
+      #t
+      'key-sequence))
+
+  (define picker-next-keypress
+    (command-create
+      'picker-next-keypress
+      "Selects the next match in the picker"
+      (lambda (keys) (picker-next))
+      #t
+      'key-sequence))
+
+  (define picker-previous-keypress
+    (command-create
+      'picker-previous-keypress
+      "Selects the previous match in the picker"
+      (lambda (keys) (picker-previous))
+      #t
+      'key-sequence))
+
+  (define picker-accept-keypress
+    (command-create
+      'picker-accept-keypress
+      "Closes the picker and picks the selected match"
+      (lambda (keys) (picker-accept))
+      #t
+      'key-sequence))
+
+  (define picker-cancel-keypress
+    (command-create
+      'picker-cancel-keypress
+      "Closes the picker without picking anything"
+      (lambda (keys) (picker-cancel))
+      #t
+      'key-sequence))
+
+  (define (picker-key-map)
+    (let ((key-map (key-map-create picker-insert-keypress)))
+      (key-map-insert key-map "SPC" picker-insert-space-keypress)
+      (key-map-insert key-map "BS" picker-delete-back-keypress)
+      (key-map-insert key-map "DOWN" picker-next-keypress)
+      (key-map-insert key-map "C-n" picker-next-keypress)
+      (key-map-insert key-map "TAB" picker-next-keypress)
+      (key-map-insert key-map "UP" picker-previous-keypress)
+      (key-map-insert key-map "C-p" picker-previous-keypress)
+      (key-map-insert key-map "S-TAB" picker-previous-keypress)
+      (key-map-insert key-map "ENTER" picker-accept-keypress)
+      (key-map-insert key-map "ESC" picker-cancel-keypress)
+      (key-map-insert key-map "C-g" picker-cancel-keypress)
+      (key-map-insert key-map "C-c" picker-cancel-keypress)
+      key-map))
+
+  ;; The files are found a batch at a time so the picker opens straight away in big projects
+  (define picker-files
+    (command-create
+      'picker-files
+      "Picks a file in the project to open"
+      (lambda ()
+        (let ((walk (picker-project-walk)))
+          (picker-open "Files: "
+            (lambda () (picker-project-walk-next walk))
+            (lambda (path) (buffer-change-focus (buffer-from-path path)))
+            picker-file-preview)))))
+
+  (define picker-files-keypress
+    (command-create
+      'picker-files-keypress
+      "Picks a file in the project to open in response to a keypress"
+      (lambda (keys) (command-apply picker-files))
+      #t
+      'key-sequence))
+
+  (define picker-buffers
+    (command-create
+      'picker-buffers
+      "Picks an open buffer to show in the current pane"
+      (lambda ()
+        (picker-open "Buffers: "
+          (picker-buffer-items)
+          buffer-change-focus
+          picker-buffer-preview))))
+
+  (define picker-buffers-keypress
+    (command-create
+      'picker-buffers-keypress
+      "Picks an open buffer to show in the current pane in response to a keypress"
+      (lambda (keys) (command-apply picker-buffers))
+      #t
+      'key-sequence))
+
+  ;; Commands that take arguments have to be run from the command bar
+  (define (run-picked-command command)
+    (if (null? (command-arguments command))
+      (command-apply command)
+      (message-bar-set
+        (string-append (symbol->string (command-name command)) " takes arguments, run it from the command bar"))))
+
+  (define picker-commands
+    (command-create
+      'picker-commands
+      "Picks a command to run"
+      (lambda ()
+        (picker-open "Commands: "
+          (picker-command-items)
+          run-picked-command
+          picker-command-preview))))
+
+  (define picker-commands-keypress
+    (command-create
+      'picker-commands-keypress
+      "Picks a command to run in response to a keypress"
+      (lambda (keys) (command-apply picker-commands))
+      #t
+      'key-sequence))
+
+  (define (picker-setup)
+    (picker-key-map-set! (picker-key-map))))
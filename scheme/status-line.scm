@@ -0,0 +1,54 @@
+(library (scheme status-line)
+  (export status-line-setup
+    status-line-default)
+  (import (rnrs)
+    (koru-buffer)
+    (koru-session)
+    (koru-modal)
+    (minor-mode)
+    (koru-status-line)
+    (styled-text))
+
+  (define (segment text fg-color)
+    (styled-text-create text fg-color "Surface0"))
+
+  (define (join-symbols symbols)
+    (fold-left
+      (lambda (text symbol)
+        (if (string=? text "")
+          (symbol->string symbol)
+          (string-append text " " (symbol->string symbol))))
+      ""
+      symbols))
+
+  ;; Only the focused pane's buffer is the current buffer, so the other panes don't show a state
+  (define (modal-segments modal-mode buffer-name focused?)
+    (if (and focused? (memq modal-mode (buffer-minor-modes buffer-name)))
+      (let ((state (modal-state (minor-mode-data (minor-mode-get modal-mode)))))
+        (list (styled-text-create
+                (string-append " " (symbol->string state) " ")
+                "Base" "Accent" "bold")))
+      '()))
+
+  ;; Makes a status line that shows the state of the modal in the minor mode named modal-mode
+  (define (status-line-default modal-mode)
+    (lambda (pane-id buffer-name line column focused?)
+      (let ((minor-modes (buffer-minor-modes buffer-name)))
+        (list
+          (append
+            (modal-segments modal-mode buffer-name focused?)
+            (cons (segment (string-append " " buffer-name) (if focused? "Text" "Subtext0"))
+              (if (buffer-modified? buffer-name)
+                (list (segment " [+]" "Warning"))
+                '())))
+          '()
+          (list
+            (segment (string-append (join-symbols minor-modes) " ") "Subtext0")
+            (segment
+              (string-append (number->string (+ line 1)) ":" (number->string (+ column 1)) " ")
+              "Text"))))))
+
+  ;; The state of the modal can change without a keypress, so the status line is redrawn on its hook
+  (define (status-line-setup modal-mode modal-hook)
+    (status-line-set! (status-line-default modal-mode))
+    (add-hook modal-hook 'status-line (lambda args (status-line-refresh)))))
@@ -0,0 +1,20 @@
+(library (scheme syntax-mode)
+  (export syntax-mode-create
+    syntax-mode-file-open-hook)
+  (import (rnrs)
+    (minor-mode)
+    (koru-buffer)
+    (koru-syntax))
+
+  ;; The syntax tree lives on the buffer so there is nothing to do on focus changes
+  (define (syntax-mode-gain-focus minor-mode) '())
+  (define (syntax-mode-lose-focus minor-mode) '())
+
+  (define (syntax-mode-create buffer-name grammar)
+    (syntax-enable buffer-name grammar)
+    (minor-mode-create 'syntax-mode syntax-mode-gain-focus syntax-mode-lose-focus grammar))
+
+  (define (syntax-mode-file-open-hook buffer-name file-ext)
+    (let ((grammar (syntax-file-type file-ext)))
+      (when (and grammar (syntax-grammar-loaded? grammar))
+        (minor-mode-add buffer-name (syntax-mode-create buffer-name grammar))))))
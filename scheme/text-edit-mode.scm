@@ -1,6 +1,8 @@
 (library (scheme text-edit-mode)
   (export text-edit-mode-cursor-up
     text-edit-mode-cursor-down
+    text-edit-mode-cursor-visual-up
+    text-edit-mode-cursor-visual-down
     text-edit-mode-cursor-left
     text-edit-mode-cursor-right
     text-edit-mode-cursor-up-predicate
@@ -20,6 +22,7 @@
     text-edit-mode-cursor-create
     text-edit-mode-cursor-destroy
     text-edit-mode-cursor-position
+    text-edit-mode-cursor-set-position
     text-edit-mode-cursor-count
     text-edit-mode-main-cursor-index
     text-edit-mode-is-mark-set?
@@ -27,7 +30,14 @@
     text-edit-mode-delete-before-cursor
     text-edit-mode-delete-after-cursor
     text-edit-mode-delete-cursor-region
+    text-edit-mode-copy-cursor-region
+    text-edit-mode-paste-at-cursor
+    text-edit-mode-copy-cursor-region-to
+    text-edit-mode-paste-at-cursor-from
     text-edit-mode-replace-at-cursor
+    text-edit-mode-insert-newline
+    text-edit-mode-indent
+    text-edit-mode-dedent
     text-edit-mode-undo
     text-edit-mode-redo
     text-edit-mode-start-transaction
@@ -58,6 +68,20 @@
       (lambda (index) (text-edit-move-cursor-down (current-major-mode) index))
       'number))
 
+  (define text-edit-mode-cursor-visual-up
+    (command-create
+      'text-edit-mode-cursor-visual-up
+      "Moves the cursor at the index up a row of wrapped text"
+      (lambda (index) (text-edit-move-cursor-visual-up (current-major-mode) index))
+      'number))
+
+  (define text-edit-mode-cursor-visual-down
+    (command-create
+      'text-edit-mode-cursor-visual-down
+      "Moves the cursor at the index down a row of wrapped text"
+      (lambda (index) (text-edit-move-cursor-visual-down (current-major-mode) index))
+      'number))
+
   (define text-edit-mode-cursor-left
     (command-create
       'text-edit-mode-cursor-left
@@ -170,6 +194,15 @@
     (define (text-edit-mode-cursor-position index)
       (text-edit-cursor-position (current-major-mode) index))
 
+    (define text-edit-mode-cursor-set-position
+      (command-create
+        'text-edit-mode-cursor-set-position
+        "Moves the cursor at the indicated index to a row and column"
+        (lambda (index row column) (text-edit-cursor-set-position (current-major-mode) index row column))
+        'number
+        'number
+        'number))
+
     (define (text-edit-mode-cursor-count)
       (text-edit-cursor-count (current-major-mode)))
 
@@ -215,6 +248,26 @@
       (lambda (index) (text-edit-delete-region-cursor (current-major-mode) index))
       'number))
 
+  (define text-edit-mode-copy-cursor-region
+    (command-create
+      'text-edit-mode-copy-cursor-region
+      "Copies text within marked area under cursor into the kill ring"
+      (lambda (index) (text-edit-copy-region-cursor (current-major-mode) index))
+      'number))
+
+  (define text-edit-mode-paste-at-cursor
+    (command-create
+      'text-edit-mode-paste-at-cursor
+      "Pastes the text from the kill ring that belongs to the cursor"
+      (lambda (index) (text-edit-paste-at-cursor (current-major-mode) index))
+      'number))
+
+  (define (text-edit-mode-copy-cursor-region-to index register)
+    (text-edit-copy-region-cursor (current-major-mode) index register))
+
+  (define (text-edit-mode-paste-at-cursor-from index register)
+    (text-edit-paste-at-cursor (current-major-mode) index register))
+
   (define text-edit-mode-replace-at-cursor
     (command-create
       'text-edit-mode-replace-at-cursor
@@ -223,6 +276,25 @@
       'number
       'text))
 
+  (define text-edit-mode-insert-newline
+    (command-create
+      'text-edit-mode-insert-newline
+      "Inserts a newline at the indicated cursor index and indents the new line"
+      (lambda (index) (text-edit-insert-newline (current-major-mode) index))
+      'number))
+
+  (define text-edit-mode-indent
+    (command-create
+      'text-edit-mode-indent
+      "Indents the lines under every cursor and its marked area"
+      (lambda () (text-edit-indent-cursors (current-major-mode)))))
+
+  (define text-edit-mode-dedent
+    (command-create
+      'text-edit-mode-dedent
+      "Dedents the lines under every cursor and its marked area"
+      (lambda () (text-edit-dedent-cursors (current-major-mode)))))
+
   (define text-edit-mode-undo
     (command-create
       'text-edit-mode-undo
@@ -5,12 +5,17 @@ use iced_core::widget::text::{Catalog, Style, StyleFn};
 use iced_core::widget::{text, tree, Tree};
 use iced_core::widget::tree::Tag;
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 #[derive(Debug, Clone, Copy, Default)]
 pub struct VisibleTextMetrics {
     pub line_count: usize,
     /// None if not monospaced or can't be determined
     pub max_columns: Option<usize>,
+    /// The height of a line in pixels
+    pub line_height: f32,
+    /// The width of a character in pixels, None if not monospaced or can't be determined
+    pub char_width: Option<f32>,
 }
 
 #[allow(missing_debug_implementations)]
@@ -237,10 +242,16 @@ where
     fn calculate_metrics(&self, viewport: &Rectangle, renderer: &Renderer) -> VisibleTextMetrics {
         let line_count = self.visible_line_count(viewport.height, renderer);
         let max_columns = self.calculate_max_columns(viewport.width, renderer);
+        let line_height = self.calculate_line_height(renderer);
+        let default_font = renderer.default_font();
+        let font = self.font.as_ref().unwrap_or(&default_font);
+        let char_width = self.measure_char_width(self.size.unwrap_or(renderer.default_size()), font, renderer);
 
         VisibleTextMetrics {
             line_count,
             max_columns,
+            line_height,
+            char_width,
         }
     }
 }
@@ -777,6 +788,8 @@ where
 }
 
 /// Helper function to apply column offset to a slice of spans
+///
+/// The offset is in display columns, so wide characters like CJK ideographs and emoji count as two.
 fn apply_column_offset_to_spans<'a, Link, Font>(
     spans: &[Span<'a, Link, Font>],
     column_offset: usize,
@@ -790,7 +803,7 @@ where
     }
 
     let mut result = Vec::new();
-    let mut graphemes_processed = 0; // Renamed from chars_processed
+    let mut columns_processed = 0;
 
     for span in spans {
         let text = &span.text;
@@ -799,76 +812,39 @@ where
             let lines: Vec<&str> = text.split('\n').collect();
 
             for (i, line) in lines.iter().enumerate() {
-                let line_grapheme_count = line.graphemes(true).count(); // Changed
-
-                if graphemes_processed < column_offset {
-                    let graphemes_to_skip = column_offset.saturating_sub(graphemes_processed);
-
-                    if graphemes_to_skip < line_grapheme_count {
-                        // Skip graphemes instead of chars
-                        let trimmed: String = line.graphemes(true)
-                            .skip(graphemes_to_skip)
-                            .collect();
-
-                        if !trimmed.is_empty() {
-                            let mut new_span = span.clone();
-                            let final_text = if i < lines.len() - 1 {
-                                format!("{}\n", trimmed)
-                            } else {
-                                trimmed
-                            };
-                            new_span.text = Fragment::from(final_text);
-                            result.push(new_span);
-                        } else if i < lines.len() - 1 {
-                            let mut new_span = span.clone();
-                            new_span.text = Fragment::from("\n");
-                            result.push(new_span);
-                        }
-                        graphemes_processed = column_offset;
-                    } else if i < lines.len() - 1 {
-                        let mut new_span = span.clone();
-                        new_span.text = Fragment::from("\n");
-                        result.push(new_span);
-                        graphemes_processed = 0;
-                    }
+                let is_last = i == lines.len() - 1;
+                let visible = if columns_processed < column_offset {
+                    let (rest, skipped) = skip_columns(line, column_offset - columns_processed);
+                    columns_processed += skipped;
+                    rest
                 } else {
+                    line.to_string()
+                };
+
+                let final_text = if is_last {
+                    visible
+                } else {
+                    format!("{}\n", visible)
+                };
+                if !final_text.is_empty() {
                     let mut new_span = span.clone();
-                    let final_text = if i < lines.len() - 1 {
-                        format!("{}\n", line)
-                    } else {
-                        line.to_string()
-                    };
                     new_span.text = Fragment::from(final_text);
                     result.push(new_span);
                 }
 
-                if i < lines.len() - 1 {
-                    graphemes_processed = 0;
+                if !is_last {
+                    columns_processed = 0;
                 }
             }
+        } else if columns_processed >= column_offset {
+            result.push(span.clone());
         } else {
-            let grapheme_count = text.graphemes(true).count(); // Changed
-
-            if graphemes_processed + grapheme_count <= column_offset {
-                graphemes_processed += grapheme_count;
-                continue;
-            }
-
-            if graphemes_processed < column_offset {
-                let graphemes_to_skip = column_offset - graphemes_processed;
-                let trimmed: String = text.graphemes(true)
-                    .skip(graphemes_to_skip)
-                    .collect();
-
-                if !trimmed.is_empty() {
-                    let mut new_span = span.clone();
-                    new_span.text = Fragment::from(trimmed);
-                    result.push(new_span);
-                }
-                graphemes_processed += grapheme_count;
-            } else {
-                result.push(span.clone());
-                graphemes_processed += grapheme_count;
+            let (rest, skipped) = skip_columns(text, column_offset - columns_processed);
+            columns_processed += skipped;
+            if !rest.is_empty() {
+                let mut new_span = span.clone();
+                new_span.text = Fragment::from(rest);
+                result.push(new_span);
             }
         }
     }
@@ -876,6 +852,29 @@ where
     result
 }
 
+/// Drops the first `columns` display columns of some text without a newline.
+///
+/// Returns what is left and how many columns were dropped.
+/// A wide grapheme that is cut in half is replaced with a space for the half that is still visible.
+fn skip_columns(text: &str, columns: usize) -> (String, usize) {
+    let mut skipped = 0;
+    let mut rest = String::new();
+    for grapheme in text.graphemes(true) {
+        if skipped >= columns {
+            rest.push_str(grapheme);
+            continue;
+        }
+        let width = grapheme.width();
+        if skipped + width > columns {
+            rest.push_str(&" ".repeat(skipped + width - columns));
+            skipped = columns;
+        } else {
+            skipped += width;
+        }
+    }
+    (rest, skipped)
+}
+
 impl<'a, Link, Theme, Renderer> From<Rich<'a, Link, Theme, Renderer>>
 for Element<'a, Link, Theme, Renderer>
 where